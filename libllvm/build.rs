@@ -0,0 +1,31 @@
+#[cfg(feature = "debuginfo")]
+extern crate gcc;
+
+/// `llvm-config` of the LLVM linked by `llvm-sys`
+#[cfg(feature = "debuginfo")]
+fn llvm_config() -> String {
+    match std::env::var("LLVM_SYS_39_PREFIX") {
+        Ok(prefix) => format!("{}/bin/llvm-config", prefix),
+        Err(_) => "llvm-config".to_string(),
+    }
+}
+
+/// Build the C++ shim of debug information
+#[cfg(feature = "debuginfo")]
+fn main() {
+    let output = std::process::Command::new(llvm_config())
+        .arg("--cxxflags")
+        .output()
+        .expect("failed to run llvm-config");
+    let flags = String::from_utf8(output.stdout).expect("llvm-config output is not UTF-8");
+
+    let mut build = gcc::Build::new();
+    build.cpp(true).file("shim/debuginfo.cpp");
+    for flag in flags.split_whitespace() {
+        build.flag(flag);
+    }
+    build.compile("llvmshim");
+}
+
+#[cfg(not(feature = "debuginfo"))]
+fn main() {}
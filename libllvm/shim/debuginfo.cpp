@@ -0,0 +1,12 @@
+// Debug information of LLVM C++ API missing in its C API
+
+#include "llvm-c/Core.h"
+#include "llvm/IR/DebugInfoMetadata.h"
+#include "llvm/IR/Function.h"
+
+using namespace llvm;
+
+// Attach the subprogram of function `From` to function `To`
+extern "C" void LLVMMikoCopySubprogram(LLVMValueRef To, LLVMValueRef From) {
+    unwrap<Function>(To)->setSubprogram(unwrap<Function>(From)->getSubprogram());
+}
@@ -0,0 +1,174 @@
+use llvm_sys::core::*;
+use llvm_sys::ir_reader::LLVMParseIRInContext;
+use llvm_sys::prelude::*;
+
+use std::ptr;
+use std::fmt::Write;
+use std::ffi::{CStr, CString};
+use libc::c_char;
+
+use wrapper::*;
+
+/// Function a location is in, created by `DebugInfoBuilder::create_function`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DIScope(usize);
+
+/// Builder of debug information: a compile unit of one file,
+///   subprograms of functions and locations of instructions.
+///
+/// The C API of LLVM 3.9 has no `DIBuilder`, so the metadata is written
+///   as text and parsed in the same context by `finalize`,
+///   then locations are attached to the instructions as `!dbg`.
+///   Functions could not be attached their subprograms by the C API,
+///   it is done by `Function::setSubprogram` in `shim/debuginfo.cpp`,
+///   which is built only with feature `debuginfo` as it needs a C++ compiler.
+#[derive(Debug)]
+pub struct DebugInfoBuilder {
+    file: String,
+    dir: String,
+    producer: String,
+    /// Each function with its name and line
+    functions: Vec<(LLVMFunction, String, usize)>,
+    /// Instruction, its scope, line and column
+    locations: Vec<(LLVMValue, DIScope, usize, usize)>,
+}
+
+impl DebugInfoBuilder {
+    pub fn new(file: &str, dir: &str, producer: &str) -> Self {
+        DebugInfoBuilder {
+            file: file.to_string(),
+            dir: dir.to_string(),
+            producer: producer.to_string(),
+            functions: vec![],
+            locations: vec![],
+        }
+    }
+
+    /// Create a subprogram of function `fun` named `name` defined at `line`
+    pub fn create_function(&mut self, fun: &LLVMFunction, name: &str, line: usize) -> DIScope {
+        self.functions.push((*fun, name.to_string(), line));
+        DIScope(self.functions.len() - 1)
+    }
+
+    /// Locate an instruction at line and column in a function
+    pub fn set_location(&mut self, instr: LLVMValue, scope: DIScope, line: usize, col: usize) {
+        self.locations.push((instr, scope, line, col));
+    }
+
+    /// IR of the metadata, a function has an instruction at each location
+    ///   and a function `dbg.sp.N` is attached each subprogram.
+    ///   Nodes are numbered as compile unit, flags, file, subroutine type,
+    ///   its type list, then subprograms and locations.
+    fn metadata_source(&self) -> String {
+        let first_sp = 5;
+        let first_loc = first_sp + self.functions.len();
+        let mut ir = String::from("define void @dbg.locations() {\nentry:\n");
+        for i in 0..self.locations.len() {
+            writeln!(ir, "  %l{} = add i32 0, 0, !dbg !{}", i, first_loc + i).unwrap();
+        }
+        ir.push_str("  ret void\n}\n\n");
+        for i in 0..self.functions.len() {
+            writeln!(ir, "define void @dbg.sp.{}() !dbg !{} {{\nentry:\n  ret void\n}}\n", i, first_sp + i).unwrap();
+        }
+        ir.push_str("!llvm.dbg.cu = !{!0}\n!llvm.module.flags = !{!1}\n\n");
+        writeln!(ir,
+                 "!0 = distinct !DICompileUnit(language: DW_LANG_C, file: !2, producer: \"{}\", \
+                  isOptimized: false, runtimeVersion: 0, emissionKind: FullDebug)",
+                 escape(&self.producer)).unwrap();
+        ir.push_str("!1 = !{i32 2, !\"Debug Info Version\", i32 3}\n");
+        writeln!(ir, "!2 = !DIFile(filename: \"{}\", directory: \"{}\")",
+                 escape(&self.file), escape(&self.dir)).unwrap();
+        ir.push_str("!3 = !DISubroutineType(types: !4)\n!4 = !{null}\n");
+        for (i, &(_, ref name, line)) in self.functions.iter().enumerate() {
+            writeln!(ir,
+                     "!{} = distinct !DISubprogram(name: \"{}\", scope: !2, file: !2, line: {}, type: !3, \
+                      isLocal: false, isDefinition: true, scopeLine: {}, unit: !0)",
+                     first_sp + i, escape(name), line, line).unwrap();
+        }
+        for (i, &(_, DIScope(scope), line, col)) in self.locations.iter().enumerate() {
+            writeln!(ir, "!{} = !DILocation(line: {}, column: {}, scope: !{})",
+                     first_loc + i, line, col, first_sp + scope).unwrap();
+        }
+        ir
+    }
+
+    /// Create the metadata in context of `module`, attach locations
+    ///   to instructions and list the compile unit in module
+    pub fn finalize(self, module: &LLVMModule, context: &LLVMContext) -> Result<(), String> {
+        if !cfg!(feature = "debuginfo") {
+            return Err("debug information needs feature `debuginfo`".to_string());
+        }
+        let src = self.metadata_source();
+        let buf_name = CString::new("debuginfo").unwrap();
+        unsafe {
+            let buf = LLVMCreateMemoryBufferWithMemoryRangeCopy(src.as_ptr() as *const c_char,
+                                                                src.len(),
+                                                                buf_name.as_ptr());
+            let mut parsed: LLVMModuleRef = ptr::null_mut();
+            let mut msg: *mut c_char = ptr::null_mut();
+            // The buffer is taken by parser
+            if LLVMParseIRInContext(context.raw_ptr(), buf, &mut parsed, &mut msg) != 0 {
+                let err = CStr::from_ptr(msg).to_string_lossy().into_owned();
+                LLVMDisposeMessage(msg);
+                return Err(err);
+            }
+
+            let dbg_kind = LLVMGetMDKindIDInContext(context.raw_ptr(), "dbg".as_ptr() as *const c_char, 3);
+            let fun_name = CString::new("dbg.locations").unwrap();
+            let fun = LLVMGetNamedFunction(parsed, fun_name.as_ptr());
+            let mut instr = LLVMGetFirstInstruction(LLVMGetEntryBasicBlock(fun));
+            for &(target, ..) in self.locations.iter() {
+                let loc = LLVMGetMetadata(instr, dbg_kind);
+                LLVMSetMetadata(target.raw_ptr(), dbg_kind, loc);
+                instr = LLVMGetNextInstruction(instr);
+            }
+
+            for (i, &(target, ..)) in self.functions.iter().enumerate() {
+                let sp_name = CString::new(format!("dbg.sp.{}", i)).unwrap();
+                let sp_fun = LLVMGetNamedFunction(parsed, sp_name.as_ptr());
+                copy_subprogram(target.raw_ptr(), sp_fun);
+            }
+
+            for name in ["llvm.dbg.cu", "llvm.module.flags"].iter() {
+                let name = CString::new(*name).unwrap();
+                let count = LLVMGetNamedMetadataNumOperands(parsed, name.as_ptr());
+                let mut nodes: Vec<LLVMValueRef> = vec![ptr::null_mut(); count as usize];
+                LLVMGetNamedMetadataOperands(parsed, name.as_ptr(), nodes.as_mut_ptr());
+                for node in nodes {
+                    LLVMAddNamedMetadataOperand(module.raw_ptr(), name.as_ptr(), node);
+                }
+            }
+            // Metadata is owned by context, still alive without the module
+            LLVMDisposeModule(parsed);
+        }
+        Ok(())
+    }
+}
+
+/// Attach the subprogram of function `from` to function `to`
+#[cfg(feature = "debuginfo")]
+fn copy_subprogram(to: LLVMValueRef, from: LLVMValueRef) {
+    extern "C" {
+        fn LLVMMikoCopySubprogram(to: LLVMValueRef, from: LLVMValueRef);
+    }
+    unsafe { LLVMMikoCopySubprogram(to, from) }
+}
+
+#[cfg(not(feature = "debuginfo"))]
+fn copy_subprogram(_to: LLVMValueRef, _from: LLVMValueRef) {
+    unreachable!("the shim is built with feature `debuginfo` only")
+}
+
+/// Escape a string in metadata, quotes and backslashes
+///   and characters not printable are written as hex `\XX`
+fn escape(s: &str) -> String {
+    let mut res = String::new();
+    for b in s.bytes() {
+        if b == b'"' || b == b'\\' || b < 0x20 || b >= 0x7f {
+            write!(res, "\\{:02X}", b).unwrap();
+        } else {
+            res.push(b as char);
+        }
+    }
+    res
+}
@@ -3,6 +3,10 @@ extern crate llvm_sys;
 extern crate libc;
 
 pub mod wrapper;
+pub mod target;
+pub mod debuginfo;
 
 pub use wrapper::*;
+pub use target::{LLVMTargetMachine, ObjectOrAsm, emit_to_file};
+pub use debuginfo::{DebugInfoBuilder, DIScope};
 pub use llvm_sys::analysis::LLVMVerifierFailureAction;
\ No newline at end of file
@@ -0,0 +1,122 @@
+use llvm_sys::target_machine::*;
+use llvm_sys::target::*;
+
+use std::ptr;
+use std::path::Path;
+use std::sync::{Once, ONCE_INIT};
+use std::ffi::CString;
+use libc::c_char;
+
+use wrapper::*;
+
+static NATIVE_INIT: Once = ONCE_INIT;
+
+/// Initialize the native target, only done once for a process
+fn init_native_target() {
+    NATIVE_INIT.call_once(|| unsafe {
+        LLVM_InitializeNativeTarget();
+        LLVM_InitializeNativeAsmParser();
+        LLVM_InitializeNativeAsmPrinter();
+    });
+}
+
+/// Kind of file emitted by target machine
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ObjectOrAsm {
+    Object,
+    Assembly,
+}
+
+impl ObjectOrAsm {
+    fn file_type(&self) -> LLVMCodeGenFileType {
+        match *self {
+            ObjectOrAsm::Object => LLVMCodeGenFileType::LLVMObjectFile,
+            ObjectOrAsm::Assembly => LLVMCodeGenFileType::LLVMAssemblyFile,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LLVMTargetMachine {
+    machine: LLVMTargetMachineRef,
+    triple: String,
+}
+
+impl LLVMTargetMachine {
+    /// Create a target machine for the host
+    pub fn native() -> Result<Self, String> {
+        init_native_target();
+        let triple = unsafe {
+            let t = LLVMGetDefaultTargetTriple();
+            let res = take_message(t);
+            LLVMDisposeMessage(t);
+            res
+        };
+        LLVMTargetMachine::from_triple(&triple)
+    }
+
+    pub fn from_triple(triple: &str) -> Result<Self, String> {
+        init_native_target();
+        let ctriple = CString::new(triple).map_err(|e| e.to_string())?;
+        let cpu = CString::new("generic").unwrap();
+        let features = CString::new("").unwrap();
+        let mut target: LLVMTargetRef = ptr::null_mut();
+        let mut msg: *mut c_char = ptr::null_mut();
+        unsafe {
+            if LLVMGetTargetFromTriple(ctriple.as_ptr(), &mut target, &mut msg) != 0 {
+                let err = take_message(msg);
+                LLVMDisposeMessage(msg);
+                return Err(err);
+            }
+            let machine = LLVMCreateTargetMachine(target,
+                                                  ctriple.as_ptr(),
+                                                  cpu.as_ptr(),
+                                                  features.as_ptr(),
+                                                  LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
+                                                  LLVMRelocMode::LLVMRelocDefault,
+                                                  LLVMCodeModel::LLVMCodeModelDefault);
+            if machine.is_null() {
+                return Err(format!("cannot create target machine for {}", triple));
+            }
+            Ok(LLVMTargetMachine { machine, triple: triple.to_owned() })
+        }
+    }
+
+    pub fn triple(&self) -> &str {
+        self.triple.as_str()
+    }
+
+    pub fn emit_to_file(&self, module: &LLVMModule, path: &Path, file_type: ObjectOrAsm) -> Result<(), String> {
+        let p = path.to_str()
+            .ok_or_else(|| format!("invalid path: {}", path.display()))?;
+        let cp = CString::new(p).map_err(|e| e.to_string())?;
+        let mut msg: *mut c_char = ptr::null_mut();
+        module.set_target(&self.triple);
+        unsafe {
+            if LLVMTargetMachineEmitToFile(self.machine,
+                                           module.raw_ptr(),
+                                           cp.as_ptr() as *mut c_char,
+                                           file_type.file_type(),
+                                           &mut msg) != 0 {
+                let err = take_message(msg);
+                LLVMDisposeMessage(msg);
+                Err(err)
+            } else {
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Drop for LLVMTargetMachine {
+    fn drop(&mut self) {
+        unsafe {
+            LLVMDisposeTargetMachine(self.machine);
+        }
+    }
+}
+
+/// Emit module as a object or assembly file for the host
+pub fn emit_to_file(module: &LLVMModule, path: &Path, file_type: ObjectOrAsm) -> Result<(), String> {
+    LLVMTargetMachine::native()?.emit_to_file(module, path, file_type)
+}
@@ -2,17 +2,41 @@ pub use llvm_sys::prelude::{LLVMBuilderRef, LLVMContextRef, LLVMModuleRef, LLVMP
                         LLVMTypeRef, LLVMValueRef, LLVMBasicBlockRef};
 use llvm_sys::execution_engine::{LLVMExecutionEngineRef, LLVMGenericValueRef,
                                  LLVMGenericValueToFloat, LLVMRunFunction};
-use llvm_sys::analysis::{LLVMVerifierFailureAction, LLVMVerifyFunction};
+use llvm_sys::execution_engine as engine;
+use llvm_sys::target as target_init;
+use llvm_sys::analysis::{LLVMVerifierFailureAction, LLVMVerifyFunction, LLVMVerifyModule};
 pub use llvm_sys::{ LLVMIntPredicate, LLVMRealPredicate };
+use llvm_sys::LLVMTypeKind;
+use llvm_sys::{ LLVMLinkage, LLVMCallConv };
+use llvm_sys::bit_writer::LLVMWriteBitcodeToFile;
 use llvm_sys::transforms;
 
 use std::ptr;
+use std::mem;
+use std::path::Path;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::collections::HashMap;
 use libc::{c_char, c_uint, c_ulonglong};
-use std::ffi::CString;
+use std::ffi::{CString, CStr};
 
 pub use llvm_sys::core::*;
 
 
+/// Wrapper of a LLVM reference.
+///
+/// Wrappers owning the reference (`LLVMModule`, `LLVMContext`, `LLVMBuilder`, ...)
+/// dispose it when dropped, so they are not `Clone`, otherwise a clone would
+/// dispose the same reference twice:
+///
+/// ```compile_fail
+/// use libllvm::*;
+/// let module = LLVMModule::new("m");
+/// let alias = module.clone();
+/// ```
+///
+/// Wrappers only referring to something owned by others
+/// (`LLVMValue`, `LLVMType`, `LLVMBasicBlock`, ...) are `Copy`.
 pub trait LLVMWrapper<T> {
     fn from_ref(ptr: T) -> Self;
     fn raw_ptr(&self) -> T;
@@ -20,7 +44,7 @@ pub trait LLVMWrapper<T> {
 
 macro_rules! make_LLVM_wrapper {
     ($origin:ty, $wrapper:ident) => {
-        #[derive(Debug, Clone)]
+        #[derive(Debug)]
         pub struct $wrapper($origin);
         impl LLVMWrapper<$origin> for $wrapper {
             fn from_ref(ptr: $origin) -> Self {
@@ -42,21 +66,157 @@ macro_rules! make_LLVM_wrapper {
                 self.0.clone()
             }
         }
+    };
+    ($origin:ty, $wrapper:ident, Named) => {
+        #[derive(Debug)]
+        pub struct $wrapper($origin, CStringArena, Option<LLVMContext>);
+        impl LLVMWrapper<$origin> for $wrapper {
+            fn from_ref(ptr: $origin) -> Self {
+                $wrapper(ptr, CStringArena::new(), None)
+            }
+            fn raw_ptr(&self) -> $origin {
+                self.0.clone()
+            }
+        }
+        impl $wrapper {
+            /// Wrap a reference created in `ctx`,
+            ///   `ctx` will be kept alive as long as the wrapper.
+            fn with_context(ptr: $origin, ctx: &LLVMContext) -> Self {
+                $wrapper(ptr, CStringArena::new(), Some(ctx.clone()))
+            }
+
+            /// Get the context it created in, `None` for the global context
+            pub fn get_context(&self) -> Option<&LLVMContext> {
+                self.2.as_ref()
+            }
+
+            /// Get a C string of `s` living as long as `self`
+            fn name(&self, s: &str) -> *const c_char {
+                self.1.alloc(s)
+            }
+        }
+    }
+}
+
+/// Owner of a context, disposes it when the last `LLVMContext` referring it dropped
+#[derive(Debug)]
+struct ContextOwner {
+    ctx: LLVMContextRef,
+    names: CStringArena,
+}
+
+impl Drop for ContextOwner {
+    fn drop(&mut self) {
+        unsafe {
+            LLVMContextDispose(self.ctx);
+        }
+    }
+}
+
+/// A reference counted LLVM context.
+///
+/// Modules and builders created in a context hold a clone of it,
+/// so the context is only disposed after everything created in it.
+/// A lifetime tying them to `&LLVMContext` would reject freeing the context
+/// at compile time instead, but it also makes a struct owning both the context
+/// and its module (like the code generator) impossible to write.
+///
+/// Types and values are plain `Copy` handles and not tracked,
+/// they are valid as long as the module or context owning them.
+#[derive(Debug, Clone)]
+pub struct LLVMContext(Rc<ContextOwner>);
+
+impl LLVMWrapper<LLVMContextRef> for LLVMContext {
+    /// Take the ownership of `ptr`
+    fn from_ref(ptr: LLVMContextRef) -> Self {
+        LLVMContext(Rc::new(ContextOwner { ctx: ptr, names: CStringArena::new() }))
+    }
+    fn raw_ptr(&self) -> LLVMContextRef {
+        self.0.ctx
+    }
+}
+
+impl LLVMContext {
+    fn name(&self, s: &str) -> *const c_char {
+        self.0.names.alloc(s)
     }
 }
 
-make_LLVM_wrapper!(LLVMModuleRef, LLVMModule);
-make_LLVM_wrapper!(LLVMContextRef, LLVMContext);
+make_LLVM_wrapper!(LLVMModuleRef, LLVMModule, Named);
 make_LLVM_wrapper!(LLVMPassManagerRef, LLVMFunctionPassManager);
 make_LLVM_wrapper!(LLVMValueRef, LLVMValue, Copy);
 make_LLVM_wrapper!(LLVMValueRef, LLVMFunction, Copy);
+make_LLVM_wrapper!(LLVMValueRef, LLVMSwitchValue, Copy);
 make_LLVM_wrapper!(LLVMTypeRef, LLVMType, Copy);
-make_LLVM_wrapper!(LLVMBuilderRef, LLVMBuilder);
-make_LLVM_wrapper!(LLVMBasicBlockRef, LLVMBasicBlock);
+make_LLVM_wrapper!(LLVMBuilderRef, LLVMBuilder, Named);
+make_LLVM_wrapper!(LLVMBasicBlockRef, LLVMBasicBlock, Copy);
+
+/// JIT engine, owning the module it created for
+#[derive(Debug)]
+pub struct LLVMExecutionEngine(LLVMExecutionEngineRef, Option<LLVMContext>);
+
+impl LLVMWrapper<LLVMExecutionEngineRef> for LLVMExecutionEngine {
+    fn from_ref(ptr: LLVMExecutionEngineRef) -> Self {
+        LLVMExecutionEngine(ptr, None)
+    }
+    fn raw_ptr(&self) -> LLVMExecutionEngineRef {
+        self.0
+    }
+}
+make_LLVM_wrapper!(LLVMGenericValueRef, LLVMGenericValue);
+
+
+/// Copy a message allocated by LLVM into an owned `String`.
+///   The message itself should still be disposed by caller.
+pub(crate) unsafe fn take_message(msg: *const c_char) -> String {
+    if msg.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(msg).to_string_lossy().into_owned()
+    }
+}
+
+/// Owns the C strings handed to LLVM as names.
+///   Strings are interned, so the names repeated on every
+///   emitted instruction (`tmp`, `call`, ...) share one allocation,
+///   and all of them are freed together with the arena.
+///
+/// An arena lives as long as the context, module or builder owning it,
+///   which is one compilation since the code generator creates its own.
+///   LLVM copies names it is given, so an owner kept across compilations
+///   (like a REPL) could `clear` the arena between them.
+#[derive(Debug, Clone)]
+pub struct CStringArena {
+    strings: RefCell<HashMap<String, CString>>,
+}
+
+impl CStringArena {
+    pub fn new() -> Self {
+        CStringArena { strings: RefCell::new(HashMap::new()) }
+    }
+
+    /// Get a pointer to the C string of `s`,
+    ///   valid until the arena is dropped.
+    pub fn alloc(&self, s: &str) -> *const c_char {
+        let mut strings = self.strings.borrow_mut();
+        if let Some(cs) = strings.get(s) {
+            return cs.as_ptr();
+        }
+        // Moving a `CString` does not move its buffer, so the pointer stays valid
+        let cs = CString::new(s).unwrap();
+        let ptr = cs.as_ptr();
+        strings.insert(s.to_owned(), cs);
+        ptr
+    }
 
+    pub fn len(&self) -> usize {
+        self.strings.borrow().len()
+    }
 
-pub unsafe fn raw_string(s: &str) -> *mut c_char {
-    CString::new(s).unwrap().into_raw()
+    /// Free all strings, pointers given before are invalid after it
+    pub fn clear(&self) {
+        self.strings.borrow_mut().clear();
+    }
 }
 
 
@@ -64,7 +224,7 @@ macro_rules! method_type_getter {
     ($name: ident, $fun: ident) => {
         pub fn $name(&self) -> LLVMType {
             unsafe {
-                LLVMType::from_ref($fun(self.0))
+                LLVMType::from_ref($fun(self.raw_ptr()))
             }
         }
     };
@@ -72,15 +232,20 @@ macro_rules! method_type_getter {
 
 impl LLVMContext {
     pub fn new() -> Self {
-        unsafe { LLVMContext(LLVMContextCreate()) }
+        unsafe { LLVMContext::from_ref(LLVMContextCreate()) }
     }
     method_type_getter!(get_int1_type, LLVMInt1TypeInContext);
     method_type_getter!(get_int8_type, LLVMInt8TypeInContext);
     method_type_getter!(get_int16_type, LLVMInt16TypeInContext);
     method_type_getter!(get_int32_type, LLVMInt32TypeInContext);
+    method_type_getter!(get_int64_type, LLVMInt64TypeInContext);
     method_type_getter!(get_double_type, LLVMDoubleTypeInContext);
     method_type_getter!(get_void_type, LLVMVoidTypeInContext);
 
+    pub fn get_int_type(&self, bits: u32) -> LLVMType {
+        unsafe { LLVMType::from_ref(LLVMIntTypeInContext(self.raw_ptr(), bits as c_uint)) }
+    }
+
     pub fn get_function_type(ret: &LLVMType, param: &Vec<LLVMType>, is_var_arg: bool) -> LLVMType {
         let mut ps: Vec<_> = param.iter().map(|t| t.raw_ptr()).collect();
         let pc = ps.len() as c_uint;
@@ -93,7 +258,7 @@ impl LLVMContext {
         let mut mems: Vec<_> = types.iter().map(|t| t.raw_ptr()).collect();
         let flag = if packed { 1 } else { 0 };
         let t = unsafe {
-            LLVMStructTypeInContext(self.0.clone(),
+            LLVMStructTypeInContext(self.raw_ptr(),
                                     mems.as_mut_ptr(),
                                     mems.len() as c_uint,
                                     flag)
@@ -101,10 +266,17 @@ impl LLVMContext {
         LLVMType(t)
     }
 
+    /// Create a opaque struct type with name,
+    ///   its body could be set later by `LLVMType::set_struct_body`,
+    ///   so it can be referred by itself (e.g. through pointer).
+    pub fn create_named_struct(&self, name: &str) -> LLVMType {
+        unsafe { LLVMType::from_ref(LLVMStructCreateNamed(self.raw_ptr(), self.name(name))) }
+    }
+
     pub fn get_const_string(&self, s: &str) -> LLVMValue {
         unsafe {
             LLVMValue::from_ref(LLVMConstStringInContext(self.raw_ptr(),
-                                                         raw_string(s),
+                                                         s.as_ptr() as *const c_char,
                                                          s.len() as ::libc::c_uint,
                                                          0))
         }
@@ -123,11 +295,45 @@ impl LLVMContext {
                                              1))
         }
     }
+    pub fn get_int64_const(&self, val: i64) -> LLVMValue {
+        unsafe {
+            LLVMValue::from_ref(LLVMConstInt(self.get_int64_type().raw_ptr(),
+                                             val as c_ulonglong,
+                                             1))
+        }
+    }
+    /// Constant array of `vals`, all of them should be in type `elem_ty`
+    pub fn get_array_const(&self, elem_ty: &LLVMType, vals: &[LLVMValue]) -> LLVMValue {
+        let mut vs: Vec<_> = vals.iter().map(|v| v.raw_ptr()).collect();
+        unsafe {
+            LLVMValue::from_ref(LLVMConstArray(elem_ty.raw_ptr(), vs.as_mut_ptr(), vs.len() as c_uint))
+        }
+    }
+    /// Constant struct of `vals`, in type of an unnamed struct of their types
+    pub fn get_struct_const(&self, vals: &[LLVMValue], packed: bool) -> LLVMValue {
+        let mut vs: Vec<_> = vals.iter().map(|v| v.raw_ptr()).collect();
+        unsafe {
+            LLVMValue::from_ref(LLVMConstStructInContext(self.raw_ptr(), vs.as_mut_ptr(),
+                                                         vs.len() as c_uint, packed as ::libc::c_int))
+        }
+    }
     pub fn get_uint8_const(&self, val: u8) -> LLVMValue {
         unsafe {
             LLVMValue::from_ref(LLVMConstInt(self.get_int8_type().raw_ptr(), val as c_ulonglong, 0))
         }
     }
+    /// Unicode scalar value as `i32`
+    pub fn get_char_const(&self, c: char) -> LLVMValue {
+        unsafe {
+            LLVMValue::from_ref(LLVMConstInt(self.get_int32_type().raw_ptr(),
+                                             c as u32 as c_ulonglong,
+                                             0))
+        }
+    }
+    /// Booleans are `i1`, the same as results of comparisons
+    pub fn bool_const(&self, b: bool) -> LLVMValue {
+        self.get_int1_const(if b { 1 } else { 0 })
+    }
     pub fn get_int1_const(&self, val: u64) -> LLVMValue {
         unsafe {
             LLVMValue::from_ref(LLVMConstInt(self.get_int1_type().raw_ptr(), val as c_ulonglong, 1))
@@ -138,26 +344,53 @@ impl LLVMContext {
         unsafe {
             LLVMBasicBlock::from_ref(LLVMAppendBasicBlockInContext(self.raw_ptr(),
                                                                    fun.raw_ptr(),
-                                                                   raw_string(name)))
+                                                                   self.name(name)))
         }
     }
 }
 
 impl LLVMModule {
     pub fn new(name: &str) -> Self {
-        unsafe { LLVMModule(LLVMModuleCreateWithName(raw_string(name))) }
+        let n = CString::new(name).unwrap();
+        unsafe { LLVMModule::from_ref(LLVMModuleCreateWithName(n.as_ptr())) }
     }
     pub fn in_ctx(name: &str, ctx: &LLVMContext) -> Self {
-        unsafe { LLVMModule(LLVMModuleCreateWithNameInContext(raw_string(name), ctx.raw_ptr())) }
+        let n = CString::new(name).unwrap();
+        unsafe { LLVMModule::with_context(LLVMModuleCreateWithNameInContext(n.as_ptr(), ctx.raw_ptr()), ctx) }
     }
     pub fn dump(&self) {
         unsafe { LLVMDumpModule(self.raw_ptr()) }
     }
 
+    pub fn set_target(&self, triple: &str) {
+        unsafe { LLVMSetTarget(self.raw_ptr(), self.name(triple)) }
+    }
+
+    /// Get the textual IR of module
+    pub fn print_to_string(&self) -> String {
+        unsafe {
+            let ir = LLVMPrintModuleToString(self.raw_ptr());
+            let res = take_message(ir);
+            LLVMDisposeMessage(ir);
+            res
+        }
+    }
+
+    pub fn write_bitcode_to_file(&self, path: &Path) -> Result<(), String> {
+        let p = path.to_str()
+            .ok_or_else(|| format!("invalid path: {}", path.display()))?;
+        let cp = CString::new(p).map_err(|e| e.to_string())?;
+        let failed = unsafe { LLVMWriteBitcodeToFile(self.raw_ptr(), cp.as_ptr()) };
+        if failed != 0 {
+            Err(format!("write bitcode to {} failed", p))
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn get_function(&self, fun_name: &str) -> Option<LLVMFunction> {
         unsafe {
-            let n = raw_string(fun_name);
-            let f = LLVMGetNamedFunction(self.0.clone(), n);
+            let f = LLVMGetNamedFunction(self.0.clone(), self.name(fun_name));
             if f.is_null() {
                 None
             } else {
@@ -168,28 +401,145 @@ impl LLVMModule {
 
     pub fn add_function(&self, fun_name: &str, fty: &LLVMType) -> LLVMFunction {
         unsafe {
-            let n = raw_string(fun_name);
-            let f = LLVMAddFunction(self.raw_ptr(), raw_string(fun_name), fty.raw_ptr());
+            let f = LLVMAddFunction(self.raw_ptr(), self.name(fun_name), fty.raw_ptr());
             LLVMFunction::from_ref(f)
         }
     }
 
+    pub fn get_global(&self, name: &str) -> Option<LLVMValue> {
+        unsafe {
+            let g = LLVMGetNamedGlobal(self.raw_ptr(), self.name(name));
+            if g.is_null() {
+                None
+            } else {
+                Some(LLVMValue::from_ref(g))
+            }
+        }
+    }
+
+    /// Add a global variable of `ty`, the value is a pointer to it
+    pub fn add_global(&self, name: &str, ty: &LLVMType) -> LLVMValue {
+        unsafe { LLVMValue::from_ref(LLVMAddGlobal(self.raw_ptr(), ty.raw_ptr(), self.name(name))) }
+    }
+
+    /// Give up the ownership of module without disposing it,
+    ///   for APIs taking the ownership such as execution engine.
+    ///   The context should be kept alive by new owner.
+    pub fn into_raw(self) -> (LLVMModuleRef, Option<LLVMContext>) {
+        let m = self.raw_ptr();
+        // Names handed to LLVM are copied, so the arena can go now
+        let (arena, ctx) = unsafe { (ptr::read(&self.1), ptr::read(&self.2)) };
+        mem::forget(self);
+        drop(arena);
+        (m, ctx)
+    }
+
+    /// Verify the whole module, returns the message of verifier if it is broken
+    pub fn verify(&self, action: LLVMVerifierFailureAction) -> Result<(), String> {
+        let mut msg: *mut c_char = ptr::null_mut();
+        unsafe {
+            let broken = LLVMVerifyModule(self.raw_ptr(), action, &mut msg);
+            let res = if broken != 0 {
+                Err(take_message(msg))
+            } else {
+                Ok(())
+            };
+            if !msg.is_null() {
+                LLVMDisposeMessage(msg);
+            }
+            res
+        }
+    }
+}
+
+/// Function passes could be added into `LLVMFunctionPassManager`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassKind {
+    BasicAliasAnalysis,
+    InstructionCombining,
+    Reassociate,
+    GVN,
+    CFGSimplification,
+    DeadStoreElimination,
+    AggressiveDCE,
+    SCCP,
+    MergedLoadStoreMotion,
+    ConstantPropagation,
+    PromoteMemoryToRegister,
+    TailCallElimination,
+}
+
+impl PassKind {
+    unsafe fn add_to(&self, pm: LLVMPassManagerRef) {
+        use self::PassKind::*;
+        use self::transforms::scalar::*;
+        match *self {
+            BasicAliasAnalysis => LLVMAddBasicAliasAnalysisPass(pm),
+            InstructionCombining => LLVMAddInstructionCombiningPass(pm),
+            Reassociate => LLVMAddReassociatePass(pm),
+            GVN => LLVMAddGVNPass(pm),
+            CFGSimplification => LLVMAddCFGSimplificationPass(pm),
+            DeadStoreElimination => LLVMAddDeadStoreEliminationPass(pm),
+            AggressiveDCE => LLVMAddAggressiveDCEPass(pm),
+            SCCP => LLVMAddSCCPPass(pm),
+            MergedLoadStoreMotion => LLVMAddMergedLoadStoreMotionPass(pm),
+            ConstantPropagation => LLVMAddConstantPropagationPass(pm),
+            PromoteMemoryToRegister => LLVMAddPromoteMemoryToRegisterPass(pm),
+            TailCallElimination => LLVMAddTailCallEliminationPass(pm),
+        }
+    }
+}
+
+/// Optimization levels, each is a preset of passes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptLevel {
+    O0,
+    O1,
+    O2,
+    O3,
+}
+
+impl OptLevel {
+    pub fn passes(&self) -> Vec<PassKind> {
+        use self::PassKind::*;
+        match *self {
+            OptLevel::O0 => vec![],
+            OptLevel::O1 => vec![PromoteMemoryToRegister, InstructionCombining, CFGSimplification],
+            OptLevel::O2 => vec![BasicAliasAnalysis,
+                                 InstructionCombining,
+                                 Reassociate,
+                                 GVN,
+                                 CFGSimplification,
+                                 MergedLoadStoreMotion,
+                                 ConstantPropagation,
+                                 PromoteMemoryToRegister,
+                                 TailCallElimination],
+            OptLevel::O3 => {
+                let mut ps = OptLevel::O2.passes();
+                ps.extend(vec![SCCP, AggressiveDCE, CFGSimplification]);
+                ps
+            }
+        }
+    }
 }
 
 impl LLVMFunctionPassManager {
+    /// Default pipeline, same as `OptLevel::O2`
     pub fn init_for_module(m: &LLVMModule) -> Self {
+        LLVMFunctionPassManager::with_level(m, OptLevel::O2)
+    }
+
+    pub fn with_level(m: &LLVMModule, level: OptLevel) -> Self {
+        LLVMFunctionPassManager::with_passes(m, &level.passes())
+    }
+
+    /// Create a pass manager running `passes` in order
+    pub fn with_passes(m: &LLVMModule, passes: &[PassKind]) -> Self {
         unsafe {
             let llfpm = LLVMCreateFunctionPassManagerForModule(m.raw_ptr());
-            transforms::scalar::LLVMAddBasicAliasAnalysisPass(llfpm);
-            transforms::scalar::LLVMAddInstructionCombiningPass(llfpm);
-            transforms::scalar::LLVMAddReassociatePass(llfpm);
-            transforms::scalar::LLVMAddGVNPass(llfpm);
-            transforms::scalar::LLVMAddCFGSimplificationPass(llfpm);
-            // transforms::scalar::LLVMAddDeadStoreEliminationPass(llfpm);
-            transforms::scalar::LLVMAddMergedLoadStoreMotionPass(llfpm);
-            transforms::scalar::LLVMAddConstantPropagationPass(llfpm);
-            transforms::scalar::LLVMAddPromoteMemoryToRegisterPass(llfpm);
-            transforms::scalar::LLVMAddTailCallEliminationPass(llfpm);
+            for p in passes {
+                p.add_to(llfpm);
+            }
             LLVMInitializeFunctionPassManager(llfpm);
             LLVMFunctionPassManager(llfpm)
         }
@@ -209,24 +559,110 @@ impl LLVMType {
         unsafe { LLVMType(LLVMGetElementType(self.0.clone())) }
     }
 
+    /// Number of fields, `None` if it is not a struct type
+    pub fn count_struct_elements(&self) -> Option<usize> {
+        unsafe {
+            match LLVMGetTypeKind(self.0) {
+                LLVMTypeKind::LLVMStructTypeKind => {
+                    Some(LLVMCountStructElementTypes(self.0) as usize)
+                }
+                _ => None,
+            }
+        }
+    }
+
+    pub fn is_pointer(&self) -> bool {
+        unsafe { LLVMGetTypeKind(self.0) == LLVMTypeKind::LLVMPointerTypeKind }
+    }
+
+    /// Set fields of a named struct created by `LLVMContext::create_named_struct`
+    pub fn set_struct_body(&self, fields: &[LLVMType], packed: bool) {
+        let mut mems: Vec<_> = fields.iter().map(|t| t.raw_ptr()).collect();
+        let flag = if packed { 1 } else { 0 };
+        unsafe { LLVMStructSetBody(self.0.clone(), mems.as_mut_ptr(), mems.len() as c_uint, flag) }
+    }
+
+    /// Array type `[count x self]`.
+    ///   Like any other type it is owned by the context of element type.
+    pub fn array(&self, count: u32) -> Self {
+        unsafe { LLVMType(LLVMArrayType(self.0.clone(), count as c_uint)) }
+    }
+    /// Vector type `<count x self>`, element should be a integer, float or pointer type
+    pub fn vector(&self, count: u32) -> Self {
+        unsafe { LLVMType(LLVMVectorType(self.0.clone(), count as c_uint)) }
+    }
+
     pub fn get_null_ptr(&self) -> LLVMValue {
         unsafe {
             LLVMValue::from_ref(LLVMConstNull(self.raw_ptr()))
         }
     }
+
+    pub fn get_undef(&self) -> LLVMValue {
+        unsafe {
+            LLVMValue::from_ref(LLVMGetUndef(self.raw_ptr()))
+        }
+    }
+
+    /// Constant of this integer type
+    pub fn const_int(&self, val: u64, signed: bool) -> LLVMValue {
+        unsafe {
+            LLVMValue::from_ref(LLVMConstInt(self.raw_ptr(), val as c_ulonglong, signed as ::libc::c_int))
+        }
+    }
 }
 
 impl LLVMValue {
     pub fn set_name(&self, name: &str) {
-        unsafe { LLVMSetValueName(self.0.clone(), raw_string(name)) }
+        // LLVM copies the name, so it only needs to live through the call
+        let n = CString::new(name).unwrap();
+        unsafe { LLVMSetValueName(self.0.clone(), n.as_ptr()) }
     }
 
+    /// Convert without checking, the value may be e.g. a function pointer
+    ///   loaded from a closure. Prefer `as_function` for declared functions.
     pub fn into_function(self) -> LLVMFunction {
         LLVMFunction::from_ref(self.0)
     }
+
+    /// The value as a function, `None` if it is not a function declaration or definition
+    pub fn as_function(&self) -> Option<LLVMFunction> {
+        let f = unsafe { LLVMIsAFunction(self.0) };
+        if f.is_null() {
+            None
+        } else {
+            Some(LLVMFunction::from_ref(f))
+        }
+    }
     pub fn get_type(&self) -> LLVMType {
         unsafe { LLVMType::from_ref(LLVMTypeOf(self.0)) }
     }
+
+    /// Cast a constant without instructions
+    pub fn const_bit_cast(&self, ty: &LLVMType) -> LLVMValue {
+        unsafe { LLVMValue::from_ref(LLVMConstBitCast(self.0, ty.raw_ptr())) }
+    }
+
+    /// Set the initial value of a global variable, which should be a constant
+    pub fn set_initializer(&self, val: &LLVMValue) {
+        unsafe { LLVMSetInitializer(self.0, val.raw_ptr()) }
+    }
+    pub fn set_global_constant(&self, is_const: bool) {
+        unsafe { LLVMSetGlobalConstant(self.0, is_const as ::libc::c_int) }
+    }
+    /// Linkage of a global value
+    pub fn set_linkage(&self, linkage: Linkage) {
+        unsafe { LLVMSetLinkage(self.0, linkage.to_llvm()) }
+    }
+
+    /// Mark a call as `tail`, the callee does not access allocas of caller.
+    ///   The value should be a call instruction.
+    pub fn set_tail_call(&self, is_tail: bool) {
+        unsafe { LLVMSetTailCall(self.0, is_tail as ::libc::c_int) }
+    }
+    pub fn is_tail_call(&self) -> bool {
+        unsafe { LLVMIsTailCall(self.0) != 0 }
+    }
     pub fn dump(&self) {
         unsafe {
             LLVMDumpValue(self.raw_ptr())
@@ -234,7 +670,120 @@ impl LLVMValue {
     }
 }
 
+/// Linkage of global values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Linkage {
+    External,
+    Internal,
+    Private,
+    LinkOnceODR,
+}
+
+impl Linkage {
+    fn to_llvm(&self) -> LLVMLinkage {
+        match *self {
+            Linkage::External => LLVMLinkage::LLVMExternalLinkage,
+            Linkage::Internal => LLVMLinkage::LLVMInternalLinkage,
+            Linkage::Private => LLVMLinkage::LLVMPrivateLinkage,
+            Linkage::LinkOnceODR => LLVMLinkage::LLVMLinkOnceODRLinkage,
+        }
+    }
+
+    fn from_llvm(l: LLVMLinkage) -> Option<Linkage> {
+        match l {
+            LLVMLinkage::LLVMExternalLinkage => Some(Linkage::External),
+            LLVMLinkage::LLVMInternalLinkage => Some(Linkage::Internal),
+            LLVMLinkage::LLVMPrivateLinkage => Some(Linkage::Private),
+            LLVMLinkage::LLVMLinkOnceODRLinkage => Some(Linkage::LinkOnceODR),
+            _ => None,
+        }
+    }
+}
+
+/// Calling conventions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallConv {
+    C,
+    Fast,
+    Cold,
+}
+
+impl CallConv {
+    fn to_llvm(&self) -> c_uint {
+        let cc = match *self {
+            CallConv::C => LLVMCallConv::LLVMCCallConv,
+            CallConv::Fast => LLVMCallConv::LLVMFastCallConv,
+            CallConv::Cold => LLVMCallConv::LLVMColdCallConv,
+        };
+        cc as c_uint
+    }
+
+    fn from_llvm(cc: c_uint) -> Option<CallConv> {
+        match cc {
+            x if x == LLVMCallConv::LLVMCCallConv as c_uint => Some(CallConv::C),
+            x if x == LLVMCallConv::LLVMFastCallConv as c_uint => Some(CallConv::Fast),
+            x if x == LLVMCallConv::LLVMColdCallConv as c_uint => Some(CallConv::Cold),
+            _ => None,
+        }
+    }
+}
+
+/// Function attributes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FnAttr {
+    NoUnwind,
+    ReadOnly,
+    ReadNone,
+    NoInline,
+    AlwaysInline,
+    NoReturn,
+}
+
+impl FnAttr {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            FnAttr::NoUnwind => "nounwind",
+            FnAttr::ReadOnly => "readonly",
+            FnAttr::ReadNone => "readnone",
+            FnAttr::NoInline => "noinline",
+            FnAttr::AlwaysInline => "alwaysinline",
+            FnAttr::NoReturn => "noreturn",
+        }
+    }
+}
+
+/// Attribute index refers to function itself rather than return value or parameters
+const FUNCTION_ATTR_INDEX: c_uint = !0;
+
 impl LLVMFunction {
+    pub fn set_linkage(&self, linkage: Linkage) {
+        unsafe { LLVMSetLinkage(self.raw_ptr(), linkage.to_llvm()) }
+    }
+
+    pub fn set_call_conv(&self, cc: CallConv) {
+        unsafe { LLVMSetFunctionCallConv(self.raw_ptr(), cc.to_llvm()) }
+    }
+
+    /// Get calling convention of function, `None` if it is not one of `CallConv`
+    pub fn get_call_conv(&self) -> Option<CallConv> {
+        unsafe { CallConv::from_llvm(LLVMGetFunctionCallConv(self.raw_ptr())) }
+    }
+
+    pub fn add_attribute(&self, attr: FnAttr) {
+        let name = attr.as_str();
+        unsafe {
+            let kind = LLVMGetEnumAttributeKindForName(name.as_ptr() as *const c_char, name.len());
+            let ctx = LLVMGetTypeContext(LLVMTypeOf(self.raw_ptr()));
+            let a = LLVMCreateEnumAttribute(ctx, kind, 0);
+            LLVMAddAttributeAtIndex(self.raw_ptr(), FUNCTION_ATTR_INDEX, a);
+        }
+    }
+
+    /// Get linkage of function, `None` if it is not one of `Linkage`
+    pub fn get_linkage(&self) -> Option<Linkage> {
+        unsafe { Linkage::from_llvm(LLVMGetLinkage(self.raw_ptr())) }
+    }
+
     pub fn into_value(self) -> LLVMValue {
         LLVMValue::from_ref(self.0)
     }
@@ -246,14 +795,37 @@ impl LLVMFunction {
         unsafe { LLVMCountParams(self.raw_ptr()) as usize }
     }
 
+    /// Get the `idx`th parameter, panics if out of range
     pub fn get_param(&self, idx: usize) -> LLVMValue {
+        let count = self.count_params();
+        if idx >= count {
+            panic!("parameter index {} out of range, function has {} parameters", idx, count);
+        }
         unsafe { LLVMValue::from_ref(LLVMGetParam(self.raw_ptr(), idx as c_uint)) }
     }
 
+    /// All parameters in order
+    pub fn params(&self) -> Vec<LLVMValue> {
+        (0..self.count_params()).map(|i| self.get_param(i)).collect()
+    }
+
     pub fn get_entry_basic_block(&self) -> LLVMBasicBlock {
         unsafe { LLVMBasicBlock::from_ref(LLVMGetEntryBasicBlock(self.raw_ptr())) }
     }
 
+    /// Basic blocks of function in layout order
+    pub fn basic_blocks(&self) -> Vec<LLVMBasicBlock> {
+        let mut res = vec![];
+        unsafe {
+            let mut bb = LLVMGetFirstBasicBlock(self.raw_ptr());
+            while !bb.is_null() {
+                res.push(LLVMBasicBlock::from_ref(bb));
+                bb = LLVMGetNextBasicBlock(bb);
+            }
+        }
+        res
+    }
+
     pub fn verify(&self, action: LLVMVerifierFailureAction) -> bool {
         unsafe { LLVMVerifyFunction(self.raw_ptr(), action) == 0 }
     }
@@ -263,7 +835,7 @@ macro_rules! method_build_instr {
     ($name: ident, $fun: ident, $($param:ident : $ty:ty),* => $t:ident: &str) => {
     pub fn $name(&self, $($param: $ty),* , $t: &str) -> LLVMValue {
         unsafe {
-            LLVMValue::from_ref($fun(self.raw_ptr(), $($param.raw_ptr()),*, raw_string($t)))
+            LLVMValue::from_ref($fun(self.raw_ptr(), $($param.raw_ptr()),*, self.name($t)))
         }
     }
     };
@@ -278,7 +850,7 @@ macro_rules! method_build_instr {
 
 impl LLVMBuilder {
     pub fn in_ctx(ctx: &LLVMContext) -> Self {
-        unsafe { LLVMBuilder(LLVMCreateBuilderInContext(ctx.raw_ptr())) }
+        unsafe { LLVMBuilder::with_context(LLVMCreateBuilderInContext(ctx.raw_ptr()), ctx) }
     }
 
     pub fn set_position(&self, block: &LLVMBasicBlock, instr: &LLVMValue) {
@@ -292,6 +864,17 @@ impl LLVMBuilder {
         unsafe { LLVMBasicBlock::from_ref(LLVMGetInsertBlock(self.raw_ptr())) }
     }
 
+    /// Branch to `fallback` unless the current block already ends in a terminator,
+    ///   returns whether a `br` is inserted
+    pub fn ensure_terminated(&self, fallback: &LLVMBasicBlock) -> bool {
+        if self.get_insert_block().has_terminator() {
+            false
+        } else {
+            self.br(fallback);
+            true
+        }
+    }
+
     method_build_instr!(alloca, LLVMBuildAlloca, ty: &LLVMType => dest: &str);
     method_build_instr!(phi, LLVMBuildPhi, ty: &LLVMType => dest: &str);
     method_build_instr!(load, LLVMBuildLoad, ptr: &LLVMValue => dest: &str);
@@ -301,11 +884,89 @@ impl LLVMBuilder {
     method_build_instr!(br, LLVMBuildBr, cont: &LLVMBasicBlock);
     method_build_instr!(bit_cast, LLVMBuildBitCast, val: &LLVMValue, dest_ty: &LLVMType => dest: &str);
 
+    // Heap allocation, calls `malloc` and `free` of libc
+    method_build_instr!(build_malloc, LLVMBuildMalloc, ty: &LLVMType => dest: &str);
+    method_build_instr!(build_array_malloc, LLVMBuildArrayMalloc, ty: &LLVMType, len: &LLVMValue => dest: &str);
+    method_build_instr!(build_free, LLVMBuildFree, ptr: &LLVMValue);
+
+    // Casts
+    method_build_instr!(build_sext, LLVMBuildSExt, val: &LLVMValue, dest_ty: &LLVMType => dest: &str);
+    method_build_instr!(build_zext, LLVMBuildZExt, val: &LLVMValue, dest_ty: &LLVMType => dest: &str);
+    method_build_instr!(build_trunc, LLVMBuildTrunc, val: &LLVMValue, dest_ty: &LLVMType => dest: &str);
+    method_build_instr!(build_sitofp, LLVMBuildSIToFP, val: &LLVMValue, dest_ty: &LLVMType => dest: &str);
+    method_build_instr!(build_fptosi, LLVMBuildFPToSI, val: &LLVMValue, dest_ty: &LLVMType => dest: &str);
+
+    // Integer arithmetic
+    method_build_instr!(build_add, LLVMBuildAdd, lhs: &LLVMValue, rhs: &LLVMValue => dest: &str);
+    method_build_instr!(build_sub, LLVMBuildSub, lhs: &LLVMValue, rhs: &LLVMValue => dest: &str);
+    method_build_instr!(build_mul, LLVMBuildMul, lhs: &LLVMValue, rhs: &LLVMValue => dest: &str);
+    method_build_instr!(build_sdiv, LLVMBuildSDiv, lhs: &LLVMValue, rhs: &LLVMValue => dest: &str);
+    method_build_instr!(build_srem, LLVMBuildSRem, lhs: &LLVMValue, rhs: &LLVMValue => dest: &str);
+    method_build_instr!(build_and, LLVMBuildAnd, lhs: &LLVMValue, rhs: &LLVMValue => dest: &str);
+    method_build_instr!(build_or, LLVMBuildOr, lhs: &LLVMValue, rhs: &LLVMValue => dest: &str);
+    method_build_instr!(build_lshr, LLVMBuildLShr, lhs: &LLVMValue, rhs: &LLVMValue => dest: &str);
+
+    // Floating-point arithmetic
+    method_build_instr!(build_fadd, LLVMBuildFAdd, lhs: &LLVMValue, rhs: &LLVMValue => dest: &str);
+    method_build_instr!(build_fsub, LLVMBuildFSub, lhs: &LLVMValue, rhs: &LLVMValue => dest: &str);
+    method_build_instr!(build_fmul, LLVMBuildFMul, lhs: &LLVMValue, rhs: &LLVMValue => dest: &str);
+    method_build_instr!(build_fdiv, LLVMBuildFDiv, lhs: &LLVMValue, rhs: &LLVMValue => dest: &str);
+
+    method_build_instr!(build_select, LLVMBuildSelect, cond: &LLVMValue, then_v: &LLVMValue, else_v: &LLVMValue => dest: &str);
+
+    pub fn build_extract_value(&self, agg: &LLVMValue, idx: u32, dest: &str) -> LLVMValue {
+        unsafe {
+            LLVMValue::from_ref(LLVMBuildExtractValue(self.raw_ptr(), agg.raw_ptr(), idx as c_uint, self.name(dest)))
+        }
+    }
+
+    pub fn build_insert_value(&self, agg: &LLVMValue, elt: &LLVMValue, idx: u32, dest: &str) -> LLVMValue {
+        unsafe {
+            LLVMValue::from_ref(LLVMBuildInsertValue(self.raw_ptr(),
+                                                     agg.raw_ptr(),
+                                                     elt.raw_ptr(),
+                                                     idx as c_uint,
+                                                     self.name(dest)))
+        }
+    }
+
+    /// Build a `switch` jumping to `default` if no case matched,
+    ///   cases could be added by `add_case` later.
+    pub fn build_switch(&self, val: &LLVMValue, default: &LLVMBasicBlock, num_cases: u32) -> LLVMSwitchValue {
+        unsafe {
+            LLVMSwitchValue::from_ref(LLVMBuildSwitch(self.raw_ptr(),
+                                                      val.raw_ptr(),
+                                                      default.raw_ptr(),
+                                                      num_cases as c_uint))
+        }
+    }
+
+    pub fn add_case(&self, switch: &LLVMSwitchValue, on: &LLVMValue, dest: &LLVMBasicBlock) {
+        unsafe { LLVMAddCase(switch.raw_ptr(), on.raw_ptr(), dest.raw_ptr()) }
+    }
+
+    // Unary operators
+    method_build_instr!(build_neg, LLVMBuildNeg, val: &LLVMValue => dest: &str);
+    method_build_instr!(build_fneg, LLVMBuildFNeg, val: &LLVMValue => dest: &str);
+    method_build_instr!(build_not, LLVMBuildNot, val: &LLVMValue => dest: &str);
+
+    pub fn build_icmp(&self, pred: LLVMIntPredicate, lhs: &LLVMValue, rhs: &LLVMValue, dest: &str) -> LLVMValue {
+        unsafe {
+            LLVMValue::from_ref(LLVMBuildICmp(self.raw_ptr(), pred, lhs.raw_ptr(), rhs.raw_ptr(), self.name(dest)))
+        }
+    }
+
+    pub fn build_fcmp(&self, pred: LLVMRealPredicate, lhs: &LLVMValue, rhs: &LLVMValue, dest: &str) -> LLVMValue {
+        unsafe {
+            LLVMValue::from_ref(LLVMBuildFCmp(self.raw_ptr(), pred, lhs.raw_ptr(), rhs.raw_ptr(), self.name(dest)))
+        }
+    }
+
     pub fn phi_node<'a, I>(&self, ty: &LLVMType, incoming: I, dest: &str) -> LLVMValue
         where I: IntoIterator<Item=&'a (&'a LLVMValue, &'a LLVMBasicBlock)>
     {
         unsafe {
-            let phi = LLVMBuildPhi(self.raw_ptr(), ty.raw_ptr(), raw_string(dest));
+            let phi = LLVMBuildPhi(self.raw_ptr(), ty.raw_ptr(), self.name(dest));
             let (mut vals, mut blks): (Vec<_>, Vec<_>) = incoming
                 .into_iter()
                 .map(|&(val, blk)| (val.raw_ptr(), blk.raw_ptr()))
@@ -316,12 +977,35 @@ impl LLVMBuilder {
         }
     }
 
+    /// Same as `ret`
+    pub fn build_ret(&self, val: &LLVMValue) -> LLVMValue {
+        self.ret(val)
+    }
+
     pub fn ret_void(&self) -> LLVMValue {
         unsafe {
-            LLVMValue::from_ref(LLVMBuildRet(self.raw_ptr(), ptr::null_mut()))
+            LLVMValue::from_ref(LLVMBuildRetVoid(self.raw_ptr()))
+        }
+    }
+
+    pub fn build_unreachable(&self) -> LLVMValue {
+        unsafe {
+            LLVMValue::from_ref(LLVMBuildUnreachable(self.raw_ptr()))
         }
     }
+    /// Build a call, get `fun` from a value with `LLVMValue::as_function`
     pub fn call(&self, fun: &LLVMFunction, args: &mut Vec<LLVMValue>, name: &str) -> LLVMValue {
+        self.call_with_conv(fun, args, None, name)
+    }
+
+    /// Build a call with calling convention,
+    ///   which should match the convention of callee declaration.
+    pub fn call_with_conv(&self,
+                          fun: &LLVMFunction,
+                          args: &mut Vec<LLVMValue>,
+                          conv: Option<CallConv>,
+                          name: &str)
+                          -> LLVMValue {
         let mut _args: Vec<_> = args.iter_mut().map(|arg| arg.raw_ptr()).collect();
         unsafe {
             let f = fun.raw_ptr();
@@ -329,16 +1013,69 @@ impl LLVMBuilder {
                                     f,
                                     _args.as_mut_ptr(),
                                     args.len() as c_uint,
-                                    raw_string(name));
+                                    self.name(name));
+            if let Some(cc) = conv {
+                LLVMSetInstructionCallConv(ret, cc.to_llvm());
+            }
             LLVMValue::from_ref(ret)
         }
+    }
 
+    /// Pointer to a new private global string constant, typed `i8*`
+    pub fn build_global_string_ptr(&self, s: &str, name: &str) -> LLVMValue {
+        let content = CString::new(s).expect("string constant contains nul");
+        unsafe {
+            LLVMValue::from_ref(LLVMBuildGlobalStringPtr(self.raw_ptr(), content.as_ptr(), self.name(name)))
+        }
     }
 
+    /// Pointer to the `idx`th field of struct pointed by `ptr`,
+    ///   panics if the struct has not so many fields
     pub fn struct_field_ptr(&self, ptr: &LLVMValue, idx: usize, name: &str) -> LLVMValue {
+        let ty = ptr.get_type();
+        debug_assert!(ty.is_pointer(), "field of a value not a pointer");
+        if ty.is_pointer() {
+            let count = ty.get_element().count_struct_elements();
+            debug_assert!(count.is_some(), "field of a pointer not to a struct");
+            if let Some(count) = count {
+                if idx >= count {
+                    panic!("field index {} out of range, struct has {} fields", idx, count);
+                }
+            }
+        }
         unsafe {
             let ret =
-                LLVMBuildStructGEP(self.raw_ptr(), ptr.raw_ptr(), idx as u32, raw_string(name));
+                LLVMBuildStructGEP(self.raw_ptr(), ptr.raw_ptr(), idx as u32, self.name(name));
+            LLVMValue::from_ref(ret)
+        }
+    }
+
+    /// Load the `idx`th field of struct pointed by `ptr`
+    pub fn load_field(&self, ptr: &LLVMValue, idx: usize, name: &str) -> LLVMValue {
+        let field = self.struct_field_ptr(ptr, idx, "");
+        self.load(&field, name)
+    }
+
+    pub fn build_gep(&self, ptr: &LLVMValue, indices: &[LLVMValue], dest: &str) -> LLVMValue {
+        let mut idx: Vec<_> = indices.iter().map(|i| i.raw_ptr()).collect();
+        unsafe {
+            let ret = LLVMBuildGEP(self.raw_ptr(),
+                                   ptr.raw_ptr(),
+                                   idx.as_mut_ptr(),
+                                   idx.len() as c_uint,
+                                   self.name(dest));
+            LLVMValue::from_ref(ret)
+        }
+    }
+
+    pub fn build_in_bounds_gep(&self, ptr: &LLVMValue, indices: &[LLVMValue], dest: &str) -> LLVMValue {
+        let mut idx: Vec<_> = indices.iter().map(|i| i.raw_ptr()).collect();
+        unsafe {
+            let ret = LLVMBuildInBoundsGEP(self.raw_ptr(),
+                                           ptr.raw_ptr(),
+                                           idx.as_mut_ptr(),
+                                           idx.len() as c_uint,
+                                           self.name(dest));
             LLVMValue::from_ref(ret)
         }
     }
@@ -352,6 +1089,111 @@ impl LLVMBasicBlock {
     pub fn get_first_instr(&self) -> LLVMValue {
         unsafe { LLVMValue::from_ref(LLVMGetFirstInstruction(self.raw_ptr())) }
     }
+
+    pub fn get_terminator(&self) -> Option<LLVMValue> {
+        let term = unsafe { LLVMGetBasicBlockTerminator(self.raw_ptr()) };
+        if term.is_null() {
+            None
+        } else {
+            Some(LLVMValue::from_ref(term))
+        }
+    }
+
+    pub fn has_terminator(&self) -> bool {
+        self.get_terminator().is_some()
+    }
+
+    /// Instructions of block in order
+    pub fn instructions(&self) -> Instructions {
+        unsafe { Instructions(LLVMGetFirstInstruction(self.raw_ptr())) }
+    }
+
+    /// Instructions of block in order after `instr`, which is in the block
+    pub fn instructions_after(&self, instr: &LLVMValue) -> Instructions {
+        unsafe { Instructions(LLVMGetNextInstruction(instr.raw_ptr())) }
+    }
+}
+
+/// Iterator over instructions of a basic block
+pub struct Instructions(LLVMValueRef);
+
+impl Iterator for Instructions {
+    type Item = LLVMValue;
+
+    fn next(&mut self) -> Option<LLVMValue> {
+        if self.0.is_null() {
+            return None;
+        }
+        let instr = self.0;
+        self.0 = unsafe { LLVMGetNextInstruction(instr) };
+        Some(LLVMValue::from_ref(instr))
+    }
+}
+
+impl LLVMExecutionEngine {
+    /// Create a JIT engine for module.
+    ///   The engine owns the module and disposes it when dropped.
+    pub fn create_for_module(module: LLVMModule) -> Result<Self, String> {
+        let mut ee: LLVMExecutionEngineRef = ptr::null_mut();
+        let mut msg: *mut c_char = ptr::null_mut();
+        let (m, ctx) = module.into_raw();
+        unsafe {
+            engine::LLVMLinkInMCJIT();
+            target_init::LLVM_InitializeNativeTarget();
+            target_init::LLVM_InitializeNativeAsmPrinter();
+            if engine::LLVMCreateExecutionEngineForModule(&mut ee, m, &mut msg) != 0 {
+                // LLVM owns the module after the call, it is deleted on failure
+                let err = take_message(msg);
+                LLVMDisposeMessage(msg);
+                Err(err)
+            } else {
+                Ok(LLVMExecutionEngine(ee, ctx))
+            }
+        }
+    }
+
+    /// Find a function in the module owned by engine
+    pub fn find_function(&self, name: &str) -> Option<LLVMFunction> {
+        let n = CString::new(name).unwrap();
+        let mut f: LLVMValueRef = ptr::null_mut();
+        unsafe {
+            if engine::LLVMFindFunction(self.raw_ptr(), n.as_ptr(), &mut f) != 0 {
+                None
+            } else {
+                Some(LLVMFunction::from_ref(f))
+            }
+        }
+    }
+
+    pub fn run_function(&self, f: &LLVMFunction, args: &[LLVMGenericValue]) -> LLVMGenericValue {
+        let mut argsv: Vec<_> = args.iter().map(|a| a.raw_ptr()).collect();
+        unsafe {
+            LLVMGenericValue(LLVMRunFunction(self.raw_ptr(),
+                                             f.raw_ptr(),
+                                             argsv.len() as c_uint,
+                                             argsv.as_mut_ptr()))
+        }
+    }
+}
+
+impl LLVMGenericValue {
+    pub fn of_int(ty: &LLVMType, val: u64, signed: bool) -> Self {
+        let flag = if signed { 1 } else { 0 };
+        unsafe { LLVMGenericValue(engine::LLVMCreateGenericValueOfInt(ty.raw_ptr(), val, flag)) }
+    }
+
+    pub fn of_float(ty: &LLVMType, val: f64) -> Self {
+        unsafe { LLVMGenericValue(engine::LLVMCreateGenericValueOfFloat(ty.raw_ptr(), val)) }
+    }
+
+    pub fn to_int(&self, signed: bool) -> u64 {
+        let flag = if signed { 1 } else { 0 };
+        unsafe { engine::LLVMGenericValueToInt(self.raw_ptr(), flag) }
+    }
+
+    pub fn to_float(&self, ty: &LLVMType) -> f64 {
+        unsafe { LLVMGenericValueToFloat(ty.raw_ptr(), self.raw_ptr()) }
+    }
 }
 
 impl Drop for LLVMModule {
@@ -361,17 +1203,24 @@ impl Drop for LLVMModule {
         }
     }
 }
-impl Drop for LLVMContext {
+impl Drop for LLVMBuilder {
     fn drop(&mut self) {
         unsafe {
-            LLVMContextDispose(self.0);
+            LLVMDisposeBuilder(self.0);
         }
     }
 }
-impl Drop for LLVMBuilder {
+impl Drop for LLVMExecutionEngine {
     fn drop(&mut self) {
         unsafe {
-            LLVMDisposeBuilder(self.0);
+            engine::LLVMDisposeExecutionEngine(self.0);
+        }
+    }
+}
+impl Drop for LLVMGenericValue {
+    fn drop(&mut self) {
+        unsafe {
+            engine::LLVMDisposeGenericValue(self.0);
         }
     }
 }
@@ -388,3 +1237,452 @@ pub type LLVMOperateBuild = unsafe extern "C" fn(LLVMBuilderRef,
                                                  LLVMValueRef,
                                                  *const ::libc::c_char)
                                                  -> LLVMValueRef;
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_function_then_get() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("test", &ctx);
+        let fty = LLVMContext::get_function_type(&ctx.get_int32_type(), &vec![], false);
+        let f = module.add_function("answer", &fty);
+
+        let found = module.get_function("answer").expect("function should be declared");
+        assert_eq!(f.raw_ptr(), found.raw_ptr());
+        assert!(module.get_function("question").is_none());
+    }
+
+    #[test]
+    fn arena_interns_names() {
+        let arena = CStringArena::new();
+        let a = arena.alloc("tmp");
+        let b = arena.alloc("tmp");
+        assert_eq!(a, b);
+        arena.alloc("call");
+        assert_eq!(arena.len(), 2);
+        arena.clear();
+        assert_eq!(arena.len(), 0);
+    }
+
+    #[test]
+    fn verify_module() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("test", &ctx);
+        let builder = LLVMBuilder::in_ctx(&ctx);
+        let fty = LLVMContext::get_function_type(&ctx.get_int32_type(), &vec![], false);
+        let f = module.add_function("answer", &fty);
+        let entry = ctx.append_basic_block(&f, "entry");
+        builder.set_position_at_end(&entry);
+        assert!(module.verify(LLVMVerifierFailureAction::LLVMReturnStatusAction).is_err());
+
+        builder.ret(&ctx.get_int32_const(42));
+        assert_eq!(module.verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+    }
+
+    /// `i32 answer()` storing 42 in a stack slot, then loading it back
+    fn slot_function(ctx: &LLVMContext, module: &LLVMModule) -> LLVMFunction {
+        let builder = LLVMBuilder::in_ctx(ctx);
+        let int = ctx.get_int32_type();
+        let fty = LLVMContext::get_function_type(&int, &vec![], false);
+        let f = module.add_function("answer", &fty);
+        let entry = ctx.append_basic_block(&f, "entry");
+        builder.set_position_at_end(&entry);
+        let slot = builder.alloca(&int, "slot");
+        builder.store(&ctx.get_int32_const(42), &slot);
+        let v = builder.load(&slot, "v");
+        builder.ret(&v);
+        f
+    }
+
+    #[test]
+    fn pass_levels() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("test", &ctx);
+        let f = slot_function(&ctx, &module);
+        LLVMFunctionPassManager::with_level(&module, OptLevel::O0).run(&f);
+        assert!(module.print_to_string().contains("alloca i32"));
+
+        LLVMFunctionPassManager::with_passes(&module, &[PassKind::PromoteMemoryToRegister]).run(&f);
+        let ir = module.print_to_string();
+        assert!(!ir.contains("alloca"), "{}", ir);
+        assert!(ir.contains("ret i32 42"), "{}", ir);
+        assert!(OptLevel::O3.passes().starts_with(&OptLevel::O2.passes()));
+    }
+
+    #[test]
+    fn walk_instructions() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("test", &ctx);
+        let builder = LLVMBuilder::in_ctx(&ctx);
+        let int = ctx.get_int32_type();
+        let fty = LLVMContext::get_function_type(&int, &vec![], false);
+        let f = module.add_function("answer", &fty);
+        let entry = ctx.append_basic_block(&f, "entry");
+        let exit = ctx.append_basic_block(&f, "exit");
+        builder.set_position_at_end(&entry);
+        let slot = builder.alloca(&int, "slot");
+        builder.store(&ctx.get_int32_const(42), &slot);
+        builder.br(&exit);
+        builder.set_position_at_end(&exit);
+        let v = builder.load(&slot, "v");
+        builder.ret(&v);
+
+        let blocks = f.basic_blocks();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].raw_ptr(), entry.raw_ptr());
+        assert_eq!(blocks[0].instructions().count(), 3);
+        assert_eq!(blocks[1].instructions().count(), 2);
+        assert_eq!(blocks[1].instructions().next().unwrap().raw_ptr(), v.raw_ptr());
+    }
+
+    #[test]
+    fn terminate_arms_once() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("test", &ctx);
+        let builder = LLVMBuilder::in_ctx(&ctx);
+        let int = ctx.get_int32_type();
+        let fty = LLVMContext::get_function_type(&int, &vec![ctx.get_int1_type()], false);
+        let f = module.add_function("pick", &fty);
+        let entry = ctx.append_basic_block(&f, "entry");
+        let then_blk = ctx.append_basic_block(&f, "if.then");
+        let else_blk = ctx.append_basic_block(&f, "if.else");
+        let cont_blk = ctx.append_basic_block(&f, "if.cont");
+        builder.set_position_at_end(&entry);
+        builder.cond_br(&f.get_param(0), &then_blk, &else_blk);
+        assert!(!builder.ensure_terminated(&cont_blk));
+
+        // `then` returns early, `else` falls through
+        builder.set_position_at_end(&then_blk);
+        let ret = builder.ret(&ctx.get_int32_const(1));
+        assert_eq!(then_blk.get_terminator().map(|t| t.raw_ptr()), Some(ret.raw_ptr()));
+        assert!(!builder.ensure_terminated(&cont_blk));
+        builder.set_position_at_end(&else_blk);
+        assert!(!else_blk.has_terminator());
+        assert!(builder.ensure_terminated(&cont_blk));
+        assert!(else_blk.has_terminator());
+
+        builder.set_position_at_end(&cont_blk);
+        builder.ret(&ctx.get_int32_const(2));
+        assert_eq!(then_blk.instructions().count(), 1);
+        assert_eq!(module.verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+    }
+
+    #[test]
+    fn value_as_function() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("test", &ctx);
+        let fty = LLVMContext::get_function_type(&ctx.get_int32_type(), &vec![], false);
+        let f = module.add_function("answer", &fty);
+        let v = f.into_value();
+        assert_eq!(v.as_function().map(|f| f.raw_ptr()), Some(v.raw_ptr()));
+        assert!(ctx.get_int32_const(42).as_function().is_none());
+    }
+
+    #[test]
+    fn function_params() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("test", &ctx);
+        let int = ctx.get_int32_type();
+        let fty = LLVMContext::get_function_type(&int, &vec![int, ctx.get_double_type()], false);
+        let f = module.add_function("add", &fty);
+        let params = f.params();
+        assert_eq!(params.len(), f.count_params());
+        assert_eq!(params[1].raw_ptr(), f.get_param(1).raw_ptr());
+        assert_eq!(params[1].get_type().raw_ptr(), ctx.get_double_type().raw_ptr());
+    }
+
+    #[test]
+    fn load_struct_field() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("test", &ctx);
+        let builder = LLVMBuilder::in_ctx(&ctx);
+        let int = ctx.get_int32_type();
+        let pair = ctx.get_struct_type(&vec![ctx.get_double_type(), int], false);
+        assert_eq!(pair.count_struct_elements(), Some(2));
+        assert_eq!(int.count_struct_elements(), None);
+
+        let fty = LLVMContext::get_function_type(&int, &vec![pair.get_ptr(0)], false);
+        let f = module.add_function("second", &fty);
+        builder.set_position_at_end(&ctx.append_basic_block(&f, "entry"));
+        let v = builder.load_field(&f.get_param(0), 1, "snd");
+        builder.ret(&v);
+        assert_eq!(v.get_type().raw_ptr(), int.raw_ptr());
+        assert_eq!(module.verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+    }
+
+    #[test]
+    fn heap_closure_env() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("test", &ctx);
+        let builder = LLVMBuilder::in_ctx(&ctx);
+        let int = ctx.get_int32_type();
+        let env = ctx.get_struct_type(&vec![ctx.get_int8_type().get_ptr(0), int], false);
+        let fty = LLVMContext::get_function_type(&int, &vec![], false);
+        let f = module.add_function("captured", &fty);
+        builder.set_position_at_end(&ctx.append_basic_block(&f, "entry"));
+        let ptr = builder.build_malloc(&env, "env");
+        builder.store(&ctx.get_int32_const(42), &builder.struct_field_ptr(&ptr, 1, ""));
+        let v = builder.load_field(&ptr, 1, "v");
+        builder.build_free(&ptr);
+        let buf = builder.build_array_malloc(&int, &ctx.get_int32_const(4), "buf");
+        builder.build_free(&buf);
+        builder.ret(&v);
+
+        assert_eq!(ptr.get_type().raw_ptr(), env.get_ptr(0).raw_ptr());
+        assert_eq!(buf.get_type().raw_ptr(), int.get_ptr(0).raw_ptr());
+        let ir = module.print_to_string();
+        assert!(ir.contains("call i8* @malloc"), "{}", ir);
+        assert!(ir.contains("call void @free"), "{}", ir);
+        assert_eq!(module.verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+    }
+
+    #[test]
+    fn print_module_to_string() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("test", &ctx);
+        let fty = LLVMContext::get_function_type(&ctx.get_int32_type(), &vec![], false);
+        module.add_function("answer", &fty);
+
+        let ir = module.print_to_string();
+        assert!(ir.contains("declare i32 @answer()"));
+    }
+
+    #[test]
+    fn jit_run_function() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("jit", &ctx);
+        let builder = LLVMBuilder::in_ctx(&ctx);
+        let double = ctx.get_double_type();
+        let fty = LLVMContext::get_function_type(&double, &vec![], false);
+        let f = module.add_function("half", &fty);
+        let entry = ctx.append_basic_block(&f, "entry");
+        builder.set_position_at_end(&entry);
+        builder.ret(&ctx.get_double_const(0.5));
+
+        let engine = LLVMExecutionEngine::create_for_module(module).unwrap();
+        let found = engine.find_function("half").expect("function should be found");
+        let res = engine.run_function(&found, &[]);
+        assert_eq!(res.to_float(&double), 0.5);
+    }
+
+    #[test]
+    fn array_and_vector_type() {
+        let ctx = LLVMContext::new();
+        let int = ctx.get_int32_type();
+        let arr = int.array(3);
+        let vals = vec![ctx.get_int32_const(1), ctx.get_int32_const(2), ctx.get_int32_const(3)];
+        let lit = ctx.get_array_const(&int, &vals);
+        unsafe {
+            assert_eq!(LLVMGetArrayLength(arr.raw_ptr()), 3);
+            assert_eq!(LLVMGetVectorSize(int.vector(4).raw_ptr()), 4);
+            assert_eq!(lit.get_type().raw_ptr(), arr.raw_ptr());
+        }
+        assert_eq!(arr.get_element().raw_ptr(), int.raw_ptr());
+    }
+
+    #[test]
+    fn recursive_named_struct() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("test", &ctx);
+        let list = ctx.create_named_struct("List");
+        list.set_struct_body(&[ctx.get_int32_type(), list.get_ptr(0)], false);
+        let fty = LLVMContext::get_function_type(&ctx.get_void_type(), &vec![list.get_ptr(0)], false);
+        module.add_function("walk", &fty);
+
+        let ir = module.print_to_string();
+        assert!(ir.contains("%List = type { i32, %List* }"));
+    }
+
+    #[test]
+    fn select_and_branch_if() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("test", &ctx);
+        let builder = LLVMBuilder::in_ctx(&ctx);
+        let int = ctx.get_int32_type();
+        let fty = LLVMContext::get_function_type(&int, &vec![ctx.get_int1_type(), int, int], false);
+
+        // if (c) a else b, as a select
+        let sel = module.add_function("pick_select", &fty);
+        builder.set_position_at_end(&ctx.append_basic_block(&sel, "entry"));
+        let res = builder.build_select(&sel.get_param(0), &sel.get_param(1), &sel.get_param(2), "if.res");
+        builder.ret(&res);
+
+        // if (c) a else b, as branches joined by phi
+        let br = module.add_function("pick_branch", &fty);
+        let entry = ctx.append_basic_block(&br, "entry");
+        let then_blk = ctx.append_basic_block(&br, "if.then");
+        let else_blk = ctx.append_basic_block(&br, "if.else");
+        let cont_blk = ctx.append_basic_block(&br, "if.cont");
+        builder.set_position_at_end(&entry);
+        builder.cond_br(&br.get_param(0), &then_blk, &else_blk);
+        builder.set_position_at_end(&then_blk);
+        builder.br(&cont_blk);
+        builder.set_position_at_end(&else_blk);
+        builder.br(&cont_blk);
+        builder.set_position_at_end(&cont_blk);
+        let (a, b) = (br.get_param(1), br.get_param(2));
+        let res = builder.phi_node(&int, &[(&a, &then_blk), (&b, &else_blk)], "if.res");
+        builder.ret(&res);
+
+        assert_eq!(module.verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+        let ir = module.print_to_string();
+        assert!(ir.contains("select i1"));
+        assert!(ir.contains("phi i32"));
+    }
+
+    #[test]
+    fn insert_then_extract_value() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("test", &ctx);
+        let builder = LLVMBuilder::in_ctx(&ctx);
+        let int = ctx.get_int32_type();
+        let pair = ctx.get_struct_type(&vec![int, int], false);
+        let fty = LLVMContext::get_function_type(&int, &vec![], false);
+        let f = module.add_function("pair", &fty);
+        builder.set_position_at_end(&ctx.append_basic_block(&f, "entry"));
+
+        let agg = builder.build_insert_value(&pair.get_undef(), &ctx.get_int32_const(7), 0, "agg");
+        let agg = builder.build_insert_value(&agg, &ctx.get_int32_const(9), 1, "agg");
+        let fst = builder.build_extract_value(&agg, 0, "fst");
+        let snd = builder.build_extract_value(&agg, 1, "snd");
+        builder.ret(&builder.build_add(&fst, &snd, "sum"));
+
+        // Operands are constants, so builder folds them
+        unsafe {
+            assert_eq!(LLVMConstIntGetSExtValue(fst.raw_ptr()), 7);
+            assert_eq!(LLVMConstIntGetSExtValue(snd.raw_ptr()), 9);
+        }
+        assert_eq!(module.verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+    }
+
+    #[test]
+    fn switch_on_tag() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("test", &ctx);
+        let builder = LLVMBuilder::in_ctx(&ctx);
+        let int = ctx.get_int32_type();
+        let fty = LLVMContext::get_function_type(&int, &vec![int], false);
+        let f = module.add_function("dispatch", &fty);
+        let entry = ctx.append_basic_block(&f, "entry");
+        let default = ctx.append_basic_block(&f, "default");
+        builder.set_position_at_end(&default);
+        builder.ret(&ctx.get_int32_const(-1));
+
+        builder.set_position_at_end(&entry);
+        let switch = builder.build_switch(&f.get_param(0), &default, 3);
+        for tag in 0..3 {
+            let arm = ctx.append_basic_block(&f, "arm");
+            builder.add_case(&switch, &ctx.get_int32_const(tag), &arm);
+            builder.set_position_at_end(&arm);
+            builder.ret(&ctx.get_int32_const(tag * 10));
+        }
+
+        assert_eq!(module.verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+        assert!(module.print_to_string().contains("switch i32"));
+        assert_eq!(f.count_basic_blocks(), 5);
+    }
+
+    #[test]
+    fn function_linkage() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("test", &ctx);
+        let fty = LLVMContext::get_function_type(&ctx.get_void_type(), &vec![], false);
+        let f = module.add_function("helper", &fty);
+        assert_eq!(f.get_linkage(), Some(Linkage::External));
+
+        for &l in &[Linkage::Internal, Linkage::Private, Linkage::LinkOnceODR, Linkage::External] {
+            f.set_linkage(l);
+            assert_eq!(f.get_linkage(), Some(l));
+        }
+    }
+
+    #[test]
+    fn fastcc_lifted_function() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("test", &ctx);
+        let builder = LLVMBuilder::in_ctx(&ctx);
+        let fty = LLVMContext::get_function_type(&ctx.get_void_type(), &vec![], false);
+        let lifted = module.add_function("main.inner.closure.f1", &fty);
+        lifted.set_call_conv(CallConv::Fast);
+        lifted.add_attribute(FnAttr::NoUnwind);
+        builder.set_position_at_end(&ctx.append_basic_block(&lifted, "entry"));
+        builder.ret_void();
+        assert_eq!(lifted.get_call_conv(), Some(CallConv::Fast));
+
+        let caller = module.add_function("caller", &fty);
+        builder.set_position_at_end(&ctx.append_basic_block(&caller, "entry"));
+        builder.call_with_conv(&lifted, &mut vec![], Some(CallConv::Fast), "");
+        builder.ret_void();
+
+        assert_eq!(module.verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+        let ir = module.print_to_string();
+        assert!(ir.contains("call fastcc void"));
+        assert!(ir.contains("nounwind"));
+    }
+
+    #[test]
+    fn module_keeps_context_alive() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("test", &ctx);
+        let builder = LLVMBuilder::in_ctx(&ctx);
+        drop(ctx);
+
+        let ctx = module.get_context().expect("module should keep its context");
+        let fty = LLVMContext::get_function_type(&ctx.get_int32_type(), &vec![], false);
+        let f = module.add_function("answer", &fty);
+        builder.set_position_at_end(&ctx.append_basic_block(&f, "entry"));
+        builder.ret(&ctx.get_int32_const(42));
+        assert_eq!(module.verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+    }
+
+    #[test]
+    fn unreachable_default_arm() {
+        let ctx = LLVMContext::new();
+        let module = LLVMModule::in_ctx("test", &ctx);
+        let builder = LLVMBuilder::in_ctx(&ctx);
+        let int = ctx.get_int32_type();
+        let fty = LLVMContext::get_function_type(&int, &vec![ctx.get_int1_type()], false);
+        let f = module.add_function("exhaustive", &fty);
+        let entry = ctx.append_basic_block(&f, "entry");
+        let default = ctx.append_basic_block(&f, "default");
+        let arm = ctx.append_basic_block(&f, "arm");
+
+        builder.set_position_at_end(&entry);
+        let switch = builder.build_switch(&f.get_param(0), &default, 2);
+        builder.add_case(&switch, &ctx.get_int1_const(0), &arm);
+        builder.add_case(&switch, &ctx.get_int1_const(1), &arm);
+        builder.set_position_at_end(&default);
+        builder.build_unreachable();
+        builder.set_position_at_end(&arm);
+        builder.build_ret(&ctx.get_int32_const(0));
+
+        assert_eq!(module.verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+        assert!(module.print_to_string().contains("unreachable"));
+    }
+
+    #[test]
+    fn numeric_casts() {
+        let ctx = LLVMContext::new();
+        let builder = LLVMBuilder::in_ctx(&ctx);
+        let int = ctx.get_int32_type();
+        let double = ctx.get_double_type();
+
+        // Operands are constants, so builder folds them
+        let widened = builder.build_zext(&ctx.get_int1_const(1), &int, "widen");
+        let signed = builder.build_sext(&ctx.get_int1_const(1), &int, "widen");
+        let float = builder.build_sitofp(&ctx.get_int32_const(-3), &double, "float");
+        let back = builder.build_fptosi(&float, &int, "int");
+        let narrowed = builder.build_trunc(&ctx.get_int32_const(257), &ctx.get_int8_type(), "narrow");
+        assert_eq!(widened.get_type().raw_ptr(), int.raw_ptr());
+        assert_eq!(float.get_type().raw_ptr(), double.raw_ptr());
+        unsafe {
+            assert_eq!(LLVMConstIntGetSExtValue(widened.raw_ptr()), 1);
+            assert_eq!(LLVMConstIntGetSExtValue(signed.raw_ptr()), -1);
+            assert_eq!(LLVMConstIntGetSExtValue(back.raw_ptr()), -3);
+            assert_eq!(LLVMConstIntGetZExtValue(narrowed.raw_ptr()), 1);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+/// Source locations of emitted code
+
+use std::cmp;
+use std::collections::HashMap;
+use std::path::Path;
+
+use syntax::form::{Pos, Span};
+
+use super::llvm::*;
+
+/// Locations of instructions in source, each instruction is located
+///   at the innermost term being lowered when it is built.
+///
+/// Instructions are located when a term is entered or left and when
+///   the builder leaves a block, from the last one located in the block,
+///   so each instruction is visited once.
+///   Allocas inserted at the start of entry block are not located.
+pub struct DebugInfo {
+    builder: DebugInfoBuilder,
+    src: String,
+    /// Offsets of the start of each line in source
+    lines: Vec<usize>,
+    /// Function being lowered
+    function: Option<LLVMFunction>,
+    /// Terms being lowered, at their line and column
+    scopes: Vec<(DIScope, Pos)>,
+    /// Last instruction located in each block of current function
+    cursors: HashMap<LLVMBasicBlockRef, LLVMValue>,
+}
+
+impl DebugInfo {
+    pub fn new(file: &str, src: &str) -> DebugInfo {
+        let path = Path::new(file);
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or(file);
+        let dir = path.parent().and_then(|d| d.to_str()).unwrap_or("");
+        let mut lines = vec![0];
+        lines.extend(src.char_indices().filter(|&(_, c)| c == '\n').map(|(i, _)| i + 1));
+        DebugInfo {
+            builder: DebugInfoBuilder::new(name, dir, "miko"),
+            src: src.to_string(),
+            lines,
+            function: None,
+            scopes: vec![],
+            cursors: HashMap::new(),
+        }
+    }
+
+    /// Line and column of a offset, same as `Pos::locate`
+    fn locate(&self, offset: usize) -> Pos {
+        let offset = cmp::min(offset, self.src.len());
+        let line = match self.lines.binary_search(&offset) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        let col = self.src[self.lines[line]..offset].chars().count() + 1;
+        Pos { line: line + 1, col }
+    }
+
+    /// Start lowering function `fun` named `name` defined at `span`
+    pub fn enter_function(&mut self, fun: &LLVMFunction, name: &str, span: Span) {
+        let pos = self.locate(span.start());
+        let scope = self.builder.create_function(fun, name, pos.line);
+        self.function = Some(*fun);
+        self.cursors.clear();
+        self.scopes.push((scope, pos));
+    }
+
+    /// Start lowering a term with the builder in `block`,
+    ///   instructions built so far belong to the enclosing term
+    pub fn enter(&mut self, block: &LLVMBasicBlock, span: Span) {
+        self.locate_built(block);
+        let pos = self.locate(span.start());
+        let scope = self.scopes.last().map(|&(scope, _)| scope);
+        if let Some(scope) = scope {
+            self.scopes.push((scope, pos));
+        }
+    }
+
+    /// Finish lowering a term or function with the builder in `block`
+    pub fn leave(&mut self, block: &LLVMBasicBlock) {
+        self.locate_built(block);
+        self.scopes.pop();
+    }
+
+    /// Locate instructions of `block` not located yet at the current term,
+    ///   blocks of other functions are skipped
+    pub fn locate_built(&mut self, block: &LLVMBasicBlock) {
+        let (scope, Pos { line, col }) = match self.scopes.last() {
+            Some(&s) => s,
+            None => return,
+        };
+        match self.function {
+            Some(f) if f.raw_ptr() == block.get_parent().raw_ptr() => {}
+            _ => return,
+        }
+        let built = match self.cursors.get(&block.raw_ptr()) {
+            Some(last) => block.instructions_after(last),
+            None => block.instructions(),
+        };
+        let mut last = None;
+        for instr in built {
+            self.builder.set_location(instr, scope, line, col);
+            last = Some(instr);
+        }
+        if let Some(instr) = last {
+            self.cursors.insert(block.raw_ptr(), instr);
+        }
+    }
+
+    /// Attach the locations in `module`
+    pub fn finalize(self, module: &LLVMModule, context: &LLVMContext) -> Result<(), String> {
+        self.builder.finalize(module, context)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locate_by_lines() {
+        let src = "def main() = {\n  println(\"é\")\n}\n";
+        let dbg = DebugInfo::new("main.gs", src);
+        for offset in (0..src.len() + 1).filter(|&i| src.is_char_boundary(i)) {
+            assert_eq!(dbg.locate(offset), Pos::locate(src, offset));
+        }
+    }
+}
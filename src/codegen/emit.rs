@@ -4,8 +4,12 @@ use internal::*;
 use utils::*;
 
 use codegen::llvm::*;
+use codegen::debug::DebugInfo;
+use syntax::form::Span;
 
 use std::ops::Deref;
+use std::collections::HashMap;
+use std::mem;
 
 
 pub trait EmitProvider {
@@ -16,6 +20,25 @@ pub struct LLVMEmit<'i> {
     pub generator: LLVMCodegen,
     interner: &'i mut Interner,
     funpass: bool,
+    /// Abort on integer division by zero instead of undefined behavior
+    div_check: bool,
+    /// Abort on signed integer overflow of arithmetic
+    overflow_check: bool,
+    /// Abort on list index out of range
+    bounds_check: bool,
+    /// Locate instructions in source
+    debug: Option<DebugInfo>,
+    /// The term being lowered is in tail position,
+    ///   its value is returned by current function directly
+    in_tail: bool,
+    /// A closure is built in stack frame of current function,
+    ///   so calls may refer to it and could not be `tail`
+    frame_escaped: bool,
+    /// Variants matched by arms of `Case` being generated,
+    ///   fields of a scrutinee are of its matched variant
+    matched: Vec<(Id, usize)>,
+    /// Global strings of the module, by their contents
+    strings: HashMap<String, LLVMValue>,
 }
 
 pub type VarEnv<'a> = SymTable<'a, Id, LLVMValue>;
@@ -36,10 +59,21 @@ pub type VarEnv<'a> = SymTable<'a, Id, LLVMValue>;
 
 impl<'i> LLVMEmit<'i> {
     pub fn new(name: &str, interner: &'i mut Interner) -> Self {
+        LLVMEmit::with_options(name, interner, CodegenOptions::default())
+    }
+    pub fn with_options(name: &str, interner: &'i mut Interner, options: CodegenOptions) -> Self {
         LLVMEmit {
-            generator: LLVMCodegen::new(name),
+            generator: LLVMCodegen::with_options(name, &options),
             interner,
-            funpass: true
+            funpass: options.optimize,
+            div_check: true,
+            overflow_check: false,
+            bounds_check: false,
+            debug: None,
+            in_tail: false,
+            frame_escaped: false,
+            matched: vec![],
+            strings: HashMap::new(),
         }
     }
     pub fn dump(&mut self) {
@@ -48,6 +82,41 @@ impl<'i> LLVMEmit<'i> {
     pub fn close_function_pass(&mut self) {
         self.funpass = false;
     }
+    pub fn close_div_check(&mut self) {
+        self.div_check = false;
+    }
+    pub fn open_overflow_check(&mut self) {
+        self.overflow_check = true;
+    }
+    pub fn open_bounds_check(&mut self) {
+        self.bounds_check = true;
+    }
+    /// Emit debug information of `file` with its source `src`.
+    ///   Locations are attached after all functions generated,
+    ///   so function passes are not run to keep the instructions.
+    pub fn open_debug_info(&mut self, file: &str, src: &str) {
+        self.debug = Some(DebugInfo::new(file, src));
+        self.funpass = false;
+    }
+    /// Attach debug information to the module, after all functions generated
+    pub fn finalize_debug_info(&mut self) -> Result<(), String> {
+        match self.debug.take() {
+            Some(dbg) => dbg.finalize(&self.generator.module, &self.generator.context),
+            None => Ok(()),
+        }
+    }
+    /// Lay out `data` definitions before generating functions using them
+    pub fn define_types<'a, I>(&mut self, defs: I)
+        where I: IntoIterator<Item = &'a TypeDef>
+    {
+        self.generator.define_types(defs)
+    }
+    /// Declare a global function without body,
+    ///   so it could be called before defined
+    pub fn declare_top_level(&mut self, def: &FunDef) -> LLVMFunction {
+        let def_name = self.interner.trace(def.name()).to_owned();
+        self.generator.get_or_add_function(&def_name, def.ref_type())
+    }
     pub fn gen_top_level(&mut self, def: &FunDef, prelude: &VarEnv) {
         // A global function definition
         let fun_type = def.ref_type();
@@ -58,7 +127,8 @@ impl<'i> LLVMEmit<'i> {
         };
         let def_name = self.interner.trace(def.name()).to_owned();
         let fun = self.generator.get_or_add_function(&def_name, fun_type);
-        let arg_count = fun.count_params();
+        let args = fun.params();
+        let arg_count = args.len();
 
         // Check redefinition
 
@@ -68,36 +138,23 @@ impl<'i> LLVMEmit<'i> {
         let block = self.context().append_basic_block(&fun, "entry");
 
         self.builder().set_position_at_end(&block);
+        if let Some(ref mut dbg) = self.debug {
+            dbg.enter_function(&fun, &def_name, def.body().pos());
+        }
+        self.in_tail = true;
+        self.frame_escaped = false;
 
         // Create a sub environment for current function generating
         let mut symtbl = prelude.sub_env();
 
+        // Each parameter is an argument, followed by free variables
         let param_defs = def.parameters();
-        let mut param_count = param_defs.len();
+        let param_count = param_defs.len();
         let param_allocas = self.alloca_for_vars(&fun, param_defs);
 
-        if param_allocas.len() + 1 < arg_count {
-            let last_formal = param_defs.last();
-            if let Some(p) = last_formal {
-                let &VarDecl(ref id, ref ty) = p;
-                param_count = param_count - 1;
-                // Actual parameters counts more than formal, last formal parameter type is production
-                let flatten_count = ty.body().prod_to_vec().len();
-                let last_alloca = self.alloca_for_var(&fun, p);
-                for i in 0..flatten_count {
-                    let idx_name = i.to_string();
-                    let arg = fun.get_param(i + param_count);
-                    let arg_name = self.trace_id(id.to_owned()).to_owned() + idx_name.as_str();
-                    arg.set_name(arg_name.as_str());
-                    let field = self.builder().struct_field_ptr(&last_alloca, i, idx_name.as_str());
-                    self.builder().store(&arg, &field);
-                }
-                symtbl.insert(id.to_owned(), last_alloca);
-            }
-        }
         for i in 0..param_count {
             let var = param_allocas[i];
-            let arg = fun.get_param(i);
+            let arg = args[i];
             let pname = param_defs[i].name();
             arg.set_name(self.trace_id(pname));
             self.builder().store(&arg, &var);
@@ -105,7 +162,7 @@ impl<'i> LLVMEmit<'i> {
         }
 
 
-        let p_fvs = fun.get_param(arg_count - 1);
+        let p_fvs = args[arg_count - 1];
         p_fvs.set_name("fvs");
 
         let formal_fvs = def.fv();
@@ -117,9 +174,7 @@ impl<'i> LLVMEmit<'i> {
         for (i, fv) in fv_allocas.into_iter().enumerate() {
             let fv_id = formal_fvs[i].name();
             let fv_name = self.interner.trace(fv_id);
-            let fv_val_ptr = self.builder().struct_field_ptr(&fv_ptr_actual, i, "tmp");
-            let fv_ty_actual = fv.get_type();
-            let fv_val = self.builder().load(&fv_val_ptr, fv_name);
+            let fv_val = self.builder().load_field(&fv_ptr_actual, i, fv_name);
             self.builder().store(&fv_val, &fv);
             symtbl.insert(fv_id, fv);
         }
@@ -127,7 +182,12 @@ impl<'i> LLVMEmit<'i> {
 
         let fun_body = self.gen_expr(def.body(), &mut symtbl);
 
-        self.builder().ret(&fun_body);
+        if void_ret {
+            self.builder().ret_void();
+        } else {
+            self.builder().ret(&fun_body);
+        }
+        self.debug_leave();
 
         if !fun.verify(LLVMVerifierFailureAction::LLVMPrintMessageAction) {
             panic!();
@@ -143,29 +203,462 @@ impl<'i> LLVMEmit<'i> {
         let block = self.context().append_basic_block(&fun, "entry");
         self.builder().set_position_at_end(&block);
 
+        if let Some(ref mut dbg) = self.debug {
+            dbg.enter_function(&fun, "main", def.body().pos());
+        }
+        // Value of body is not returned
+        self.in_tail = false;
+
         let mut symtbl = prelude.sub_env();
         self.gen_expr(def.body(), &mut symtbl);
 
         let zero = self.context().get_int32_const(0);
         self.builder().ret(&zero);
+        self.debug_leave();
 
         
         fun.verify(LLVMVerifierFailureAction::LLVMPrintMessageAction);
         if self.funpass { self.generator.passer.run(&fun); }
     }
+    /// Abort if the divisor is zero
+    fn gen_div_check(&mut self, divisor: &LLVMValue) {
+        let zero = divisor.get_type().const_int(0, false);
+        let is_zero = self.builder().build_icmp(LLVMIntPredicate::LLVMIntEQ, divisor, &zero, "div.zero");
+        self.gen_trap_if(&is_zero, "div");
+    }
+
+    /// Abort if the index is not less than the length of list.
+    ///   Compared unsigned so a negative index is out of range as well.
+    fn gen_bounds_check(&mut self, list: &LLVMValue, index: &LLVMValue) {
+        let len = self.builder().load_field(list, 0, "list.len");
+        let out = self.builder().build_icmp(LLVMIntPredicate::LLVMIntUGE, index, &len, "idx.out");
+        self.gen_trap_if(&out, "idx");
+    }
+
+    /// Add, subtract or multiply integers by overflow intrinsics,
+    ///   abort if the result overflows
+    fn gen_checked_arith(&mut self, op: BinOp, lhs: LLVMValue, rhs: LLVMValue) -> LLVMValue {
+        let intrinsic = self.generator.get_overflow_intrinsic(op, &lhs.get_type());
+        let pair = self.builder().call(&intrinsic, &mut vec![lhs, rhs], "ovf.pair");
+        let res = self.builder().build_extract_value(&pair, 0, "ovf.res");
+        let overflow = self.builder().build_extract_value(&pair, 1, "ovf.bit");
+        self.gen_trap_if(&overflow, "ovf");
+        res
+    }
+
+    /// Branch to abort if the condition holds,
+    ///   continue in a new block otherwise
+    fn gen_trap_if(&mut self, cond: &LLVMValue, prefix: &str) {
+        let parent = self.builder().get_insert_block().get_parent();
+        let trap_blk = self.context().append_basic_block(&parent, &format!("{}.trap", prefix));
+        let ok_blk = self.context().append_basic_block(&parent, &format!("{}.ok", prefix));
+        self.builder().cond_br(cond, &trap_blk, &ok_blk);
+
+        self.position_at_end(&trap_blk);
+        let abort = self.generator.runtime().abort();
+        self.builder().call(&abort, &mut vec![], "");
+        self.builder().build_unreachable();
+
+        self.position_at_end(&ok_blk);
+    }
+
+    /// Lower a conditional into then, else and merge blocks.
+    ///   Arms are generated by `then_arm` and `else_arm` with `state`,
+    ///   values of arms reaching the merge block are joined by `phi`.
+    ///   An arm already ended by a terminator, like `unreachable` after abort,
+    ///   diverges and has no incoming edge.
+    pub fn emit_if<S, T, F>(&mut self, cond: &LLVMValue, state: &mut S, then_arm: T, else_arm: F) -> LLVMValue
+        where T: FnOnce(&mut Self, &mut S) -> LLVMValue,
+              F: FnOnce(&mut Self, &mut S) -> LLVMValue
+    {
+        let parent = self.builder().get_insert_block().get_parent();
+        let then_blk = self.context().append_basic_block(&parent, "if.then");
+        let else_blk = self.context().append_basic_block(&parent, "if.else");
+        let cont_blk = self.context().append_basic_block(&parent, "if.cont");
+        self.builder().cond_br(cond, &then_blk, &else_blk);
+
+        self.position_at_end(&then_blk);
+        let then = then_arm(self, state);
+        let then_end = self.close_arm(&cont_blk);
+
+        self.position_at_end(&else_blk);
+        let els = else_arm(self, state);
+        let els_end = self.close_arm(&cont_blk);
+
+        self.position_at_end(&cont_blk);
+        let incoming: Vec<(LLVMValue, LLVMBasicBlock)> = [(then, then_end), (els, els_end)]
+            .iter()
+            .filter_map(|&(v, end)| end.map(|b| (v, b)))
+            .collect();
+        // Nothing to join if both diverge, or of `void`
+        let ty = then.get_type();
+        if incoming.is_empty() || ty.raw_ptr() == self.context().get_void_type().raw_ptr() {
+            return ty.get_undef();
+        }
+        let edges: Vec<_> = incoming.iter().map(|&(ref v, ref b)| (v, b)).collect();
+        self.builder().phi_node(&ty, &edges, "if.res")
+    }
+
+    /// Branch to `cont` at the end of an arm unless it diverges,
+    ///   returning the block branching from
+    fn close_arm(&mut self, cont: &LLVMBasicBlock) -> Option<LLVMBasicBlock> {
+        let end = self.builder().get_insert_block();
+        if self.builder().ensure_terminated(cont) {
+            Some(end)
+        } else {
+            None
+        }
+    }
+
+    /// Compare values of a type without a comparison instruction,
+    ///   strings by `strcmp`, tuples, lists and `data` by a helper comparing elements.
+    fn gen_equal(&mut self, lhs: &LLVMValue, rhs: &LLVMValue, ty: &Type) -> LLVMValue {
+        if get_cmp_predicate(BinOp::Eq, ty).is_some() {
+            return self.generator.bin_operator(BinOp::Eq, *lhs, *rhs, ty);
+        }
+        match *ty {
+            Type::Con(ref n) if n == "String" => {
+                let strcmp = self.generator.runtime().strcmp();
+                let ord = self.builder().call(&strcmp, &mut vec![*lhs, *rhs], "str.cmp");
+                let zero = self.context().get_int32_const(0);
+                self.builder().build_icmp(LLVMIntPredicate::LLVMIntEQ, &ord, &zero, "str.eq")
+            }
+            Type::Prod(..) => {
+                let helper = self.get_equal_helper(ty);
+                self.builder().call(&helper, &mut vec![*lhs, *rhs], "tuple.eq")
+            }
+            Type::Comp(box Type::Con(ref n), _) if n == "List" => {
+                let helper = self.get_equal_helper(ty);
+                self.builder().call(&helper, &mut vec![*lhs, *rhs], "list.eq")
+            }
+            Type::Con(_) | Type::Comp(..) => {
+                let helper = self.get_equal_helper(ty);
+                self.builder().call(&helper, &mut vec![*lhs, *rhs], "data.eq")
+            }
+            _ => panic!("Cannot compare values of type {}", ty),
+        }
+    }
+
+    /// Get the function `eq.T` comparing values of type `T` by their elements,
+    ///   it is generated when first requested
+    fn get_equal_helper(&mut self, ty: &Type) -> LLVMFunction {
+        let name = format!("eq.{}", ty);
+        if let Some(f) = self.module().get_function(&name) {
+            return f;
+        }
+        let val_ty = self.generator.get_llvm_type(ty);
+        let bool_ty = self.context().get_int1_type();
+        let fun_ty = LLVMContext::get_function_type(&bool_ty, &vec![val_ty, val_ty], false);
+        // Added before generated, so a recursive type compares by itself
+        let fun = self.module().add_function(&name, &fun_ty);
+
+        // Generate the helper aside, then go back to where it is called
+        let caller = self.builder().get_insert_block();
+        let entry = self.context().append_basic_block(&fun, "entry");
+        self.position_at_end(&entry);
+
+        let (lhs, rhs) = (fun.get_param(0), fun.get_param(1));
+        match *ty {
+            Type::Prod(ref elems) => {
+                let mut res = self.context().bool_const(true);
+                for (i, elem_ty) in elems.iter().enumerate() {
+                    let l = self.builder().build_extract_value(&lhs, i as u32, "l");
+                    let r = self.builder().build_extract_value(&rhs, i as u32, "r");
+                    let eq = self.gen_equal(&l, &r, elem_ty);
+                    res = self.builder().build_and(&res, &eq, "eq");
+                }
+                self.builder().ret(&res);
+            }
+            Type::Comp(box Type::Con(ref n), box ref item) if n == "List" => {
+                self.gen_list_equal(&fun, &lhs, &rhs, item);
+            }
+            _ => self.gen_data_equal(&fun, &lhs, &rhs, ty),
+        }
+
+        self.position_at_end(&caller);
+        fun
+    }
+
+    /// Compare lists by lengths, then elements in order
+    fn gen_list_equal(&mut self, fun: &LLVMFunction, lhs: &LLVMValue, rhs: &LLVMValue, item: &Type) {
+        let cond_blk = self.context().append_basic_block(fun, "eq.cond");
+        let body_blk = self.context().append_basic_block(fun, "eq.body");
+        let same_blk = self.context().append_basic_block(fun, "eq.same");
+        let differ_blk = self.context().append_basic_block(fun, "eq.differ");
+
+        let int = self.context().get_int32_type();
+        let index_ptr = self.builder().alloca(&int, "eq.idx");
+        self.builder().store(&self.context().get_int32_const(0), &index_ptr);
+        let len = self.builder().load_field(lhs, 0, "l.len");
+        let rlen = self.builder().load_field(rhs, 0, "r.len");
+        let same_len = self.builder().build_icmp(LLVMIntPredicate::LLVMIntEQ, &len, &rlen, "eq.len");
+        self.builder().cond_br(&same_len, &cond_blk, &differ_blk);
+
+        self.position_at_end(&cond_blk);
+        let index = self.builder().load(&index_ptr, "idx");
+        let more = self.builder().build_icmp(LLVMIntPredicate::LLVMIntSLT, &index, &len, "eq.more");
+        self.builder().cond_br(&more, &body_blk, &same_blk);
+
+        self.position_at_end(&body_blk);
+        let zero = self.context().get_int32_const(0);
+        let items = self.context().get_int32_const(1);
+        let l_ptr = self.builder().build_in_bounds_gep(lhs, &[zero, items, index], "l.ptr");
+        let r_ptr = self.builder().build_in_bounds_gep(rhs, &[zero, items, index], "r.ptr");
+        let l = self.builder().load(&l_ptr, "l");
+        let r = self.builder().load(&r_ptr, "r");
+        let eq = self.gen_equal(&l, &r, item);
+        let next = self.builder().build_add(&index, &self.context().get_int32_const(1), "idx.next");
+        self.builder().store(&next, &index_ptr);
+        self.builder().cond_br(&eq, &cond_blk, &differ_blk);
+
+        self.position_at_end(&same_blk);
+        self.builder().ret(&self.context().bool_const(true));
+        self.position_at_end(&differ_blk);
+        self.builder().ret(&self.context().bool_const(false));
+    }
+
+    /// Compare `data` values by tags, then fields of the variant
+    fn gen_data_equal(&mut self, fun: &LLVMFunction, lhs: &LLVMValue, rhs: &LLVMValue, ty: &Type) {
+        let count = self.generator.get_variants(data_name(ty)).len();
+        let differ_blk = self.context().append_basic_block(fun, "eq.differ");
+        let variant_blks: Vec<_> = if count == 1 {
+            vec![self.builder().get_insert_block()]
+        } else {
+            let blks: Vec<_> = (0..count)
+                .map(|_| self.context().append_basic_block(fun, "eq.variant"))
+                .collect();
+            let tag = self.builder().load_field(lhs, 0, "l.tag");
+            let rtag = self.builder().load_field(rhs, 0, "r.tag");
+            let same_tag = self.builder().build_icmp(LLVMIntPredicate::LLVMIntEQ, &tag, &rtag, "eq.tag");
+            let same_blk = self.context().append_basic_block(fun, "eq.same");
+            self.builder().cond_br(&same_tag, &same_blk, &differ_blk);
+            self.position_at_end(&same_blk);
+            let switch = self.builder().build_switch(&tag, &differ_blk, count as u32);
+            for (i, blk) in blks.iter().enumerate() {
+                self.builder().add_case(&switch, &self.context().get_int32_const(i as i32), blk);
+            }
+            blks
+        };
+
+        for (i, blk) in variant_blks.iter().enumerate() {
+            self.position_at_end(blk);
+            let field_tys = self.generator.get_field_types(ty, i);
+            let mut res = self.context().bool_const(true);
+            if !field_tys.is_empty() {
+                let l_fields = self.get_payload(lhs, ty, i);
+                let r_fields = self.get_payload(rhs, ty, i);
+                for (j, field_ty) in field_tys.iter().enumerate() {
+                    let val_ty = self.generator.get_value_type(field_ty);
+                    let l = self.builder().load_field(&l_fields, j, "l");
+                    let l = self.unbox_field(&l, &val_ty);
+                    let r = self.builder().load_field(&r_fields, j, "r");
+                    let r = self.unbox_field(&r, &val_ty);
+                    let eq = self.gen_equal(&l, &r, field_ty);
+                    res = self.builder().build_and(&res, &eq, "eq");
+                }
+            }
+            self.builder().ret(&res);
+        }
+
+        self.position_at_end(&differ_blk);
+        self.builder().ret(&self.context().bool_const(false));
+    }
+
+    /// Print a value by `printf` with format of its type,
+    ///   booleans are printed as `true` or `false`, characters in UTF-8
+    fn gen_print(&mut self, val: &LLVMValue, ty: &Type, newline: bool) -> LLVMValue {
+        let spec = match *ty {
+            Type::Con(ref n) => {
+                match n.as_str() {
+                    "Int" => "%d",
+                    "Long" => "%ld",
+                    "Float" => "%g",
+                    "Char" | "Bool" | "String" => "%s",
+                    _ => unreachable!("printing {} is not checked", ty),
+                }
+            }
+            _ => unreachable!("printing {} is not checked", ty),
+        };
+        let fmt = if newline { format!("{}\n", spec) } else { spec.to_string() };
+        let fmt_ptr = self.global_string(&fmt, "fmt");
+        match *ty {
+            Type::Con(ref n) if n == "Char" => {
+                let print_char = self.get_print_char();
+                self.builder().call(&print_char, &mut vec![*val, fmt_ptr], "");
+            }
+            Type::Con(ref n) if n == "Bool" => {
+                let t = self.global_string("true", "str.true");
+                let f = self.global_string("false", "str.false");
+                let arg = self.builder().build_select(val, &t, &f, "bool.str");
+                let printf = self.generator.runtime().printf();
+                self.builder().call(&printf, &mut vec![fmt_ptr, arg], "");
+            }
+            _ => {
+                let printf = self.generator.runtime().printf();
+                self.builder().call(&printf, &mut vec![fmt_ptr, *val], "");
+            }
+        }
+        self.context().get_void_type().get_undef()
+    }
+
+    /// Get a global string of `s`, created when first requested in the module
+    fn global_string(&mut self, s: &str, name: &str) -> LLVMValue {
+        if let Some(&ptr) = self.strings.get(s) {
+            return ptr;
+        }
+        let ptr = self.builder().build_global_string_ptr(s, name);
+        self.strings.insert(s.to_string(), ptr);
+        ptr
+    }
+
+    /// Get the function `print.char` printing a `Char` encoded in UTF-8 by a format,
+    ///   it is generated when first requested
+    fn get_print_char(&mut self) -> LLVMFunction {
+        let name = "print.char";
+        if let Some(f) = self.module().get_function(name) {
+            return f;
+        }
+        let int = self.context().get_int32_type();
+        let str_ty = self.context().get_int8_type().get_ptr(0);
+        let fun_ty = LLVMContext::get_function_type(&self.context().get_void_type(), &vec![int, str_ty], false);
+        let fun = self.module().add_function(name, &fun_ty);
+
+        // Generate the helper aside, then go back to where it is called
+        let caller = self.builder().get_insert_block();
+        let entry = self.context().append_basic_block(&fun, "entry");
+        self.position_at_end(&entry);
+
+        let (c, fmt) = (fun.get_param(0), fun.get_param(1));
+        let buf = self.builder().alloca(&self.context().get_int8_type().array(5), "utf8");
+        let done = self.context().append_basic_block(&fun, "utf8.done");
+        // A code point below a bound is encoded in that many bytes
+        for &(len, bound) in [(1, 0x80), (2, 0x800), (3, 0x10000)].iter() {
+            let store_blk = self.context().append_basic_block(&fun, "utf8.store");
+            let next_blk = self.context().append_basic_block(&fun, "utf8.next");
+            let fits = self.builder().build_icmp(LLVMIntPredicate::LLVMIntULT, &c,
+                                                 &self.context().get_int32_const(bound), "utf8.fits");
+            self.builder().cond_br(&fits, &store_blk, &next_blk);
+            self.position_at_end(&store_blk);
+            self.store_utf8(&buf, &c, len);
+            self.builder().br(&done);
+            self.position_at_end(&next_blk);
+        }
+        self.store_utf8(&buf, &c, 4);
+        self.builder().br(&done);
+
+        self.position_at_end(&done);
+        let zero = self.context().get_int32_const(0);
+        let bytes = self.builder().build_in_bounds_gep(&buf, &[zero, zero], "utf8.str");
+        let printf = self.generator.runtime().printf();
+        self.builder().call(&printf, &mut vec![fmt, bytes], "");
+        self.builder().ret_void();
+
+        self.position_at_end(&caller);
+        fun
+    }
+
+    /// Store `c` encoded in `len` bytes of UTF-8 to `buf`, followed by a NUL.
+    ///   The leading byte marks the length, each following one takes 6 bits.
+    fn store_utf8(&mut self, buf: &LLVMValue, c: &LLVMValue, len: usize) {
+        let lead = [0, 0xC0, 0xE0, 0xF0][len - 1];
+        let byte_ty = self.context().get_int8_type();
+        let zero = self.context().get_int32_const(0);
+        for i in 0..len {
+            let shift = self.context().get_int32_const(6 * (len - 1 - i) as i32);
+            let bits = self.builder().build_lshr(c, &shift, "utf8.bits");
+            let (bits, mark) = if i == 0 {
+                (bits, lead)
+            } else {
+                (self.builder().build_and(&bits, &self.context().get_int32_const(0x3F), "utf8.bits"), 0x80)
+            };
+            let byte = self.builder().build_or(&bits, &self.context().get_int32_const(mark), "utf8.byte");
+            let byte = self.builder().build_trunc(&byte, &byte_ty, "utf8.byte");
+            let ptr = self.builder().build_in_bounds_gep(buf, &[zero, self.context().get_int32_const(i as i32)], "utf8.ptr");
+            self.builder().store(&byte, &ptr);
+        }
+        let end = self.builder().build_in_bounds_gep(buf, &[zero, self.context().get_int32_const(len as i32)], "utf8.end");
+        self.builder().store(&self.context().get_uint8_const(0), &end);
+    }
+
+    /// Build closure `{ i8*, { captured... } }` of function `entry` in stack frame,
+    ///   `captured` are slots of free variables.
+    ///   The closure is stored to `slot` before free variables are loaded,
+    ///   so a recursive closure captures itself.
+    fn gen_closure(&mut self, entry: &LLVMFunction, captured: &[LLVMValue], slot: &LLVMValue) -> LLVMValue {
+        let cls_ty = self.generator.get_closure_type().get_ptr(0);
+        let fv_tys: Vec<_> = captured.iter().map(|v| v.get_type().get_element()).collect();
+
+        // allocate for closure
+        let cls_ty_actual = self.generator.get_actual_cls_type(&fv_tys);
+        let cls_value = self.builder().alloca(&cls_ty_actual, "cls.actual");
+        self.frame_escaped = true;
+        let cls_cast = self.builder().bit_cast(&cls_value, &cls_ty, "cls.cast");
+        self.builder().store(&cls_cast, slot);
+
+        // set function entry
+        let cls_fun = self.builder().struct_field_ptr(&cls_value, 0, "cls.fn");
+        let fn_ent_ptr = {
+            let ptr_ty = self.context().get_int8_type().get_ptr(0);
+            self.builder().bit_cast(&entry.into_value(), &ptr_ty, "fn")
+        };
+        self.builder().store(&fn_ent_ptr, &cls_fun);
+
+        // store free vars
+        let cls_fv = self.builder().struct_field_ptr(&cls_value, 1, "cls.fv");
+        for (i, val_ptr) in captured.iter().enumerate() {
+            // if variable stored a pointer, it will get (type **)
+            // if stored a value, (type *)
+            let val = self.builder().load(val_ptr, "tmp");
+            let fv = self.builder().struct_field_ptr(&cls_fv, i, "tmp");
+            self.builder().store(&val, &fv);
+        }
+        cls_cast
+    }
+
+    /// Closure `{ i8*, {} }` of a global function `name`, a constant global
+    ///   made once and shared by every reference, as it has nothing captured.
+    fn gen_global_closure(&mut self, name: &str, entry: &LLVMFunction) -> LLVMValue {
+        let cls_ty = self.generator.get_closure_type().get_ptr(0);
+        let cls_name = format!("{}.cls", name);
+        let global = match self.module().get_global(&cls_name) {
+            Some(g) => g,
+            None => {
+                let ptr_ty = self.context().get_int8_type().get_ptr(0);
+                let fn_ent_ptr = entry.into_value().const_bit_cast(&ptr_ty);
+                let no_fv = self.context().get_struct_const(&[], false);
+                let cls = self.context().get_struct_const(&[fn_ent_ptr, no_fv], false);
+                let g = self.module().add_global(&cls_name, &cls.get_type());
+                g.set_initializer(&cls);
+                g.set_global_constant(true);
+                g.set_linkage(Linkage::Private);
+                g
+            }
+        };
+        global.const_bit_cast(&cls_ty)
+    }
+
     /// Long bull shit
     fn gen_expr<'a: 'b, 'b>(&mut self,
                             term: &'a TaggedTerm,
                             symbols: &mut VarEnv<'b>)
                             -> LLVMValue {
         use self::Term::*;
+        self.debug_enter(term.pos());
+        // Sub-terms are not in tail position, unless the value of term is theirs
+        let tail = mem::replace(&mut self.in_tail, false);
         let ret = match *term.body() {
             Lit(ref lit) => self.generator.gen_lit(lit),
             Var(vn) => {
-                let var_name = self.interner.trace(vn);
-                match symbols.lookup(&vn) {
-                    Some(v) => self.builder().load(v, var_name),
-                    _ => {
+                let slot = symbols.lookup(&vn).map(|v| *v);
+                match slot {
+                    Some(v) => self.builder().load(&v, self.interner.trace(vn)),
+                    // A global function as a value
+                    None if term.ref_scheme().is_fn() => {
+                        let name = self.interner.trace_string(vn);
+                        let fun = self.generator.get_or_add_function(&name, term.ref_scheme().body());
+                        self.gen_global_closure(&name, &fun)
+                    }
+                    None => {
                         eprintln!("cannot find variable {}", self.interner.trace(vn));
                         unreachable!()
                     }
@@ -175,7 +668,25 @@ impl<'i> LLVMEmit<'i> {
                 let lval = self.gen_expr(lhs, symbols);
                 let rval = self.gen_expr(rhs, symbols);
 
-                self.generator.bin_operator(op, lval, rval, lhs.ref_scheme().body())
+                let operand_ty = lhs.ref_scheme().body();
+                let divide = op == BinOp::Div || op == BinOp::Rem;
+                if divide && self.div_check && is_integral_type(operand_ty) {
+                    self.gen_div_check(&rval);
+                }
+                let arith = op == BinOp::Add || op == BinOp::Sub || op == BinOp::Mul;
+                let equality = op == BinOp::Eq || op == BinOp::Ne;
+                if arith && self.overflow_check && is_signed_int_type(operand_ty) {
+                    self.gen_checked_arith(op, lval, rval)
+                } else if equality && get_cmp_predicate(op, operand_ty).is_none() {
+                    let eq = self.gen_equal(&lval, &rval, operand_ty);
+                    if op == BinOp::Ne {
+                        self.builder().build_not(&eq, "ne")
+                    } else {
+                        eq
+                    }
+                } else {
+                    self.generator.bin_operator(op, lval, rval, operand_ty)
+                }
             }
             Let(ref var_decl, ref val, ref exp) => {
                 let &VarDecl(var, ref tyvar) = var_decl;
@@ -189,7 +700,7 @@ impl<'i> LLVMEmit<'i> {
                 };
                 self.builder().store(&init, &alloca);
 
-
+                self.in_tail = tail;
                 symbols.with_var(var, alloca, |sym| self.gen_expr(exp.deref(), sym))
             }
             ApplyCls(ref callee, ref args) => {
@@ -207,16 +718,23 @@ impl<'i> LLVMEmit<'i> {
                 argsv.push(fvs);
 
                 // get actual function entry
-                let fn_entry_ptr = self.builder().struct_field_ptr(&callee_ptr, 0, "cls.fn");
                 // get a void* pointer
-                let fn_entry = self.builder().load(&fn_entry_ptr, "cls.fn.actual");
+                let fn_entry = self.builder().load_field(&callee_ptr, 0, "cls.fn.actual");
                 // cast to function pointer
                 let callee_ty = self.generator.get_llvm_type(callee.ref_scheme().body()).get_ptr(0);
                 // self.builder().bit_cast(&fn_entry, &callee_ty, "cls.callee")
                 let fun =
                     self.builder().bit_cast(&fn_entry, &callee_ty, "cls.callee").into_function();
 
-                self.builder().call(&fun, &mut argsv, "call")
+                let call = self.builder().call(&fun, &mut argsv, "call");
+                self.mark_tail_call(tail, &call);
+                call
+            }
+            ApplyDir(VarDecl(fun, _), ref args) if is_print(self.interner.trace(fun)) => {
+                let newline = self.interner.trace(fun) == "println";
+                let arg = &args[0];
+                let val = self.gen_expr(arg, symbols);
+                self.gen_print(&val, arg.ref_scheme().body(), newline)
             }
             ApplyDir(VarDecl(fun, ref fun_ty), ref args) => {
                 let empty_fv_ty = self.context().get_int8_type().get_ptr(0);
@@ -225,21 +743,24 @@ impl<'i> LLVMEmit<'i> {
                     args.iter().map(|arg| self.gen_expr(arg, symbols)).collect();
                 argsv.push(empty_fv_ptr);
                 let callee_name = self.interner.trace(fun);
-                let callee = self.generator.get_or_add_function(callee_name, fun_ty.body());
+                // Builtins are implemented by runtime helpers
+                let callee = match self.generator.get_runtime_function(callee_name) {
+                    Some(f) => f,
+                    None => self.generator.get_or_add_function(callee_name, fun_ty.body()),
+                };
 
-                self.builder().call(&callee, &mut argsv, "calldirect")
+                let call = self.builder().call(&callee, &mut argsv, "calldirect");
+                self.mark_tail_call(tail, &call);
+                call
             }
             Block(ref fs) => {
-                let mut it = fs.iter();
-                if let Some(v) = it.next() {
-                    let mut ret = self.gen_expr(v, symbols);
-                    for n in it {
-                        ret = self.gen_expr(n, symbols);
-                    }
-                    ret
-                } else {
-                    panic!("Empty block")
+                // Values except the last one are discarded
+                let mut ret = self.context().get_void_type().get_undef();
+                for (i, n) in fs.iter().enumerate() {
+                    self.in_tail = tail && i + 1 == fs.len();
+                    ret = self.gen_expr(n, symbols);
                 }
+                ret
             }
             MakeCls(ref var_decl, box ref cls, box ref exp) => {
                 let &VarDecl(ref var, ref tyvar) = var_decl;
@@ -250,12 +771,12 @@ impl<'i> LLVMEmit<'i> {
                     self.builder().alloca(&cls_ty, "cls")
                 };
 
-                let fv_tys: Vec<_> = symbols.with_var(*var, cls_ptr, |sym| {
+                let captured: Vec<_> = symbols.with_var(*var, cls_ptr, |sym| {
                     cls.fv()
                         .into_iter()
                         .map(|fv_name| {
                             match sym.lookup(&fv_name) {
-                                Some(v) => v.get_type().get_element(),
+                                Some(v) => *v,
                                 None => {
                                     eprintln!("unexpected free var: {}", self.interner.trace(fv_name));
                                     panic!()
@@ -265,75 +786,343 @@ impl<'i> LLVMEmit<'i> {
                         .collect()
                 });
 
-
-                // make a closure type
-                let cls_ty_actual = self.generator.get_actual_cls_type(&fv_tys);
-                // allocate for closure
-                let cls_value = self.builder().alloca(&cls_ty_actual, "cls.actual");
-                let cls_cast = self.builder().bit_cast(&cls_value, &cls_ty, "cls.cast");
-                self.builder().store(&cls_cast, &cls_ptr);
-
-
-                // set function entry
-                let cls_fun = self.builder().struct_field_ptr(&cls_value, 0, "cls.fn");
                 let fn_ent = {
                     let ent_name = self.interner.trace_string(cls.entry());
-                    self.generator.get_or_add_function(&ent_name, tyvar.body()).into_value()
-                };
-                let fn_ent_ptr = {
-                    let ptr_ty = self.context().get_int8_type().get_ptr(0);
-                    self.builder().bit_cast(&fn_ent, &ptr_ty, "fn")
+                    self.generator.get_or_add_function(&ent_name, tyvar.body())
                 };
-                self.builder().store(&fn_ent_ptr, &cls_fun);
-
-                symbols.with_var(var_decl.name(), cls_ptr, |sym| {
-                    // store free vars
-                    let cls_fv = self.builder().struct_field_ptr(&cls_value, 1, "cls.fv");
-                    for (i, val_id) in cls.fv().into_iter().enumerate() {
-                        // if variable stored a pointer, it will get (type **)
-                        // if stored a value, (type *)
-                        let val_ptr = sym.lookup(&val_id).expect("cant found free var in evironment");
-                        let val = self.builder().load(&val_ptr, "tmp");
-                        let fv = self.builder().struct_field_ptr(&cls_fv, i, "tmp");
-                        self.builder().store(&val, &fv);
-                    }
-                    self.gen_expr(exp, sym)
-                })
+                self.gen_closure(&fn_ent, &captured, &cls_ptr);
 
+                self.in_tail = tail;
+                symbols.with_var(var_decl.name(), cls_ptr, |sym| self.gen_expr(exp, sym))
             }
             If(box ref c, box ref t, box ref f) => {
-                //                unimplemented!()
+                // Condition is a `i1` as booleans and comparisons are
                 let cond = self.gen_expr(c, symbols);
-                let zero = self.context().get_int1_const(0);
-
+                self.emit_if(&cond, symbols, |e, syms| {
+                    e.in_tail = tail;
+                    e.gen_expr(t, syms)
+                }, |e, syms| {
+                    e.in_tail = tail;
+                    e.gen_expr(f, syms)
+                })
+            }
+            While(box ref c, box ref b) => {
                 let blk = self.builder().get_insert_block();
                 let parent = blk.get_parent();
-                let mut then_blk = self.context().append_basic_block(&parent, "if.then");
-                let mut else_blk = self.context().append_basic_block(&parent, "if.else");
-                let mut cont_blk = self.context().append_basic_block(&parent, "if.cont");
+                let cond_blk = self.context().append_basic_block(&parent, "while.cond");
+                let body_blk = self.context().append_basic_block(&parent, "while.body");
+                let exit_blk = self.context().append_basic_block(&parent, "while.exit");
 
-                self.builder().cond_br(&cond, &then_blk, &else_blk);
+                self.builder().br(&cond_blk);
 
-                self.builder().set_position_at_end(&then_blk);
-                let then = self.gen_expr(t, symbols);
-                self.builder().br(&cont_blk);
-                let then_end = self.builder().get_insert_block();
+                self.position_at_end(&cond_blk);
+                let cond = self.gen_expr(c, symbols);
+                self.builder().cond_br(&cond, &body_blk, &exit_blk);
 
-                self.builder().set_position_at_end(&else_blk);
-                let els = self.gen_expr(f, symbols);
-                self.builder().br(&cont_blk);
-                let els_end = self.builder().get_insert_block();
+                self.position_at_end(&body_blk);
+                self.gen_expr(b, symbols);
+                self.builder().br(&cond_blk);
 
-                self.builder().set_position_at_end(&cont_blk);
-                let ret_ty = then.get_type();
-                self.builder().phi_node(&ret_ty, &[(&then, &then_end), (&els, &els_end)], "if.res")
+                self.position_at_end(&exit_blk);
+                self.context().get_void_type().get_undef()
+            }
+            // A list points to its length followed by elements
+            Index(box ref l, box ref i) => {
+                let list = self.gen_expr(l, symbols);
+                let index = self.gen_expr(i, symbols);
+                if self.bounds_check {
+                    self.gen_bounds_check(&list, &index);
+                }
+                let zero = self.context().get_int32_const(0);
+                let items = self.context().get_int32_const(1);
+                let ptr = self.builder().build_in_bounds_gep(&list, &[zero, items, index], "elem.ptr");
+                self.builder().load(&ptr, "elem")
+            }
+            // A tuple is a struct value of its elements
+            Tuple(ref elems) => {
+                let tuple_ty = self.generator.get_llvm_type(term.ref_scheme().body());
+                let mut tuple = tuple_ty.get_undef();
+                for (i, elem) in elems.iter().enumerate() {
+                    let val = self.gen_expr(elem, symbols);
+                    tuple = self.builder().build_insert_value(&tuple, &val, i as u32, "tuple");
+                }
+                tuple
+            }
+            // A list is allocated in heap as its length followed by elements,
+            //   sized for the elements then casted to the list type
+            List(ref elems) => {
+                let item = match *term.ref_scheme().body() {
+                    Type::Comp(_, box ref item) => item.clone(),
+                    ref t => panic!("list of type {}", t),
+                };
+                let int = self.context().get_int32_type();
+                let elem_ty = self.generator.get_llvm_type_or_ptr(&item);
+                let sized = self.context().get_struct_type(&vec![int, elem_ty.array(elems.len() as u32)], false);
+                let mem = self.builder().build_malloc(&sized, "list.mem");
+                let list_ty = self.generator.get_list_type(&item).get_ptr(0);
+                let list = self.builder().bit_cast(&mem, &list_ty, "list");
+
+                let zero = self.context().get_int32_const(0);
+                let items = self.context().get_int32_const(1);
+                let len = self.context().get_int32_const(elems.len() as i32);
+                let len_ptr = self.builder().struct_field_ptr(&list, 0, "list.len");
+                self.builder().store(&len, &len_ptr);
+                for (i, elem) in elems.iter().enumerate() {
+                    let val = self.gen_expr(elem, symbols);
+                    let index = self.context().get_int32_const(i as i32);
+                    let ptr = self.builder().build_in_bounds_gep(&list, &[zero, items, index], "elem.ptr");
+                    self.builder().store(&val, &ptr);
+                }
+                list
+            }
+            Construct(i, ref fields) => {
+                let ty = term.ref_scheme().body();
+                let data = self.gen_data(ty, i);
+                // A unit variant has only the tag
+                if !fields.is_empty() {
+                    let payload = self.get_payload(&data, ty, i);
+                    for (j, field) in fields.iter().enumerate() {
+                        let val = self.gen_expr(field, symbols);
+                        let slot = self.builder().struct_field_ptr(&payload, j, "field.ptr");
+                        let stored = self.box_field(&val, &slot.get_type().get_element());
+                        self.builder().store(&stored, &slot);
+                    }
+                }
+                data
+            }
+            // A tuple is a struct value, a data value points to its fields
+            Field(box ref e, i) => {
+                let val = self.gen_expr(e, symbols);
+                let ty = e.ref_scheme().body();
+                if let Type::Prod(..) = *ty {
+                    self.builder().build_extract_value(&val, i as u32, "elem")
+                } else {
+                    let variant = self.matched_variant(e);
+                    let payload = self.get_payload(&val, ty, variant);
+                    let raw = self.builder().load_field(&payload, i, "field");
+                    let field_ty = self.generator.get_value_type(term.ref_scheme().body());
+                    self.unbox_field(&raw, &field_ty)
+                }
+            }
+            Case(box ref scrut, ref arms, ref default) => self.gen_case(term, scrut, arms, default, tail, symbols),
+            Unary(op, ref e) => {
+                let val = self.gen_expr(e, symbols);
+                self.generator.un_operator(op, val, e.ref_scheme().body())
             }
-            List(_) => unimplemented!(),
-            Unary(_, _) => unimplemented!(),
         };
+        self.debug_leave();
         ret
     }
 
+    /// Allocate a value of `data` type `ty` in heap, tagged with `variant`
+    ///   if the type has more than one.
+    fn gen_data(&mut self, ty: &Type, variant: usize) -> LLVMValue {
+        let name = data_name(ty);
+        let layout = self.generator.get_data_layout(name);
+        let tagged = self.generator.get_variants(name).len() > 1;
+        let data = self.builder().build_malloc(&layout, "data");
+        if tagged {
+            let tag = self.context().get_int32_const(variant as i32);
+            let tag_ptr = self.builder().struct_field_ptr(&data, 0, "data.tag");
+            self.builder().store(&tag, &tag_ptr);
+        }
+        data
+    }
+
+    /// Pointer to fields of `variant` of a `data` value.
+    ///   Fields of the only variant are the layout itself,
+    ///   otherwise the payload after tag is casted to the fields.
+    fn get_payload(&mut self, data: &LLVMValue, ty: &Type, variant: usize) -> LLVMValue {
+        let fields_ty = {
+            let variants = self.generator.get_variants(data_name(ty));
+            if variants.len() == 1 {
+                return *data;
+            }
+            self.generator.get_variant_type(&variants[variant])
+        };
+        let payload = self.builder().struct_field_ptr(data, 1, "data.payload");
+        self.builder().bit_cast(&payload, &fields_ty.get_ptr(0), "data.fields")
+    }
+
+    /// Variant of a value matched by the enclosing `Case`,
+    ///   a field outside of `Case` is of the only variant.
+    fn matched_variant(&self, term: &TaggedTerm) -> usize {
+        if let Term::Var(id) = *term.body() {
+            if let Some(&(_, variant)) = self.matched.iter().rev().find(|&&(v, _)| v == id) {
+                return variant;
+            }
+        }
+        let ty = term.ref_scheme().body();
+        if self.generator.get_variants(data_name(ty)).len() == 1 {
+            0
+        } else {
+            panic!("Field of {} is read out of a case on its variants", ty)
+        }
+    }
+
+    /// Convert a value to be stored in a field of `field_ty`,
+    ///   a value not of it is boxed behind a `i8*`.
+    fn box_field(&mut self, val: &LLVMValue, field_ty: &LLVMType) -> LLVMValue {
+        let val_ty = val.get_type();
+        if val_ty.raw_ptr() == field_ty.raw_ptr() {
+            return *val;
+        }
+        let ptr = if val_ty.is_pointer() {
+            *val
+        } else {
+            let mem = self.builder().build_malloc(&val_ty, "field.box");
+            self.builder().store(val, &mem);
+            mem
+        };
+        self.builder().bit_cast(&ptr, field_ty, "field.cast")
+    }
+
+    /// Get the value of type `val_ty` from a field stored by `box_field`
+    fn unbox_field(&mut self, raw: &LLVMValue, val_ty: &LLVMType) -> LLVMValue {
+        if raw.get_type().raw_ptr() == val_ty.raw_ptr() {
+            *raw
+        } else if val_ty.is_pointer() {
+            self.builder().bit_cast(raw, val_ty, "field.cast")
+        } else {
+            let ptr = self.builder().bit_cast(raw, &val_ty.get_ptr(0), "field.box");
+            self.builder().load(&ptr, "field.unbox")
+        }
+    }
+
+    /// Lower `Case` into a `switch` on the tag of a `data` value or an integral literal,
+    ///   other literals are compared in order.
+    ///   Without default arm, a value matched by no arm aborts.
+    fn gen_case<'a: 'b, 'b>(&mut self,
+                            term: &'a TaggedTerm,
+                            scrut: &'a TaggedTerm,
+                            arms: &'a [(Tag, P<TaggedTerm>)],
+                            default: &'a Option<P<TaggedTerm>>,
+                            tail: bool,
+                            symbols: &mut VarEnv<'b>)
+                            -> LLVMValue {
+        let val = self.gen_expr(scrut, symbols);
+        let scrut_ty = scrut.ref_scheme().body();
+        let parent = self.builder().get_insert_block().get_parent();
+        let arm_blks: Vec<_> = arms.iter()
+            .map(|_| self.context().append_basic_block(&parent, "case.arm"))
+            .collect();
+        let default_blk = self.context().append_basic_block(&parent, "case.default");
+        let cont_blk = self.context().append_basic_block(&parent, "case.cont");
+
+        let by_tag = match arms.first() {
+            Some(&(Tag::Variant(_), _)) => true,
+            _ => false,
+        };
+        if by_tag || is_integral_type(scrut_ty) {
+            let on = if !by_tag {
+                val
+            } else if self.generator.get_variants(data_name(scrut_ty)).len() > 1 {
+                self.builder().load_field(&val, 0, "data.tag")
+            } else {
+                self.context().get_int32_const(0)
+            };
+            let switch = self.builder().build_switch(&on, &default_blk, arms.len() as u32);
+            for (&(ref tag, _), blk) in arms.iter().zip(arm_blks.iter()) {
+                let case = match *tag {
+                    Tag::Variant(i) => self.context().get_int32_const(i as i32),
+                    Tag::Lit(ref lit) => self.generator.gen_lit(lit),
+                };
+                self.builder().add_case(&switch, &case, blk);
+            }
+        } else {
+            for (&(ref tag, _), blk) in arms.iter().zip(arm_blks.iter()) {
+                let lit = match *tag {
+                    Tag::Lit(ref lit) => self.generator.gen_lit(lit),
+                    Tag::Variant(_) => unreachable!(),
+                };
+                let eq = self.gen_equal(&val, &lit, scrut_ty);
+                let next = self.context().append_basic_block(&parent, "case.next");
+                self.builder().cond_br(&eq, blk, &next);
+                self.position_at_end(&next);
+            }
+            self.builder().br(&default_blk);
+        }
+
+        let scrut_id = match *scrut.body() {
+            Term::Var(id) => Some(id),
+            _ => None,
+        };
+        let mut incoming = vec![];
+        for (&(ref tag, ref body), blk) in arms.iter().zip(arm_blks.iter()) {
+            self.position_at_end(blk);
+            let matched = match (scrut_id, tag) {
+                (Some(id), &Tag::Variant(i)) => {
+                    self.matched.push((id, i));
+                    true
+                }
+                _ => false,
+            };
+            self.in_tail = tail;
+            let res = self.gen_expr(body, symbols);
+            if matched {
+                self.matched.pop();
+            }
+            if let Some(end) = self.close_arm(&cont_blk) {
+                incoming.push((res, end));
+            }
+        }
+
+        self.position_at_end(&default_blk);
+        match *default {
+            Some(ref body) => {
+                self.in_tail = tail;
+                let res = self.gen_expr(body, symbols);
+                if let Some(end) = self.close_arm(&cont_blk) {
+                    incoming.push((res, end));
+                }
+            }
+            None => {
+                let abort = self.generator.runtime().abort();
+                self.builder().call(&abort, &mut vec![], "");
+                self.builder().build_unreachable();
+            }
+        }
+
+        self.position_at_end(&cont_blk);
+        let ty = self.generator.get_value_type(term.ref_scheme().body());
+        // Nothing to join if all arms diverge, or of `void`
+        if incoming.is_empty() || ty.raw_ptr() == self.context().get_void_type().raw_ptr() {
+            return ty.get_undef();
+        }
+        let edges: Vec<_> = incoming.iter().map(|&(ref v, ref b)| (v, b)).collect();
+        self.builder().phi_node(&ty, &edges, "case.res")
+    }
+
+    /// Mark a call in tail position as `tail`,
+    ///   unless a closure in stack frame may be referred by it
+    fn mark_tail_call(&self, tail: bool, call: &LLVMValue) {
+        if tail && !self.frame_escaped {
+            call.set_tail_call(true);
+        }
+    }
+
+    /// Start locating instructions of a term
+    fn debug_enter(&mut self, span: Span) {
+        if let Some(ref mut dbg) = self.debug {
+            dbg.enter(&self.generator.builder.get_insert_block(), span);
+        }
+    }
+
+    fn debug_leave(&mut self) {
+        if let Some(ref mut dbg) = self.debug {
+            dbg.leave(&self.generator.builder.get_insert_block());
+        }
+    }
+
+    /// Move the builder to the end of `block`,
+    ///   instructions built in current block are located before leaving it
+    fn position_at_end(&mut self, block: &LLVMBasicBlock) {
+        if let Some(ref mut dbg) = self.debug {
+            dbg.locate_built(&self.generator.builder.get_insert_block());
+        }
+        self.builder().set_position_at_end(block);
+    }
+
     fn builder(&self) -> &LLVMBuilder {
         &self.generator.builder
     }
@@ -368,6 +1157,591 @@ impl<'i> LLVMEmit<'i> {
     }
 }
 
+/// Builtins lowered to `printf`
+fn is_print(name: &str) -> bool {
+    name == "print" || name == "println"
+}
+
 impl EmitProvider for LLVMCodegen {
     fn gen_module<T>(&mut self, module: T) where T: IntoIterator<Item = FunDef> {}
 }
+
+
+/// Name of the definition of a `data` type
+fn data_name(ty: &Type) -> &str {
+    match ty.split_comp() {
+        (&Type::Con(ref n), _) => n,
+        _ => panic!("Type {} is not a data type", ty),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::form::Span;
+    use std::io::Read;
+
+    fn term(ty: &str, node: Term) -> P<TaggedTerm> {
+        P(TaggedTerm::new(Span::new(0, 0), Scheme::con(ty), node))
+    }
+
+    #[test]
+    fn emit_bool_condition() {
+        let mut interner = Interner::new();
+        let main = interner.intern("main");
+        // if (true) 1 else 0
+        let body = Term::If(term("Bool", Term::Lit(Lit::Bool(true))),
+                            term("Int", Term::Lit(Lit::Int(1))),
+                            term("Int", Term::Lit(Lit::Int(0))));
+        let def = FunDef::new(main, Scheme::arrow(vec![], Type::Con("Int".to_string())),
+                              vec![], vec![], *term("Int", body));
+
+        let mut emitter = LLVMEmit::new("test", &mut interner);
+        emitter.close_function_pass();
+        emitter.gen_main(&def, &VarEnv::new());
+        let ir = emitter.generator.module.print_to_string();
+        assert!(ir.contains("br i1 true"), "{}", ir);
+        assert!(ir.contains("phi i32 [ 1"), "{}", ir);
+    }
+
+    fn divide_main(interner: &mut Interner) -> FunDef {
+        let main = interner.intern("main");
+        // 1 / 0
+        let body = Term::Binary(BinOp::Div, term("Int", Term::Lit(Lit::Int(1))), term("Int", Term::Lit(Lit::Int(0))));
+        FunDef::new(main, Scheme::arrow(vec![], Type::Con("Int".to_string())),
+                    vec![], vec![], *term("Int", body))
+    }
+
+    #[test]
+    fn emit_div_check() {
+        let mut interner = Interner::new();
+        let def = divide_main(&mut interner);
+        let ir = {
+            let mut emitter = LLVMEmit::new("test", &mut interner);
+            emitter.close_function_pass();
+            emitter.gen_main(&def, &VarEnv::new());
+            emitter.generator.module.print_to_string()
+        };
+        assert!(ir.contains("div.trap:"), "{}", ir);
+        assert!(ir.contains("call void @abort()"), "{}", ir);
+        assert!(ir.contains("sdiv i32"), "{}", ir);
+
+        let mut emitter = LLVMEmit::new("test", &mut interner);
+        emitter.close_function_pass();
+        emitter.close_div_check();
+        emitter.gen_main(&def, &VarEnv::new());
+        let ir = emitter.generator.module.print_to_string();
+        assert!(!ir.contains("div.trap"), "{}", ir);
+    }
+
+    #[test]
+    fn emit_overflow_check() {
+        let mut interner = Interner::new();
+        let main = interner.intern("main");
+        // 2147483647 + 1
+        let body = Term::Binary(BinOp::Add,
+                                term("Int", Term::Lit(Lit::Int(::std::i32::MAX))),
+                                term("Int", Term::Lit(Lit::Int(1))));
+        let mut def = FunDef::new(main, Scheme::arrow(vec![], Type::Con("Int".to_string())),
+                                  vec![], vec![], *term("Int", body));
+        // Folding leaves the overflow to trap at runtime
+        const_fold(def.body_mut());
+
+        let mut emitter = LLVMEmit::new("test", &mut interner);
+        emitter.close_function_pass();
+        emitter.open_overflow_check();
+        emitter.gen_main(&def, &VarEnv::new());
+        let ir = emitter.generator.module.print_to_string();
+        assert!(ir.contains("call { i32, i1 } @llvm.sadd.with.overflow.i32(i32 2147483647, i32 1)"), "{}", ir);
+        assert!(ir.contains("ovf.trap:"), "{}", ir);
+        assert!(ir.contains("call void @abort()"), "{}", ir);
+    }
+
+    #[test]
+    fn emit_void_return() {
+        let mut interner = Interner::new();
+        let f = interner.intern("f");
+        // def f() = while (false) 0
+        let body = Term::While(term("Bool", Term::Lit(Lit::Bool(false))), term("Int", Term::Lit(Lit::Int(0))));
+        let def = FunDef::new(f, Scheme::arrow(vec![], Type::Void), vec![], vec![],
+                              TaggedTerm::new(Span::new(0, 0), Scheme::Mono(Type::Void), body));
+
+        let mut emitter = LLVMEmit::new("test", &mut interner);
+        emitter.close_function_pass();
+        emitter.gen_top_level(&def, &VarEnv::new());
+        let ir = emitter.generator.module.print_to_string();
+        assert!(ir.contains("define void @f(i8*"), "{}", ir);
+        assert!(ir.contains("ret void"), "{}", ir);
+    }
+
+    #[test]
+    fn emit_bool_by_value() {
+        let mut interner = Interner::new();
+        let (pos, pick, x, b) = (interner.intern("pos"), interner.intern("pick"), interner.intern("x"), interner.intern("b"));
+        let con = |n: &str| Type::Con(n.to_string());
+        // def pos(x: Int) = x > 0
+        let gt = Term::Binary(BinOp::Gt, term("Int", Term::Var(x)), term("Int", Term::Lit(Lit::Int(0))));
+        let pos_def = FunDef::new(pos, Scheme::arrow(vec![con("Int")], con("Bool")),
+                                  vec![VarDecl(x, Scheme::con("Int"))], vec![], *term("Bool", gt));
+        // def pick(b: Bool) = if (b) 1 else 0
+        let cond = Term::If(term("Bool", Term::Var(b)),
+                            term("Int", Term::Lit(Lit::Int(1))),
+                            term("Int", Term::Lit(Lit::Int(0))));
+        let pick_def = FunDef::new(pick, Scheme::arrow(vec![con("Bool")], con("Int")),
+                                   vec![VarDecl(b, Scheme::con("Bool"))], vec![], *term("Int", cond));
+
+        let mut emitter = LLVMEmit::new("test", &mut interner);
+        emitter.close_function_pass();
+        emitter.gen_top_level(&pos_def, &VarEnv::new());
+        emitter.gen_top_level(&pick_def, &VarEnv::new());
+        let ir = emitter.generator.module.print_to_string();
+        assert!(ir.contains("define i1 @pos(i32"), "{}", ir);
+        assert!(ir.contains("define i32 @pick(i1"), "{}", ir);
+        assert_eq!(emitter.module().verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+    }
+
+    fn index_fun(interner: &mut Interner) -> FunDef {
+        let (f, xs) = (interner.intern("f"), interner.intern("xs"));
+        let list = Type::compose(Type::Con("List".to_string()), Type::Con("Int".to_string()));
+        // def f(xs: List Int) = xs[2]
+        let body = Term::Index(P(TaggedTerm::new(Span::new(0, 0), Scheme::Mono(list.clone()), Term::Var(xs))),
+                               term("Int", Term::Lit(Lit::Int(2))));
+        FunDef::new(f, Scheme::arrow(vec![list.clone()], Type::Con("Int".to_string())),
+                    vec![VarDecl(xs, Scheme::Mono(list))], vec![], *term("Int", body))
+    }
+
+    #[test]
+    fn emit_index() {
+        let mut interner = Interner::new();
+        let def = index_fun(&mut interner);
+        let ir = {
+            let mut emitter = LLVMEmit::new("test", &mut interner);
+            emitter.close_function_pass();
+            emitter.gen_top_level(&def, &VarEnv::new());
+            emitter.generator.module.print_to_string()
+        };
+        assert!(ir.contains("getelementptr inbounds { i32, [0 x i32] }"), "{}", ir);
+        assert!(ir.contains("i32 0, i32 1, i32 2"), "{}", ir);
+        assert!(ir.contains("load i32"), "{}", ir);
+        assert!(!ir.contains("idx.trap"), "{}", ir);
+
+        let mut emitter = LLVMEmit::new("test", &mut interner);
+        emitter.close_function_pass();
+        emitter.open_bounds_check();
+        emitter.gen_top_level(&def, &VarEnv::new());
+        let ir = emitter.generator.module.print_to_string();
+        assert!(ir.contains("icmp uge i32 2"), "{}", ir);
+        assert!(ir.contains("idx.trap:"), "{}", ir);
+        assert!(ir.contains("call void @abort()"), "{}", ir);
+    }
+
+    #[test]
+    fn emit_list_literal() {
+        let mut interner = Interner::new();
+        let f = interner.intern("f");
+        let list = Type::compose(Type::Con("List".to_string()), Type::Con("Int".to_string()));
+        // def f() = [1, 2, 3][2]
+        let elems = (1..4).map(|i| term("Int", Term::Lit(Lit::Int(i)))).collect();
+        let lit = P(TaggedTerm::new(Span::new(0, 0), Scheme::Mono(list), Term::List(elems)));
+        let body = Term::Index(lit, term("Int", Term::Lit(Lit::Int(2))));
+        let def = FunDef::new(f, Scheme::arrow(vec![], Type::Con("Int".to_string())),
+                              vec![], vec![], *term("Int", body));
+
+        let mut emitter = LLVMEmit::new("test", &mut interner);
+        emitter.close_function_pass();
+        emitter.gen_top_level(&def, &VarEnv::new());
+        let ir = emitter.generator.module.print_to_string();
+        assert!(ir.contains("call i8* @malloc"), "{}", ir);
+        assert!(ir.contains("bitcast i8*"), "{}", ir);
+        assert!(ir.contains("to { i32, [0 x i32] }*"), "{}", ir);
+        assert!(ir.contains("store i32 3, i32* %list.len"), "{}", ir);
+        for i in 1..4 {
+            assert!(ir.contains(&format!("store i32 {}, i32* %elem.ptr", i)), "{}", ir);
+        }
+        assert!(ir.contains("i32 0, i32 1, i32 2"), "{}", ir);
+        assert!(ir.contains("load i32"), "{}", ir);
+    }
+
+    /// Start `i32 f(i1)` at its entry block
+    fn start_fun(emitter: &mut LLVMEmit) -> LLVMFunction {
+        let (int, flag) = (emitter.context().get_int32_type(), emitter.context().get_int1_type());
+        let fty = LLVMContext::get_function_type(&int, &vec![flag], false);
+        let fun = emitter.module().add_function("f", &fty);
+        let entry = emitter.context().append_basic_block(&fun, "entry");
+        emitter.builder().set_position_at_end(&entry);
+        fun
+    }
+
+    #[test]
+    fn emit_if_diverging_arm() {
+        let mut interner = Interner::new();
+        let mut emitter = LLVMEmit::new("test", &mut interner);
+        // if (c) 1 else abort()
+        let fun = start_fun(&mut emitter);
+        let res = emitter.emit_if(&fun.get_param(0), &mut (), |e, _| e.context().get_int32_const(1), |e, _| {
+            let abort = e.generator.runtime().abort();
+            e.builder().call(&abort, &mut vec![], "");
+            e.builder().build_unreachable();
+            e.context().get_int32_const(0)
+        });
+        emitter.builder().ret(&res);
+        let by_helper = emitter.generator.module.print_to_string();
+
+        let mut interner = Interner::new();
+        let mut emitter = LLVMEmit::new("test", &mut interner);
+        let fun = start_fun(&mut emitter);
+        let then_blk = emitter.context().append_basic_block(&fun, "if.then");
+        let else_blk = emitter.context().append_basic_block(&fun, "if.else");
+        let cont_blk = emitter.context().append_basic_block(&fun, "if.cont");
+        emitter.builder().cond_br(&fun.get_param(0), &then_blk, &else_blk);
+        emitter.builder().set_position_at_end(&then_blk);
+        let one = emitter.context().get_int32_const(1);
+        emitter.builder().br(&cont_blk);
+        emitter.builder().set_position_at_end(&else_blk);
+        let abort = emitter.generator.runtime().abort();
+        emitter.builder().call(&abort, &mut vec![], "");
+        emitter.builder().build_unreachable();
+        emitter.builder().set_position_at_end(&cont_blk);
+        let ty = one.get_type();
+        let res = emitter.builder().phi_node(&ty, &[(&one, &then_blk)], "if.res");
+        emitter.builder().ret(&res);
+        let by_hand = emitter.generator.module.print_to_string();
+
+        assert_eq!(by_helper, by_hand);
+        assert!(by_helper.contains("phi i32 [ 1, %if.then ]"), "{}", by_helper);
+    }
+
+    #[test]
+    fn emit_closure_call() {
+        let mut interner = Interner::new();
+        let (main, g, f) = (interner.intern("main"), interner.intern("g"), interner.intern("f"));
+        let (x, y) = (interner.intern("x"), interner.intern("y"));
+        let int = || Type::Con("Int".to_string());
+        let var = |n: Id| term("Int", Term::Var(n));
+        // def g(x) = x + y, where `y` is captured
+        let lifted = FunDef::new(g, Scheme::arrow(vec![int()], int()),
+                                 vec![VarDecl(x, Scheme::con("Int"))], vec![VarDecl(y, Scheme::con("Int"))],
+                                 *term("Int", Term::Binary(BinOp::Add, var(x), var(y))));
+        // let y = 1 in (f = closure g [y]) in f(2)
+        let fun_ty = Scheme::arrow(vec![int()], int());
+        let call = Term::ApplyCls(P(TaggedTerm::new(Span::new(0, 0), fun_ty.clone(), Term::Var(f))),
+                                  vec![term("Int", Term::Lit(Lit::Int(2)))]);
+        let cls = Term::MakeCls(VarDecl(f, fun_ty), P(Closure::new(g, vec![y])), term("Int", call));
+        let body = Term::Let(VarDecl(y, Scheme::con("Int")), term("Int", Term::Lit(Lit::Int(1))), term("Int", cls));
+        let def = FunDef::new(main, Scheme::arrow(vec![], int()), vec![], vec![], *term("Int", body));
+
+        let mut emitter = LLVMEmit::new("test", &mut interner);
+        emitter.close_function_pass();
+        emitter.gen_top_level(&lifted, &VarEnv::new());
+        emitter.gen_main(&def, &VarEnv::new());
+        let ir = emitter.generator.module.print_to_string();
+        assert!(ir.contains("alloca { i8*, { i32 } }"), "{}", ir);
+        assert!(ir.contains("bitcast (i32 (i32, i8*)* @g to i8*)"), "{}", ir);
+        assert!(ir.contains("call i32 %cls.callee(i32 2"), "{}", ir);
+        assert_eq!(emitter.module().verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+    }
+
+    #[test]
+    fn emit_print() {
+        let mut interner = Interner::new();
+        let (main, println, print) = (interner.intern("main"), interner.intern("println"), interner.intern("print"));
+        let print_ty = |arg: &str| Scheme::arrow(vec![Type::Con(arg.to_string())], Type::Void);
+        let call = |f: Id, ty: &str, lit: Lit| {
+            P(TaggedTerm::new(Span::new(0, 0), Scheme::Mono(Type::Void),
+                              Term::ApplyDir(VarDecl(f, print_ty(ty)), vec![term(ty, Term::Lit(lit))])))
+        };
+        // { println(42), print(true), print(false), println('é') }
+        let body = Term::Block(vec![call(println, "Int", Lit::Int(42)),
+                                    call(print, "Bool", Lit::Bool(true)),
+                                    call(print, "Bool", Lit::Bool(false)),
+                                    call(println, "Char", Lit::Char('é'))]);
+        let def = FunDef::new(main, Scheme::arrow(vec![], Type::Con("Int".to_string())),
+                              vec![], vec![], TaggedTerm::new(Span::new(0, 0), Scheme::Mono(Type::Void), body));
+
+        let mut emitter = LLVMEmit::new("test", &mut interner);
+        emitter.close_function_pass();
+        emitter.gen_main(&def, &VarEnv::new());
+        let ir = emitter.generator.module.print_to_string();
+        assert!(ir.contains("c\"%d\\0A\\00\""), "{}", ir);
+        // Strings are created once in a module
+        assert_eq!(ir.matches("c\"true\\00\"").count(), 1, "{}", ir);
+        assert_eq!(ir.matches("c\"false\\00\"").count(), 1, "{}", ir);
+        assert_eq!(ir.matches("c\"%s\\00\"").count(), 1, "{}", ir);
+        assert!(ir.contains("i32 42)"), "{}", ir);
+        // A character is encoded to UTF-8 before printed
+        assert!(ir.contains("define void @print.char(i32"), "{}", ir);
+        assert!(ir.contains("call void @print.char(i32 233"), "{}", ir);
+        assert!(ir.contains("alloca [5 x i8]"), "{}", ir);
+        assert_eq!(ir.matches("declare i32 @printf(i8*, ...)").count(), 1, "{}", ir);
+        assert_eq!(emitter.module().verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+    }
+
+    #[test]
+    fn emit_computed_callee() {
+        let mut interner = Interner::new();
+        let (main, inc, dec, a) = (interner.intern("main"), interner.intern("inc"), interner.intern("dec"), interner.intern("a"));
+        let fun_ty = || Scheme::arrow(vec![Type::Con("Int".to_string())], Type::Con("Int".to_string()));
+        let fun = |n: Id| P(TaggedTerm::new(Span::new(0, 0), fun_ty(), Term::Var(n)));
+        // (if (true) inc else dec)(2)
+        let callee = TaggedTerm::new(Span::new(0, 0), fun_ty(),
+                                     Term::If(term("Bool", Term::Lit(Lit::Bool(true))), fun(inc), fun(dec)));
+        let body = Term::ApplyCls(P(callee), vec![term("Int", Term::Lit(Lit::Int(2)))]);
+        let def = FunDef::new(main, Scheme::arrow(vec![], Type::Con("Int".to_string())),
+                              vec![], vec![], *term("Int", body));
+        let defs: Vec<_> = vec![(inc, BinOp::Add), (dec, BinOp::Sub)].into_iter().map(|(f, op)| {
+            let body = Term::Binary(op, term("Int", Term::Var(a)), term("Int", Term::Lit(Lit::Int(1))));
+            FunDef::new(f, fun_ty(), vec![VarDecl(a, Scheme::con("Int"))], vec![], *term("Int", body))
+        }).collect();
+
+        let mut emitter = LLVMEmit::new("test", &mut interner);
+        emitter.close_function_pass();
+        for d in defs.iter() {
+            emitter.gen_top_level(d, &VarEnv::new());
+        }
+        emitter.gen_main(&def, &VarEnv::new());
+        let ir = emitter.generator.module.print_to_string();
+        assert!(ir.contains("bitcast (i32 (i32, i8*)* @inc to i8*)"), "{}", ir);
+        assert!(ir.contains("bitcast (i32 (i32, i8*)* @dec to i8*)"), "{}", ir);
+        assert!(ir.contains("call i32 %cls.callee(i32 2"), "{}", ir);
+        // Closures of global functions are constants, neither in stack frame nor heap
+        assert!(ir.contains("@inc.cls = private constant { i8*, {} }"), "{}", ir);
+        assert!(ir.contains("@dec.cls = private constant { i8*, {} }"), "{}", ir);
+        assert!(!ir.contains("call i8* @malloc"), "{}", ir);
+        assert!(!ir.contains("alloca { i8*, {} }"), "{}", ir);
+        // Referred again, the same closure is used
+        let inc_fn = emitter.module().get_function("inc").unwrap();
+        emitter.gen_global_closure("inc", &inc_fn);
+        assert_eq!(emitter.generator.module.print_to_string().matches("@inc.cls =").count(), 1);
+        assert_eq!(emitter.module().verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+    }
+
+    #[test]
+    fn emit_structural_equality() {
+        let mut interner = Interner::new();
+        let (main, same, a, b) = (interner.intern("main"), interner.intern("same"), interner.intern("a"), interner.intern("b"));
+        let con = |n: &str| Type::Con(n.to_string());
+        let tuple = || {
+            let pair = P(TaggedTerm::new(Span::new(0, 0), Scheme::Mono(Type::product(con("Float"), con("Char"))),
+                                         Term::Tuple(vec![term("Float", Term::Lit(Lit::Float(2.5))),
+                                                          term("Char", Term::Lit(Lit::Char('c')))])));
+            let ty = Type::product(pair.ref_scheme().body().clone(), con("Int"));
+            P(TaggedTerm::new(Span::new(0, 0), Scheme::Mono(ty),
+                              Term::Tuple(vec![pair, term("Int", Term::Lit(Lit::Int(1)))])))
+        };
+        // ((2.5, 'c'), 1) == ((2.5, 'c'), 1)
+        let main_def = FunDef::new(main, Scheme::arrow(vec![], con("Bool")), vec![], vec![],
+                                   *term("Bool", Term::Binary(BinOp::Eq, tuple(), tuple())));
+        // def same(a: String, b: String) = if (a == b) 1 else 0
+        let cmp = Term::Binary(BinOp::Eq, term("String", Term::Var(a)), term("String", Term::Var(b)));
+        let body = Term::If(term("Bool", cmp), term("Int", Term::Lit(Lit::Int(1))), term("Int", Term::Lit(Lit::Int(0))));
+        let same_def = FunDef::new(same, Scheme::arrow(vec![con("String"), con("String")], con("Int")),
+                                   vec![VarDecl(a, Scheme::con("String")), VarDecl(b, Scheme::con("String"))],
+                                   vec![], *term("Int", body));
+
+        let mut emitter = LLVMEmit::new("test", &mut interner);
+        emitter.close_function_pass();
+        emitter.gen_top_level(&same_def, &VarEnv::new());
+        emitter.gen_main(&main_def, &VarEnv::new());
+        let ir = emitter.generator.module.print_to_string();
+        assert!(ir.contains("call i32 @strcmp(i8* %"), "{}", ir);
+        assert!(ir.contains("define i1 @\"eq.(Float * Char) * Int\""), "{}", ir);
+        // Nested tuple is compared by its own helper
+        assert!(ir.contains("call i1 @\"eq.Float * Char\""), "{}", ir);
+        assert_eq!(emitter.module().verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+    }
+
+    #[test]
+    fn emit_data_equality() {
+        let mut interner = Interner::new();
+        let (main, same, a, b) = (interner.intern("main"), interner.intern("same"), interner.intern("a"), interner.intern("b"));
+        let con = |n: &str| Type::Con(n.to_string());
+        let field = |ty: Type| Field { pos: Span::point(0), name: None, ty: P(ty) };
+        let variant = |name: &str, fields: Vec<Field>| Variant { pos: Span::point(0), name: name.to_string(), body: VariantBody::Tuple(fields) };
+        // data Shape { Circle(Float), Rect(Int, String) }
+        let shape = TypeDef::new("Shape".to_string(), vec![],
+                                 TypeKind::Algebra(vec![variant("Circle", vec![field(con("Float"))]),
+                                                        variant("Rect", vec![field(con("Int")), field(con("String"))])]));
+        let list = Type::compose(con("List"), con("Shape"));
+        // def same(a: List Shape, b: List Shape) = a == b
+        let cmp = Term::Binary(BinOp::Eq,
+                               P(TaggedTerm::new(Span::new(0, 0), Scheme::Mono(list.clone()), Term::Var(a))),
+                               P(TaggedTerm::new(Span::new(0, 0), Scheme::Mono(list.clone()), Term::Var(b))));
+        let same_def = FunDef::new(same, Scheme::arrow(vec![list.clone(), list.clone()], con("Bool")),
+                                   vec![VarDecl(a, Scheme::Mono(list.clone())), VarDecl(b, Scheme::Mono(list))],
+                                   vec![], *term("Bool", cmp));
+        // Circle(2.5) == Circle(2.5)
+        let circle = || term("Shape", Term::Construct(0, vec![term("Float", Term::Lit(Lit::Float(2.5)))]));
+        let main_def = FunDef::new(main, Scheme::arrow(vec![], con("Bool")), vec![], vec![],
+                                   *term("Bool", Term::Binary(BinOp::Eq, circle(), circle())));
+
+        let mut emitter = LLVMEmit::new("test", &mut interner);
+        emitter.close_function_pass();
+        emitter.define_types(vec![&shape]);
+        emitter.gen_top_level(&same_def, &VarEnv::new());
+        emitter.gen_main(&main_def, &VarEnv::new());
+        let ir = emitter.generator.module.print_to_string();
+        assert!(ir.contains("define i1 @\"eq.List Shape\"({ i32, [0 x %Shape*] }*"), "{}", ir);
+        assert!(ir.contains("define i1 @eq.Shape(%Shape*"), "{}", ir);
+        assert!(ir.contains("call i1 @eq.Shape(%Shape*"), "{}", ir);
+        // Tags are compared before fields
+        assert!(ir.contains("eq.tag = icmp eq i32"), "{}", ir);
+        assert!(ir.contains("fcmp oeq double"), "{}", ir);
+        assert!(ir.contains("call i32 @strcmp(i8* %"), "{}", ir);
+        assert_eq!(emitter.module().verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+    }
+
+    #[test]
+    fn emit_debug_allocas() {
+        let mut interner = Interner::new();
+        let (f, a, x) = (interner.intern("f"), interner.intern("a"), interner.intern("x"));
+        // def f(a: Int) = let x = a + 1 in x * x
+        let var = |n: Id| term("Int", Term::Var(n));
+        let inc = Term::Binary(BinOp::Add, var(a), term("Int", Term::Lit(Lit::Int(1))));
+        let body = Term::Let(VarDecl(x, Scheme::con("Int")), term("Int", inc),
+                             term("Int", Term::Binary(BinOp::Mul, var(x), var(x))));
+        let def = FunDef::new(f, Scheme::arrow(vec![Type::Con("Int".to_string())], Type::Con("Int".to_string())),
+                              vec![VarDecl(a, Scheme::con("Int"))], vec![], *term("Int", body));
+        let emit = |interner: &mut Interner, options: CodegenOptions| {
+            let mut emitter = LLVMEmit::with_options("test", interner, options);
+            emitter.gen_top_level(&def, &VarEnv::new());
+            emitter.generator.module.print_to_string()
+        };
+
+        let debug = emit(&mut interner, CodegenOptions::debug());
+        assert!(debug.contains("%a = alloca i32"), "{}", debug);
+        assert!(debug.contains("%x = alloca i32"), "{}", debug);
+        let optimized = emit(&mut interner, CodegenOptions::default());
+        assert!(!optimized.contains("alloca"), "{}", optimized);
+    }
+
+    #[test]
+    #[cfg(feature = "debuginfo")]
+    fn emit_debug_locations() {
+        let src = "def main() = {\n  println(42)\n}\n";
+        let mut interner = Interner::new();
+        let (main, println) = (interner.intern("main"), interner.intern("println"));
+        let arg = P(TaggedTerm::new(Span::new(25, 27), Scheme::con("Int"), Term::Lit(Lit::Int(42))));
+        let call = Term::ApplyDir(VarDecl(println, Scheme::arrow(vec![Type::Con("Int".to_string())], Type::Void)), vec![arg]);
+        let body = TaggedTerm::new(Span::new(13, 31), Scheme::Mono(Type::Void),
+                                   Term::Block(vec![P(TaggedTerm::new(Span::new(17, 28), Scheme::Mono(Type::Void), call))]));
+        let def = FunDef::new(main, Scheme::arrow(vec![], Type::Con("Int".to_string())), vec![], vec![], body);
+
+        let mut emitter = LLVMEmit::new("test", &mut interner);
+        emitter.close_function_pass();
+        emitter.open_debug_info("test/main.gs", src);
+        emitter.gen_main(&def, &VarEnv::new());
+        assert_eq!(emitter.finalize_debug_info(), Ok(()));
+        let ir = emitter.generator.module.print_to_string();
+        assert!(ir.contains("call i32 (i8*, ...) @printf(") && ir.contains("), !dbg !"), "{}", ir);
+        assert!(ir.contains("!DILocation(line: 2, column: 3"), "{}", ir);
+        assert!(ir.contains("!DISubprogram(name: \"main\""), "{}", ir);
+        assert!(ir.contains("!DIFile(filename: \"main.gs\", directory: \"test\")"), "{}", ir);
+        // Subprogram is attached to the function
+        let define = ir.lines().find(|l| l.starts_with("define i32 @main")).unwrap();
+        assert!(define.contains("!dbg !"), "{}", ir);
+        assert_eq!(emitter.module().verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+
+        // Line table is in the object
+        let path = ::std::env::temp_dir().join("miko_debug_locations.o");
+        assert_eq!(emit_to_file(emitter.module(), &path, ObjectOrAsm::Object), Ok(()));
+        let mut obj = vec![];
+        ::std::fs::File::open(&path).unwrap().read_to_end(&mut obj).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+        let has = |s: &str| obj.windows(s.len()).any(|w| w == s.as_bytes());
+        assert!(has("debug_line"));
+        assert!(has("main.gs"));
+    }
+
+    #[test]
+    fn emit_tail_call() {
+        let mut interner = Interner::new();
+        let (count, n, acc) = (interner.intern("count"), interner.intern("n"), interner.intern("acc"));
+        let int = || Type::Con("Int".to_string());
+        let count_ty = Scheme::arrow(vec![int(), int()], int());
+        let var = |v: Id| term("Int", Term::Var(v));
+        let lit = |i: i32| term("Int", Term::Lit(Lit::Int(i)));
+        // def count(n, acc) = if (n == 0) acc else count(n - 1, acc + 1)
+        let recur = Term::ApplyDir(VarDecl(count, count_ty.clone()),
+                                   vec![term("Int", Term::Binary(BinOp::Sub, var(n), lit(1))),
+                                        term("Int", Term::Binary(BinOp::Add, var(acc), lit(1)))]);
+        let body = Term::If(term("Bool", Term::Binary(BinOp::Eq, var(n), lit(0))), var(acc), term("Int", recur));
+        let def = FunDef::new(count, count_ty, vec![VarDecl(n, Scheme::con("Int")), VarDecl(acc, Scheme::con("Int"))],
+                              vec![], *term("Int", body));
+
+        let mut emitter = LLVMEmit::new("test", &mut interner);
+        emitter.close_function_pass();
+        emitter.declare_top_level(&def);
+        emitter.gen_top_level(&def, &VarEnv::new());
+        let ir = emitter.generator.module.print_to_string();
+        assert!(ir.contains("tail call i32 @count(i32"), "{}", ir);
+        assert_eq!(emitter.module().verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+    }
+
+    #[test]
+    fn emit_tail_position() {
+        let mut interner = Interner::new();
+        let (h, f, g, c) = (interner.intern("h"), interner.intern("f"), interner.intern("g"), interner.intern("c"));
+        let int = || Type::Con("Int".to_string());
+        let fun = |n: Id| VarDecl(n, Scheme::arrow(vec![int()], int()));
+        let call = |n: Id, arg: P<TaggedTerm>| term("Int", Term::ApplyDir(fun(n), vec![arg]));
+        let lit = |i: i32| term("Int", Term::Lit(Lit::Int(i)));
+        // def h(c: Bool) = if (c) f(1) else { f(2), g(f(3)) }
+        let body = Term::If(term("Bool", Term::Var(c)), call(f, lit(1)),
+                            term("Int", Term::Block(vec![call(f, lit(2)), call(g, call(f, lit(3)))])));
+        let def = FunDef::new(h, Scheme::arrow(vec![Type::Con("Bool".to_string())], int()),
+                              vec![VarDecl(c, Scheme::con("Bool"))], vec![], *term("Int", body));
+
+        let mut emitter = LLVMEmit::new("test", &mut interner);
+        emitter.close_function_pass();
+        emitter.declare_top_level(&def);
+        emitter.gen_top_level(&def, &VarEnv::new());
+        let ir = emitter.generator.module.print_to_string();
+        // Neither a discarded value nor an argument is in tail position
+        assert!(ir.contains("tail call i32 @f(i32 1"), "{}", ir);
+        assert!(ir.contains("tail call i32 @g(i32"), "{}", ir);
+        assert_eq!(ir.matches("tail call").count(), 2, "{}", ir);
+        assert_eq!(emitter.module().verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+    }
+
+    #[test]
+    fn emit_data_case() {
+        let mut interner = Interner::new();
+        let (f, s, w, h) = (interner.intern("f"), interner.intern("s"), interner.intern("w"), interner.intern("h"));
+        let field = |ty: &str| Field { pos: Span::point(0), name: None, ty: P(Type::Con(ty.to_string())) };
+        let variant = |name: &str, fields: Vec<Field>| Variant { pos: Span::point(0), name: name.to_string(), body: VariantBody::Tuple(fields) };
+        // data Shape { Circle(Float), Rect(Int, Int) }
+        let shape = TypeDef::new("Shape".to_string(), vec![],
+                                 TypeKind::Algebra(vec![variant("Circle", vec![field("Float")]),
+                                                        variant("Rect", vec![field("Int"), field("Int")])]));
+        let var = |id| term("Shape", Term::Var(id));
+        let int_var = |id| term("Int", Term::Var(id));
+        // def f() = let s = Rect(2, 3) in match s { Circle(_) => 0, Rect(w, h) => w * h }
+        let area = Term::Let(VarDecl(w, Scheme::con("Int")), term("Int", Term::Field(var(s), 0)),
+                             term("Int", Term::Let(VarDecl(h, Scheme::con("Int")), term("Int", Term::Field(var(s), 1)),
+                                                   term("Int", Term::Binary(BinOp::Mul, int_var(w), int_var(h))))));
+        let case = Term::Case(var(s),
+                              vec![(Tag::Variant(0), term("Int", Term::Lit(Lit::Int(0)))),
+                                   (Tag::Variant(1), term("Int", area))],
+                              None);
+        let rect = term("Shape", Term::Construct(1, vec![term("Int", Term::Lit(Lit::Int(2))),
+                                                          term("Int", Term::Lit(Lit::Int(3)))]));
+        let body = Term::Let(VarDecl(s, Scheme::con("Shape")), rect, term("Int", case));
+        let def = FunDef::new(f, Scheme::arrow(vec![], Type::Con("Int".to_string())),
+                              vec![], vec![], *term("Int", body));
+
+        let mut emitter = LLVMEmit::new("test", &mut interner);
+        emitter.close_function_pass();
+        emitter.define_types(vec![&shape]);
+        emitter.gen_top_level(&def, &VarEnv::new());
+        let ir = emitter.generator.module.print_to_string();
+        assert!(ir.contains("%Shape = type { i32, [1 x i64] }"), "{}", ir);
+        assert!(ir.contains("alloca %Shape*"), "{}", ir);
+        assert!(ir.contains("store i32 1, i32* %data.tag"), "{}", ir);
+        assert!(ir.contains("to { i32, i32 }*"), "{}", ir);
+        assert!(ir.contains("switch i32 %data.tag"), "{}", ir);
+        assert!(ir.contains("call void @abort()"), "{}", ir);
+        assert!(ir.contains("phi i32"), "{}", ir);
+        assert_eq!(emitter.module().verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+    }
+}
@@ -12,16 +12,68 @@ use internal::*;
 use types::*;
 use utils::*;
 
+use super::runtime::Runtime;
+
 pub use libllvm::*;
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct LLVMCodegen {
-    pub module: LLVMModule,
+    // Fields are dropped in order, pass manager and builder before the module
+    pub passer: LLVMFunctionPassManager,
     pub builder: LLVMBuilder,
+    pub module: LLVMModule,
     pub context: LLVMContext,
-    pub passer: LLVMFunctionPassManager,
     unique: usize,
+    /// Layouts of `data` definitions, with the definitions
+    types: HashMap<Name, (LLVMType, TypeDef)>,
+}
+
+/// How a module is lowered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodegenOptions {
+    /// Run function passes on generated functions
+    pub optimize: bool,
+    /// Keep a `alloca` for each variable and blocks as lowered,
+    ///   so the IR maps back to the source
+    pub emit_debug_allocas: bool,
+}
+
+impl CodegenOptions {
+    /// Lowering for debugging, nothing is optimized away
+    pub fn debug() -> CodegenOptions {
+        CodegenOptions {
+            optimize: false,
+            emit_debug_allocas: true,
+        }
+    }
+
+    /// Passes run on each function
+    pub fn passes(&self) -> Vec<PassKind> {
+        use self::PassKind::*;
+        if !self.optimize {
+            return vec![];
+        }
+        let mut passes = OptLevel::O2.passes();
+        if self.emit_debug_allocas {
+            // Passes promoting or merging memory accesses, or folding blocks
+            passes.retain(|p| match *p {
+                PromoteMemoryToRegister | CFGSimplification | InstructionCombining |
+                GVN | MergedLoadStoreMotion => false,
+                _ => true,
+            });
+        }
+        passes
+    }
+}
+
+impl Default for CodegenOptions {
+    fn default() -> CodegenOptions {
+        CodegenOptions {
+            optimize: true,
+            emit_debug_allocas: false,
+        }
+    }
 }
 
 type LLVMOpBuilder<'a> = Fn(LLVMBuilderRef,
@@ -29,46 +81,124 @@ type LLVMOpBuilder<'a> = Fn(LLVMBuilderRef,
                             LLVMValueRef,
                             *const ::libc::c_char)
                             -> LLVMValueRef + 'a;
+
+/// Predicate of a comparison instruction,
+///   `icmp` for integral operands and `fcmp` for floating-point ones.
+#[derive(Debug, Clone, Copy)]
+pub enum CmpPredicate {
+    Int(LLVMIntPredicate),
+    Real(LLVMRealPredicate),
+}
+
+/// Get the comparison predicate of a binary operator on given operand type.
+///   Integers are compared signed, floats ordered.
+///   Returns `None` if `op` is not a comparison or the type is not comparable.
+pub fn get_cmp_predicate(op: BinOp, operand_ty: &Type) -> Option<CmpPredicate> {
+    use self::BinOp::*;
+    use self::LLVMIntPredicate::*;
+    use self::LLVMRealPredicate::*;
+    let ty_name = match operand_ty {
+        &Type::Con(ref n) => n.as_str(),
+        _ => return None,
+    };
+    match ty_name {
+        "Int" | "Long" | "Char" | "Bool" => {
+            let pred = match op {
+                Eq => LLVMIntEQ,
+                Ne => LLVMIntNE,
+                Lt => LLVMIntSLT,
+                Le => LLVMIntSLE,
+                Gt => LLVMIntSGT,
+                Ge => LLVMIntSGE,
+                _ => return None,
+            };
+            Some(CmpPredicate::Int(pred))
+        }
+        "Float" => {
+            let pred = match op {
+                Eq => LLVMRealOEQ,
+                Ne => LLVMRealONE,
+                Lt => LLVMRealOLT,
+                Le => LLVMRealOLE,
+                Gt => LLVMRealOGT,
+                Ge => LLVMRealOGE,
+                _ => return None,
+            };
+            Some(CmpPredicate::Real(pred))
+        }
+        _ => None,
+    }
+}
+
 pub fn get_llvm_op<'a>(op: BinOp, operand_ty: &'a Type) -> Box<LLVMOpBuilder<'a>> {
     use self::BinOp::*;
     use self::Type::*;
-    box move |builder, lhs, rhs, dest|
+    box move |builder, lhs, rhs, dest| unsafe {
+        match get_cmp_predicate(op, operand_ty) {
+            Some(CmpPredicate::Int(pred)) => return LLVMBuildICmp(builder, pred, lhs, rhs, dest),
+            Some(CmpPredicate::Real(pred)) => return LLVMBuildFCmp(builder, pred, lhs, rhs, dest),
+            None => {}
+        }
         if let &Con(ref ty_name) = operand_ty {
             let name_ref = ty_name.as_str();
-            unsafe {
-                match (op, name_ref) {
-                    (Add, "Int") => LLVMBuildAdd(builder, lhs, rhs, dest),
-                    (Add, "Float") => LLVMBuildFAdd(builder, lhs, rhs, dest),
-                    (Sub, "Int") => LLVMBuildSub(builder, lhs, rhs, dest),
-                    (Sub, "Float") => LLVMBuildFSub(builder, lhs, rhs, dest),
-                    (Mul, "Int") => LLVMBuildMul(builder, lhs, rhs, dest),
-                    (Mul, "Float") => LLVMBuildFMul(builder, lhs, rhs, dest),
-                    (Eq, "Int") => LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntEQ, lhs, rhs, dest),
-                    (Lt, "Int") => LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntSLT, lhs, rhs, dest),
-                    (Le, "Int") => LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntSLE, lhs, rhs, dest),
-                    (Gt, "Int") => LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntSGT, lhs, rhs, dest),
-                    (Ge, "Int") => LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntSGE, lhs, rhs, dest),
-                    (Eq, "Double") => LLVMBuildFCmp(builder, LLVMRealPredicate::LLVMRealOEQ, lhs, rhs, dest),
-                    (Lt, "Double") => LLVMBuildFCmp(builder, LLVMRealPredicate::LLVMRealOLT, lhs, rhs, dest),
-                    (Le, "Double") => LLVMBuildFCmp(builder, LLVMRealPredicate::LLVMRealOLE, lhs, rhs, dest),
-                    (Gt, "Double") => LLVMBuildFCmp(builder, LLVMRealPredicate::LLVMRealOGT, lhs, rhs, dest),
-                    (Ge, "Double") => LLVMBuildFCmp(builder, LLVMRealPredicate::LLVMRealOGE, lhs, rhs, dest),
-                    (And, "Bool") => LLVMBuildAnd(builder, lhs, rhs, dest),
-                    (Or, "Bool") => LLVMBuildOr(builder, lhs, rhs, dest),
-                    (Div, _) => LLVMBuildFDiv(builder, lhs, rhs, dest), // TODO: I dont know exactly which builder
-                    (Rem, _) => LLVMBuildURem(builder, lhs, rhs, dest), //       should I use for these two
-                    _ => unimplemented!(),
-                }
+            match (op, name_ref) {
+                (Add, "Int") | (Add, "Long") => LLVMBuildAdd(builder, lhs, rhs, dest),
+                (Add, "Float") => LLVMBuildFAdd(builder, lhs, rhs, dest),
+                (Sub, "Int") | (Sub, "Long") => LLVMBuildSub(builder, lhs, rhs, dest),
+                (Sub, "Float") => LLVMBuildFSub(builder, lhs, rhs, dest),
+                (Mul, "Int") | (Mul, "Long") => LLVMBuildMul(builder, lhs, rhs, dest),
+                (Mul, "Float") => LLVMBuildFMul(builder, lhs, rhs, dest),
+                (And, "Bool") => LLVMBuildAnd(builder, lhs, rhs, dest),
+                (Or, "Bool") => LLVMBuildOr(builder, lhs, rhs, dest),
+                (Div, "Float") => LLVMBuildFDiv(builder, lhs, rhs, dest),
+                (Div, _) => LLVMBuildSDiv(builder, lhs, rhs, dest),
+                (Rem, "Float") => LLVMBuildFRem(builder, lhs, rhs, dest),
+                (Rem, _) => LLVMBuildSRem(builder, lhs, rhs, dest),
+                _ => unimplemented!(),
             }
         } else {
             unreachable!()
         }
+    }
+}
+
+/// Size in bytes of a scalar stored in a field, `None` for boxed values
+fn scalar_size(ty: &Type) -> Option<usize> {
+    if let &Type::Con(ref n) = ty {
+        match n.as_str() {
+            "Int" | "Char" => Some(4),
+            "Long" | "Float" => Some(8),
+            "Bool" => Some(1),
+            "String" => Some(8),
+            _ => None,
+        }
+    } else {
+        None
+    }
 }
 
-pub fn is_primitive_type(t: &Type) -> bool {
+/// Size in bytes of the fields of a variant laid out in a struct,
+///   each field is aligned to its own size.
+fn variant_size(variant: &Variant) -> usize {
+    variant.fields().iter().fold(0, |offset, f| {
+        let size = scalar_size(&f.ty).unwrap_or(8);
+        (offset + size - 1) / size * size + size
+    })
+}
+
+/// Replace type variables by types in `sub`
+fn instantiate(ty: &Type, sub: &HashMap<&str, &Type>) -> Type {
+    match *ty {
+        Type::Var(ref n) => sub.get(n.as_str()).map(|&t| t.clone()).unwrap_or_else(|| ty.clone()),
+        _ => ty.map_children(|t| instantiate(t, sub)),
+    }
+}
+
+/// Whether the type is represented as a LLVM integer
+pub fn is_integral_type(t: &Type) -> bool {
     if let &Type::Con(ref n) = t {
         match n.as_str() {
-            "Int" | "Float" | "Char" | "String" | "Void" => true,
+            "Int" | "Long" | "Char" | "Bool" => true,
             _ => false,
         }
     } else {
@@ -76,18 +206,47 @@ pub fn is_primitive_type(t: &Type) -> bool {
     }
 }
 
+/// Whether the type is a signed integer, which may overflow
+pub fn is_signed_int_type(t: &Type) -> bool {
+    if let &Type::Con(ref n) = t {
+        n == "Int" || n == "Long"
+    } else {
+        false
+    }
+}
+
+/// Types passed by value, a `String`, `List` or `data` is a pointer itself
+pub fn is_primitive_type(t: &Type) -> bool {
+    match *t {
+        Type::Con(ref n) => {
+            match n.as_str() {
+                "Int" | "Long" | "Float" | "Char" | "Bool" | "String" | "Void" => true,
+                // User defined types
+                _ => true,
+            }
+        }
+        Type::Comp(..) => true,
+        _ => false,
+    }
+}
+
 impl LLVMCodegen {
     pub fn new(name: &str) -> LLVMCodegen {
+        LLVMCodegen::with_options(name, &CodegenOptions::default())
+    }
+
+    pub fn with_options(name: &str, options: &CodegenOptions) -> LLVMCodegen {
         let context = LLVMContext::new();
         let module = LLVMModule::in_ctx(name, &context);
         let builder = LLVMBuilder::in_ctx(&context);
-        let passer = LLVMFunctionPassManager::init_for_module(&module);
+        let passer = LLVMFunctionPassManager::with_passes(&module, &options.passes());
         LLVMCodegen {
             module,
             context,
             builder,
             passer,
             unique: 0,
+            types: HashMap::new(),
         }
     }
 
@@ -141,8 +300,9 @@ impl LLVMCodegen {
 
                     // Primary types
                     "Int" => self.context.get_int32_type(),
+                    "Long" => self.context.get_int64_type(),
                     "Float" => self.context.get_double_type(),
-                    "Char" => self.context.get_int8_type(),
+                    "Char" => self.context.get_int32_type(),
                     "Bool" => self.context.get_int1_type(),
                     "String" => self.context.get_int8_type().get_ptr(0),
                     "Void" => self.context.get_void_type(),
@@ -151,7 +311,7 @@ impl LLVMCodegen {
                     t => self.gen_user_type(t),
                 }
             }
-            &Arr(box ref p, box ref ret) => {
+            &Arr(ref ps, box ref ret) => {
                 // Type of parameters and returned value should be pointer if not primitive
                 let fvs_ty = self.context.get_int8_type().get_ptr(0);
                 let retty = if let &Type::Arr(..) = ret {
@@ -159,44 +319,158 @@ impl LLVMCodegen {
                 } else {
                     self.get_llvm_type_or_ptr(ret)
                 };
-                let psty = match p {
-                    &Type::Void => vec![],
-                    _ => p.prod_to_vec()
-                };
                 let mut llvm_psty: Vec<_> =
-                    psty.into_iter().map(|t| self.get_llvm_type_or_ptr(t)).collect();
+                    ps.iter().map(|t| self.get_llvm_type_or_ptr(t)).collect();
                 llvm_psty.push(fvs_ty);
                 LLVMContext::get_function_type(&retty, &llvm_psty, false)
             }
             &Void => self.context.get_void_type(),
-            &Prod(..) => {
-                let tys: Vec<_> = ty.prod_to_vec()
-                    .iter()
+            &Prod(ref ts) => {
+                let tys: Vec<_> = ts.iter()
                     .map(|t| self.get_llvm_type(t))
                     .collect();
                 self.context.get_struct_type(&tys, true)
             }
-            &Comp(ref c, ref p) => unimplemented!(), // TODO: determine the type
+            &Comp(box Con(ref c), box ref item) if c == "List" => self.get_list_type(item).get_ptr(0),
+            &Comp(..) => {
+                match ty.split_comp() {
+                    (&Con(ref n), _) => self.gen_user_type(n),
+                    (head, _) => panic!("Type {} is not a data type", head),
+                }
+            }
 
             &Var(..) => panic!("Unmaterized type"),
         }
     }
 
+    /// Layout of a list, length of it followed by elements: `{ i32, [0 x T] }`.
+    ///   A list value is a pointer to it.
+    pub fn get_list_type(&self, item: &Type) -> LLVMType {
+        let len = self.context.get_int32_type();
+        let items = self.get_llvm_type_or_ptr(item).array(0);
+        self.context.get_struct_type(&vec![len, items], false)
+    }
+
     pub fn gen_lit(&mut self, lit: &Lit) -> LLVMValue {
         use self::Lit::*;
         match lit {
             &Float(f) => self.context.get_double_const(f),
             &Int(i) => self.context.get_int32_const(i),
-            &Bool(true) => self.context.get_int1_const(1),
-            &Bool(false) => self.context.get_int1_const(0),
+            &Long(i) => self.context.get_int64_const(i),
+            &Bool(b) => self.context.bool_const(b),
+            &Char(c) => self.context.get_char_const(c),
             // TODO: String represent
             &Str(ref s) => self.context.get_const_string(s.as_str()),
         }
     }
 
+    /// Generate the layout of a `data` declaration as a named struct.
+    ///   A type with more than one variant is a tagged union,
+    ///   an `i32` tag followed by payload large enough for any variant:
+    ///   `%T = type { i32, [n x i64] }`.
+    ///   The payload is in words so fields of any variant are aligned when casted to.
+    ///   A single variant is laid out as its fields without the tag.
+    pub fn gen_type_def(&self, def: &TypeDef) -> LLVMType {
+        let named = self.context.create_named_struct(def.name());
+        let variants = match *def.body() {
+            TypeKind::Algebra(ref vs) => vs,
+            TypeKind::Alias(..) => panic!("Alias {} has no layout", def.name()),
+        };
+        if variants.len() == 1 {
+            named.set_struct_body(&self.get_variant_fields(&variants[0]), false);
+            return named;
+        }
+        let payload = variants.iter()
+            .map(|v| variant_size(v))
+            .max()
+            .unwrap_or(0);
+        let mut body = vec![self.context.get_int32_type()];
+        if payload > 0 {
+            let words = (payload + 7) / 8;
+            body.push(self.context.get_int64_type().array(words as u32));
+        }
+        named.set_struct_body(&body, false);
+        named
+    }
+
+    /// Types of the fields of a variant, so the payload could be casted to
+    pub fn get_variant_type(&self, variant: &Variant) -> LLVMType {
+        self.context.get_struct_type(&self.get_variant_fields(variant), false)
+    }
+
+    fn get_variant_fields(&self, variant: &Variant) -> Vec<LLVMType> {
+        variant.fields().iter().map(|f| self.get_field_type(&f.ty)).collect()
+    }
+
+    /// Scalars are stored in place, other values are boxed behind a pointer
+    fn get_field_type(&self, ty: &Type) -> LLVMType {
+        match scalar_size(ty) {
+            Some(_) => self.get_llvm_type(ty),
+            None => self.context.get_int8_type().get_ptr(0),
+        }
+    }
+
+    /// Lay out `data` definitions, so values of them could be generated.
+    ///   Fields of data types are boxed, so definitions could refer to each other.
+    pub fn define_types<'a, I>(&mut self, defs: I)
+        where I: IntoIterator<Item = &'a TypeDef>
+    {
+        for def in defs {
+            if let TypeKind::Algebra(_) = *def.body() {
+                let layout = self.gen_type_def(def);
+                self.types.insert(def.name().clone(), (layout, def.clone()));
+            }
+        }
+    }
+
+    /// A value of `data` is a pointer to its layout
     pub fn gen_user_type(&self, tyname: &str) -> LLVMType {
-        // TODO: make a user defined type definition
-        unimplemented!()
+        self.get_data_layout(tyname).get_ptr(0)
+    }
+
+    fn get_data(&self, tyname: &str) -> &(LLVMType, TypeDef) {
+        match self.types.get(tyname) {
+            Some(data) => data,
+            None => panic!("Type {} is not defined", tyname),
+        }
+    }
+
+    /// Layout of a `data` definition
+    pub fn get_data_layout(&self, tyname: &str) -> LLVMType {
+        self.get_data(tyname).0
+    }
+
+    /// Variants of a `data` definition
+    pub fn get_variants(&self, tyname: &str) -> &[Variant] {
+        match *self.get_data(tyname).1.body() {
+            TypeKind::Algebra(ref vs) => vs,
+            TypeKind::Alias(..) => unreachable!(),
+        }
+    }
+
+    /// Types of fields of a variant of `data` type `ty`,
+    ///   with parameters of the definition instantiated by arguments of `ty`
+    pub fn get_field_types(&self, ty: &Type, variant: usize) -> Vec<Type> {
+        let (head, args) = ty.split_comp();
+        let name = match *head {
+            Type::Con(ref n) => n,
+            _ => panic!("Type {} is not a data type", ty),
+        };
+        let params = self.get_data(name).1.params();
+        let sub: HashMap<&str, &Type> = params.iter().map(|p| p.as_str()).zip(args).collect();
+        self.get_variants(name)[variant]
+            .fields()
+            .iter()
+            .map(|f| instantiate(&f.ty, &sub))
+            .collect()
+    }
+
+    /// Type of a value in register, a function value is a pointer to closure
+    pub fn get_value_type(&self, ty: &Type) -> LLVMType {
+        match ty {
+            &Type::Arr(..) => self.get_closure_type().get_ptr(0),
+            _ => self.get_llvm_type(ty),
+        }
     }
 
     pub fn get_or_add_function(&self, fun_name: &str, fty: &Type) -> LLVMFunction {
@@ -210,6 +484,48 @@ impl LLVMCodegen {
     }
 
 
+    /// Get the runtime helper implementing a builtin function,
+    ///   `None` if `name` is not a helper. Builtins are resolved to helpers in conversion,
+    ///   so definitions of the same names are not taken.
+    pub fn get_runtime_function(&self, name: &str) -> Option<LLVMFunction> {
+        let str_ty = self.context.get_int8_type().get_ptr(0);
+        let fvs_ty = self.context.get_int8_type().get_ptr(0);
+        let (ret_ty, params) = match name {
+            // char *strConcat(const char *, const char *, void *)
+            "strConcat" => (str_ty, vec![str_ty, str_ty, fvs_ty]),
+            // int32_t strLength(const char *, void *)
+            "strLength" => (self.context.get_int32_type(), vec![str_ty, fvs_ty]),
+            _ => return None,
+        };
+        Some(self.runtime().declare(name, ret_ty, params, false))
+    }
+
+    /// Get the intrinsic doing signed arithmetic with overflow bit,
+    ///   e.g. `{ i32, i1 } @llvm.sadd.with.overflow.i32(i32, i32)`
+    pub fn get_overflow_intrinsic(&self, op: BinOp, int_ty: &LLVMType) -> LLVMFunction {
+        let name = match op {
+            BinOp::Add => "sadd",
+            BinOp::Sub => "ssub",
+            BinOp::Mul => "smul",
+            _ => panic!("No overflow intrinsic of {:?}", op),
+        };
+        let bits = unsafe { LLVMGetIntTypeWidth(int_ty.raw_ptr()) };
+        let fun_name = format!("llvm.{}.with.overflow.i{}", name, bits);
+        match self.module.get_function(&fun_name) {
+            Some(f) => f,
+            None => {
+                let ret = self.context.get_struct_type(&vec![*int_ty, self.context.get_int1_type()], false);
+                let fty = LLVMContext::get_function_type(&ret, &vec![*int_ty, *int_ty], false);
+                self.module.add_function(&fun_name, &fty)
+            }
+        }
+    }
+
+    /// Declarations of C library functions in module
+    pub fn runtime(&self) -> Runtime {
+        Runtime::new(&self.module, &self.context)
+    }
+
     pub fn create_entry_block_alloca(&self,
                                      fun: &LLVMFunction,
                                      var_name: &str,
@@ -218,10 +534,7 @@ impl LLVMCodegen {
         let builder = LLVMBuilder::in_ctx(&self.context);
         let block = fun.get_entry_basic_block();
         let fi = block.get_first_instr();
-        let llvm_ty = match ty {
-            &Type::Arr(..) => self.get_closure_type().get_ptr(0),
-            _ => self.get_llvm_type(ty)
-        };
+        let llvm_ty = self.get_value_type(ty);
         builder.set_position(&block, &fi);
         builder.alloca(&llvm_ty, var_name)
     }
@@ -233,11 +546,64 @@ impl LLVMCodegen {
                         operand_ty: &Type)
                         -> LLVMValue {
         let fun = get_llvm_op(op, operand_ty);
+        let dest = self.new_symbol().unwrap();
         unsafe {
             LLVMValue::from_ref(fun(self.builder.raw_ptr(),
                                     lhs.raw_ptr(),
                                     rhs.raw_ptr(),
-                                    self.new_symbol().unwrap().into_raw()))
+                                    dest.as_ptr()))
         }
     }
+
+    pub fn un_operator(&mut self, op: UnOp, val: LLVMValue, operand_ty: &Type) -> LLVMValue {
+        let dest = self.new_symbol_string();
+        match (op, operand_ty) {
+            (UnOp::Neg, &Type::Con(ref n)) if n == "Float" => self.builder.build_fneg(&val, &dest),
+            (UnOp::Neg, _) => self.builder.build_neg(&val, &dest),
+            (UnOp::Not, _) => self.builder.build_not(&val, &dest),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::form::Span;
+
+    fn field(ty: &str) -> Field {
+        Field { pos: Span::point(0), name: None, ty: P(Type::Con(ty.to_string())) }
+    }
+
+    fn variant(name: &str, fields: Vec<Field>) -> Variant {
+        Variant { pos: Span::point(0), name: name.to_string(), body: VariantBody::Tuple(fields) }
+    }
+
+    fn layout_ir(def: TypeDef) -> String {
+        let gen = LLVMCodegen::new("test");
+        let ty = gen.gen_type_def(&def);
+        // Named types are printed only if used
+        let fty = LLVMContext::get_function_type(&gen.context.get_void_type(), &vec![ty.get_ptr(0)], false);
+        gen.module.add_function("use", &fty);
+        gen.module.print_to_string()
+    }
+
+    #[test]
+    fn layout_tagged_union() {
+        // data Shape { Circle(Float), Rect(Int, Int, Bool) }
+        let def = TypeDef::new("Shape".to_string(), vec![],
+                               TypeKind::Algebra(vec![variant("Circle", vec![field("Float")]),
+                                                      variant("Rect", vec![field("Int"), field("Int"), field("Bool")])]));
+        let ir = layout_ir(def);
+        assert!(ir.contains("%Shape = type { i32, [2 x i64] }"), "{}", ir);
+    }
+
+    #[test]
+    fn layout_single_variant() {
+        // data Point { Point(Int, Float, Point) }
+        let def = TypeDef::new("Point".to_string(), vec![],
+                               TypeKind::Algebra(vec![variant("Point", vec![field("Int"), field("Float"), field("Point")])]));
+        let ir = layout_ir(def);
+        assert!(ir.contains("%Point = type { i32, double, i8* }"), "{}", ir);
+    }
 }
@@ -1,9 +1,13 @@
+mod debug;
 mod emit;
 mod llvm;
+mod runtime;
 
 mod target;
 
+pub use self::debug::*;
 pub use self::emit::*;
 pub use self::llvm::*;
+pub use self::runtime::*;
 pub use self::target::*;
 
@@ -0,0 +1,85 @@
+/// External functions of C library and runtime called by generated code,
+///   except `malloc` declared by LLVM for `build_malloc`
+
+use super::llvm::*;
+
+/// Declarations of runtime functions in a module.
+///   A function is declared when first requested, later requests
+///   get the same declaration by its name in module.
+pub struct Runtime<'a> {
+    module: &'a LLVMModule,
+    context: &'a LLVMContext,
+}
+
+impl<'a> Runtime<'a> {
+    pub fn new(module: &'a LLVMModule, context: &'a LLVMContext) -> Runtime<'a> {
+        Runtime { module, context }
+    }
+
+    /// Get function `name`, declare it if absent
+    pub fn declare(&self, name: &str, ret: LLVMType, params: Vec<LLVMType>, var_arg: bool) -> LLVMFunction {
+        match self.module.get_function(name) {
+            Some(f) => f,
+            None => {
+                let fty = LLVMContext::get_function_type(&ret, &params, var_arg);
+                self.module.add_function(name, &fty)
+            }
+        }
+    }
+
+    fn str_type(&self) -> LLVMType {
+        self.context.get_int8_type().get_ptr(0)
+    }
+
+    /// `int printf(const char *, ...)`
+    pub fn printf(&self) -> LLVMFunction {
+        let (int, s) = (self.context.get_int32_type(), self.str_type());
+        self.declare("printf", int, vec![s], true)
+    }
+
+    /// `void free(void *)`
+    pub fn free(&self) -> LLVMFunction {
+        let (void, ptr) = (self.context.get_void_type(), self.str_type());
+        self.declare("free", void, vec![ptr], false)
+    }
+
+    /// `void abort(void)`
+    pub fn abort(&self) -> LLVMFunction {
+        self.declare("abort", self.context.get_void_type(), vec![], false)
+    }
+
+    /// `size_t strlen(const char *)`
+    pub fn strlen(&self) -> LLVMFunction {
+        let (size, s) = (self.context.get_int64_type(), self.str_type());
+        self.declare("strlen", size, vec![s], false)
+    }
+
+    /// `int strcmp(const char *, const char *)`
+    pub fn strcmp(&self) -> LLVMFunction {
+        let (int, s) = (self.context.get_int32_type(), self.str_type());
+        self.declare("strcmp", int, vec![s, s], false)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runtime_declared_once() {
+        let gen = LLVMCodegen::new("test");
+        let rt = gen.runtime();
+        let printf = rt.printf();
+        assert_eq!(rt.printf().raw_ptr(), printf.raw_ptr());
+        assert_eq!(gen.runtime().abort().raw_ptr(), rt.abort().raw_ptr());
+        rt.free();
+        rt.strlen();
+
+        let ir = gen.module.print_to_string();
+        assert_eq!(ir.matches("@printf").count(), 1, "{}", ir);
+        assert!(ir.contains("declare i32 @printf(i8*, ...)"), "{}", ir);
+        assert!(ir.contains("declare void @free(i8*)"), "{}", ir);
+        assert_eq!(gen.module.verify(LLVMVerifierFailureAction::LLVMReturnStatusAction), Ok(()));
+    }
+}
@@ -0,0 +1,48 @@
+/// Front end of compiling, from source to typed definitions
+
+use utils::*;
+use types::*;
+use error::*;
+use typeinfer::Infer;
+
+use syntax::form::*;
+use syntax::parser::parse;
+use syntax::resolve::resolve;
+
+/// Parse, resolve and infer types of a program,
+///   names in `prelude` are builtin.
+///   Parsing and resolving stop at the first error,
+///   type errors of every definition are reported.
+pub fn check(src: &str, prelude: &TypeEnv, interner: &mut Interner) -> Result<Vec<Def>, Vec<CompileError>> {
+    let mut defs = parse(src, interner).map_err(|e| vec![CompileError::from(e)])?;
+    resolve(&defs, |n| prelude.lookup(&n).is_some(), interner).map_err(|e| vec![CompileError::from(e)])?;
+    Infer::new(interner)
+        .infer_defs(prelude, &mut defs)
+        .map_err(|es| es.into_iter().map(CompileError::from).collect())?;
+    Ok(defs)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::parser;
+
+    #[test]
+    fn check_reports_all_errors() {
+        let mut interner = Interner::new();
+        let env = Infer::new_env();
+        let plus = interner.intern("+");
+        let prelude = env.extend_n(vec![(plus, parser::parse_type("forall a. a * a -> a", &mut interner))]);
+        let src = "def f() = 1 + true\n\
+                   def g() = f() + 1\n\
+                   def h() = 'c' + 1.5";
+        let errors = check(src, &prelude, &mut interner).unwrap_err();
+        // `g` uses `f` failed, which is not an error again
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().all(|e| match *e {
+            CompileError::Type(..) => true,
+            _ => false,
+        }));
+    }
+}
@@ -3,6 +3,7 @@
 use std::collections::HashSet;
 use std::collections::HashMap;
 use std::iter::FromIterator;
+use std::mem;
 use std::ops::Deref;
 use std::ops::DerefMut;
 
@@ -10,11 +11,52 @@ use utils::*;
 use types::*;
 use syntax::form::*;
 use internal::*;
+use internal;
 
 use core::term::*;
+use core::exhaust::{check_match, MatchWarning};
 
 type Direct = HashMap<Id, Id>;
 
+/// A row of the pattern matrix when lowering `match`
+#[derive(Clone, Debug)]
+struct Row {
+    /// Tests not yet done, as (occurrence, pattern)
+    tests: Vec<(VarDecl, Pattern)>,
+    /// Pattern variables bound to occurrences
+    binds: Vec<(Id, VarDecl)>,
+    body: Form,
+}
+
+impl Row {
+    /// Move out tests always succeed, binding variables
+    fn simplify(&mut self) {
+        let tests = mem::replace(&mut self.tests, vec![]);
+        for (occ, pat) in tests {
+            match pat {
+                Pattern::Wildcard => {}
+                Pattern::Var(id) => self.binds.push((id, occ)),
+                p => self.tests.push((occ, p)),
+            }
+        }
+    }
+
+    /// Remove the test on occurrence
+    fn take_test(&mut self, occ: Id) -> Option<Pattern> {
+        let pos = self.tests.iter().position(|&(ref o, _)| o.name() == occ);
+        pos.map(|i| self.tests.remove(i).1)
+    }
+}
+
+/// Closure conversion over a module.
+///   Every lambda is lifted to a top level function with
+///   its captured variables made explicit.
+pub fn closure_convert(defs: Vec<Def>, interner: &mut Interner) -> (Vec<FunDef>, Vec<TypeDef>) {
+    let (funs, types) = K::go(defs, interner);
+    (funs.into_iter().map(|(_, f)| *f).collect(),
+     types.into_iter().map(|(_, t)| *t).collect())
+}
+
 #[derive(Debug)]
 pub struct K<'i> {
     count: usize,
@@ -24,6 +66,7 @@ pub struct K<'i> {
     current: Id,
     interner: &'i mut Interner,
     direct: Direct,
+    warnings: Vec<MatchWarning>,
 }
 
 macro_rules! with_var {
@@ -45,6 +88,16 @@ impl<'i> K<'i> {
                  interner: &mut Interner)
                  -> (HashMap<Id, P<FunDef>>, HashMap<Id, P<TypeDef>>)
         where I: IntoIterator<Item = Def>
+    {
+        let (global, typedefs, _) = K::go_with_warnings(module, interner);
+        (global, typedefs)
+    }
+
+    /// Do transformation like `go`, also giving problems found in `match`
+    pub fn go_with_warnings<I>(module: I,
+                               interner: &mut Interner)
+                               -> (HashMap<Id, P<FunDef>>, HashMap<Id, P<TypeDef>>, Vec<MatchWarning>)
+        where I: IntoIterator<Item = Def>
     {
         let current_tmp = interner.intern("");
         let mut runner = K {
@@ -55,6 +108,7 @@ impl<'i> K<'i> {
             current: current_tmp,
             direct: HashMap::new(),
             interner,
+            warnings: vec![],
         };
         let defs: Vec<_> = module.into_iter()
             .map(|def| {
@@ -62,16 +116,21 @@ impl<'i> K<'i> {
                      def
                  })
             .collect();
+        // Types are defined before bodies, which may match on a type declared later
+        let (funs, types): (Vec<_>, Vec<_>) = defs.into_iter().partition(|def| match def.node {
+            Item::Form(..) => true,
+            _ => false,
+        });
         {
             let b = &mut runner;
 
-            for def in defs {
+            for def in types.into_iter().chain(funs) {
                 b.convert_def(def);
             }
         }
 
-        let K { global, typedefs, .. } = runner;
-        (global, typedefs)
+        let K { global, typedefs, warnings, .. } = runner;
+        (global, typedefs, warnings)
     }
 
     /// Get a unique id, then increase the counter
@@ -85,6 +144,12 @@ impl<'i> K<'i> {
         String::from(".tmp") + self.unique().to_string().as_str()
     }
 
+    /// Generate a new temporary variable
+    fn fresh_id(&mut self) -> Id {
+        let name = self.fresh();
+        self.interner.intern(&name)
+    }
+
     /// Generate a name for closure
     fn make_cls_name(&mut self, bound: &str) -> Id {
         let mut name = self.interner.trace(self.current).to_owned();
@@ -146,6 +211,27 @@ impl<'i> K<'i> {
     }
 
 
+    /// Define a function applying a binary operator on its two parameters,
+    ///   one for each operator and type
+    fn lift_operator(&mut self, op: BinOp, ty: &Scheme, pos: Span) -> Id {
+        let (operand, ret) = match *ty.body() {
+            Type::Arr(ref ps, box ref ret) => (ps[0].clone(), ret.clone()),
+            ref t => panic!("operator of non-function type {}", t),
+        };
+        let name = format!("op.{:?}.{}", op, operand);
+        let label = self.interner.intern(&name);
+        if !self.global.contains_key(&label) {
+            let (x, y) = (self.fresh_id(), self.fresh_id());
+            let var = |id| P(Form::typed(pos, Scheme::Mono(operand.clone()), Expr::Var(id)));
+            let app = Form::typed(pos, Scheme::Mono(ret), Expr::Binary(op, var(x), var(y)));
+            let params = vec![VarDecl(x, Scheme::Mono(operand.clone())), VarDecl(y, Scheme::Mono(operand.clone()))];
+            let body = self.transform(app);
+            self.define_fn(label, ty.clone(), params, vec![], body);
+            self.direct.insert(label, label);
+        }
+        label
+    }
+
     /// Get free variables of a term
     fn fv<'a>(&mut self, source: &'a TaggedTerm) -> HashSet<Id> {
         use self::Term::*;
@@ -158,6 +244,8 @@ impl<'i> K<'i> {
             }
 
             List(ref lst) |
+            Tuple(ref lst) |
+            Construct(_, ref lst) |
             Block(ref lst) |
             ApplyDir(_, ref lst) => {
                 lst.iter().fold(HashSet::new(), |mut res, v| {
@@ -188,7 +276,8 @@ impl<'i> K<'i> {
                 r.extend(self.fv(n.deref()));
                 r
             }
-            Binary(_, ref lhs, ref rhs) => {
+            Binary(_, ref lhs, ref rhs) |
+            Index(ref lhs, ref rhs) => {
                 let mut r = self.fv(lhs.deref());
                 r.extend(self.fv(rhs.deref()));
                 r
@@ -200,6 +289,22 @@ impl<'i> K<'i> {
                 r.extend(self.fv(f.deref()));
                 r
             }
+            While(ref c, ref b) => {
+                let mut r = self.fv(c.deref());
+                r.extend(self.fv(b.deref()));
+                r
+            }
+            Field(ref e, _) => self.fv(e.deref()),
+            Case(ref e, ref arms, ref default) => {
+                let mut r = self.fv(e.deref());
+                for &(_, ref arm) in arms.iter() {
+                    r.extend(self.fv(arm.deref()));
+                }
+                if let Some(ref d) = *default {
+                    r.extend(self.fv(d.deref()));
+                }
+                r
+            }
         }
     }
 
@@ -217,6 +322,45 @@ impl<'i> K<'i> {
         self.env.get(var)
     }
 
+    /// Make `f(a)` of `f: A * B -> C` into `let x = a in (b) -> f(x, b)`,
+    ///   so arguments are evaluated when partially applied.
+    fn partial(&mut self, callee: Form, args: Vec<E>, rest: Vec<Type>, pos: Span, ty: Scheme) -> Form {
+        let ret = match *callee.tag.ref_type() {
+            Type::Arr(_, box ref ret) => ret.clone(),
+            _ => unreachable!(),
+        };
+        let mut binds = vec![];
+        let callee = self.bind_atom(callee, &mut binds);
+        let mut actual: Vec<E> = args.into_iter()
+            .map(|a| P(self.bind_atom(*a, &mut binds)))
+            .collect();
+        let mut params = vec![];
+        for t in rest.into_iter() {
+            let id = self.fresh_id();
+            actual.push(P(Form::typed(pos, Scheme::Mono(t.clone()), Expr::Var(id))));
+            params.push(VarDecl(id, Scheme::Mono(t)));
+        }
+
+        let call = Form::typed(pos, Scheme::Mono(ret), Expr::Apply(P(callee), actual));
+        let lambda = Lambda { param: params, ret: Scheme::Slot, body: P(call) };
+        binds.into_iter().rev().fold(Form::typed(pos, ty.clone(), Expr::Abs(lambda)), |body, (v, val)| {
+            Form::typed(pos, ty.clone(), Expr::Let(v, val, P(body)))
+        })
+    }
+
+    /// Bind a form to a temporary variable unless it is a literal or variable
+    fn bind_atom(&mut self, form: Form, binds: &mut Vec<(VarDecl, E)>) -> Form {
+        match form.node {
+            Expr::Lit(_) | Expr::Var(_) => form,
+            _ => {
+                let id = self.fresh_id();
+                let (pos, scm) = (form.tag.pos, form.tag.ty.clone());
+                binds.push((VarDecl(id, scm.clone()), P(form)));
+                Form::typed(pos, scm, Expr::Var(id))
+            }
+        }
+    }
+
     /// Get parameters, free variables, function body term from lambda
     fn trans_lambda(&mut self, lambda: Lambda, cap_fv: bool) -> (Vec<VarDecl>, Vec<VarDecl>, TaggedTerm) {
         let params = lambda.param;
@@ -239,14 +383,15 @@ impl<'i> K<'i> {
                 }
             }
 
-            let body_term = self.transform(bd);
             let fvs = if cap_fv {
-                let mut _fvs = self.fv(&body_term);
-                for &VarDecl(ref n, _) in params.iter() {
-                    _fvs.remove(n);
-                }
-                _fvs.iter().map(|vn| VarDecl(vn.to_owned(), self.env[vn].to_owned())).collect()
+                let bound = params.iter().map(|p| p.name()).collect();
+                free_vars(&bd.node, &bound)
+                    .into_iter()
+                    // Names out of local environment are globals
+                    .filter_map(|vn| self.env.get(&vn).map(|t| VarDecl(vn, t.to_owned())))
+                    .collect()
             } else { vec![] };
+            let body_term = self.transform(bd);
             // Reset env
             for bname in present {
                 self.release_var(&bname);
@@ -260,6 +405,147 @@ impl<'i> K<'i> {
         (params, fvs, body_term)
     }
 
+    /// Find the index and field types of a constructor,
+    ///   which is known after type check
+    fn find_variant(&self, ctor: Id) -> (usize, Vec<Type>) {
+        let name = self.interner.trace(ctor);
+        for def in self.typedefs.values() {
+            if let Some((i, v)) = def.variant(name) {
+                return (i, v.fields().iter().map(|f| f.ty.deref().clone()).collect());
+            }
+        }
+        unreachable!("constructor {} is not checked", name)
+    }
+
+    /// Find the index of a named field in the type,
+    ///   which is known after type check
+    fn find_field(&self, ty: &Type, field: &str) -> usize {
+        if let (&Type::Con(ref name), _) = ty.split_comp() {
+            for (id, def) in self.typedefs.iter() {
+                if self.interner.trace(*id) != name {
+                    continue;
+                }
+                if let Some(i) = def.field_index(field) {
+                    return i;
+                }
+            }
+        }
+        unreachable!("field {} is not checked", field)
+    }
+
+    /// Transform body of a row whose tests all passed,
+    ///   binding pattern variables
+    fn trans_row(&mut self, row: Row) -> TaggedTerm {
+        let Row { binds, body, .. } = row;
+        let backup: Vec<_> = binds.iter()
+            .map(|&(id, ref occ)| (id, self.close_var(id, occ.1.clone())))
+            .collect();
+        let body_term = self.transform(body);
+        for (id, origin) in backup.into_iter().rev() {
+            match origin {
+                Some(o) => self.close_var(id, o),
+                None => self.release_var(&id),
+            };
+        }
+
+        binds.into_iter().rev().fold(body_term, |exp, (id, occ)| {
+            let VarDecl(occ, scm) = occ;
+            let ty = exp.ref_scheme().clone();
+            let pos = exp.pos();
+            let val = TaggedTerm::new(pos, scm.clone(), Term::Var(occ));
+            TaggedTerm::new(pos, ty, Term::Let(VarDecl(id, scm), box val, box exp))
+        })
+    }
+
+    /// Compile pattern rows into `Case` on simple tags,
+    ///   generated terms are at `pos` of the `match`.
+    /// Returns `None` if no row could match.
+    fn compile_rows(&mut self, mut rows: Vec<Row>, ty: &Scheme, pos: Span) -> Option<TaggedTerm> {
+        for row in rows.iter_mut() {
+            row.simplify();
+        }
+        // Test the first pending occurrence of the first row,
+        // if there is none, the first row always matches.
+        let occ = match rows.first().map(|r| r.tests.first().map(|t| t.0.clone())) {
+            None => return None,
+            Some(None) => return Some(self.trans_row(rows.remove(0))),
+            Some(Some(occ)) => occ,
+        };
+        let VarDecl(occ_id, occ_ty) = occ.clone();
+
+        // Collect tags in order of appearance
+        let mut tags = vec![];
+        for row in rows.iter() {
+            let tag = match row.tests.iter().find(|&&(ref o, _)| o.name() == occ_id) {
+                Some(&(_, Pattern::Ctor(ctor, _))) => Tag::Variant(self.find_variant(ctor).0),
+                Some(&(_, Pattern::Lit(ref l))) => Tag::Lit(l.clone()),
+                _ => continue,
+            };
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+
+        let mut arms = vec![];
+        for tag in tags {
+            // Occurrences of the variant fields
+            let fields: Vec<_> = match tag {
+                Tag::Variant(i) => {
+                    let ctor = rows.iter()
+                        .filter_map(|r| match r.tests.iter().find(|&&(ref o, _)| o.name() == occ_id) {
+                            Some(&(_, Pattern::Ctor(c, _))) if self.find_variant(c).0 == i => Some(c),
+                            _ => None,
+                        })
+                        .next()
+                        .unwrap();
+                    let (_, tys) = self.find_variant(ctor);
+                    tys.into_iter().map(|t| VarDecl(self.fresh_id(), Scheme::Mono(t))).collect()
+                }
+                Tag::Lit(_) => vec![],
+            };
+
+            let mut spec = vec![];
+            for row in rows.iter() {
+                let mut row = row.clone();
+                match row.take_test(occ_id) {
+                    None => {}
+                    Some(Pattern::Ctor(c, ps)) => {
+                        if Tag::Variant(self.find_variant(c).0) != tag {
+                            continue;
+                        }
+                        // Number of fields is checked in type inference
+                        row.tests.extend(fields.iter().cloned().zip(ps));
+                    }
+                    Some(Pattern::Lit(l)) => {
+                        if Tag::Lit(l) != tag {
+                            continue;
+                        }
+                    }
+                    Some(_) => unreachable!(),
+                }
+                spec.push(row);
+            }
+
+            let body = self.compile_rows(spec, ty, pos).unwrap();
+            // Bind field occurrences used by the arm
+            let body = fields.into_iter().enumerate().rev().fold(body, |exp, (i, field)| {
+                let scm = field.1.clone();
+                let val = TaggedTerm::new(pos, occ_ty.clone(), Term::Var(occ_id));
+                let get = TaggedTerm::new(pos, scm, Term::Field(box val, i));
+                TaggedTerm::new(pos, ty.clone(), Term::Let(field, box get, box exp))
+            });
+            arms.push((tag, box body));
+        }
+
+        let default_rows = rows.into_iter()
+            .filter(|r| r.tests.iter().all(|&(ref o, _)| o.name() != occ_id))
+            .collect();
+        let default = self.compile_rows(default_rows, ty, pos).map(|t| box t);
+
+        let scrut = TaggedTerm::new(pos, occ_ty, Term::Var(occ_id));
+        Some(TaggedTerm::new(pos, ty.clone(), Term::Case(box scrut, arms, default)))
+    }
+
     fn transform_list(&mut self, lst: Vec<P<Form>>) -> Vec<P<TaggedTerm>> {
         lst.into_iter().map(|f| box self.transform(*f)).collect()
     }
@@ -267,14 +553,22 @@ impl<'i> K<'i> {
     /// Transform syntax form into core term
     fn transform(&mut self, form: Form) -> TaggedTerm {
         use self::Expr::*;
-        let Form { node, tag: FormTag { ty: tform, .. } } = form;
+        let Form { node, tag: FormTag { ty: tform, pos, .. } } = form;
         let t = match node {
             Lit(l) => Term::Lit(l),
             Var(n) => {
                 // A global definition should not be in scope env
                 if self.find_var(&n).is_none() && tform.is_fn() {
+                    let op = BinOp::take(self.interner.trace(n), pos).ok();
                     if let Some(label) = self.direct.get(&n).map(|id| id.to_owned()) {
                         Term::Var(label)
+                    } else if let Some(op) = op {
+                        Term::Var(self.lift_operator(op, &tform, pos))
+                    } else if let Some(sym) = builtin_symbol(self.interner.trace(n)) {
+                        // A builtin not shadowed by a definition is a runtime helper
+                        let label = self.interner.intern(sym);
+                        self.direct.insert(label, label);
+                        Term::Var(label)
                     } else {
                         // For global function name, make a closure
                         self.direct.insert(n, n);
@@ -284,13 +578,45 @@ impl<'i> K<'i> {
                     Term::Var(n)
                 }
             }
-            List(e) | Block(e) => Term::List(self.transform_list(e)),
+            List(e) => Term::List(self.transform_list(e)),
+            // Every element is evaluated in order, the last one is the value
+            Block(e) => Term::Block(self.transform_list(e)),
             Unary(op, e) => Term::Unary(op, box self.transform(*e)),
             If(cond, tr, fl) => {
                 Term::If(box self.transform(*cond),
                          box self.transform(*tr),
                          box self.transform(*fl))
             }
+            Tuple(e) => Term::Tuple(self.transform_list(e)),
+            TupleProj(e, n) => Term::Field(box self.transform(*e), n),
+            Index(l, i) => Term::Index(box self.transform(*l), box self.transform(*i)),
+            Record(ctor, fields) => {
+                // Fields are already in declaration order after type check
+                let (i, _) = {
+                    let id = self.interner.intern(&ctor);
+                    self.find_variant(id)
+                };
+                let values = fields.into_iter().map(|(_, f)| f).collect();
+                Term::Construct(i, self.transform_list(values))
+            }
+            Field(e, name) => {
+                let i = self.find_field(e.tag.ref_type(), &name);
+                Term::Field(box self.transform(*e), i)
+            }
+            While(cond, body) => {
+                Term::While(box self.transform(*cond), box self.transform(*body))
+            }
+            // Short circuit, the right side is evaluated only if needed.
+            // `a && b` is `if (a) b else false`
+            Binary(BinOp::And, left, right) => {
+                let fl = TaggedTerm::new(pos, Scheme::con("Bool"), Term::Lit(internal::Lit::Bool(false)));
+                Term::If(box self.transform(*left), box self.transform(*right), box fl)
+            }
+            // `a || b` is `if (a) true else b`
+            Binary(BinOp::Or, left, right) => {
+                let tr = TaggedTerm::new(pos, Scheme::con("Bool"), Term::Lit(internal::Lit::Bool(true)));
+                Term::If(box self.transform(*left), box tr, box self.transform(*right))
+            }
             Binary(op, left, right) => {
                 Term::Binary(op, box self.transform(*left), box self.transform(*right))
             }
@@ -367,7 +693,13 @@ impl<'i> K<'i> {
                 ret
             }
 
+            // Applying fewer arguments makes a closure waiting for the rest
             Apply(callee, params) => {
+                let rest = missing_params(callee.tag.ref_type(), params.len());
+                if !rest.is_empty() {
+                    let curried = self.partial(*callee, params, rest, pos, tform);
+                    return self.transform(curried);
+                }
                 let callee_term = self.transform(*callee);
                 let params_term = self.transform_list(params);
 
@@ -384,6 +716,26 @@ impl<'i> K<'i> {
                 }
             }
 
+            // Bind the scrutinee, then decompose patterns
+            // into simple tag tests on it
+            Match(e, arms) => {
+                let warnings = check_match(&arms, pos, &self.typedefs, self.interner);
+                self.warnings.extend(warnings);
+                let scrut = self.transform(*e);
+                let occ = VarDecl(self.fresh_id(), scrut.ref_scheme().clone());
+                let rows = arms.into_iter()
+                    .map(|Arm { pattern, body }| {
+                        Row {
+                            tests: vec![(occ.clone(), pattern)],
+                            binds: vec![],
+                            body: *body,
+                        }
+                    })
+                    .collect();
+                let body = self.compile_rows(rows, &tform, pos).unwrap();
+                Term::Let(occ, box scrut, box body)
+            }
+
             // Give anonymous lambda a name binding
             Abs(lambda) => {
                 let ty = tform.clone();
@@ -398,9 +750,423 @@ impl<'i> K<'i> {
 
                 Term::MakeCls(VarDecl(tmp_id, ty.clone()),
                               box cls,
-                              box TaggedTerm::new(ty, Term::Var(tmp_id)))
+                              box TaggedTerm::new(pos, ty, Term::Var(tmp_id)))
             }
         };
-        TaggedTerm::new(tform, t)
+        TaggedTerm::new(pos, tform, t)
+    }
+}
+
+
+/// Symbol of the runtime helper implementing a builtin function
+fn builtin_symbol(name: &str) -> Option<&'static str> {
+    match name {
+        "strcat" => Some("strConcat"),
+        "strlen" => Some("strLength"),
+        _ => None,
+    }
+}
+
+/// Types of parameters not given by `count` arguments
+fn missing_params(callee: &Type, count: usize) -> Vec<Type> {
+    match *callee {
+        Type::Arr(ref ps, _) => ps.iter().skip(count).cloned().collect(),
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(ty: &str, e: Expr) -> P<Form> {
+        P(Form::typed(Span::new(0, 0), Scheme::con(ty), e))
+    }
+
+    /// `print("effect")` as the right operand
+    fn effect(interner: &mut Interner) -> P<Form> {
+        let print = interner.intern("print");
+        let print_ty = Scheme::arrow(vec![Type::Con("String".to_string())], Type::Con("Bool".to_string()));
+        let callee = P(Form::typed(Span::new(0, 0), print_ty, Expr::Var(print)));
+        let arg = typed("String", Expr::Lit(internal::Lit::Str("effect".to_string())));
+        typed("Bool", Expr::Apply(callee, vec![arg]))
+    }
+
+    fn lower(interner: &mut Interner, form: Form) -> TaggedTerm {
+        let current = interner.intern("");
+        let mut k = K {
+            count: 0,
+            env: HashMap::new(),
+            global: HashMap::new(),
+            typedefs: HashMap::new(),
+            current,
+            interner,
+            direct: HashMap::new(),
+            warnings: vec![],
+        };
+        k.transform(form)
+    }
+
+    fn is_bool(term: &TaggedTerm, b: bool) -> bool {
+        *term.body() == Term::Lit(internal::Lit::Bool(b))
+    }
+
+    fn is_effect(term: &TaggedTerm) -> bool {
+        match *term.body() {
+            Term::ApplyDir(..) => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn short_circuit_and() {
+        use core::eval::*;
+
+        let mut interner = Interner::new();
+        let rhs = effect(&mut interner);
+        let lhs = typed("Bool", Expr::Lit(internal::Lit::Bool(false)));
+        let form = *typed("Bool", Expr::Binary(BinOp::And, lhs, rhs));
+
+        // The effect only runs in the `then` branch
+        let term = lower(&mut interner, form);
+        match *term.body() {
+            Term::If(ref c, ref t, ref f) => {
+                assert!(is_bool(c, false));
+                assert!(is_effect(t));
+                assert!(is_bool(f, false));
+            }
+            ref t => panic!("expect if, found {:?}", t),
+        }
+        // `print` is not defined, evaluating the effect would panic
+        assert_eq!(eval(&HashMap::new(), &term, &Env::new()), Value::Bool(false));
+    }
+
+    #[test]
+    fn short_circuit_or() {
+        use core::eval::*;
+
+        let mut interner = Interner::new();
+        let rhs = effect(&mut interner);
+        let lhs = typed("Bool", Expr::Lit(internal::Lit::Bool(true)));
+        let form = *typed("Bool", Expr::Binary(BinOp::Or, lhs, rhs));
+
+        // The effect only runs in the `else` branch
+        let term = lower(&mut interner, form);
+        match *term.body() {
+            Term::If(ref c, ref t, ref f) => {
+                assert!(is_bool(c, true));
+                assert!(is_bool(t, true));
+                assert!(is_effect(f));
+            }
+            ref t => panic!("expect if, found {:?}", t),
+        }
+        assert_eq!(eval(&HashMap::new(), &term, &Env::new()), Value::Bool(true));
+    }
+
+    #[test]
+    fn lower_block() {
+        let mut interner = Interner::new();
+        let stmts = vec![effect(&mut interner), typed("Int", Expr::Lit(internal::Lit::Int(1)))];
+        let form = *typed("Int", Expr::Block(stmts));
+
+        match *lower(&mut interner, form).body() {
+            Term::Block(ref ts) => {
+                assert_eq!(ts.len(), 2);
+                assert!(is_effect(&ts[0]));
+                assert_eq!(*ts[1].body(), Term::Lit(internal::Lit::Int(1)));
+            }
+            ref t => panic!("expect block, found {:?}", t),
+        }
+    }
+
+    #[test]
+    fn convert_curried_adder() {
+        use syntax::parser;
+        use typeinfer::Infer;
+
+        let mut interner = Interner::new();
+        let mut defs = parser::parse("def add(a) = (b) -> a + b + 1", &mut interner).unwrap();
+        let env = Infer::new_env();
+        let plus = interner.intern("+");
+        let prelude = env.extend_n(vec![(plus, parser::parse_type("forall a. a * a -> a", &mut interner))]);
+        Infer::new(&mut interner).infer_defs(&prelude, &mut defs).unwrap();
+
+        let add = interner.intern("add");
+        let a = interner.intern("a");
+        let (funs, types) = closure_convert(defs, &mut interner);
+        assert!(types.is_empty());
+        assert_eq!(funs.len(), 2);
+
+        let outer = funs.iter().find(|f| f.name() == add).unwrap();
+        let inner = funs.iter().find(|f| f.name() != add).unwrap();
+        assert!(outer.fv().is_empty());
+        // `a` is captured by the inner lambda
+        assert_eq!(inner.fv().iter().map(|v| v.name()).collect::<Vec<_>>(), vec![a]);
+        match *outer.body().body() {
+            Term::MakeCls(_, ref cls, _) => {
+                assert_eq!(cls.entry(), inner.name());
+                assert_eq!(cls.fv(), vec![a]);
+            }
+            ref t => panic!("expect closure, found {:?}", t),
+        }
+    }
+
+    #[test]
+    fn lower_keeps_pos() {
+        let mut interner = Interner::new();
+        let at = |l, r, e| P(Form::typed(Span::new(l, r), Scheme::con("Int"), e));
+        let one = at(0, 1, Expr::Lit(internal::Lit::Int(1)));
+        let two = at(3, 5, Expr::Lit(internal::Lit::Int(2)));
+        let form = *at(0, 5, Expr::Binary(BinOp::Add, one, two));
+
+        let term = lower(&mut interner, form);
+        assert_eq!(term.pos(), Span::new(0, 5));
+        match *term.body() {
+            Term::Binary(_, ref l, ref r) => {
+                assert_eq!(l.pos(), Span::new(0, 1));
+                assert_eq!(r.pos(), Span::new(3, 5));
+            }
+            ref t => panic!("expect binary, found {:?}", t),
+        }
+    }
+
+    #[test]
+    fn convert_type_definition() {
+        use syntax::parser;
+
+        let mut interner = Interner::new();
+        let src = "data Pair a { Pair(a, Int), Nil { len: Int } }";
+        let defs = parser::parse(src, &mut interner).unwrap();
+        let (_, types) = closure_convert(defs, &mut interner);
+        assert_eq!(types.len(), 1);
+
+        let pair = &types[0];
+        assert_eq!(pair.name(), "Pair");
+        assert_eq!(pair.params(), &["a".to_string()][..]);
+        match *pair.body() {
+            TypeKind::Algebra(ref vs) => {
+                assert_eq!(vs.iter().map(|v| v.name.as_str()).collect::<Vec<_>>(), vec!["Pair", "Nil"]);
+            }
+            ref k => panic!("expect algebra, found {:?}", k),
+        }
+    }
+
+    #[test]
+    fn convert_partial_application() {
+        use syntax::parser;
+        use typeinfer::Infer;
+        use core::eval::*;
+
+        let mut interner = Interner::new();
+        let src = "def add(a, b) = a + b\ndef main() = { let inc = add(1) in inc(2) }";
+        let mut defs = parser::parse(src, &mut interner).unwrap();
+        let env = Infer::new_env();
+        let plus = interner.intern("+");
+        let prelude = env.extend_n(vec![(plus, parser::parse_type("forall a. a * a -> a", &mut interner))]);
+        Infer::new(&mut interner).infer_defs(&prelude, &mut defs).unwrap();
+
+        let main = interner.intern("main");
+        let (funs, _) = closure_convert(defs, &mut interner);
+        let funs: HashMap<_, _> = funs.into_iter().map(|f| (f.name(), P(f))).collect();
+        // `add(1)` is a closure of one parameter
+        assert_eq!(funs.len(), 3);
+        assert!(funs.values().any(|f| f.parameters().len() == 1));
+        assert_eq!(eval(&funs, funs[&main].body(), &Env::new()), Value::Int(3));
+    }
+
+    #[test]
+    fn convert_computed_callee() {
+        use syntax::parser;
+        use typeinfer::Infer;
+        use core::eval::*;
+
+        let mut interner = Interner::new();
+        let src = "def inc(a) = a + 1\ndef dec(a) = a - 1\ndef main() = (if (true) inc else dec)(2)";
+        let mut defs = parser::parse(src, &mut interner).unwrap();
+        let env = Infer::new_env();
+        let (plus, minus) = (interner.intern("+"), interner.intern("-"));
+        let arith = parser::parse_type("forall a. a * a -> a", &mut interner);
+        let prelude = env.extend_n(vec![(plus, arith.clone()), (minus, arith)]);
+        Infer::new(&mut interner).infer_defs(&prelude, &mut defs).unwrap();
+
+        let main = interner.intern("main");
+        let (funs, _) = closure_convert(defs, &mut interner);
+        let funs: HashMap<_, _> = funs.into_iter().map(|f| (f.name(), P(f))).collect();
+        // The callee is not a name, so it is called as a closure
+        match *funs[&main].body().body() {
+            Term::ApplyCls(ref callee, _) => match *callee.body() {
+                Term::If(..) => {}
+                ref t => panic!("expect if, found {:?}", t),
+            },
+            ref t => panic!("expect closure call, found {:?}", t),
+        }
+        assert_eq!(eval(&funs, funs[&main].body(), &Env::new()), Value::Int(3));
+    }
+
+    #[test]
+    fn convert_operator_value() {
+        use syntax::parser;
+        use typeinfer::Infer;
+        use core::eval::*;
+
+        let mut interner = Interner::new();
+        let src = "def fold(f, acc, n) = if (n == 0) acc else fold(f, f(acc, n), n - 1)\n\
+                   def main() = fold((+), 0, 3) + fold((*), 1, 3)";
+        let mut defs = parser::parse(src, &mut interner).unwrap();
+        let env = Infer::new_env();
+        let arith = parser::parse_type("forall a. a * a -> a", &mut interner);
+        let cmp = parser::parse_type("forall a. a * a -> Bool", &mut interner);
+        let ops = vec![("+", arith.clone()), ("-", arith.clone()), ("*", arith), ("==", cmp)];
+        let prelude = env.extend_n(ops.into_iter().map(|(n, t)| (interner.intern(n), t)));
+        Infer::new(&mut interner).infer_defs(&prelude, &mut defs).unwrap();
+
+        let main = interner.intern("main");
+        let (funs, _) = closure_convert(defs, &mut interner);
+        let funs: HashMap<_, _> = funs.into_iter().map(|f| (f.name(), P(f))).collect();
+        // One function for each operator
+        assert_eq!(funs.len(), 4);
+        assert!(funs.contains_key(&interner.intern("op.Add.Int")));
+        // 3 + 2 + 1 + 3 * 2 * 1
+        assert_eq!(eval(&funs, funs[&main].body(), &Env::new()), Value::Int(12));
+    }
+
+    #[test]
+    fn convert_local_recursion() {
+        use syntax::parser;
+        use typeinfer::Infer;
+        use core::eval::*;
+
+        let mut interner = Interner::new();
+        let src = "def count(k) = let go = (n) -> if (n == 0) k else go(n - 1) in go(3)\n\
+                   def main() = let fact = (n) -> if (n == 0) 1 else n * fact(n - 1) in fact(5) + count(2)";
+        let mut defs = parser::parse(src, &mut interner).unwrap();
+        let env = Infer::new_env();
+        let arith = parser::parse_type("forall a. a * a -> a", &mut interner);
+        let cmp = parser::parse_type("forall a. a * a -> Bool", &mut interner);
+        let ops = vec![("+", arith.clone()), ("-", arith.clone()), ("*", arith), ("==", cmp)];
+        let prelude = env.extend_n(ops.into_iter().map(|(n, t)| (interner.intern(n), t)));
+        Infer::new(&mut interner).infer_defs(&prelude, &mut defs).unwrap();
+
+        let (main, count) = (interner.intern("main"), interner.intern("count"));
+        let (funs, _) = closure_convert(defs, &mut interner);
+        let funs: HashMap<_, _> = funs.into_iter().map(|f| (f.name(), P(f))).collect();
+        // `fact` captures nothing and calls itself directly,
+        //   `go` captures `k` and itself
+        assert_eq!(funs.len(), 4);
+        match *funs[&count].body().body() {
+            Term::MakeCls(_, ref cls, _) => assert_eq!(cls.fv().len(), 2),
+            ref t => panic!("expect closure, found {:?}", t),
+        }
+        assert_eq!(eval(&funs, funs[&main].body(), &Env::new()), Value::Int(122));
+    }
+
+    #[test]
+    fn convert_tuple_variant() {
+        use syntax::parser;
+        use typeinfer::Infer;
+        use core::eval::*;
+
+        let mut interner = Interner::new();
+        let src = "data Pair { Pair(Int, Float) }\n\
+                   def main() = let p = Pair(1, 2.5) in (p.0, p.1)";
+        let mut defs = parser::parse(src, &mut interner).unwrap();
+        Infer::new(&mut interner).infer_defs(&Infer::new_env(), &mut defs).unwrap();
+
+        let main = interner.intern("main");
+        let (funs, _) = closure_convert(defs, &mut interner);
+        let funs: HashMap<_, _> = funs.into_iter().map(|f| (f.name(), P(f))).collect();
+        match *funs[&main].body().body() {
+            Term::Let(_, ref val, _) => match *val.body() {
+                Term::Construct(0, ref fields) => assert_eq!(fields.len(), 2),
+                ref t => panic!("expect construct, found {:?}", t),
+            },
+            ref t => panic!("expect let, found {:?}", t),
+        }
+        assert_eq!(eval(&funs, funs[&main].body(), &Env::new()),
+                   Value::Tuple(vec![Value::Int(1), Value::Float(2.5)]));
+    }
+
+    #[test]
+    fn convert_tuple_parameter() {
+        use syntax::parser;
+        use typeinfer::Infer;
+        use core::eval::*;
+
+        let mut interner = Interner::new();
+        let src = "def pick(p: Int * Int, first: Bool) = if (first) p.0 else p.1\n\
+                   def main() = let second = pick((1, 2)) in (second(false), pick((3, 4), true))";
+        let mut defs = parser::parse(src, &mut interner).unwrap();
+        Infer::new(&mut interner).infer_defs(&Infer::new_env(), &mut defs).unwrap();
+
+        let main = interner.intern("main");
+        let (funs, _) = closure_convert(defs, &mut interner);
+        let funs: HashMap<_, _> = funs.into_iter().map(|f| (f.name(), P(f))).collect();
+        assert_eq!(eval(&funs, funs[&main].body(), &Env::new()),
+                   Value::Tuple(vec![Value::Int(2), Value::Int(3)]));
+    }
+
+    #[test]
+    fn convert_match() {
+        use syntax::parser;
+        use typeinfer::Infer;
+        use core::eval::*;
+
+        let mut interner = Interner::new();
+        let src = "data Shape { Circle(Int), Rect(Int, Bool) }\n\
+                   def area(s) = match (s) { Circle(r) => r, Rect(w, true) => w, _ => 0 }\n\
+                   def main() = (area(Rect(2, true)), area(Rect(3, false)), area(Circle(5)))";
+        let mut defs = parser::parse(src, &mut interner).unwrap();
+        Infer::new(&mut interner).infer_defs(&Infer::new_env(), &mut defs).unwrap();
+
+        let main = interner.intern("main");
+        let (funs, _, warnings) = K::go_with_warnings(defs, &mut interner);
+        assert_eq!(warnings, vec![]);
+        assert_eq!(eval(&funs, funs[&main].body(), &Env::new()),
+                   Value::Tuple(vec![Value::Int(2), Value::Int(0), Value::Int(5)]));
+    }
+
+    #[test]
+    fn convert_match_later_type() {
+        use syntax::parser;
+        use typeinfer::Infer;
+
+        let mut interner = Interner::new();
+        let src = "def area(s) = match (s) { Circle(r) => r, Rect(w, _) => w }\n\
+                   data Shape { Circle(Int), Rect(Int, Bool) }";
+        let mut defs = parser::parse(src, &mut interner).unwrap();
+        Infer::new(&mut interner).infer_defs(&Infer::new_env(), &mut defs).unwrap();
+
+        let (_, typedefs, warnings) = K::go_with_warnings(defs, &mut interner);
+        assert_eq!(typedefs.len(), 1);
+        assert_eq!(warnings, vec![]);
+    }
+
+    #[test]
+    fn convert_builtin() {
+        use syntax::parser;
+        use typeinfer::Infer;
+
+        let mut interner = Interner::new();
+        let src = "def strlen(s: String) = 0\n\
+                   def f(a: String) = strlen(strcat(a, a))";
+        let mut defs = parser::parse(src, &mut interner).unwrap();
+        let env = Infer::new_env();
+        let strcat = parser::parse_type("String * String -> String", &mut interner);
+        let prelude = env.extend_n(vec![(interner.intern("strcat"), strcat)]);
+        Infer::new(&mut interner).infer_defs(&prelude, &mut defs).unwrap();
+
+        let (funs, _) = K::go(defs, &mut interner);
+        let f = &funs[&interner.intern("f")];
+        // The definition shadows the builtin, the other builtin calls its helper
+        match *f.body().body() {
+            Term::ApplyDir(VarDecl(outer, _), ref args) => {
+                assert_eq!(outer, interner.intern("strlen"));
+                match *args[0].body() {
+                    Term::ApplyDir(VarDecl(inner, _), _) => assert_eq!(inner, interner.intern("strConcat")),
+                    ref t => panic!("expect direct call, found {:?}", t),
+                }
+            }
+            ref t => panic!("expect direct call, found {:?}", t),
+        }
     }
 }
@@ -0,0 +1,186 @@
+/// Remove bindings which are never used
+
+use std::mem;
+
+use utils::*;
+use internal::*;
+
+use core::term::*;
+
+/// Replace `let` bindings never used by their bodies,
+///   if the bound value is pure and need not to be evaluated.
+pub fn eliminate_dead_lets(term: &mut TaggedTerm) {
+    use self::Term::*;
+    match *term.body_mut() {
+        Lit(_) | Var(_) => {}
+        List(ref mut ts) |
+        Tuple(ref mut ts) |
+        Construct(_, ref mut ts) |
+        Block(ref mut ts) |
+        ApplyDir(_, ref mut ts) => {
+            for t in ts.iter_mut() {
+                eliminate_dead_lets(t);
+            }
+        }
+        ApplyCls(ref mut callee, ref mut args) => {
+            eliminate_dead_lets(callee);
+            for t in args.iter_mut() {
+                eliminate_dead_lets(t);
+            }
+        }
+        MakeCls(_, _, ref mut e) |
+        Unary(_, ref mut e) |
+        Field(ref mut e, _) => eliminate_dead_lets(e),
+        Let(_, ref mut l, ref mut r) |
+        Binary(_, ref mut l, ref mut r) |
+        Index(ref mut l, ref mut r) |
+        While(ref mut l, ref mut r) => {
+            eliminate_dead_lets(l);
+            eliminate_dead_lets(r);
+        }
+        If(ref mut c, ref mut t, ref mut f) => {
+            eliminate_dead_lets(c);
+            eliminate_dead_lets(t);
+            eliminate_dead_lets(f);
+        }
+        Case(ref mut e, ref mut arms, ref mut default) => {
+            eliminate_dead_lets(e);
+            for &mut (_, ref mut arm) in arms.iter_mut() {
+                eliminate_dead_lets(arm);
+            }
+            if let Some(ref mut d) = *default {
+                eliminate_dead_lets(d);
+            }
+        }
+    }
+
+    let dead = match *term.body() {
+        Let(ref var, ref val, ref exp) => is_pure(val) && uses(var.name(), exp) == 0,
+        _ => false,
+    };
+    if dead {
+        if let Let(_, _, exp) = mem::replace(term.body_mut(), Block(vec![])) {
+            *term.body_mut() = exp.body().clone();
+        }
+    }
+}
+
+/// Count uses of a variable, a closure capturing it also counts
+pub fn uses(var: Id, term: &TaggedTerm) -> usize {
+    use self::Term::*;
+    match *term.body() {
+        Lit(_) => 0,
+        Var(n) => if n == var { 1 } else { 0 },
+        List(ref ts) |
+        Tuple(ref ts) |
+        Construct(_, ref ts) |
+        Block(ref ts) |
+        ApplyDir(_, ref ts) => uses_all(var, ts),
+        ApplyCls(ref callee, ref args) => uses(var, callee) + uses_all(var, args),
+        // The name is shadowed by a new binding
+        MakeCls(ref v, ref cls, ref e) => {
+            let captured = cls.fv().into_iter().filter(|&n| n == var).count();
+            captured + if v.name() == var { 0 } else { uses(var, e) }
+        }
+        Let(ref v, ref val, ref e) => {
+            uses(var, val) + if v.name() == var { 0 } else { uses(var, e) }
+        }
+        Unary(_, ref e) |
+        Field(ref e, _) => uses(var, e),
+        Binary(_, ref l, ref r) |
+        Index(ref l, ref r) |
+        While(ref l, ref r) => uses(var, l) + uses(var, r),
+        If(ref c, ref t, ref f) => uses(var, c) + uses(var, t) + uses(var, f),
+        Case(ref e, ref arms, ref default) => {
+            uses(var, e) + arms.iter().map(|&(_, ref t)| uses(var, t)).sum::<usize>() +
+            default.as_ref().map_or(0, |d| uses(var, d))
+        }
+    }
+}
+
+fn uses_all(var: Id, terms: &[P<TaggedTerm>]) -> usize {
+    terms.iter().map(|t| uses(var, t)).sum()
+}
+
+/// Whether evaluating a term has no effect.
+///   Calls are considered effectful, so is division which may trap.
+fn is_pure(term: &TaggedTerm) -> bool {
+    use self::Term::*;
+    match *term.body() {
+        Lit(_) | Var(_) => true,
+        List(ref ts) |
+        Tuple(ref ts) |
+        Construct(_, ref ts) |
+        Block(ref ts) => ts.iter().all(|t| is_pure(t)),
+        // Index out of range aborts
+        ApplyCls(..) | ApplyDir(..) | While(..) | Index(..) => false,
+        Binary(BinOp::Div, ..) | Binary(BinOp::Rem, ..) => false,
+        Binary(_, ref l, ref r) |
+        Let(_, ref l, ref r) => is_pure(l) && is_pure(r),
+        MakeCls(_, _, ref e) |
+        Unary(_, ref e) |
+        Field(ref e, _) => is_pure(e),
+        If(ref c, ref t, ref f) => is_pure(c) && is_pure(t) && is_pure(f),
+        Case(ref e, ref arms, ref default) => {
+            is_pure(e) && arms.iter().all(|&(_, ref t)| is_pure(t)) &&
+            default.as_ref().map_or(true, |d| is_pure(d))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::form::Span;
+    use types::*;
+
+    fn int() -> Scheme {
+        Scheme::con("Int")
+    }
+
+    fn term(node: Term) -> P<TaggedTerm> {
+        P(TaggedTerm::new(Span::new(0, 0), int(), node))
+    }
+
+    fn let_in(var: Id, val: Term, body: Term) -> P<TaggedTerm> {
+        term(Term::Let(VarDecl(var, int()), term(val), term(body)))
+    }
+
+    #[test]
+    fn remove_pure_dead_let() {
+        let mut interner = Interner::new();
+        let (x, y) = (interner.intern("x"), interner.intern("y"));
+        // let x = 1 + y in y
+        let val = Term::Binary(BinOp::Add, term(Term::Lit(Lit::Int(1))), term(Term::Var(y)));
+        let mut t = let_in(x, val, Term::Var(y));
+        eliminate_dead_lets(&mut t);
+        assert_eq!(*t.body(), Term::Var(y));
+
+        // let x = 1 in let x = 2 in x, the outer one is dead
+        let mut t = let_in(x, Term::Lit(Lit::Int(1)),
+                           Term::Let(VarDecl(x, int()), term(Term::Lit(Lit::Int(2))), term(Term::Var(x))));
+        eliminate_dead_lets(&mut t);
+        assert_eq!(*t.body(), Term::Let(VarDecl(x, int()), term(Term::Lit(Lit::Int(2))), term(Term::Var(x))));
+    }
+
+    #[test]
+    fn keep_effectful_let() {
+        let mut interner = Interner::new();
+        let (x, print) = (interner.intern("x"), interner.intern("print"));
+        // let x = print(1) in 0
+        let call = Term::ApplyDir(VarDecl(print, Scheme::arrow(vec![Type::Con("Int".to_string())], Type::Void)),
+                                  vec![term(Term::Lit(Lit::Int(1)))]);
+        let mut t = let_in(x, call, Term::Lit(Lit::Int(0)));
+        let origin = t.clone();
+        eliminate_dead_lets(&mut t);
+        assert_eq!(t, origin);
+
+        // let x = 1 / 0 in 0
+        let div = Term::Binary(BinOp::Div, term(Term::Lit(Lit::Int(1))), term(Term::Lit(Lit::Int(0))));
+        let mut t = let_in(x, div, Term::Lit(Lit::Int(0)));
+        let origin = t.clone();
+        eliminate_dead_lets(&mut t);
+        assert_eq!(t, origin);
+    }
+}
@@ -0,0 +1,154 @@
+/// Call known functions directly instead of through closures
+
+use std::collections::HashSet;
+use std::collections::HashMap;
+use std::mem;
+
+use utils::*;
+use internal::*;
+
+use core::term::*;
+
+/// Rewrite closure applications into direct ones if the callee
+///   is a top level function without free variables,
+///   which needs no closure environment to be called.
+pub fn direct_calls(funs: &mut HashMap<Id, P<FunDef>>) {
+    let known: HashSet<Id> = funs.values()
+        .filter(|f| f.fv().is_empty())
+        .map(|f| f.name())
+        .collect();
+    for fun in funs.values_mut() {
+        // Parameters and free variables shadow global names
+        let mut scope = known.clone();
+        for v in fun.parameters().iter().chain(fun.fv().iter()) {
+            scope.remove(&v.name());
+        }
+        rewrite(fun.body_mut(), &scope);
+    }
+}
+
+/// Names in scope without the one newly bound
+fn shadow(known: &HashSet<Id>, bound: Id) -> HashSet<Id> {
+    let mut res = known.clone();
+    res.remove(&bound);
+    res
+}
+
+fn rewrite(term: &mut TaggedTerm, known: &HashSet<Id>) {
+    use self::Term::*;
+    let callee = match *term.body() {
+        ApplyCls(ref callee, _) => {
+            match *callee.body() {
+                Var(n) if known.contains(&n) => Some(VarDecl(n, callee.ref_scheme().clone())),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    if let Some(callee) = callee {
+        let node = mem::replace(term.body_mut(), Block(vec![]));
+        if let ApplyCls(_, args) = node {
+            *term.body_mut() = ApplyDir(callee, args);
+        }
+    }
+
+    match *term.body_mut() {
+        Lit(_) | Var(_) => {}
+        List(ref mut ts) |
+        Tuple(ref mut ts) |
+        Construct(_, ref mut ts) |
+        Block(ref mut ts) |
+        ApplyDir(_, ref mut ts) => {
+            for t in ts.iter_mut() {
+                rewrite(t, known);
+            }
+        }
+        ApplyCls(ref mut callee, ref mut args) => {
+            rewrite(callee, known);
+            for t in args.iter_mut() {
+                rewrite(t, known);
+            }
+        }
+        MakeCls(ref var, _, ref mut exp) => rewrite(exp, &shadow(known, var.name())),
+        Let(ref var, ref mut val, ref mut exp) => {
+            rewrite(val, known);
+            rewrite(exp, &shadow(known, var.name()));
+        }
+        Binary(_, ref mut l, ref mut r) |
+        Index(ref mut l, ref mut r) |
+        While(ref mut l, ref mut r) => {
+            rewrite(l, known);
+            rewrite(r, known);
+        }
+        Unary(_, ref mut e) |
+        Field(ref mut e, _) => rewrite(e, known),
+        If(ref mut c, ref mut t, ref mut f) => {
+            rewrite(c, known);
+            rewrite(t, known);
+            rewrite(f, known);
+        }
+        Case(ref mut e, ref mut arms, ref mut default) => {
+            rewrite(e, known);
+            for &mut (_, ref mut arm) in arms.iter_mut() {
+                rewrite(arm, known);
+            }
+            if let Some(ref mut d) = *default {
+                rewrite(d, known);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::form::Span;
+    use types::*;
+
+    fn term(node: Term) -> P<TaggedTerm> {
+        P(TaggedTerm::new(Span::new(0, 0), Scheme::con("Int"), node))
+    }
+
+    fn int_fn() -> Scheme {
+        Scheme::arrow(vec![Type::Con("Int".to_string())], Type::Con("Int".to_string()))
+    }
+
+    #[test]
+    fn direct_call_known() {
+        let mut interner = Interner::new();
+        let (fuck, shit, f, x) = (interner.intern("fuck"), interner.intern("shit"),
+                                  interner.intern("f"), interner.intern("x"));
+        let call = |callee: Id| {
+            let callee = P(TaggedTerm::new(Span::new(0, 0), int_fn(), Term::Var(callee)));
+            term(Term::ApplyCls(callee, vec![term(Term::Var(x))]))
+        };
+        // def fuck(x) = x
+        // def shit(f, x) = fuck(x) + f(x)
+        let fuck_def = FunDef::new(fuck, int_fn(), vec![VarDecl(x, Scheme::con("Int"))], vec![],
+                                   *term(Term::Var(x)));
+        let shit_def = FunDef::new(shit, int_fn(),
+                                   vec![VarDecl(f, int_fn()), VarDecl(x, Scheme::con("Int"))],
+                                   vec![],
+                                   *term(Term::Binary(BinOp::Add, call(fuck), call(f))));
+        let mut funs = HashMap::new();
+        funs.insert(fuck, P(fuck_def));
+        funs.insert(shit, P(shit_def));
+
+        direct_calls(&mut funs);
+
+        match *funs[&shit].body().body() {
+            Term::Binary(_, ref l, ref r) => {
+                match *l.body() {
+                    Term::ApplyDir(VarDecl(n, _), _) => assert_eq!(n, fuck),
+                    ref t => panic!("expect direct call, found {:?}", t),
+                }
+                match *r.body() {
+                    Term::ApplyCls(..) => {}
+                    ref t => panic!("expect closure call, found {:?}", t),
+                }
+            }
+            ref t => panic!("unexpected term {:?}", t),
+        }
+    }
+}
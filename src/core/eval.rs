@@ -0,0 +1,221 @@
+/// Evaluate terms directly, as a reference of the generated code
+
+use std::collections::HashMap;
+
+use utils::*;
+use internal::*;
+
+use core::term::*;
+
+/// Runtime value of a term
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Int(i32),
+    Long(i64),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    List(Vec<Value>),
+    Tuple(Vec<Value>),
+    /// A variant of `data` by index, with its fields
+    Data(usize, Vec<Value>),
+    /// Entry function with values of its free variables
+    Closure(Id, Vec<Value>),
+    Void,
+}
+
+lit_value!(Value);
+
+pub type Env<'a> = SymTable<'a, Id, Value>;
+
+/// Evaluate a term with functions after closure conversion.
+///   Names not bound in `env` refer to functions in `funs`.
+///   Runtime errors, like division by zero, panic.
+pub fn eval(funs: &HashMap<Id, P<FunDef>>, term: &TaggedTerm, env: &Env) -> Value {
+    use self::Term::*;
+    let all = |ts: &Vec<P<TaggedTerm>>| ts.iter().map(|t| eval(funs, t, env)).collect::<Vec<_>>();
+    match *term.body() {
+        Lit(ref lit) => Value::from_lit(lit),
+        Var(n) => eval_var(funs, n, env),
+        List(ref ts) => Value::List(all(ts)),
+        Tuple(ref ts) => Value::Tuple(all(ts)),
+        Construct(idx, ref ts) => Value::Data(idx, all(ts)),
+        Block(ref ts) => all(ts).pop().unwrap_or(Value::Void),
+        MakeCls(ref var, ref cls, ref exp) => {
+            // The closure may capture itself, which is
+            //   left without free variables until called
+            let fvs = cls.fv()
+                .into_iter()
+                .map(|n| if n == var.name() {
+                    Value::Closure(cls.entry(), vec![])
+                } else {
+                    eval_var(funs, n, env)
+                })
+                .collect();
+            let val = Value::Closure(cls.entry(), fvs);
+            eval(funs, exp, &env.extend(var.name(), val))
+        }
+        ApplyCls(ref callee, ref args) => {
+            match eval(funs, callee, env) {
+                Value::Closure(entry, fvs) => call(funs, entry, all(args), fvs),
+                v => panic!("Expect a closure, found {:?}", v),
+            }
+        }
+        ApplyDir(ref callee, ref args) => call(funs, callee.name(), all(args), vec![]),
+        Binary(op, ref l, ref r) => {
+            let (l, r) = (eval(funs, l, env), eval(funs, r, env));
+            let res = match (l.to_lit(), r.to_lit()) {
+                (Some(a), Some(b)) => fold_binary(op, &a, &b),
+                _ => None,
+            };
+            match res {
+                Some(lit) => Value::from_lit(&lit),
+                None => panic!("Cannot apply {:?} on {:?} and {:?}", op, l, r),
+            }
+        }
+        Unary(op, ref e) => {
+            let v = eval(funs, e, env);
+            match v.to_lit().and_then(|lit| fold_unary(op, &lit)) {
+                Some(lit) => Value::from_lit(&lit),
+                None => panic!("Cannot apply {:?} on {:?}", op, v),
+            }
+        }
+        Let(ref var, ref val, ref exp) => {
+            let val = eval(funs, val, env);
+            eval(funs, exp, &env.extend(var.name(), val))
+        }
+        If(ref c, ref t, ref f) => {
+            if eval(funs, c, env).truth() {
+                eval(funs, t, env)
+            } else {
+                eval(funs, f, env)
+            }
+        }
+        While(ref c, ref b) => {
+            while eval(funs, c, env).truth() {
+                eval(funs, b, env);
+            }
+            Value::Void
+        }
+        Field(ref e, idx) => {
+            match eval(funs, e, env) {
+                Value::Tuple(mut vs) |
+                Value::Data(_, mut vs) => vs.swap_remove(idx),
+                v => panic!("Cannot get field {} of {:?}", idx, v),
+            }
+        }
+        Index(ref l, ref i) => {
+            match (eval(funs, l, env), eval(funs, i, env)) {
+                (Value::List(mut vs), Value::Int(i)) if i >= 0 && (i as usize) < vs.len() => {
+                    vs.swap_remove(i as usize)
+                }
+                (l, i) => panic!("Cannot get element {:?} of {:?}", i, l),
+            }
+        }
+        Case(ref e, ref arms, ref default) => {
+            let v = eval(funs, e, env);
+            let arm = arms.iter()
+                .find(|&&(ref tag, _)| match (tag, &v) {
+                    (&Tag::Variant(i), &Value::Data(j, _)) => i == j,
+                    (&Tag::Lit(ref lit), v) => Value::from_lit(lit) == *v,
+                    _ => false,
+                })
+                .map(|&(_, ref arm)| arm)
+                .or(default.as_ref());
+            match arm {
+                Some(arm) => eval(funs, arm, env),
+                None => panic!("No arm matches {:?}", v),
+            }
+        }
+    }
+}
+
+fn eval_var(funs: &HashMap<Id, P<FunDef>>, n: Id, env: &Env) -> Value {
+    match env.lookup(&n) {
+        Some(v) => v.clone(),
+        // A global function as a value
+        None if funs.contains_key(&n) => Value::Closure(n, vec![]),
+        None => panic!("Unbound variable {:?}", n),
+    }
+}
+
+/// Call a function with arguments and values of its free variables
+fn call(funs: &HashMap<Id, P<FunDef>>, entry: Id, args: Vec<Value>, fvs: Vec<Value>) -> Value {
+    let fun = match funs.get(&entry) {
+        Some(f) => f,
+        None => panic!("Unknown function {:?}", entry),
+    };
+    let this = Value::Closure(entry, vec![]);
+    let whole = Value::Closure(entry, fvs.clone());
+    let fvs = fvs.into_iter().map(|v| if v == this { whole.clone() } else { v });
+    let binds = fun.parameters()
+        .iter()
+        .zip(args.into_iter())
+        .chain(fun.fv().iter().zip(fvs))
+        .map(|(v, val)| (v.name(), val));
+    eval(funs, fun.body(), &Env::from_iter(binds))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::form::Span;
+    use types::*;
+
+    fn term(node: Term) -> P<TaggedTerm> {
+        P(TaggedTerm::new(Span::new(0, 0), Scheme::con("Int"), node))
+    }
+
+    fn int(i: i32) -> P<TaggedTerm> {
+        term(Term::Lit(Lit::Int(i)))
+    }
+
+    fn binary(op: BinOp, l: P<TaggedTerm>, r: P<TaggedTerm>) -> P<TaggedTerm> {
+        term(Term::Binary(op, l, r))
+    }
+
+    fn run(t: &TaggedTerm) -> Value {
+        eval(&HashMap::new(), t, &Env::new())
+    }
+
+    #[test]
+    fn eval_arithmetic() {
+        let mut interner = Interner::new();
+        let x = interner.intern("x");
+        // let x = 2 + 3 in x * -4
+        let body = binary(BinOp::Mul, term(Term::Var(x)), term(Term::Unary(UnOp::Neg, int(4))));
+        let t = term(Term::Let(VarDecl(x, Scheme::con("Int")), binary(BinOp::Add, int(2), int(3)), body));
+        assert_eq!(run(&t), Value::Int(-20));
+    }
+
+    #[test]
+    fn eval_conditional() {
+        // if (1 < 2) { 0, 1 } else 2
+        let cond = binary(BinOp::Lt, int(1), int(2));
+        let t = term(Term::If(cond, term(Term::Block(vec![int(0), int(1)])), int(2)));
+        assert_eq!(run(&t), Value::Int(1));
+        let t = term(Term::List(vec![int(1), term(Term::Block(vec![]))]));
+        assert_eq!(run(&t), Value::List(vec![Value::Int(1), Value::Void]));
+    }
+
+    #[test]
+    fn eval_factorial() {
+        let mut interner = Interner::new();
+        let (fact, n) = (interner.intern("fact"), interner.intern("n"));
+        let int_fn = Scheme::arrow(vec![Type::Con("Int".to_string())], Type::Con("Int".to_string()));
+        // def fact(n) = if (n == 0) 1 else n * fact(n - 1)
+        let rec = term(Term::ApplyDir(VarDecl(fact, int_fn.clone()),
+                                      vec![binary(BinOp::Sub, term(Term::Var(n)), int(1))]));
+        let body = Term::If(binary(BinOp::Eq, term(Term::Var(n)), int(0)),
+                            int(1),
+                            binary(BinOp::Mul, term(Term::Var(n)), rec));
+        let mut funs = HashMap::new();
+        funs.insert(fact, P(FunDef::new(fact, int_fn.clone(), vec![VarDecl(n, Scheme::con("Int"))],
+                                        vec![], *term(body))));
+
+        let t = term(Term::ApplyDir(VarDecl(fact, int_fn), vec![int(5)]));
+        assert_eq!(eval(&funs, &t, &Env::new()), Value::Int(120));
+    }
+}
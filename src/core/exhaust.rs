@@ -0,0 +1,295 @@
+/// Exhaustiveness and usefulness of `match` arms,
+///   after "Warnings for pattern matching" by Maranget
+
+use std::collections::HashMap;
+use std::fmt;
+
+use syntax::form::*;
+use syntax::pretty::print_pattern;
+use core::term::*;
+use internal::*;
+use utils::*;
+
+/// Problems of arms of a `match`
+#[derive(Clone, PartialEq, Debug)]
+pub enum MatchWarning {
+    /// Values like the pattern are matched by no arm
+    NonExhaustive(String, Span),
+    /// Arm matches only values matched by arms before it
+    Unreachable(Span),
+}
+
+impl MatchWarning {
+    pub fn pos(&self) -> Span {
+        match *self {
+            MatchWarning::NonExhaustive(_, pos) | MatchWarning::Unreachable(pos) => pos,
+        }
+    }
+}
+
+impl fmt::Display for MatchWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MatchWarning::NonExhaustive(ref p, _) => write!(f, "non-exhaustive match, `{}` is not covered", p),
+            MatchWarning::Unreachable(_) => write!(f, "unreachable arm"),
+        }
+    }
+}
+
+/// Check arms of the `match` at `pos`,
+///   an unreachable arm is reported at its body.
+pub fn check_match(arms: &[Arm],
+                   pos: Span,
+                   typedefs: &HashMap<Id, P<TypeDef>>,
+                   interner: &mut Interner)
+                   -> Vec<MatchWarning> {
+    let sigs = Signatures::new(typedefs, interner);
+    let mut warnings = vec![];
+    let mut rows: Vec<Vec<Pattern>> = vec![];
+    for arm in arms.iter() {
+        let row = vec![arm.pattern.clone()];
+        if !sigs.useful(&rows, &row) {
+            warnings.push(MatchWarning::Unreachable(arm.body.tag.pos));
+        }
+        rows.push(row);
+    }
+    if let Some(missing) = sigs.missing(&rows, 1) {
+        warnings.push(MatchWarning::NonExhaustive(print_pattern(&missing[0], interner), pos));
+    }
+    warnings
+}
+
+/// What a pattern tests, a constructor with its arity or a literal
+#[derive(Clone, PartialEq, Debug)]
+enum Head {
+    Ctor(Id, usize),
+    Lit(Lit),
+}
+
+impl Head {
+    fn arity(&self) -> usize {
+        match *self {
+            Head::Ctor(_, n) => n,
+            Head::Lit(_) => 0,
+        }
+    }
+
+    /// Pattern of the head with sub patterns
+    fn pattern(&self, args: Vec<Pattern>) -> Pattern {
+        match *self {
+            Head::Ctor(c, _) => Pattern::Ctor(c, args),
+            Head::Lit(ref l) => Pattern::Lit(l.clone()),
+        }
+    }
+}
+
+type Row = Vec<Pattern>;
+
+/// Every constructor with all constructors of its type
+struct Signatures {
+    ctors: HashMap<Id, Vec<Head>>,
+}
+
+impl Signatures {
+    fn new(typedefs: &HashMap<Id, P<TypeDef>>, interner: &mut Interner) -> Signatures {
+        let mut ctors = HashMap::new();
+        for def in typedefs.values() {
+            if let TypeKind::Algebra(ref vs) = *def.body() {
+                let sig: Vec<_> = vs.iter()
+                    .map(|v| Head::Ctor(interner.intern(&v.name), v.fields().len()))
+                    .collect();
+                for h in sig.iter() {
+                    if let Head::Ctor(c, _) = *h {
+                        ctors.insert(c, sig.clone());
+                    }
+                }
+            }
+        }
+        Signatures { ctors }
+    }
+
+    /// Head of a pattern, `None` if it matches anything
+    fn head(&self, pat: &Pattern) -> Option<Head> {
+        match *pat {
+            Pattern::Ctor(c, ref ps) => {
+                let arity = self.ctors
+                    .get(&c)
+                    .and_then(|sig| sig.iter().find(|h| match **h {
+                        Head::Ctor(o, _) => o == c,
+                        _ => false,
+                    }))
+                    .map(|h| h.arity())
+                    .unwrap_or(ps.len());
+                Some(Head::Ctor(c, arity))
+            }
+            Pattern::Lit(ref l) => Some(Head::Lit(l.clone())),
+            Pattern::Var(_) | Pattern::Wildcard => None,
+        }
+    }
+
+    /// Heads in the first column, in order of appearance
+    fn heads(&self, rows: &[Row]) -> Vec<Head> {
+        let mut heads = vec![];
+        for h in rows.iter().filter_map(|r| self.head(&r[0])) {
+            if !heads.contains(&h) {
+                heads.push(h);
+            }
+        }
+        heads
+    }
+
+    /// All heads of the type of `heads`, `None` if there are infinitely many
+    fn signature(&self, heads: &[Head]) -> Option<Vec<Head>> {
+        match heads.first() {
+            Some(&Head::Ctor(c, _)) => self.ctors.get(&c).cloned(),
+            Some(&Head::Lit(Lit::Bool(_))) => {
+                Some(vec![Head::Lit(Lit::Bool(true)), Head::Lit(Lit::Bool(false))])
+            }
+            _ => None,
+        }
+    }
+
+    /// Rows whose first pattern matches `head`, with its sub patterns in place of it
+    fn specialize(&self, rows: &[Row], head: &Head) -> Vec<Row> {
+        rows.iter().filter_map(|r| self.specialize_row(r, head)).collect()
+    }
+
+    fn specialize_row(&self, row: &[Pattern], head: &Head) -> Option<Row> {
+        let arity = head.arity();
+        let mut args = match row[0] {
+            Pattern::Var(_) | Pattern::Wildcard => vec![Pattern::Wildcard; arity],
+            Pattern::Ctor(_, ref ps) if self.head(&row[0]).as_ref() == Some(head) => ps.clone(),
+            Pattern::Lit(_) if self.head(&row[0]).as_ref() == Some(head) => vec![],
+            _ => return None,
+        };
+        // Patterns of wrong number of fields are reported in type inference
+        args.resize(arity, Pattern::Wildcard);
+        args.extend(row[1..].iter().cloned());
+        Some(args)
+    }
+
+    /// Rows whose first pattern matches anything, without it
+    fn default(&self, rows: &[Row]) -> Vec<Row> {
+        rows.iter()
+            .filter(|r| self.head(&r[0]).is_none())
+            .map(|r| r[1..].to_vec())
+            .collect()
+    }
+
+    /// Whether some value matched by `row` is matched by none of `rows`
+    fn useful(&self, rows: &[Row], row: &[Pattern]) -> bool {
+        if row.is_empty() {
+            return rows.is_empty();
+        }
+        if let Some(head) = self.head(&row[0]) {
+            let spec = self.specialize_row(row, &head).unwrap();
+            return self.useful(&self.specialize(rows, &head), &spec);
+        }
+        let heads = self.heads(rows);
+        match self.signature(&heads) {
+            Some(ref sig) if sig.iter().all(|h| heads.contains(h)) => {
+                sig.iter().any(|h| {
+                    let spec = self.specialize_row(row, h).unwrap();
+                    self.useful(&self.specialize(rows, h), &spec)
+                })
+            }
+            _ => self.useful(&self.default(rows), &row[1..]),
+        }
+    }
+
+    /// Patterns of `n` values matched by none of `rows`
+    fn missing(&self, rows: &[Row], n: usize) -> Option<Row> {
+        if n == 0 {
+            return if rows.is_empty() { Some(vec![]) } else { None };
+        }
+        let heads = self.heads(rows);
+        match self.signature(&heads) {
+            Some(ref sig) if sig.iter().all(|h| heads.contains(h)) => {
+                for h in sig.iter() {
+                    let arity = h.arity();
+                    if let Some(mut args) = self.missing(&self.specialize(rows, h), arity + n - 1) {
+                        let rest = args.split_off(arity);
+                        let mut res = vec![h.pattern(args)];
+                        res.extend(rest);
+                        return Some(res);
+                    }
+                }
+                None
+            }
+            sig => {
+                let mut res = match self.missing(&self.default(rows), n - 1) {
+                    Some(res) => res,
+                    None => return None,
+                };
+                // A constructor not in the column if the type has finitely many
+                let first = sig.and_then(|sig| sig.into_iter().find(|h| !heads.contains(h)))
+                    .map(|h| h.pattern(vec![Pattern::Wildcard; h.arity()]))
+                    .unwrap_or(Pattern::Wildcard);
+                res.insert(0, first);
+                Some(res)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::parser;
+
+    fn check(src: &str) -> Vec<MatchWarning> {
+        let mut interner = Interner::new();
+        let defs = parser::parse(src, &mut interner).unwrap();
+        let mut typedefs = HashMap::new();
+        let mut arms = None;
+        for def in defs {
+            let name = interner.trace(def.ident).to_owned();
+            match def.node {
+                Item::Alg(_, vs) => {
+                    typedefs.insert(def.ident, box TypeDef::new(name, vec![], TypeKind::Algebra(vs)));
+                }
+                Item::Form(box Form { node: Expr::Abs(lambda), .. }) => {
+                    if let Expr::Match(_, a) = lambda.body.node {
+                        arms = Some((lambda.body.tag.pos, a));
+                    }
+                }
+                _ => {}
+            }
+        }
+        let (pos, arms) = arms.unwrap();
+        check_match(&arms, pos, &typedefs, &mut interner)
+    }
+
+    #[test]
+    fn exhaust_missing_constructor() {
+        let data = "data Shape { Circle(Float), Rect(Int, Bool), Tri(Int, Int, Int) }\n";
+        let src = format!("{}def area(s) = match (s) {{ Circle(_) => 1, Rect(_, true) => 2, Tri(a, b, c) => a }}",
+                          data);
+        match check(&src).as_slice() {
+            &[MatchWarning::NonExhaustive(ref p, _)] => assert_eq!(p, "Rect(_, false)"),
+            w => panic!("expect non-exhaustive, found {:?}", w),
+        }
+        let src = format!("{}def area(s) = match (s) {{ Rect(_, _) => 2, _ => 0 }}", data);
+        assert_eq!(check(&src), vec![]);
+        let src = format!("{}def area(s) = match (s) {{ Circle(_) => 1 }}", data);
+        match check(&src).as_slice() {
+            &[MatchWarning::NonExhaustive(ref p, _)] => assert_eq!(p, "Rect(_, _)"),
+            w => panic!("expect non-exhaustive, found {:?}", w),
+        }
+    }
+
+    #[test]
+    fn exhaust_redundant_arm() {
+        let src = "data Shape { Circle(Float), Rect(Int, Bool) }\n\
+                   def area(s) = match (s) { Rect(_, b) => 2, Circle(_) => 1, Rect(1, true) => 3, n => 0 }";
+        let bodies: Vec<_> = check(src)
+            .into_iter()
+            .map(|w| match w {
+                MatchWarning::Unreachable(pos) => src[pos.start()..].trim_left().chars().next(),
+                w => panic!("expect unreachable, found {:?}", w),
+            })
+            .collect();
+        assert_eq!(bodies, vec![Some('3'), Some('0')]);
+    }
+}
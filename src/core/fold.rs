@@ -0,0 +1,151 @@
+/// Evaluate constant expressions at compile time
+
+use std::mem;
+
+use internal::*;
+
+use core::term::*;
+
+/// Fold operators on literals into literals,
+///   and conditionals on a literal into the taken branch.
+///   Operations failing at runtime, like division by zero,
+///   are left to runtime.
+pub fn const_fold(term: &mut TaggedTerm) {
+    use self::Term::*;
+    match *term.body_mut() {
+        Lit(_) | Var(_) => {}
+        List(ref mut ts) |
+        Tuple(ref mut ts) |
+        Construct(_, ref mut ts) |
+        Block(ref mut ts) |
+        ApplyDir(_, ref mut ts) => {
+            for t in ts.iter_mut() {
+                const_fold(t);
+            }
+        }
+        ApplyCls(ref mut callee, ref mut args) => {
+            const_fold(callee);
+            for t in args.iter_mut() {
+                const_fold(t);
+            }
+        }
+        MakeCls(_, _, ref mut e) |
+        Unary(_, ref mut e) |
+        Field(ref mut e, _) => const_fold(e),
+        Let(_, ref mut l, ref mut r) |
+        Binary(_, ref mut l, ref mut r) |
+        Index(ref mut l, ref mut r) |
+        While(ref mut l, ref mut r) => {
+            const_fold(l);
+            const_fold(r);
+        }
+        If(ref mut c, ref mut t, ref mut f) => {
+            const_fold(c);
+            const_fold(t);
+            const_fold(f);
+        }
+        Case(ref mut e, ref mut arms, ref mut default) => {
+            const_fold(e);
+            for &mut (_, ref mut arm) in arms.iter_mut() {
+                const_fold(arm);
+            }
+            if let Some(ref mut d) = *default {
+                const_fold(d);
+            }
+        }
+    }
+
+    let folded = match *term.body_mut() {
+        Binary(op, ref l, ref r) => {
+            match (l.body(), r.body()) {
+                (&Lit(ref a), &Lit(ref b)) => fold_binary(op, a, b).map(Lit),
+                _ => None,
+            }
+        }
+        Unary(op, ref e) => {
+            match *e.body() {
+                Lit(ref a) => fold_unary(op, a).map(Lit),
+                _ => None,
+            }
+        }
+        If(ref mut c, ref mut t, ref mut f) => {
+            match *c.body() {
+                Lit(self::Lit::Bool(true)) => Some(mem::replace(t.body_mut(), Block(vec![]))),
+                Lit(self::Lit::Bool(false)) => Some(mem::replace(f.body_mut(), Block(vec![]))),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    if let Some(node) = folded {
+        *term.body_mut() = node;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::form::Span;
+    use types::*;
+    use utils::*;
+
+    fn lit(l: Lit) -> P<TaggedTerm> {
+        let ty = Scheme::Mono(l.lit_type());
+        P(TaggedTerm::new(Span::new(0, 0), ty, Term::Lit(l)))
+    }
+
+    fn int(i: i32) -> P<TaggedTerm> {
+        lit(Lit::Int(i))
+    }
+
+    fn binary(op: BinOp, l: P<TaggedTerm>, r: P<TaggedTerm>) -> P<TaggedTerm> {
+        let ty = l.ref_scheme().clone();
+        P(TaggedTerm::new(Span::new(0, 0), ty, Term::Binary(op, l, r)))
+    }
+
+    fn folded(mut term: P<TaggedTerm>) -> Term {
+        const_fold(&mut term);
+        term.body().clone()
+    }
+
+    #[test]
+    fn fold_arithmetic() {
+        // 2 + 3 * 4
+        let term = binary(BinOp::Add, int(2), binary(BinOp::Mul, int(3), int(4)));
+        assert_eq!(folded(term), Term::Lit(Lit::Int(14)));
+        let term = binary(BinOp::Lt, lit(Lit::Float(1.5)), lit(Lit::Float(2.0)));
+        assert_eq!(folded(term), Term::Lit(Lit::Bool(true)));
+        let term = P(TaggedTerm::new(Span::new(0, 0), Scheme::con("Bool"), Term::Unary(UnOp::Not, lit(Lit::Bool(true)))));
+        assert_eq!(folded(term), Term::Lit(Lit::Bool(false)));
+    }
+
+    #[test]
+    fn fold_long() {
+        let long = |i: i64| lit(Lit::Long(i));
+        let term = binary(BinOp::Add, long(1 << 40), binary(BinOp::Mul, long(2), long(3)));
+        assert_eq!(folded(term), Term::Lit(Lit::Long((1 << 40) + 6)));
+        // Overflow is left to runtime, which may trap
+        let term = binary(BinOp::Add, long(i64::max_value()), long(1));
+        assert_eq!(folded(term.clone()), *term.body());
+        assert_eq!(fold_unary(UnOp::Neg, &Lit::Long(i64::min_value())), None);
+        let term = binary(BinOp::Div, long(1), long(0));
+        assert_eq!(folded(term.clone()), *term.body());
+        assert_eq!(fold_unary(UnOp::Neg, &Lit::Long(5)), Some(Lit::Long(-5)));
+    }
+
+    #[test]
+    fn fold_keeps_division_by_zero() {
+        let term = binary(BinOp::Div, int(1), binary(BinOp::Sub, int(2), int(2)));
+        assert_eq!(folded(term), Term::Binary(BinOp::Div, int(1), int(0)));
+        let term = binary(BinOp::Rem, int(1), int(0));
+        assert_eq!(folded(term.clone()), *term.body());
+    }
+
+    #[test]
+    fn fold_if() {
+        let cond = binary(BinOp::Eq, int(1), int(1));
+        let term = P(TaggedTerm::new(Span::new(0, 0), Scheme::con("Int"), Term::If(cond, int(1), int(2))));
+        assert_eq!(folded(term), Term::Lit(Lit::Int(1)));
+    }
+}
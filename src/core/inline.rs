@@ -0,0 +1,257 @@
+/// Inline small functions at their direct call sites
+
+use std::collections::HashSet;
+use std::collections::HashMap;
+
+use utils::*;
+use internal::*;
+
+use core::term::*;
+use core::visit::*;
+
+/// A function which could be inlined
+struct Candidate {
+    params: Vec<Id>,
+    body: TaggedTerm,
+    /// All variables referenced in body, except parameters
+    refs: HashSet<Id>,
+    /// All variables bound in body
+    binders: HashSet<Id>,
+}
+
+/// Replace direct calls to small functions with their bodies.
+///   A function is inlined if its body has no more than `threshold` nodes,
+///   and it is neither recursive nor making closures.
+///   Arguments should be literals or variables, so they are
+///   substituted for parameters without changing evaluation.
+pub fn inline(funs: &mut HashMap<Id, P<FunDef>>, threshold: usize) {
+    let candidates: HashMap<Id, Candidate> = funs.values()
+        .filter(|f| f.fv().is_empty() && size(f.body()) <= threshold)
+        .filter(|f| !mentions(f.body(), f.name()) && !makes_closure(f.body()))
+        .map(|f| {
+            let params: Vec<_> = f.parameters().iter().map(|p| p.name()).collect();
+            let mut refs = HashSet::new();
+            let mut binders = HashSet::new();
+            collect_vars(f.body(), &mut refs, &mut binders);
+            for p in params.iter() {
+                refs.remove(p);
+            }
+            (f.name(), Candidate { params, body: f.body().clone(), refs, binders })
+        })
+        .collect();
+
+    for fun in funs.values_mut() {
+        let scope = fun.parameters()
+            .iter()
+            .chain(fun.fv().iter())
+            .map(|v| v.name())
+            .collect();
+        let body = fun.body().clone();
+        *fun.body_mut() = Rewrite { candidates: &candidates, scope }.fold_term(body);
+    }
+}
+
+/// Number of nodes in a term
+fn size(term: &TaggedTerm) -> usize {
+    let mut n = 0;
+    visit_nodes(term, |_| n += 1);
+    n
+}
+
+/// Whether a term refers to the name
+fn mentions(term: &TaggedTerm, name: Id) -> bool {
+    let mut found = false;
+    visit_nodes(term, |t| match *t.body() {
+        Term::Var(n) |
+        Term::ApplyDir(VarDecl(n, _), _) if n == name => found = true,
+        Term::MakeCls(_, ref cls, _) if cls.entry() == name => found = true,
+        _ => {}
+    });
+    found
+}
+
+fn makes_closure(term: &TaggedTerm) -> bool {
+    let mut found = false;
+    visit_nodes(term, |t| if let Term::MakeCls(..) = *t.body() {
+        found = true;
+    });
+    found
+}
+
+fn collect_vars(term: &TaggedTerm, refs: &mut HashSet<Id>, binders: &mut HashSet<Id>) {
+    visit_nodes(term, |t| match *t.body() {
+        Term::Var(n) => {
+            refs.insert(n);
+        }
+        Term::Let(ref v, ..) => {
+            binders.insert(v.name());
+        }
+        _ => {}
+    });
+}
+
+/// Try to inline a call, arguments are substituted for parameters
+fn inline_call(callee: &Candidate, args: &[P<TaggedTerm>], scope: &HashSet<Id>) -> Option<TaggedTerm> {
+    // Names referred in callee should not be captured by locals of caller
+    if callee.params.len() != args.len() || callee.refs.iter().any(|r| scope.contains(r)) {
+        return None;
+    }
+    let mut sub = HashMap::new();
+    for (&p, arg) in callee.params.iter().zip(args.iter()) {
+        match *arg.body() {
+            // Argument should not be captured by locals of callee
+            Term::Var(n) if !callee.binders.contains(&n) => {}
+            Term::Lit(_) => {}
+            _ => return None,
+        }
+        sub.insert(p, arg.body().clone());
+    }
+    Some(Substitute(sub).fold_term(callee.body.clone()))
+}
+
+/// Replace parameters with arguments
+struct Substitute(HashMap<Id, Term>);
+
+impl Folder for Substitute {
+    fn fold_term(&mut self, term: TaggedTerm) -> TaggedTerm {
+        let (pos, ty) = (term.pos(), term.ref_scheme().clone());
+        match term.into_body() {
+            Term::Var(n) => {
+                let node = self.0.get(&n).cloned().unwrap_or(Term::Var(n));
+                TaggedTerm::new(pos, ty, node)
+            }
+            // A local binding shadows the parameter
+            Term::Let(v, val, exp) => {
+                let val = P(self.fold_term(*val));
+                let shadowed = self.0.remove(&v.name());
+                let exp = P(self.fold_term(*exp));
+                if let Some(arg) = shadowed {
+                    self.0.insert(v.name(), arg);
+                }
+                TaggedTerm::new(pos, ty, Term::Let(v, val, exp))
+            }
+            node => fold_children(self, TaggedTerm::new(pos, ty, node)),
+        }
+    }
+}
+
+/// Inline calls to candidates, `scope` holds locals of the caller
+struct Rewrite<'a> {
+    candidates: &'a HashMap<Id, Candidate>,
+    scope: HashSet<Id>,
+}
+
+impl<'a> Rewrite<'a> {
+    fn fold_under(&mut self, bound: Id, term: TaggedTerm) -> TaggedTerm {
+        let fresh = self.scope.insert(bound);
+        let term = self.fold_term(term);
+        if fresh {
+            self.scope.remove(&bound);
+        }
+        term
+    }
+}
+
+impl<'a> Folder for Rewrite<'a> {
+    fn fold_term(&mut self, term: TaggedTerm) -> TaggedTerm {
+        let inlined = match *term.body() {
+            Term::ApplyDir(VarDecl(n, _), ref args) => {
+                self.candidates.get(&n).and_then(|c| inline_call(c, args, &self.scope))
+            }
+            _ => None,
+        };
+        let (pos, ty) = (term.pos(), term.ref_scheme().clone());
+        if let Some(body) = inlined {
+            // Keep the type of call site
+            return TaggedTerm::new(pos, ty, body.into_body());
+        }
+        match term.into_body() {
+            Term::Let(v, val, exp) => {
+                let val = P(self.fold_term(*val));
+                let exp = P(self.fold_under(v.name(), *exp));
+                TaggedTerm::new(pos, ty, Term::Let(v, val, exp))
+            }
+            Term::MakeCls(v, cls, exp) => {
+                let exp = P(self.fold_under(v.name(), *exp));
+                TaggedTerm::new(pos, ty, Term::MakeCls(v, cls, exp))
+            }
+            node => fold_children(self, TaggedTerm::new(pos, ty, node)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::form::Span;
+    use types::*;
+
+    fn int() -> Scheme {
+        Scheme::con("Int")
+    }
+
+    fn term(node: Term) -> P<TaggedTerm> {
+        P(TaggedTerm::new(Span::new(0, 0), int(), node))
+    }
+
+    fn int_fn() -> Scheme {
+        Scheme::arrow(vec![Type::Con("Int".to_string())], Type::Con("Int".to_string()))
+    }
+
+    fn call(callee: Id, arg: Term) -> TaggedTerm {
+        TaggedTerm::new(Span::new(0, 0), int(), Term::ApplyDir(VarDecl(callee, int_fn()), vec![term(arg)]))
+    }
+
+    #[test]
+    fn inline_identity() {
+        let mut interner = Interner::new();
+        let (id, main, x, y) = (interner.intern("id"), interner.intern("main"),
+                                interner.intern("x"), interner.intern("y"));
+        let mut funs = HashMap::new();
+        // def id(x) = x
+        funs.insert(id, P(FunDef::new(id, int_fn(), vec![VarDecl(x, int())], vec![],
+                                      *term(Term::Var(x)))));
+        // def main(y) = id(y)
+        funs.insert(main, P(FunDef::new(main, int_fn(), vec![VarDecl(y, int())], vec![],
+                                        call(id, Term::Var(y)))));
+
+        inline(&mut funs, 8);
+        assert_eq!(*funs[&main].body().body(), Term::Var(y));
+    }
+
+    #[test]
+    fn inline_guards() {
+        let mut interner = Interner::new();
+        let (fact, big, main, n) = (interner.intern("fact"), interner.intern("big"),
+                                    interner.intern("main"), interner.intern("n"));
+        let mut funs = HashMap::new();
+        // def fact(n) = fact(n)
+        funs.insert(fact, P(FunDef::new(fact, int_fn(), vec![VarDecl(n, int())], vec![],
+                                        call(fact, Term::Var(n)))));
+        // def big(n) = n + n + n
+        let sum = Term::Binary(BinOp::Add, term(Term::Var(n)), term(Term::Var(n)));
+        let sum = Term::Binary(BinOp::Add, term(sum), term(Term::Var(n)));
+        funs.insert(big, P(FunDef::new(big, int_fn(), vec![VarDecl(n, int())], vec![],
+                                       *term(sum))));
+        // def main(n) = { fact(1), big(1) }
+        let body = Term::Block(vec![P(call(fact, Term::Lit(Lit::Int(1)))),
+                                    P(call(big, Term::Lit(Lit::Int(1))))]);
+        funs.insert(main, P(FunDef::new(main, int_fn(), vec![VarDecl(n, int())], vec![],
+                                        *term(body.clone()))));
+
+        // Recursive function is never inlined, nor the one too large
+        inline(&mut funs, 4);
+        assert_eq!(*funs[&main].body().body(), body);
+        assert_eq!(*funs[&fact].body(), call(fact, Term::Var(n)));
+
+        inline(&mut funs, 5);
+        match *funs[&main].body().body() {
+            Term::Block(ref ts) => {
+                assert_eq!(*ts[0], call(fact, Term::Lit(Lit::Int(1))));
+                assert!(match *ts[1].body() { Term::Binary(..) => true, _ => false });
+            }
+            ref t => panic!("unexpected term {:?}", t),
+        }
+    }
+}
@@ -1,5 +1,23 @@
 mod term;
 mod convert;
+mod direct;
+mod inline;
+mod fold;
+mod dead;
+mod rename;
+mod scc;
+mod eval;
+mod visit;
+mod exhaust;
 
 pub use core::term::*;
 pub use core::convert::*;
+pub use core::direct::*;
+pub use core::inline::*;
+pub use core::fold::*;
+pub use core::dead::*;
+pub use core::rename::*;
+pub use core::scc::*;
+pub use core::eval::*;
+pub use core::visit::*;
+pub use core::exhaust::*;
@@ -0,0 +1,123 @@
+/// Give every local binding a unique name
+
+use std::collections::HashMap;
+
+use utils::*;
+use internal::*;
+
+use core::term::*;
+use core::visit::*;
+
+/// Rename bindings of `let` and closures in all functions,
+///   so no two bindings share a name even if one shadows another.
+///   Allocas in generated code are then never named the same.
+pub fn uniquify(funs: &mut HashMap<Id, P<FunDef>>, interner: &mut Interner) {
+    let mut renamer = Renamer { interner, count: 0, env: HashMap::new() };
+    for fun in funs.values_mut() {
+        let body = fun.body().clone();
+        *fun.body_mut() = renamer.fold_term(body);
+    }
+}
+
+struct Renamer<'i> {
+    interner: &'i mut Interner,
+    count: usize,
+    /// New names of bindings in scope
+    env: HashMap<Id, Id>,
+}
+
+impl<'i> Renamer<'i> {
+    /// Make a new name for a binding, e.g. `x.1` for `x`.
+    ///   Source identifiers never contain `.`, so it is unique.
+    fn fresh(&mut self, origin: Id) -> Id {
+        self.count = self.count + 1;
+        let name = format!("{}.{}", self.interner.trace(origin), self.count);
+        self.interner.intern(&name)
+    }
+
+    /// Rename a binding in scope, returning the outer scope
+    fn bind(&mut self, var: &mut VarDecl) -> HashMap<Id, Id> {
+        let new = self.fresh(var.name());
+        let outer = self.env.clone();
+        self.env.insert(var.name(), new);
+        var.0 = new;
+        outer
+    }
+}
+
+impl<'i> Folder for Renamer<'i> {
+    fn fold_term(&mut self, term: TaggedTerm) -> TaggedTerm {
+        let (pos, ty) = (term.pos(), term.ref_scheme().clone());
+        match term.into_body() {
+            Term::Var(n) => {
+                let n = self.env.get(&n).cloned().unwrap_or(n);
+                TaggedTerm::new(pos, ty, Term::Var(n))
+            }
+            // Closure may capture itself for recursion
+            Term::MakeCls(mut var, mut cls, exp) => {
+                let outer = self.bind(&mut var);
+                for fv in cls.fv_mut().iter_mut() {
+                    if let Some(&new) = self.env.get(&*fv) {
+                        *fv = new;
+                    }
+                }
+                let exp = P(self.fold_term(*exp));
+                self.env = outer;
+                TaggedTerm::new(pos, ty, Term::MakeCls(var, cls, exp))
+            }
+            Term::Let(mut var, val, exp) => {
+                let val = P(self.fold_term(*val));
+                let outer = self.bind(&mut var);
+                let exp = P(self.fold_term(*exp));
+                self.env = outer;
+                TaggedTerm::new(pos, ty, Term::Let(var, val, exp))
+            }
+            node => fold_children(self, TaggedTerm::new(pos, ty, node)),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::form::Span;
+    use types::*;
+
+    fn term(node: Term) -> P<TaggedTerm> {
+        P(TaggedTerm::new(Span::new(0, 0), Scheme::con("Int"), node))
+    }
+
+    #[test]
+    fn uniquify_shadowed() {
+        let mut interner = Interner::new();
+        let (main, x) = (interner.intern("main"), interner.intern("x"));
+        // let x = 1 in let x = x + 1 in x
+        let inc = Term::Binary(BinOp::Add, term(Term::Var(x)), term(Term::Lit(Lit::Int(1))));
+        let inner = Term::Let(VarDecl(x, Scheme::con("Int")), term(inc), term(Term::Var(x)));
+        let body = Term::Let(VarDecl(x, Scheme::con("Int")), term(Term::Lit(Lit::Int(1))), term(inner));
+        let fun = FunDef::new(main, Scheme::arrow(vec![], Type::Con("Int".to_string())),
+                              vec![], vec![], *term(body));
+        let mut funs = HashMap::new();
+        funs.insert(main, P(fun));
+
+        uniquify(&mut funs, &mut interner);
+
+        let (outer, inc, inner, res) = match *funs[&main].body().body() {
+            Term::Let(ref o, _, ref e) => {
+                match *e.body() {
+                    Term::Let(ref i, ref v, ref r) => (o.name(), v.clone(), i.name(), r.clone()),
+                    ref t => panic!("unexpected term {:?}", t),
+                }
+            }
+            ref t => panic!("unexpected term {:?}", t),
+        };
+        assert!(outer != inner && outer != x && inner != x);
+        // The increment refers to the outer one, the result the inner one
+        match *inc.body() {
+            Term::Binary(_, ref l, _) => assert_eq!(*l.body(), Term::Var(outer)),
+            ref t => panic!("unexpected term {:?}", t),
+        }
+        assert_eq!(*res.body(), Term::Var(inner));
+    }
+}
@@ -0,0 +1,77 @@
+/// Group mutually recursive functions
+
+use std::collections::HashMap;
+
+use utils::*;
+use internal::*;
+
+use core::term::*;
+use core::visit::*;
+
+/// Split functions into groups of mutually recursive ones,
+///   the strongly connected components of the call graph.
+///   Groups are in topological order, a function only refers to
+///   those in its own group or earlier ones,
+///   so each group could be declared then defined in turn.
+pub fn recursion_groups(funs: &HashMap<Id, P<FunDef>>) -> Vec<Vec<Id>> {
+    let graph: HashMap<Id, Vec<Id>> = funs.values()
+        .map(|f| {
+            let mut callees = vec![];
+            refers(f.body(), &mut callees);
+            callees.retain(|c| funs.contains_key(c));
+            (f.name(), callees)
+        })
+        .collect();
+    strongly_connected(&graph)
+}
+
+/// Collect names a term refers to, including called functions and closure entries
+fn refers(term: &TaggedTerm, names: &mut Vec<Id>) {
+    visit_nodes(term, |t| match *t.body() {
+        Term::Var(n) |
+        Term::ApplyDir(VarDecl(n, _), _) => names.push(n),
+        Term::MakeCls(_, ref cls, _) => names.push(cls.entry()),
+        _ => {}
+    });
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::form::Span;
+    use types::*;
+
+    fn int() -> Scheme {
+        Scheme::con("Int")
+    }
+
+    fn int_fn() -> Scheme {
+        Scheme::arrow(vec![Type::Con("Int".to_string())], Type::Con("Int".to_string()))
+    }
+
+    fn call(callee: Id, arg: Id) -> TaggedTerm {
+        let arg = P(TaggedTerm::new(Span::new(0, 0), int(), Term::Var(arg)));
+        TaggedTerm::new(Span::new(0, 0), int(), Term::ApplyDir(VarDecl(callee, int_fn()), vec![arg]))
+    }
+
+    #[test]
+    fn group_mutual_recursion() {
+        let mut interner = Interner::new();
+        let (even, odd, main, n) = (interner.intern("even"), interner.intern("odd"),
+                                    interner.intern("main"), interner.intern("n"));
+        let mut funs = HashMap::new();
+        // def even(n) = odd(n)
+        // def odd(n) = even(n)
+        // def main(n) = even(n)
+        for &(f, g) in [(even, odd), (odd, even), (main, even)].iter() {
+            funs.insert(f, P(FunDef::new(f, int_fn(), vec![VarDecl(n, int())], vec![], call(g, n))));
+        }
+
+        let groups = recursion_groups(&funs);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].len(), 2);
+        assert!(groups[0].contains(&even) && groups[0].contains(&odd));
+        assert_eq!(groups[1], vec![main]);
+    }
+}
@@ -10,6 +10,7 @@ use std::iter::DoubleEndedIterator;
 use internal::*;
 use types::*;
 use utils::*;
+use syntax::form::Span;
 
 
 type Node = Box<TaggedTerm>;
@@ -32,6 +33,41 @@ impl TypeDef {
     pub fn new(name: String, params: Vec<Name>, body: TypeKind) -> Self {
         TypeDef { name, params, body }
     }
+
+    pub fn name(&self) -> &Name {
+        &self.name
+    }
+
+    pub fn params(&self) -> &[Name] {
+        &self.params
+    }
+
+    pub fn body(&self) -> &TypeKind {
+        &self.body
+    }
+
+    /// Find a variant by its constructor name,
+    ///   along with its index in the definition
+    pub fn variant(&self, ctor: &str) -> Option<(usize, &Variant)> {
+        match self.body {
+            TypeKind::Algebra(ref vs) => vs.iter().enumerate().find(|&(_, v)| v.name == ctor),
+            _ => None,
+        }
+    }
+
+    /// Find the index of a named field in struct variants
+    pub fn field_index(&self, field: &str) -> Option<usize> {
+        match self.body {
+            TypeKind::Algebra(ref vs) => {
+                vs.iter()
+                    .filter_map(|v| {
+                        v.fields().iter().position(|f| f.name.as_ref().map(|n| n.as_str()) == Some(field))
+                    })
+                    .next()
+            }
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -72,6 +108,10 @@ impl FunDef {
         self.body.deref()
     }
 
+    pub fn body_mut(&mut self) -> &mut TaggedTerm {
+        self.body.deref_mut()
+    }
+
     pub fn ref_type(&self) -> &Type {
         self.ty.body()
     }
@@ -84,13 +124,19 @@ impl FunDef {
 /// Represents a expression term
 #[derive(Clone, PartialEq, Debug)]
 pub struct TaggedTerm {
+    /// Source position the term is lowered from
+    pos: Span,
     ty: Scheme,
     node: Term,
 }
 
 impl TaggedTerm {
-    pub fn new(ty: Scheme, node: Term) -> TaggedTerm {
-        TaggedTerm { ty, node }
+    pub fn new(pos: Span, ty: Scheme, node: Term) -> TaggedTerm {
+        TaggedTerm { pos, ty, node }
+    }
+
+    pub fn pos(&self) -> Span {
+        self.pos
     }
 
     pub fn ref_scheme(&self) -> &Scheme {
@@ -99,6 +145,12 @@ impl TaggedTerm {
     pub fn body(&self) -> &Term {
         &self.node
     }
+    pub fn body_mut(&mut self) -> &mut Term {
+        &mut self.node
+    }
+    pub fn into_body(self) -> Term {
+        self.node
+    }
 }
 
 
@@ -112,6 +164,12 @@ pub enum Term {
     /// List (array)
     /// e.g. `[fuck, shit]`
     List(Vec<Node>),
+    /// Tuple
+    /// e.g. `(fuck, shit)`
+    Tuple(Vec<Node>),
+    /// Construct a variant of `data` by index,
+    ///   with fields in declaration order
+    Construct(usize, Vec<Node>),
     /// Block (statement sequence)
     /// e.g. `{ print(fuck); print(shit); 1 }`
     Block(Vec<Node>),
@@ -141,6 +199,27 @@ pub enum Term {
     /// Conditional expression
     /// e.g. `if (fuck == shit) 1 else 0`
     If(Node, Node, Node),
+    /// While loop, evaluates to `Void`
+    /// e.g. `while (fuck < 10) print(fuck)`
+    While(Node, Node),
+
+    /// Get the n-th field of a variant or tuple value
+    Field(Node, usize),
+    /// Get an element of list by index
+    /// e.g. `fuck[1]`
+    Index(Node, Node),
+    /// Branch on the tag of a value, lowered from `match`.
+    /// Falls to the default arm if no tag matches.
+    Case(Node, Vec<(Tag, Node)>, Option<Node>),
+}
+
+/// Discriminant tested by an arm of `Case`
+#[derive(Clone, PartialEq, Debug)]
+pub enum Tag {
+    /// Index of a variant in its `data` definition
+    Variant(usize),
+    /// Literal value
+    Lit(Lit),
 }
 
 /// Represents a closure, including a entry as
@@ -163,6 +242,10 @@ impl Closure {
         self.actualFv.clone()
     }
 
+    pub fn fv_mut(&mut self) -> &mut Vec<Id> {
+        &mut self.actualFv
+    }
+
     pub fn entry(&self) -> Id {
         self.entry.clone()
     }
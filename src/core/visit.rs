@@ -0,0 +1,227 @@
+/// Traversal of terms, a pass overrides only the nodes it cares about
+
+use utils::*;
+use internal::*;
+
+use core::term::*;
+
+/// Visit a term by reference, walking into every sub-term by default
+pub trait Visitor {
+    fn visit_term(&mut self, term: &TaggedTerm) {
+        walk_term(self, term)
+    }
+
+    /// Occurrence of a variable
+    fn visit_var(&mut self, _name: Id) {}
+
+    /// Name bound by `let` or closure
+    fn visit_binder(&mut self, _var: &VarDecl) {}
+}
+
+/// Visit sub-terms of a term in evaluation order
+pub fn walk_term<V: Visitor + ?Sized>(v: &mut V, term: &TaggedTerm) {
+    use self::Term::*;
+    match *term.body() {
+        Lit(_) => {}
+        Var(n) => v.visit_var(n),
+        List(ref ts) |
+        Tuple(ref ts) |
+        Construct(_, ref ts) |
+        Block(ref ts) |
+        ApplyDir(_, ref ts) => {
+            for t in ts.iter() {
+                v.visit_term(t);
+            }
+        }
+        ApplyCls(ref callee, ref args) => {
+            v.visit_term(callee);
+            for t in args.iter() {
+                v.visit_term(t);
+            }
+        }
+        MakeCls(ref var, _, ref e) => {
+            v.visit_binder(var);
+            v.visit_term(e);
+        }
+        Unary(_, ref e) |
+        Field(ref e, _) => v.visit_term(e),
+        Let(ref var, ref val, ref exp) => {
+            v.visit_term(val);
+            v.visit_binder(var);
+            v.visit_term(exp);
+        }
+        Binary(_, ref l, ref r) |
+        Index(ref l, ref r) |
+        While(ref l, ref r) => {
+            v.visit_term(l);
+            v.visit_term(r);
+        }
+        If(ref c, ref t, ref f) => {
+            v.visit_term(c);
+            v.visit_term(t);
+            v.visit_term(f);
+        }
+        Case(ref e, ref arms, ref default) => {
+            v.visit_term(e);
+            for &(_, ref arm) in arms.iter() {
+                v.visit_term(arm);
+            }
+            if let Some(ref d) = *default {
+                v.visit_term(d);
+            }
+        }
+    }
+}
+
+/// Rebuild a term by value, folding every sub-term by default
+pub trait Folder {
+    fn fold_term(&mut self, term: TaggedTerm) -> TaggedTerm {
+        fold_children(self, term)
+    }
+}
+
+/// Rebuild a term with sub-terms folded, keeping its position and type
+pub fn fold_children<F: Folder + ?Sized>(f: &mut F, term: TaggedTerm) -> TaggedTerm {
+    use self::Term::*;
+    let (pos, ty) = (term.pos(), term.ref_scheme().clone());
+    let node = match term.into_body() {
+        t @ Lit(_) |
+        t @ Var(_) => t,
+        List(ts) => List(fold_all(f, ts)),
+        Tuple(ts) => Tuple(fold_all(f, ts)),
+        Construct(idx, ts) => Construct(idx, fold_all(f, ts)),
+        Block(ts) => Block(fold_all(f, ts)),
+        MakeCls(var, cls, e) => MakeCls(var, cls, fold_one(f, e)),
+        ApplyCls(callee, args) => {
+            let callee = fold_one(f, callee);
+            ApplyCls(callee, fold_all(f, args))
+        }
+        ApplyDir(callee, args) => ApplyDir(callee, fold_all(f, args)),
+        Binary(op, l, r) => {
+            let l = fold_one(f, l);
+            Binary(op, l, fold_one(f, r))
+        }
+        Unary(op, e) => Unary(op, fold_one(f, e)),
+        Let(var, val, exp) => {
+            let val = fold_one(f, val);
+            Let(var, val, fold_one(f, exp))
+        }
+        If(c, t, e) => {
+            let c = fold_one(f, c);
+            let t = fold_one(f, t);
+            If(c, t, fold_one(f, e))
+        }
+        While(c, b) => {
+            let c = fold_one(f, c);
+            While(c, fold_one(f, b))
+        }
+        Field(e, idx) => Field(fold_one(f, e), idx),
+        Index(l, i) => {
+            let l = fold_one(f, l);
+            Index(l, fold_one(f, i))
+        }
+        Case(e, arms, default) => {
+            let e = fold_one(f, e);
+            let arms = arms.into_iter().map(|(tag, arm)| (tag, fold_one(f, arm))).collect();
+            Case(e, arms, default.map(|d| fold_one(f, d)))
+        }
+    };
+    TaggedTerm::new(pos, ty, node)
+}
+
+fn fold_one<F: Folder + ?Sized>(f: &mut F, t: P<TaggedTerm>) -> P<TaggedTerm> {
+    P(f.fold_term(*t))
+}
+
+fn fold_all<F: Folder + ?Sized>(f: &mut F, ts: Vec<P<TaggedTerm>>) -> Vec<P<TaggedTerm>> {
+    ts.into_iter().map(|t| fold_one(f, t)).collect()
+}
+
+/// Visit every node of a term in pre-order
+pub fn visit_nodes<F: FnMut(&TaggedTerm)>(term: &TaggedTerm, f: F) {
+    struct Nodes<F>(F);
+
+    impl<F: FnMut(&TaggedTerm)> Visitor for Nodes<F> {
+        fn visit_term(&mut self, term: &TaggedTerm) {
+            (self.0)(term);
+            walk_term(self, term)
+        }
+    }
+
+    Nodes(f).visit_term(term)
+}
+
+/// Count occurrences of a variable in a term
+pub fn occurrences(term: &TaggedTerm, name: Id) -> usize {
+    struct Count {
+        name: Id,
+        count: usize,
+    }
+
+    impl Visitor for Count {
+        fn visit_var(&mut self, n: Id) {
+            if n == self.name {
+                self.count += 1;
+            }
+        }
+    }
+
+    let mut c = Count { name, count: 0 };
+    c.visit_term(term);
+    c.count
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::form::Span;
+    use types::*;
+
+    fn term(node: Term) -> P<TaggedTerm> {
+        P(TaggedTerm::new(Span::new(0, 0), Scheme::con("Int"), node))
+    }
+
+    fn var(n: Id) -> P<TaggedTerm> {
+        term(Term::Var(n))
+    }
+
+    /// Replace a variable with a literal
+    struct Replace(Id, Lit);
+
+    impl Folder for Replace {
+        fn fold_term(&mut self, t: TaggedTerm) -> TaggedTerm {
+            match *t.body() {
+                Term::Var(n) if n == self.0 => {
+                    return TaggedTerm::new(t.pos(), t.ref_scheme().clone(), Term::Lit(self.1.clone()));
+                }
+                _ => {}
+            }
+            fold_children(self, t)
+        }
+    }
+
+    #[test]
+    fn visit_count_vars() {
+        let mut interner = Interner::new();
+        let (x, y) = (interner.intern("x"), interner.intern("y"));
+        // let y = x + x in if (y) x else y
+        let cond = term(Term::If(var(y), var(x), var(y)));
+        let t = term(Term::Let(VarDecl(y, Scheme::con("Int")),
+                               term(Term::Binary(BinOp::Add, var(x), var(x))),
+                               cond));
+        assert_eq!(occurrences(&t, x), 3);
+        assert_eq!(occurrences(&t, y), 2);
+
+        let t = Replace(x, Lit::Int(1)).fold_term(*t);
+        assert_eq!(occurrences(&t, x), 0);
+        assert_eq!(occurrences(&t, y), 2);
+        match *t.body() {
+            Term::Let(_, ref val, _) => {
+                assert_eq!(*val.body(), Term::Binary(BinOp::Add, term(Term::Lit(Lit::Int(1))),
+                                                     term(Term::Lit(Lit::Int(1)))));
+            }
+            ref n => panic!("expect let, found {:?}", n),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+/// Errors of every stage of compiling
+
+use std::fmt;
+
+use syntax::form::{Span, Pos};
+use syntax::parser::ParseError;
+use syntax::resolve::ResolveError;
+use typeinfer::TypeError;
+
+/// An error with the message and where it is in source
+#[derive(Clone, PartialEq, Debug)]
+pub enum CompileError {
+    Parse(String, Span),
+    Resolve(String, Span),
+    Type(String, Span),
+    /// Failed to lower forms into core terms or code
+    Lower(String, Span),
+}
+
+impl CompileError {
+    pub fn pos(&self) -> Span {
+        use self::CompileError::*;
+        match *self {
+            Parse(_, pos) | Resolve(_, pos) | Type(_, pos) | Lower(_, pos) => pos,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        use self::CompileError::*;
+        match *self {
+            Parse(ref msg, _) | Resolve(ref msg, _) | Type(ref msg, _) | Lower(ref msg, _) => msg,
+        }
+    }
+
+    fn stage(&self) -> &'static str {
+        use self::CompileError::*;
+        match *self {
+            Parse(..) => "parse",
+            Resolve(..) => "resolve",
+            Type(..) => "type",
+            Lower(..) => "lowering",
+        }
+    }
+
+    /// Show the error at line and column of `src`
+    pub fn located<'a>(&'a self, src: &'a str) -> Located<'a> {
+        Located(self, src)
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} error at {}: {}", self.stage(), self.pos().start(), self.message())
+    }
+}
+
+/// An error with the source it is found in
+pub struct Located<'a>(&'a CompileError, &'a str);
+
+impl<'a> fmt::Display for Located<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Pos { line, col } = Pos::locate(self.1, self.0.pos().start());
+        write!(f, "{} error at {}:{}: {}", self.0.stage(), line, col, self.0.message())
+    }
+}
+
+impl From<ParseError> for CompileError {
+    fn from(e: ParseError) -> CompileError {
+        CompileError::Parse(e.to_string(), Span::point(e.offset))
+    }
+}
+
+impl From<ResolveError> for CompileError {
+    fn from(e: ResolveError) -> CompileError {
+        let pos = match e {
+            ResolveError::Unbound(_, pos) => pos,
+        };
+        CompileError::Resolve(e.to_string(), pos)
+    }
+}
+
+impl From<(TypeError, Span)> for CompileError {
+    /// Errors of unification are reported at the definition they are found in
+    fn from((e, def): (TypeError, Span)) -> CompileError {
+        let pos = e.pos().unwrap_or(def);
+        CompileError::Type(e.to_string(), pos)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::*;
+    use syntax::parser;
+    use typeinfer::Infer;
+    use compile;
+
+    /// The first error checking a program
+    fn check(src: &str) -> Result<(), CompileError> {
+        let mut interner = Interner::new();
+        let env = Infer::new_env();
+        let plus = interner.intern("+");
+        let prelude = env.extend_n(vec![(plus, parser::parse_type("forall a. a * a -> a", &mut interner))]);
+        compile::check(src, &prelude, &mut interner).map(|_| ()).map_err(|mut es| es.remove(0))
+    }
+
+    #[test]
+    fn malformed_program_is_error() {
+        assert_eq!(check("def main() = 1 + 2"), Ok(()));
+        match check("def main() = (1 +") {
+            Err(CompileError::Parse(..)) => {}
+            r => panic!("expect parse error, found {:?}", r),
+        }
+        match check("def main() = 1 + y") {
+            Err(CompileError::Resolve(_, pos)) => assert_eq!(pos.start(), 17),
+            r => panic!("expect resolve error, found {:?}", r),
+        }
+        match check("def main() = 1 + 'c'") {
+            Err(CompileError::Type(..)) => {}
+            r => panic!("expect type error, found {:?}", r),
+        }
+    }
+
+    #[test]
+    fn error_located() {
+        let src = "def main() = 1\ndef f() = y";
+        assert!(check(src).unwrap_err().located(src).to_string().starts_with("resolve error at 2:11: "));
+        // Found in unifying, reported at the definition
+        let src = "def main() = 1\ndef f(x) = x(x)";
+        let at = check(src).unwrap_err().pos().start();
+        assert!(at >= src.find('\n').unwrap() && at <= src.find("def f").unwrap());
+    }
+}
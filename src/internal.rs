@@ -9,6 +9,8 @@ use std::iter::DoubleEndedIterator;
 
 use utils::*;
 use types::*;
+use error::*;
+use syntax::form::Span;
 
 
 #[derive(Debug, PartialEq, Clone)]
@@ -23,9 +25,12 @@ impl VarDecl {
 #[derive(Clone, PartialEq, Debug)]
 pub enum Lit {
     Int(i32),
+    /// Integer out of the range of `Int`
+    Long(i64),
     Float(f64),
     Str(String),
     Bool(bool),
+    Char(char),
 }
 
 impl Lit {
@@ -33,9 +38,11 @@ impl Lit {
         use self::Lit::*;
         let ty_str = match *self {
             Lit::Int(_) => "Int",
+            Lit::Long(_) => "Long",
             Lit::Float(_) => "Float",
             Lit::Str(_) => "String",
             Lit::Bool(_) => "Bool",
+            Lit::Char(_) => "Char",
         };
 
         Type::Con(ty_str.to_string())
@@ -108,22 +115,35 @@ impl BinOp {
             Dot => ".",
         }
     }
-    pub fn take(op_str: &str) -> BinOp {
+    /// Operator of the symbol at `pos`
+    pub fn take(op_str: &str, pos: Span) -> Result<BinOp, CompileError> {
         use self::BinOp::*;
-        match op_str {
+        let op = match op_str {
             "+" => Add,
             "-" => Sub,
             "*" => Mul,
             "/" => Div,
             "%" => Rem,
+            "&&" => And,
+            "||" => Or,
+            "^" => BitXor,
+            "&" => BitAnd,
+            "|" => BitOr,
+            "<<" => Shl,
+            ">>" => Shr,
             "<" => Lt,
             "<=" => Le,
             "!=" => Ne,
             ">=" => Ge,
             ">" => Gt,
             "==" => Eq,
-            _ => unreachable!(),
-        }
+            "." => Dot,
+            _ => {
+                let msg = format!("unknown operator `{}`", op_str);
+                return Err(CompileError::Parse(msg, pos));
+            }
+        };
+        Ok(op)
     }
 }
 
@@ -143,3 +163,141 @@ impl UnOp {
         }
     }
 }
+
+/// Convert between literals and values of an evaluator,
+///   whose variants of literals are named as those of `Lit`
+macro_rules! lit_value {
+    ($value:ident) => {
+        impl $value {
+            fn from_lit(lit: &$crate::internal::Lit) -> $value {
+                use $crate::internal::Lit;
+                match *lit {
+                    Lit::Int(i) => $value::Int(i),
+                    Lit::Long(i) => $value::Long(i),
+                    Lit::Float(f) => $value::Float(f),
+                    Lit::Bool(b) => $value::Bool(b),
+                    Lit::Char(c) => $value::Char(c),
+                    Lit::Str(ref s) => $value::Str(s.clone()),
+                }
+            }
+
+            fn to_lit(&self) -> Option<$crate::internal::Lit> {
+                use $crate::internal::Lit;
+                match *self {
+                    $value::Int(i) => Some(Lit::Int(i)),
+                    $value::Long(i) => Some(Lit::Long(i)),
+                    $value::Float(f) => Some(Lit::Float(f)),
+                    $value::Bool(b) => Some(Lit::Bool(b)),
+                    $value::Char(c) => Some(Lit::Char(c)),
+                    $value::Str(ref s) => Some(Lit::Str(s.clone())),
+                    _ => None,
+                }
+            }
+
+            fn truth(&self) -> bool {
+                match *self {
+                    $value::Bool(b) => b,
+                    ref v => panic!("Expect a boolean, found {:?}", v),
+                }
+            }
+        }
+    }
+}
+
+/// Apply a binary operator on literals,
+///   `None` if not applicable or failing at runtime
+pub fn fold_binary(op: BinOp, lhs: &Lit, rhs: &Lit) -> Option<Lit> {
+    use self::BinOp::*;
+    use self::Lit::*;
+    match (lhs, rhs) {
+        (&Int(a), &Int(b)) => {
+            match op {
+                // Overflow wraps or traps by options of codegen
+                Add => a.checked_add(b).map(Int),
+                Sub => a.checked_sub(b).map(Int),
+                Mul => a.checked_mul(b).map(Int),
+                Div => a.checked_div(b).map(Int),
+                Rem => a.checked_rem(b).map(Int),
+                BitAnd => Some(Int(a & b)),
+                BitOr => Some(Int(a | b)),
+                BitXor => Some(Int(a ^ b)),
+                _ => compare(op, &a, &b).map(Bool),
+            }
+        }
+        (&Long(a), &Long(b)) => {
+            match op {
+                Add => a.checked_add(b).map(Long),
+                Sub => a.checked_sub(b).map(Long),
+                Mul => a.checked_mul(b).map(Long),
+                Div => a.checked_div(b).map(Long),
+                Rem => a.checked_rem(b).map(Long),
+                BitAnd => Some(Long(a & b)),
+                BitOr => Some(Long(a | b)),
+                BitXor => Some(Long(a ^ b)),
+                _ => compare(op, &a, &b).map(Bool),
+            }
+        }
+        (&Float(a), &Float(b)) => {
+            match op {
+                Add => Some(Float(a + b)),
+                Sub => Some(Float(a - b)),
+                Mul => Some(Float(a * b)),
+                Div if b != 0.0 => Some(Float(a / b)),
+                Div | Rem => None,
+                _ => compare(op, &a, &b).map(Bool),
+            }
+        }
+        (&Bool(a), &Bool(b)) => {
+            match op {
+                And => Some(Bool(a && b)),
+                Or => Some(Bool(a || b)),
+                _ => compare(op, &a, &b).map(Bool),
+            }
+        }
+        (&Char(a), &Char(b)) => compare(op, &a, &b).map(Bool),
+        _ => None,
+    }
+}
+
+fn compare<T: PartialOrd>(op: BinOp, a: &T, b: &T) -> Option<bool> {
+    use self::BinOp::*;
+    let res = match op {
+        Eq => a == b,
+        Ne => a != b,
+        Lt => a < b,
+        Le => a <= b,
+        Gt => a > b,
+        Ge => a >= b,
+        _ => return None,
+    };
+    Some(res)
+}
+
+/// Apply a unary operator on a literal,
+///   `None` if not applicable or failing at runtime
+pub fn fold_unary(op: UnOp, val: &Lit) -> Option<Lit> {
+    match (op, val) {
+        (UnOp::Neg, &Lit::Int(i)) => i.checked_neg().map(Lit::Int),
+        (UnOp::Neg, &Lit::Long(i)) => i.checked_neg().map(Lit::Long),
+        (UnOp::Neg, &Lit::Float(f)) => Some(Lit::Float(-f)),
+        (UnOp::Not, &Lit::Bool(b)) => Some(Lit::Bool(!b)),
+        _ => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binop_take_round_trip() {
+        use self::BinOp::*;
+        let ops = [Add, Sub, Mul, Div, Rem, And, Or, BitXor, BitAnd, BitOr,
+                   Shl, Shr, Eq, Lt, Le, Ne, Ge, Gt, Dot];
+        for &op in ops.iter() {
+            assert_eq!(BinOp::take(op.as_str(), Span::new(0, 0)), Ok(op));
+        }
+        assert!(BinOp::take("<>", Span::new(0, 0)).is_err());
+    }
+}
@@ -18,10 +18,13 @@ extern crate bytecount;
 extern crate libc;
 
 pub mod utils;
+#[macro_use]
+pub mod internal;
 
 pub mod syntax;
 pub mod typeinfer;
 pub mod codegen;
 pub mod types;
 pub mod core;
-pub mod internal;
+pub mod error;
+pub mod compile;
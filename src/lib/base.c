@@ -22,3 +22,43 @@ void putFloat(const double f, void *fv)
 {
     printf("%lf", f);
 }
+
+
+// Strings are never freed, allocate them from a bump arena
+#define STR_ARENA_SIZE 65536
+
+static char *str_arena = NULL;
+static size_t str_arena_left = 0;
+
+static char *str_alloc(size_t size)
+{
+    if (size > str_arena_left)
+    {
+        size_t chunk = size > STR_ARENA_SIZE ? size : STR_ARENA_SIZE;
+        str_arena = (char *)malloc(chunk);
+        if (str_arena == NULL)
+        {
+            fprintf(stderr, "String allocate failed\n");
+            abort();
+        }
+        str_arena_left = chunk;
+    }
+    char *ret = str_arena;
+    str_arena += size;
+    str_arena_left -= size;
+    return ret;
+}
+
+char *strConcat(const char *a, const char *b, void *fv)
+{
+    size_t la = strlen(a), lb = strlen(b);
+    char *s = str_alloc(la + lb + 1);
+    memcpy(s, a, la);
+    memcpy(s + la, b, lb + 1);
+    return s;
+}
+
+int32_t strLength(const char *s, void *fv)
+{
+    return (int32_t)strlen(s);
+}
@@ -3,10 +3,14 @@ extern crate argparse;
 
 use miko::utils::*;
 use miko::syntax::parser::*;
+use miko::syntax::resolve::*;
+use miko::syntax::form::{Span, Pos};
 use miko::typeinfer::*;
 use miko::codegen::*;
 use miko::core::*;
 use miko::types::*;
+use miko::error::*;
+use miko::compile::*;
 use std::fs;
 use std::io;
 use std::io::Write;
@@ -16,9 +20,9 @@ use std::error::Error;
 
 
 #[derive(Clone, Debug)]
-enum CompileError {
-    TypeError(TypeError),
-    ParseError(ParseError),
+enum DriverError {
+    /// Errors with the source they are found in
+    Compile(Vec<CompileError>, String),
     Normal(String)
 }
 
@@ -54,9 +58,14 @@ fn load_prelude<'a, 'b: 'a>(interner: &mut Interner, env: &'b TypeEnv) -> TypeEn
         "<=" : "forall a. a * a -> Bool",
         ">=" : "forall a. a * a -> Bool",
         "==" : "forall a. a * a -> Bool",
+        "!=" : "forall a. a * a -> Bool",
         "||" : "Bool * Bool -> Bool",
         "&&" : "Bool * Bool -> Bool",
+        "print" : "forall a. a -> Void",
+        "println" : "forall a. a -> Void",
         "printLn" : "String -> Void",
+        "strcat" : "String * String -> String",
+        "strlen" : "String -> Int",
         "putNumber" : "Int -> Void"
     );
     e
@@ -118,32 +127,69 @@ fn run_repl() {
     }
 }
 
-fn compile(name: &str, src: &str) -> Result<LLVMCodegen, CompileError> {
+/// Largest body in nodes of a function inlined at its call sites
+const INLINE_THRESHOLD: usize = 16;
+
+fn compile(name: &str,
+           src: &str,
+           div_check: bool,
+           checked: bool,
+           options: CodegenOptions,
+           debug_info: bool)
+           -> Result<LLVMCodegen, Vec<CompileError>> {
     let mut inter = Interner::new();
 
-    parse(src, &mut inter)
-    .map_err(|e| CompileError::ParseError(e))
-    .and_then(|mut defs| {
+    let typed = {
         let env = Infer::new_env();
         let prelude = load_prelude(&mut inter, &env);
-        let mut infer = Infer::new(&mut inter);
-        infer.infer_defs(&prelude, &mut defs)
-            .map_err(|te| CompileError::TypeError(te))
-            .map(|()| defs)
-    })
+        check(src, &prelude, &mut inter)
+    };
+    typed
     .and_then(|defs|{
-        let (mut top, _) = K::go(defs, &mut inter);
+        let (mut top, typedefs, warnings) = K::go_with_warnings(defs, &mut inter);
+        for w in warnings.iter() {
+            let Pos { line, col } = Pos::locate(src, w.pos().start());
+            eprintln!("warning at {}:{}: {}", line, col, w);
+        }
+        direct_calls(&mut top);
+        // Debugging keeps calls as in source
+        if options.optimize {
+            inline(&mut top, INLINE_THRESHOLD);
+        }
+        for def in top.values_mut() {
+            const_fold(def.body_mut());
+            eliminate_dead_lets(def.body_mut());
+        }
+        uniquify(&mut top, &mut inter);
         let main_id = inter.intern("main");
-        let mut emitter = LLVMEmit::new(name, &mut inter);
-    //    emitter.close_function_pass();
+        let mut emitter = LLVMEmit::with_options(name, &mut inter, options);
+        emitter.define_types(typedefs.values().map(|def| def.deref()));
+        if debug_info {
+            emitter.open_debug_info(name, src);
+        }
+        if !div_check {
+            emitter.close_div_check();
+        }
+        if checked {
+            emitter.open_overflow_check();
+            emitter.open_bounds_check();
+        }
         let main_fn = top.remove(&main_id);
+        let groups = recursion_groups(&top);
         let env = VarEnv::new();
         if let Some(mf) = main_fn {
             emitter.gen_main(mf.deref(), &env);
         }
-        for def in top.values() {
-            emitter.gen_top_level(def.deref(), &env);
+        for group in groups.iter() {
+            for f in group.iter() {
+                emitter.declare_top_level(top[f].deref());
+            }
+            for f in group.iter() {
+                emitter.gen_top_level(top[f].deref(), &env);
+            }
         }
+        emitter.finalize_debug_info()
+            .map_err(|e| vec![CompileError::Lower(e, Span::point(0))])?;
         Ok(emitter.generator)
     })
 }
@@ -156,6 +202,10 @@ fn main() {
     let mut emit_type = EmitType::Excutable;
     let mut input_file = String::new();
     let mut out_file = String::new();
+    let mut no_div_check = false;
+    let mut checked = false;
+    let mut debug = false;
+    let mut debug_info = false;
 
     {
         let mut ap = ArgumentParser::new();
@@ -169,6 +219,18 @@ fn main() {
         ap.refer(&mut emit_type)
             .add_option(&["-e", "--emit-llvm"], StoreConst(EmitType::LLVMIR),
                         "Emit LLVM IR");
+        ap.refer(&mut no_div_check)
+            .add_option(&["--no-div-check"], StoreTrue,
+                        "Do not check integer division by zero");
+        ap.refer(&mut checked)
+            .add_option(&["--checked"], StoreTrue,
+                        "Abort on integer overflow or index out of range");
+        ap.refer(&mut debug)
+            .add_option(&["--debug"], StoreTrue,
+                        "Keep variables in memory and skip optimizations");
+        ap.refer(&mut debug_info)
+            .add_option(&["-g", "--debug-info"], StoreTrue,
+                        "Emit debug information, built with feature `debuginfo`");
 //        ap.refer(&mut emit_type)
 //            .add_option(&["-e", "--emit-exe"], StoreConst(EmitType::Excutable),
 //                        "Emit excutable file");
@@ -192,11 +254,13 @@ fn main() {
     }
 
     fs::File::open(input_file.as_str())
-        .map_err(|e| CompileError::Normal("open file failed: ".to_string() + e.description()))
+        .map_err(|e| DriverError::Normal("open file failed: ".to_string() + e.description()))
         .and_then(|mut f| {
             let mut src = String::new();
             f.read_to_string(&mut src);
-            compile(input_file.as_str(), src.as_str())
+            let options = if debug { CodegenOptions::debug() } else { CodegenOptions::default() };
+            compile(input_file.as_str(), src.as_str(), !no_div_check, checked, options, debug_info)
+                .map_err(|es| DriverError::Compile(es, src.clone()))
         })
         .and_then(|gen| {
             match emit_type {
@@ -207,14 +271,19 @@ fn main() {
                 EmitType::Excutable => {
                     let LLVMCodegen { mut module, .. } = gen;
                     module_emit_file(out_file.as_str(), &mut module)
-                        .map_err(|e| CompileError::Normal(e))
+                        .map_err(|e| DriverError::Normal(e))
                 }
                 _ => unimplemented!()
             }
         })
         .map_err(|e| {
             eprintln!("Compiling error:");
-            eprintln!("{:?}", e);
+            match e {
+                DriverError::Compile(es, src) => for e in es.iter() {
+                    eprintln!("{}", e.located(&src));
+                },
+                DriverError::Normal(e) => eprintln!("{}", e),
+            }
             ::std::process::exit(1);
 //            eprintln!()
         });
@@ -0,0 +1,231 @@
+/// Evaluate forms directly, without type checking or compiling
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use utils::*;
+use internal::*;
+
+use syntax::form::*;
+
+/// Runtime value of a form
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Int(i32),
+    Long(i64),
+    Float(f64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    List(Vec<Value>),
+    Tuple(Vec<Value>),
+    /// A variant with its fields, in the order they are written
+    Data(Name, Vec<(Name, Value)>),
+    /// A function with the environment it is defined in,
+    ///   and its own name if bound by `let`, for recursion
+    Closure(Option<Id>, Rc<Lambda>, Rc<Env>),
+    Void,
+}
+
+pub type Env = HashMap<Id, Value>;
+
+lit_value!(Value);
+
+/// Evaluate a form in the environment.
+///   Functions are closures capturing the environment.
+///   Runtime errors, like division by zero, panic.
+///   Constructors in patterns are looked up by name in `interner`.
+pub fn eval_expr(e: &E, env: &Env, interner: &Interner) -> Value {
+    use self::Expr::*;
+    let all = |es: &Vec<E>| es.iter().map(|e| eval_expr(e, env, interner)).collect::<Vec<_>>();
+    match e.node {
+        Lit(ref lit) => Value::from_lit(lit),
+        Var(n) => {
+            match env.get(&n) {
+                Some(v) => v.clone(),
+                None => panic!("Unbound variable {:?}", n),
+            }
+        }
+        List(ref es) => Value::List(all(es)),
+        Tuple(ref es) => Value::Tuple(all(es)),
+        Block(ref es) => all(es).pop().unwrap_or(Value::Void),
+        Abs(ref lambda) => Value::Closure(None, Rc::new(lambda.clone()), Rc::new(env.clone())),
+        Apply(ref callee, ref args) => {
+            let callee = eval_expr(callee, env, interner);
+            apply(callee, all(args), interner)
+        }
+        // Operands are evaluated only if needed
+        Binary(BinOp::And, ref l, ref r) => {
+            let l = eval_expr(l, env, interner).truth();
+            Value::Bool(l && eval_expr(r, env, interner).truth())
+        }
+        Binary(BinOp::Or, ref l, ref r) => {
+            let l = eval_expr(l, env, interner).truth();
+            Value::Bool(l || eval_expr(r, env, interner).truth())
+        }
+        Binary(op, ref l, ref r) => {
+            let (l, r) = (eval_expr(l, env, interner), eval_expr(r, env, interner));
+            let res = match (l.to_lit(), r.to_lit()) {
+                (Some(a), Some(b)) => fold_binary(op, &a, &b),
+                _ => None,
+            };
+            match res {
+                Some(lit) => Value::from_lit(&lit),
+                None => panic!("Cannot apply {:?} on {:?} and {:?}", op, l, r),
+            }
+        }
+        Unary(op, ref e) => {
+            let v = eval_expr(e, env, interner);
+            match v.to_lit().and_then(|lit| fold_unary(op, &lit)) {
+                Some(lit) => Value::from_lit(&lit),
+                None => panic!("Cannot apply {:?} on {:?}", op, v),
+            }
+        }
+        // A function bound by `let` could call itself
+        Let(ref var, ref val, ref exp) => {
+            let val = match eval_expr(val, env, interner) {
+                Value::Closure(None, lambda, captured) => {
+                    Value::Closure(Some(var.name()), lambda, captured)
+                }
+                v => v,
+            };
+            let mut inner = env.clone();
+            inner.insert(var.name(), val);
+            eval_expr(exp, &inner, interner)
+        }
+        If(ref c, ref t, ref f) => {
+            if eval_expr(c, env, interner).truth() {
+                eval_expr(t, env, interner)
+            } else {
+                eval_expr(f, env, interner)
+            }
+        }
+        While(ref c, ref b) => {
+            while eval_expr(c, env, interner).truth() {
+                eval_expr(b, env, interner);
+            }
+            Value::Void
+        }
+        TupleProj(ref e, idx) => {
+            match eval_expr(e, env, interner) {
+                Value::Tuple(mut vs) => vs.swap_remove(idx),
+                v => panic!("Cannot get element {} of {:?}", idx, v),
+            }
+        }
+        Index(ref l, ref i) => {
+            match (eval_expr(l, env, interner), eval_expr(i, env, interner)) {
+                (Value::List(mut vs), Value::Int(i)) if i >= 0 && (i as usize) < vs.len() => {
+                    vs.swap_remove(i as usize)
+                }
+                (l, i) => panic!("Cannot get element {:?} of {:?}", i, l),
+            }
+        }
+        Record(ref ctor, ref fields) => {
+            let fields = fields.iter()
+                .map(|&(ref n, ref e)| (n.clone(), eval_expr(e, env, interner)))
+                .collect();
+            Value::Data(ctor.clone(), fields)
+        }
+        Field(ref e, ref name) => {
+            match eval_expr(e, env, interner) {
+                Value::Data(_, fields) => {
+                    match fields.into_iter().find(|&(ref n, _)| n == name) {
+                        Some((_, v)) => v,
+                        None => panic!("No field {}", name),
+                    }
+                }
+                v => panic!("Cannot get field {} of {:?}", name, v),
+            }
+        }
+        Match(ref e, ref arms) => {
+            let v = eval_expr(e, env, interner);
+            for arm in arms.iter() {
+                let mut inner = env.clone();
+                if bind(&arm.pattern, &v, &mut inner, interner) {
+                    return eval_expr(&arm.body, &inner, interner);
+                }
+            }
+            panic!("No arm matches {:?}", v)
+        }
+    }
+}
+
+/// Whether `pat` matches `v`, variables in it are bound in `env`
+fn bind(pat: &Pattern, v: &Value, env: &mut Env, interner: &Interner) -> bool {
+    match (pat, v) {
+        (&Pattern::Wildcard, _) => true,
+        (&Pattern::Var(n), _) => {
+            env.insert(n, v.clone());
+            true
+        }
+        (&Pattern::Lit(ref l), _) => v.to_lit().as_ref() == Some(l),
+        (&Pattern::Ctor(c, ref ps), &Value::Data(ref name, ref fields)) => {
+            interner.trace(c) == name && ps.len() == fields.len() &&
+            ps.iter().zip(fields.iter()).all(|(p, &(_, ref f))| bind(p, f, env, interner))
+        }
+        _ => false,
+    }
+}
+
+/// Call a closure, parameters and its own name are bound
+///   in the environment it captured
+fn apply(callee: Value, args: Vec<Value>, interner: &Interner) -> Value {
+    let (name, lambda, captured) = match callee {
+        Value::Closure(ref name, ref lambda, ref captured) => (*name, lambda.clone(), captured.clone()),
+        ref v => panic!("Expect a function, found {:?}", v),
+    };
+    if lambda.param.len() != args.len() {
+        panic!("Expect {} arguments, found {}", lambda.param.len(), args.len());
+    }
+    let mut env = (*captured).clone();
+    if let Some(n) = name {
+        env.insert(n, callee.clone());
+    }
+    for (p, a) in lambda.param.iter().zip(args.into_iter()) {
+        env.insert(p.name(), a);
+    }
+    eval_expr(&lambda.body, &env, interner)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::parser;
+
+    fn run(src: &str) -> Value {
+        let mut interner = Interner::new();
+        let form = parser::expression(src, &mut interner).unwrap();
+        eval_expr(&P(form), &Env::new(), &interner)
+    }
+
+    #[test]
+    fn eval_higher_order() {
+        assert_eq!(run("let twice = (f, x) -> f(f(x)) in twice((n) -> n * 3, 2)"), Value::Int(18));
+        // Closure keeps the environment where it is made
+        assert_eq!(run("let add = (a) -> (b) -> a + b in let inc = add(1) in let a = 10 in inc(a)"),
+                   Value::Int(11));
+    }
+
+    #[test]
+    #[should_panic(expected = "Expect 2 arguments, found 1")]
+    fn eval_arity() {
+        run("let add = (a, b) -> a + b in add(1)");
+    }
+
+    #[test]
+    fn eval_recursion() {
+        let src = "let fact = (n) -> if (n == 0) 1 else n * fact(n - 1) in fact(5)";
+        assert_eq!(run(src), Value::Int(120));
+        assert_eq!(run("{ [1, 2], (1.5, true).1 || 1 / 0 == 0 }"), Value::Bool(true));
+    }
+
+    #[test]
+    fn eval_match_record() {
+        let src = "let area = (s) -> match (s) { Rect(w, h) => w * h, Square(1) => 1, Square(a) => a * a } in \
+                   area(Rect(2, 3)) + area(Square(4)) + area(Square(1))";
+        assert_eq!(run(src), Value::Int(23));
+        assert_eq!(run("Point { x: 1, y: 2 }.y"), Value::Int(2));
+        assert_eq!(run("match (Pair(1, true)) { Pair(_, false) => 0, Pair(n, _) => n }"), Value::Int(1));
+    }
+}
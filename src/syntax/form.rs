@@ -1,4 +1,6 @@
 use std::string::*;
+use std::cmp;
+use std::collections::HashSet;
 
 use std::ops::Deref;
 use std::ops::DerefMut;
@@ -8,6 +10,7 @@ use std::iter::DoubleEndedIterator;
 
 use utils::*;
 use types::*;
+use error::CompileError;
 
 use internal::*;
 
@@ -140,6 +143,7 @@ impl Form {
         Form {
             node: Expr::Abs(Lambda {
                                 param: params,
+                                ret: Scheme::Slot,
                                 body: to,
                             }),
             tag: FormTag {
@@ -149,6 +153,18 @@ impl Form {
             },
         }
     }
+    /// Make a tuple of forms, like `Type::product_n`
+    pub fn tuple<I>(pos: Span, elems: I) -> Form
+        where I: IntoIterator<Item = Form>
+    {
+        Form::new(pos, Expr::Tuple(elems.into_iter().map(|f| P(f)).collect()))
+    }
+    pub fn proj(pos: Span, tuple: P<Form>, index: usize) -> Form {
+        Form::new(pos, Expr::TupleProj(tuple, index))
+    }
+    pub fn matching(pos: Span, scrutinee: P<Form>, arms: Vec<Arm>) -> Form {
+        Form::new(pos, Expr::Match(scrutinee, arms))
+    }
     pub fn span(&self) -> Span {
         self.tag.pos.clone()
     }
@@ -177,6 +193,14 @@ impl FormTag {
         self.ty.body()
     }
 
+    /// Type of the form, an error if not inferred
+    pub fn try_type(&self) -> Result<&Type, CompileError> {
+        match self.ty.try_body() {
+            Some(t) => Ok(t),
+            None => Err(CompileError::Lower("type of expression is not inferred".to_string(), self.pos)),
+        }
+    }
+
     pub fn clone_type(&self) -> Type {
         self.ty.body().clone()
     }
@@ -186,6 +210,24 @@ impl FormTag {
     }
 }
 
+/// Line and column in source, both start from 1
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Copy)]
+pub struct Pos {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Pos {
+    /// Find line and column of a offset in source
+    pub fn locate(src: &str, offset: usize) -> Pos {
+        let before = &src[..cmp::min(offset, src.len())];
+        let line = before.matches('\n').count() + 1;
+        let col = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+        Pos { line, col }
+    }
+}
+
+/// Range of offsets in source, `end` exclusive
 #[derive(Clone, PartialEq, Eq, Hash, Debug, Copy)]
 pub struct Span {
     start: usize,
@@ -196,12 +238,26 @@ impl Span {
     pub fn new(l: usize, c: usize) -> Span {
         Span { start: l, end: c }
     }
+    /// Span of a single point
+    pub fn point(at: usize) -> Span {
+        Span { start: at, end: at }
+    }
+    pub fn start(&self) -> usize {
+        self.start
+    }
+    pub fn end(&self) -> usize {
+        self.end
+    }
     pub fn union(&self, s: &Span) -> Span {
         Span {
-            start: self.start,
-            end: s.end
+            start: cmp::min(self.start, s.start),
+            end: cmp::max(self.end, s.end),
         }
     }
+    /// Get positions of start and end in source
+    pub fn locate(&self, src: &str) -> (Pos, Pos) {
+        (Pos::locate(src, self.start), Pos::locate(src, self.end))
+    }
 }
 
 
@@ -241,10 +297,190 @@ pub enum Expr {
     /// Conditional expression
     /// e.g. `if (fuck == shit) 1 else 0`
     If(E, E, E),
+    /// Pattern matching
+    /// e.g. `match (fuck) { Shit(a, _) => a, _ => 0 }`
+    Match(E, Vec<Arm>),
+    /// Loop while the condition holds, evaluates to nothing
+    /// e.g. `while (fuck < 10) print(fuck)`
+    While(E, E),
+    /// Tuple
+    /// e.g. `(fuck, shit)`
+    Tuple(Vec<E>),
+    /// Get the n-th element of tuple
+    /// e.g. `fuck.0`
+    TupleProj(E, usize),
+    /// Get an element of list by index
+    /// e.g. `fuck[1]`
+    Index(E, E),
+    /// Construct a struct variant
+    /// e.g. `Fucker { name: "shit", age: 1 }`
+    Record(Name, Vec<(Name, E)>),
+    /// Get a field of struct variant
+    /// e.g. `fuck.name`
+    Field(E, Name),
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Lambda {
     pub param: Vec<VarDecl>,
+    /// Annotated return type, `Slot` if not
+    pub ret: Scheme,
     pub body: E,
 }
+
+/// A arm of `match`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub body: E,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    /// Matches a literal value
+    Lit(Lit),
+    /// Binds the matched value to a variable
+    Var(Id),
+    /// `_`, matches anything
+    Wildcard,
+    /// Matches a variant and its fields
+    /// e.g. `Fucker(a, _)`
+    Ctor(Id, Vec<Pattern>),
+}
+
+impl Pattern {
+    /// Variables bound by the pattern, from left to right
+    pub fn binders(&self) -> Vec<Id> {
+        match *self {
+            Pattern::Var(id) => vec![id],
+            Pattern::Ctor(_, ref ps) => ps.iter().flat_map(|p| p.binders()).collect(),
+            Pattern::Lit(_) | Pattern::Wildcard => vec![],
+        }
+    }
+}
+
+/// Free variables of an expression in order of first occurrence.
+///   Names in `bound` are not free, e.g. top level definitions
+///   which need not to be captured by closures.
+pub fn free_vars(expr: &Expr, bound: &HashSet<Id>) -> Vec<Id> {
+    let mut res = vec![];
+    collect_free_vars(expr, bound, &mut res);
+    res
+}
+
+fn collect_free_vars(expr: &Expr, bound: &HashSet<Id>, res: &mut Vec<Id>) {
+    use self::Expr::*;
+    // Extend bound names in a nested scope
+    let extend = |ids: &[Id]| {
+        let mut inner = bound.clone();
+        inner.extend(ids.iter().cloned());
+        inner
+    };
+    match *expr {
+        Lit(_) => {}
+        Var(id) => {
+            if !bound.contains(&id) && !res.contains(&id) {
+                res.push(id);
+            }
+        }
+        List(ref es) |
+        Block(ref es) |
+        Tuple(ref es) => {
+            for e in es.iter() {
+                collect_free_vars(&e.node, bound, res);
+            }
+        }
+        Apply(ref callee, ref args) => {
+            collect_free_vars(&callee.node, bound, res);
+            for e in args.iter() {
+                collect_free_vars(&e.node, bound, res);
+            }
+        }
+        Abs(ref lambda) => {
+            let params: Vec<_> = lambda.param.iter().map(|p| p.name()).collect();
+            collect_free_vars(&lambda.body.node, &extend(&params), res);
+        }
+        Binary(_, ref l, ref r) |
+        Index(ref l, ref r) |
+        While(ref l, ref r) => {
+            collect_free_vars(&l.node, bound, res);
+            collect_free_vars(&r.node, bound, res);
+        }
+        Unary(_, ref e) |
+        TupleProj(ref e, _) |
+        Field(ref e, _) => collect_free_vars(&e.node, bound, res),
+        // A bound lambda may call itself recursively
+        Let(ref var, ref val, ref body) => {
+            let inner = extend(&[var.name()]);
+            if let Abs(..) = val.node {
+                collect_free_vars(&val.node, &inner, res);
+            } else {
+                collect_free_vars(&val.node, bound, res);
+            }
+            collect_free_vars(&body.node, &inner, res);
+        }
+        If(ref c, ref t, ref f) => {
+            collect_free_vars(&c.node, bound, res);
+            collect_free_vars(&t.node, bound, res);
+            collect_free_vars(&f.node, bound, res);
+        }
+        Match(ref e, ref arms) => {
+            collect_free_vars(&e.node, bound, res);
+            for arm in arms.iter() {
+                collect_free_vars(&arm.body.node, &extend(&arm.pattern.binders()), res);
+            }
+        }
+        Record(_, ref fields) => {
+            for &(_, ref e) in fields.iter() {
+                collect_free_vars(&e.node, bound, res);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::parser;
+
+    fn fv(src: &str, globals: &[&str]) -> Vec<String> {
+        let mut interner = Interner::new();
+        let form = parser::expression(src, &mut interner).unwrap();
+        let bound = globals.iter().map(|g| interner.intern(g)).collect();
+        free_vars(&form.node, &bound)
+            .into_iter()
+            .map(|id| interner.trace(id).to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn free_vars_lambda() {
+        assert_eq!(fv("(fuck) -> fuck + shit", &[]), vec!["shit"]);
+        assert_eq!(fv("(fuck) -> print(fuck, shit, shit)", &["print"]), vec!["shit"]);
+        assert_eq!(fv("let fuck = 1 in fuck + shit", &[]), vec!["shit"]);
+        assert_eq!(fv("let fuck = (n) -> fuck(n) in 0", &[]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn free_vars_shadowing() {
+        // Inner parameter shadows the outer one
+        assert_eq!(fv("(fuck) -> (fuck) -> fuck + shit", &[]), vec!["shit"]);
+        assert_eq!(fv("(fuck) -> { (shit) -> fuck + shit, shit }", &[]), vec!["shit"]);
+        assert_eq!(fv("let fuck = fuck in (shit) -> fuck", &[]), vec!["fuck"]);
+        assert_eq!(fv("match (fuck) { Shit(fuck) => fuck, _ => shit }", &[]),
+                   vec!["fuck", "shit"]);
+    }
+
+    #[test]
+    fn form_type_not_inferred() {
+        let mut form = Form::new(Span::new(3, 4), Expr::Lit(Lit::Int(1)));
+        assert_eq!(form.tag.ref_scheme().try_body(), None);
+        match form.tag.try_type() {
+            Err(CompileError::Lower(_, pos)) => assert_eq!(pos, Span::new(3, 4)),
+            r => panic!("expect not inferred, found {:?}", r),
+        }
+        form.tag.set_type(Type::Con("Int".to_string()));
+        assert_eq!(form.tag.try_type(), Ok(&Type::Con("Int".to_string())));
+    }
+}
@@ -1,2 +1,5 @@
 pub mod parser;
 pub mod form;
+pub mod pretty;
+pub mod eval;
+pub mod resolve;
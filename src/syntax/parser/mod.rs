@@ -13,6 +13,9 @@ fn escape_string(s: &str) -> Result<String, &str> {
                         'n' => r.push('\n'),
                         'r' => r.push('\r'),
                         't' => r.push('\t'),
+                        '\\' => r.push('\\'),
+                        '\'' => r.push('\''),
+                        '"' => r.push('"'),
                         _ => return Err("Invalid escape in string")
                     }
                 } else {
@@ -25,11 +28,28 @@ fn escape_string(s: &str) -> Result<String, &str> {
     Ok(r)
 }
 
+fn escape_char(s: &str) -> Result<char, &str> {
+    let e = escape_string(s)?;
+    let mut cs = e.chars();
+    match (cs.next(), cs.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err("Invalid character"),
+    }
+}
+
 fn make_spanned_binexpr(op: BinOp, lhs: Form, rhs: Form) -> Form {
     let span = lhs.tag.pos.union(&rhs.tag.pos);
     Form::new(span, Expr::Binary(op, box lhs, box rhs))
 }
 
+/// Suffix of a factor, call or projection
+enum Postfix {
+    Apply(Vec<Form>),
+    Proj(usize),
+    Field(String),
+    Index(Form),
+}
+
 mod grammar {
     include!(concat!(env!("OUT_DIR"), "/grammar.rs"));
 }
@@ -38,7 +58,95 @@ pub use self::grammar::*;
 // pub use self::grammar::ParseError;
 
 pub fn parse(src: &str, interner: &mut Interner) -> Result<Vec<Def>, ParseError> {
-    module(src, interner)
+    let mut defs = module(src, interner)?;
+    for d in defs.iter_mut() {
+        if let Item::Form(ref mut f) = d.node {
+            fold_neg_literals(f);
+        }
+    }
+    Ok(defs)
+}
+
+/// Parse an expression
+pub fn expression(src: &str, interner: &mut Interner) -> Result<Form, ParseError> {
+    let mut form = grammar::expression(src, interner)?;
+    fold_neg_literals(&mut form);
+    Ok(form)
+}
+
+/// Negate a number literal, `None` if it is not a number.
+///   An `Int` stays `Int` if the result fits, so `-2147483648` is not a `Long`.
+fn negate_lit(lit: &Lit) -> Option<Lit> {
+    use std::i32;
+    match *lit {
+        Lit::Int(i) => Some(i.checked_neg().map(Lit::Int).unwrap_or(Lit::Long(-(i as i64)))),
+        Lit::Long(i) => {
+            let neg = i.wrapping_neg();
+            if neg >= i32::MIN as i64 && neg <= i32::MAX as i64 {
+                Some(Lit::Int(neg as i32))
+            } else {
+                Some(Lit::Long(neg))
+            }
+        }
+        Lit::Float(f) => Some(Lit::Float(-f)),
+        _ => None,
+    }
+}
+
+/// Fold negation of number literals into literals,
+///   while negation of other forms stays `Unary(Neg, _)`
+pub fn fold_neg_literals(form: &mut Form) {
+    use self::Expr::*;
+    match form.node {
+        List(ref mut es) |
+        Block(ref mut es) |
+        Tuple(ref mut es) => {
+            for e in es.iter_mut() {
+                fold_neg_literals(e);
+            }
+        }
+        Apply(ref mut callee, ref mut args) => {
+            fold_neg_literals(callee);
+            for e in args.iter_mut() {
+                fold_neg_literals(e);
+            }
+        }
+        Abs(ref mut fun) => fold_neg_literals(&mut fun.body),
+        Binary(_, ref mut l, ref mut r) |
+        Index(ref mut l, ref mut r) |
+        While(ref mut l, ref mut r) |
+        Let(_, ref mut l, ref mut r) => {
+            fold_neg_literals(l);
+            fold_neg_literals(r);
+        }
+        If(ref mut c, ref mut t, ref mut f) => {
+            fold_neg_literals(c);
+            fold_neg_literals(t);
+            fold_neg_literals(f);
+        }
+        Unary(_, ref mut e) |
+        TupleProj(ref mut e, _) |
+        Field(ref mut e, _) => fold_neg_literals(e),
+        Record(_, ref mut fields) => {
+            for &mut (_, ref mut f) in fields.iter_mut() {
+                fold_neg_literals(f);
+            }
+        }
+        Match(ref mut e, ref mut arms) => {
+            fold_neg_literals(e);
+            for arm in arms.iter_mut() {
+                fold_neg_literals(&mut arm.body);
+            }
+        }
+        Lit(_) | Var(_) => {}
+    }
+    let folded = match form.node {
+        Unary(UnOp::Neg, box Form { node: Lit(ref l), .. }) => negate_lit(l),
+        _ => None,
+    };
+    if let Some(l) = folded {
+        form.node = Lit(l);
+    }
 }
 
 use types::Scheme;
@@ -72,7 +180,7 @@ mod tests {
     #[test]
     fn case_parse_arrow_type() {
         let output = Type::Arr(
-            box Type::Var("shit".to_string()),
+            vec![Type::Var("shit".to_string())],
             box Type::Con("Fuck".to_string())
         );
         assert_eq!(type_expression("     shit -> Fuck", &mut Interner::new()), Ok(output))
@@ -88,52 +196,52 @@ mod tests {
     #[test]
     fn case_parse_composite_and_arrow_type() {
         let output = Type::Arr(
-            box Type::Comp(
+            vec![Type::Comp(
                 box Type::Var("shit".to_string()),
                 box Type::Con("Fuck".to_string())
-            ),
+            )],
             box Type::Var("a".to_string())
         );
         assert_eq!(type_expression("     shit  Fuck -> a", &mut Interner::new()), Ok(output))
     }
     #[test]
     fn case_parse_product_type() {
-        let output = Type::Prod(
-            box Type::Con("Shit".to_string()),
-            box Type::Var("fuck".to_string())
-        );
+        let output = Type::Prod(vec![
+            Type::Con("Shit".to_string()),
+            Type::Var("fuck".to_string())
+        ]);
         assert_eq!(type_expression("     Shit * fuck", &mut Interner::new()), Ok(output))
     }
     #[test]
     fn case_parse_product_and_arrow_type() {
         let output = Type::Arr(
-            box Type::Prod(
-                box Type::Con("Shit".to_string()),
-                box Type::Var("fuck".to_string())
-            ),
+            vec![
+                Type::Con("Shit".to_string()),
+                Type::Var("fuck".to_string())
+            ],
             box Type::Con("Fuck".to_string())
         );
         assert_eq!(type_expression("     Shit  *    fuck -> Fuck", &mut Interner::new()), Ok(output))
     }
     #[test]
     fn case_parse_product_with_arrow_type() {
-        let output = Type::Prod(
-            box Type::Con("Shit".to_string()),
-            box Type::Arr(
-                box Type::Var("fuck".to_string()),
+        let output = Type::Prod(vec![
+            Type::Con("Shit".to_string()),
+            Type::Arr(
+                vec![Type::Var("fuck".to_string())],
                 box Type::Con("Fuck".to_string())
             )
-        );
+        ]);
         assert_eq!(type_expression("     Shit  *  (  fuck -> Fuck)", &mut Interner::new()), Ok(output))
     }
     #[test]
     fn case_parse_arrow_and_product_type() {
         let output = Type::Arr(
-            box Type::Con("Fuck".to_string()),
-            box Type::Prod(
-                box Type::Con("Shit".to_string()),
-                box Type::Var("fuck".to_string())
-            )
+            vec![Type::Con("Fuck".to_string())],
+            box Type::Prod(vec![
+                Type::Con("Shit".to_string()),
+                Type::Var("fuck".to_string())
+            ])
         );
         assert_eq!(type_expression("   Fuck ->    Shit * fuck", &mut Interner::new()), Ok(output))
     }
@@ -141,9 +249,9 @@ mod tests {
     fn case_parse_mono_arrow_to_arrow_type() {
         let output = Scheme::Mono(
             Type::Arr(
-                box Type::Con("Fuck".to_string()),
+                vec![Type::Con("Fuck".to_string())],
                 box Type::Arr(
-                    box Type::Con("Shit".to_string()),
+                    vec![Type::Con("Shit".to_string())],
                     box Type::Var("fuck".to_string())
                 )
             )
@@ -154,16 +262,26 @@ mod tests {
     fn case_parse_poly_product_with_arrow_type() {
         let output = Scheme::Poly(
             vec!["fuck".to_string()],
-            Type::Prod(
-                box Type::Con("Shit".to_string()),
-                box Type::Arr(
-                    box Type::Var("fuck".to_string()),
+            Type::Prod(vec![
+                Type::Con("Shit".to_string()),
+                Type::Arr(
+                    vec![Type::Var("fuck".to_string())],
                     box Type::Con("Fuck".to_string())
                 )
-            )
+            ])
         );
         assert_eq!(type_scheme("forall fuck.     Shit  *  (  fuck -> Fuck)", &mut Interner::new()), Ok(output))
     }
+    #[test]
+    fn case_parse_tuple_parameter_type() {
+        let int = || Type::Con("Int".to_string());
+        let output = Type::Arr(vec![Type::Prod(vec![int(), int()])], box int());
+        assert_eq!(type_expression("(Int * Int) -> Int", &mut Interner::new()), Ok(output));
+        let output = Type::Prod(vec![int(), Type::Prod(vec![int(), int()])]);
+        assert_eq!(type_expression("Int * (Int * Int)", &mut Interner::new()), Ok(output));
+        let output = Type::Arr(vec![], box int());
+        assert_eq!(type_expression("Void -> Int", &mut Interner::new()), Ok(output));
+    }
 
     #[test]
     fn case_parse_function_definition() {
@@ -172,6 +290,7 @@ mod tests {
             let form = Form::new(Span::new(16, 22), Expr::Abs(
                 Lambda {
                     param: vec![VarDecl(i.intern("a"), Scheme::Slot), VarDecl(i.intern("b"), Scheme::Slot)],
+                    ret: Scheme::Slot,
                     body: box Form::new(Span::new(16,22), Expr::Binary(
                         BinOp::Add,
                         box Form::new(Span::new(16,18), Expr::Var(i.intern("a"))),
@@ -187,6 +306,7 @@ mod tests {
             let form = Form::new(Span::new(13, 19), Expr::Abs(
                 Lambda {
                     param: vec![VarDecl(i.intern("a"), Scheme::Slot)],
+                    ret: Scheme::Slot,
                     body: box Form::new(Span::new(13,19), Expr::Binary(
                         BinOp::Add,
                         box Form::new(Span::new(13,15), Expr::Var(i.intern("a"))),
@@ -213,6 +333,127 @@ def shit(p) = p
         }
     }
 
+    #[test]
+    fn case_parse_tuple() {
+        let mut i = Interner::new();
+        let res = expression("(fuck, shit(1), 2).1", &mut i);
+        match res {
+            Ok(Form { node: Expr::TupleProj(box tuple, 1), .. }) => {
+                match tuple.node {
+                    Expr::Tuple(ref elems) => assert_eq!(elems.len(), 3),
+                    ref n => panic!("expect tuple, found {:?}", n),
+                }
+            }
+            r => panic!("unexpected parse result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn case_parse_record() {
+        let mut i = Interner::new();
+        let res = expression("Fucker { name: \"shit\", age: 1 }.age", &mut i);
+        match res {
+            Ok(Form { node: Expr::Field(box record, ref field), .. }) => {
+                assert_eq!(field, "age");
+                match record.node {
+                    Expr::Record(ref ctor, ref fields) => {
+                        assert_eq!(ctor, "Fucker");
+                        let names: Vec<_> = fields.iter().map(|&(ref n, _)| n.as_str()).collect();
+                        assert_eq!(names, vec!["name", "age"]);
+                    }
+                    ref n => panic!("expect record, found {:?}", n),
+                }
+            }
+            r => panic!("unexpected parse result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn case_span_locate() {
+        let src = "fuck(1,\n  shit)";
+        let res = expression(src, &mut Interner::new()).unwrap();
+        let (start, end) = res.tag.pos.locate(src);
+        assert_eq!(start, Pos { line: 1, col: 1 });
+        assert_eq!(end, Pos { line: 2, col: 8 });
+        assert_eq!(Span::point(3).union(&Span::new(1, 2)), Span::new(1, 3));
+    }
+
+    #[test]
+    fn case_parse_char() {
+        let mut i = Interner::new();
+        assert_eq!(expression("'a'", &mut i).map(|f| f.node), Ok(Expr::Lit(Lit::Char('a'))));
+        assert_eq!(expression("'\\n'", &mut i).map(|f| f.node), Ok(Expr::Lit(Lit::Char('\n'))));
+        assert_eq!(expression("'\\''", &mut i).map(|f| f.node), Ok(Expr::Lit(Lit::Char('\''))));
+        assert_eq!(expression("'字'", &mut i).map(|f| f.node), Ok(Expr::Lit(Lit::Char('字'))));
+    }
+
+    #[test]
+    fn case_parse_integer_range() {
+        let mut i = Interner::new();
+        assert_eq!(expression("-2147483648", &mut i).map(|f| f.node),
+                   Ok(Expr::Lit(Lit::Int(::std::i32::MIN))));
+        assert_eq!(expression("2147483648", &mut i).map(|f| f.node),
+                   Ok(Expr::Lit(Lit::Long(2147483648))));
+        assert!(expression("9223372036854775808", &mut i).is_err());
+    }
+
+    #[test]
+    fn case_fold_neg_literals() {
+        let mut i = Interner::new();
+        assert_eq!(expression("-2.5", &mut i).map(|f| f.node), Ok(Expr::Lit(Lit::Float(-2.5))));
+        assert_eq!(expression("--1", &mut i).map(|f| f.node), Ok(Expr::Lit(Lit::Int(1))));
+        assert_eq!(expression("-2147483649", &mut i).map(|f| f.node), Ok(Expr::Lit(Lit::Long(-2147483649))));
+        let x = i.intern("x");
+        match expression("1 - -x", &mut i).map(|f| f.node) {
+            Ok(Expr::Binary(BinOp::Sub, _, box Form { node: Expr::Unary(UnOp::Neg, box ref e), .. })) => {
+                assert_eq!(e.node, Expr::Var(x));
+            }
+            r => panic!("unexpected parse result: {:?}", r),
+        }
+
+        // Checked and lowered as an `Int` without overflow
+        let mut defs = parse("def main() = -2147483648 + 1", &mut i).unwrap();
+        let plus = i.intern("+");
+        let env = ::typeinfer::Infer::new_env();
+        let prelude = env.extend_n(vec![(plus, parse_type("forall a. a * a -> a", &mut i))]);
+        ::typeinfer::Infer::new(&mut i).infer_defs(&prelude, &mut defs).unwrap();
+        assert_eq!(*defs[0].form_type(), parse_type("Void -> Int", &mut i));
+        let (funs, _) = ::core::closure_convert(defs, &mut i);
+        match *funs[0].body().body() {
+            ::core::Term::Binary(_, ref l, _) => assert_eq!(*l.body(), ::core::Term::Lit(Lit::Int(::std::i32::MIN))),
+            ref t => panic!("unexpected term: {:?}", t),
+        }
+    }
+
+    #[test]
+    fn case_parse_while() {
+        let mut i = Interner::new();
+        let res = expression("while (fuck < 10) print(fuck)", &mut i);
+        match res {
+            Ok(Form { node: Expr::While(box cond, box body), .. }) => {
+                assert!(match cond.node { Expr::Binary(BinOp::Lt, ..) => true, _ => false });
+                assert!(match body.node { Expr::Apply(..) => true, _ => false });
+            }
+            r => panic!("unexpected parse result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn case_parse_match() {
+        let mut i = Interner::new();
+        let res = expression("match (fuck) { Shit(a, _) => a, Nil => 1, _ => 0 }", &mut i);
+        let arms = match res {
+            Ok(Form { node: Expr::Match(_, arms), .. }) => arms,
+            r => panic!("unexpected parse result: {:?}", r),
+        };
+        let patterns: Vec<_> = arms.into_iter().map(|a| a.pattern).collect();
+        assert_eq!(patterns, vec![
+            Pattern::Ctor(i.intern("Shit"), vec![Pattern::Var(i.intern("a")), Pattern::Wildcard]),
+            Pattern::Ctor(i.intern("Nil"), vec![]),
+            Pattern::Wildcard,
+        ]);
+    }
+
     #[test]
     fn case_parse_type_definition() {
         let src = "
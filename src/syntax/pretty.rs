@@ -0,0 +1,399 @@
+/// Render syntax tree back into source
+
+use std::ops::Deref;
+
+use utils::*;
+use types::*;
+use syntax::form::*;
+use internal::*;
+
+/// Precedence of expressions which extend as far as possible,
+///   e.g. lambda, `let`, `if`
+const PREC_OPEN: u8 = 0;
+const PREC_UNARY: u8 = 10;
+const PREC_POSTFIX: u8 = 12;
+const PREC_ATOM: u8 = 13;
+
+fn binop_prec(op: BinOp) -> u8 {
+    use self::BinOp::*;
+    match op {
+        Or => 1,
+        And => 2,
+        Eq | Ne | Lt | Le | Gt | Ge => 3,
+        BitOr => 4,
+        BitXor => 5,
+        BitAnd => 6,
+        Shl | Shr => 7,
+        Add | Sub => 8,
+        Mul | Div | Rem => 9,
+        Dot => 11,
+    }
+}
+
+/// Fields of a tuple variant are named by positions
+fn is_positional(fields: &[(Name, E)]) -> bool {
+    fields.iter().enumerate().all(|(i, &(ref n, _))| *n == i.to_string())
+}
+
+/// Render a form as source
+pub fn print_form(form: &Form, interner: &Interner) -> String {
+    let mut p = Printer::new(interner);
+    p.form(form, PREC_OPEN);
+    p.out
+}
+
+/// Render a top level definition as source
+pub fn print_def(def: &Def, interner: &Interner) -> String {
+    let mut p = Printer::new(interner);
+    p.def(def);
+    p.out
+}
+
+/// Render a pattern as source
+pub fn print_pattern(pat: &Pattern, interner: &Interner) -> String {
+    let mut p = Printer::new(interner);
+    p.pattern(pat);
+    p.out
+}
+
+struct Printer<'i> {
+    interner: &'i Interner,
+    out: String,
+}
+
+impl<'i> Printer<'i> {
+    fn new(interner: &'i Interner) -> Printer<'i> {
+        Printer {
+            interner,
+            out: String::new(),
+        }
+    }
+
+    fn push(&mut self, s: &str) {
+        self.out.push_str(s);
+    }
+
+    fn id(&mut self, id: Id) {
+        let interner = self.interner;
+        self.push(interner.trace(id));
+    }
+
+    /// Print items separated by `, `
+    fn comma<T, F>(&mut self, items: &[T], mut each: F)
+        where F: FnMut(&mut Self, &T)
+    {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.push(", ");
+            }
+            each(self, item);
+        }
+    }
+
+    fn def(&mut self, def: &Def) {
+        match def.node {
+            Item::Form(ref f) => {
+                if let Some(ref scm) = f.tag.annotate {
+                    self.id(def.ident);
+                    self.push(": ");
+                    self.scheme(scm);
+                    self.push("\n");
+                }
+                self.push("def ");
+                self.id(def.ident);
+                match f.node {
+                    Expr::Abs(ref lambda) => {
+                        self.params(&lambda.param);
+                        self.ret(&lambda.ret);
+                        self.push(" = ");
+                        self.form(lambda.body.deref(), PREC_OPEN);
+                    }
+                    _ => {
+                        self.push(" = ");
+                        self.form(f.deref(), PREC_OPEN);
+                    }
+                }
+            }
+            Item::Alias(ref ps, ref scm) => {
+                self.push("type ");
+                self.id(def.ident);
+                self.type_params(ps);
+                self.push(" = ");
+                self.scheme(scm);
+            }
+            Item::Alg(ref ps, ref vs) => {
+                self.push("data ");
+                self.id(def.ident);
+                self.type_params(ps);
+                self.push(" { ");
+                self.comma(vs, |p, v| p.variant(v));
+                self.push(" }");
+            }
+        }
+    }
+
+    fn type_params(&mut self, ps: &[Id]) {
+        for &p in ps.iter() {
+            self.push(" ");
+            self.id(p);
+        }
+    }
+
+    fn variant(&mut self, v: &Variant) {
+        self.push(&v.name);
+        match v.body {
+            VariantBody::Tuple(ref fs) => {
+                self.push("(");
+                self.comma(fs, |p, f| p.ty(&f.ty));
+                self.push(")");
+            }
+            VariantBody::Struct(ref fs) => {
+                self.push(" { ");
+                self.comma(fs, |p, f| {
+                    if let Some(ref n) = f.name {
+                        p.push(n);
+                        p.push(": ");
+                    }
+                    p.ty(&f.ty);
+                });
+                self.push(" }");
+            }
+            VariantBody::Unit => {}
+        }
+    }
+
+    fn params(&mut self, ps: &[VarDecl]) {
+        self.push("(");
+        self.comma(ps, |p, v| p.var_decl(v));
+        self.push(")");
+    }
+
+    /// Return type annotation, arrows and products in parentheses
+    fn ret(&mut self, scm: &Scheme) {
+        match *scm {
+            Scheme::Slot => {}
+            Scheme::Mono(ref t @ Type::Arr(..)) |
+            Scheme::Mono(ref t @ Type::Prod(..)) => {
+                self.push(": (");
+                self.ty(t);
+                self.push(")");
+            }
+            _ => {
+                self.push(": ");
+                self.scheme(scm);
+            }
+        }
+    }
+
+    fn var_decl(&mut self, v: &VarDecl) {
+        self.id(v.0);
+        if let Scheme::Slot = v.1 {
+            return;
+        }
+        self.push(": ");
+        self.scheme(&v.1);
+    }
+
+    fn form(&mut self, form: &Form, prec: u8) {
+        let own = match form.node {
+            Expr::Abs(..) | Expr::Let(..) | Expr::If(..) | Expr::While(..) => PREC_OPEN,
+            Expr::Binary(op, ..) => binop_prec(op),
+            Expr::Unary(..) => PREC_UNARY,
+            Expr::Apply(..) | Expr::TupleProj(..) | Expr::Field(..) | Expr::Index(..) => PREC_POSTFIX,
+            _ => PREC_ATOM,
+        };
+        if own < prec {
+            self.push("(");
+            self.expr(&form.node);
+            self.push(")");
+        } else {
+            self.expr(&form.node);
+        }
+    }
+
+    fn forms(&mut self, forms: &[E]) {
+        self.comma(forms, |p, f| p.form(f, PREC_OPEN));
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        use self::Expr::*;
+        match *expr {
+            Lit(ref l) => self.lit(l),
+            Var(id) => self.id(id),
+            List(ref fs) => {
+                self.push("[");
+                self.forms(fs);
+                self.push("]");
+            }
+            Block(ref fs) => {
+                self.push("{ ");
+                self.forms(fs);
+                self.push(" }");
+            }
+            Tuple(ref fs) => {
+                self.push("(");
+                self.forms(fs);
+                self.push(")");
+            }
+            Apply(ref callee, ref args) => {
+                self.form(callee, PREC_POSTFIX);
+                self.push("(");
+                self.forms(args);
+                self.push(")");
+            }
+            TupleProj(ref t, n) => {
+                self.form(t, PREC_POSTFIX);
+                self.push(".");
+                self.push(&n.to_string());
+            }
+            Index(ref l, ref i) => {
+                self.form(l, PREC_POSTFIX);
+                self.push("[");
+                self.form(i, PREC_OPEN);
+                self.push("]");
+            }
+            Field(ref r, ref name) => {
+                self.form(r, PREC_POSTFIX);
+                self.push(".");
+                self.push(name);
+            }
+            // Positional fields of a tuple variant
+            Record(ref ctor, ref fields) if is_positional(fields) => {
+                self.push(ctor);
+                self.push("(");
+                self.comma(fields, |p, &(_, ref f)| p.form(f, PREC_OPEN));
+                self.push(")");
+            }
+            Record(ref ctor, ref fields) => {
+                self.push(ctor);
+                self.push(" { ");
+                self.comma(fields, |p, &(ref n, ref f)| {
+                    p.push(n);
+                    p.push(": ");
+                    p.form(f, PREC_OPEN);
+                });
+                self.push(" }");
+            }
+            Abs(ref lambda) => {
+                self.params(&lambda.param);
+                self.ret(&lambda.ret);
+                self.push(" -> ");
+                self.form(&lambda.body, PREC_OPEN);
+            }
+            // Operators are left associative
+            Binary(op, ref l, ref r) => {
+                let prec = binop_prec(op);
+                self.form(l, prec);
+                self.push(" ");
+                self.push(op.as_str());
+                self.push(" ");
+                self.form(r, prec + 1);
+            }
+            Unary(op, ref e) => {
+                self.push(op.as_str());
+                self.form(e, PREC_UNARY);
+            }
+            Let(ref v, ref val, ref body) => {
+                self.push("let ");
+                self.var_decl(v);
+                self.push(" = ");
+                self.form(val, PREC_OPEN);
+                self.push(" in ");
+                self.form(body, PREC_OPEN);
+            }
+            If(ref c, ref t, ref f) => {
+                self.push("if (");
+                self.form(c, PREC_OPEN);
+                self.push(") ");
+                self.form(t, PREC_OPEN);
+                self.push(" else ");
+                self.form(f, PREC_OPEN);
+            }
+            While(ref c, ref body) => {
+                self.push("while (");
+                self.form(c, PREC_OPEN);
+                self.push(") ");
+                self.form(body, PREC_OPEN);
+            }
+            Match(ref e, ref arms) => {
+                self.push("match (");
+                self.form(e, PREC_OPEN);
+                self.push(") { ");
+                self.comma(arms, |p, arm| {
+                    p.pattern(&arm.pattern);
+                    p.push(" => ");
+                    p.form(&arm.body, PREC_OPEN);
+                });
+                self.push(" }");
+            }
+        }
+    }
+
+    fn pattern(&mut self, pat: &Pattern) {
+        match *pat {
+            Pattern::Lit(ref l) => self.lit(l),
+            Pattern::Var(id) => self.id(id),
+            Pattern::Wildcard => self.push("_"),
+            Pattern::Ctor(id, ref ps) => {
+                self.id(id);
+                if !ps.is_empty() {
+                    self.push("(");
+                    self.comma(ps, |p, sub| p.pattern(sub));
+                    self.push(")");
+                }
+            }
+        }
+    }
+
+    fn lit(&mut self, lit: &Lit) {
+        let s = match *lit {
+            Lit::Int(i) => i.to_string(),
+            Lit::Long(i) => i.to_string(),
+            Lit::Float(f) => format!("{:?}", f),
+            Lit::Str(ref s) => format!("{:?}", s),
+            Lit::Bool(b) => b.to_string(),
+            Lit::Char(c) => format!("{:?}", c),
+        };
+        self.push(&s);
+    }
+
+    fn scheme(&mut self, scm: &Scheme) {
+        self.push(&scm.to_string());
+    }
+
+    fn ty(&mut self, ty: &Type) {
+        self.push(&ty.to_string());
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::parser;
+
+    fn round_trip(src: &str) -> String {
+        let mut interner = Interner::new();
+        let form = parser::expression(src, &mut interner).unwrap();
+        print_form(&form, &interner)
+    }
+
+    #[test]
+    fn print_precedence() {
+        assert_eq!(round_trip("(fuck, shit) -> fuck + shit * 2"),
+                   "(fuck, shit) -> fuck + shit * 2");
+        assert_eq!(round_trip("(fuck + shit) * 2"), "(fuck + shit) * 2");
+        assert_eq!(round_trip("fuck - (shit - 1)"), "fuck - (shit - 1)");
+        assert_eq!(round_trip("fuck - shit - 1"), "fuck - shit - 1");
+        assert_eq!(round_trip("(if (fuck) 1 else 2) + 1"), "(if (fuck) 1 else 2) + 1");
+    }
+
+    #[test]
+    fn print_forms() {
+        assert_eq!(round_trip("let fuck: Int = 1 in { print(fuck), fuck }"),
+                   "let fuck: Int = 1 in { print(fuck), fuck }");
+        assert_eq!(round_trip("match (fuck) { Shit(a, _) => a, 'c' => 1, _ => 0 }"),
+                   "match (fuck) { Shit(a, _) => a, 'c' => 1, _ => 0 }");
+        assert_eq!(round_trip("(fuck, \"shit\").1"), "(fuck, \"shit\").1");
+    }
+}
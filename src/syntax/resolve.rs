@@ -0,0 +1,190 @@
+/// Resolve variables to their bindings
+
+use std::fmt;
+use std::collections::HashSet;
+use std::collections::HashMap;
+
+use utils::*;
+
+use syntax::form::*;
+
+/// What a variable is bound to
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Binding {
+    /// Bound by `let` or a pattern
+    Local,
+    /// Parameter of a function
+    Param,
+    /// Top level definition, which could be referred before defined
+    Global,
+    /// Provided by the prelude
+    Builtin,
+}
+
+/// A variable occurrence with its binding
+#[derive(Clone, PartialEq, Debug)]
+pub struct Resolution {
+    pub name: Id,
+    pub pos: Span,
+    pub binding: Binding,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ResolveError {
+    Unbound(String, Span),
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ResolveError::Unbound(ref n, _) => write!(f, "`{}` is not in scope", n),
+        }
+    }
+}
+
+type Scope = HashMap<Id, Binding>;
+
+/// Resolve every variable in definitions, in order of occurrence.
+///   Inner bindings shadow outer ones, then top level definitions,
+///   then names for which `is_builtin` holds.
+pub fn resolve<F>(defs: &[Def], is_builtin: F, interner: &Interner) -> Result<Vec<Resolution>, ResolveError>
+    where F: Fn(Id) -> bool
+{
+    let globals = defs.iter().filter(|d| d.is_form()).map(|d| d.name()).collect();
+    let mut resolver = Resolver { globals, is_builtin, interner, res: vec![] };
+    for def in defs.iter() {
+        if let Item::Form(ref f) = def.node {
+            resolver.form(f, &Scope::new())?;
+        }
+    }
+    Ok(resolver.res)
+}
+
+struct Resolver<'i, F> {
+    globals: HashSet<Id>,
+    is_builtin: F,
+    interner: &'i Interner,
+    res: Vec<Resolution>,
+}
+
+impl<'i, F> Resolver<'i, F>
+    where F: Fn(Id) -> bool
+{
+    fn var(&mut self, name: Id, pos: Span, scope: &Scope) -> Result<(), ResolveError> {
+        let binding = match scope.get(&name) {
+            Some(&b) => b,
+            None if self.globals.contains(&name) => Binding::Global,
+            None if (self.is_builtin)(name) => Binding::Builtin,
+            None => return Err(ResolveError::Unbound(self.interner.trace_string(name), pos)),
+        };
+        self.res.push(Resolution { name, pos, binding });
+        Ok(())
+    }
+
+    fn forms(&mut self, fs: &[E], scope: &Scope) -> Result<(), ResolveError> {
+        for f in fs.iter() {
+            self.form(f, scope)?;
+        }
+        Ok(())
+    }
+
+    fn form(&mut self, form: &Form, scope: &Scope) -> Result<(), ResolveError> {
+        use self::Expr::*;
+        let bind = |names: Vec<Id>, b: Binding| {
+            let mut inner = scope.clone();
+            inner.extend(names.into_iter().map(|n| (n, b)));
+            inner
+        };
+        match form.node {
+            Lit(_) => Ok(()),
+            Var(n) => self.var(n, form.tag.pos, scope),
+            List(ref fs) |
+            Block(ref fs) |
+            Tuple(ref fs) => self.forms(fs, scope),
+            Apply(ref callee, ref args) => {
+                self.form(callee, scope)?;
+                self.forms(args, scope)
+            }
+            Abs(ref lambda) => {
+                let params = lambda.param.iter().map(|p| p.name()).collect();
+                self.form(&lambda.body, &bind(params, Binding::Param))
+            }
+            Binary(_, ref l, ref r) |
+            Index(ref l, ref r) |
+            While(ref l, ref r) => {
+                self.form(l, scope)?;
+                self.form(r, scope)
+            }
+            Unary(_, ref e) |
+            TupleProj(ref e, _) |
+            Field(ref e, _) => self.form(e, scope),
+            // A function could call itself by the name bound
+            Let(ref var, ref val, ref exp) => {
+                let inner = bind(vec![var.name()], Binding::Local);
+                match val.node {
+                    Abs(_) => self.form(val, &inner)?,
+                    _ => self.form(val, scope)?,
+                }
+                self.form(exp, &inner)
+            }
+            If(ref c, ref t, ref f) => {
+                self.form(c, scope)?;
+                self.form(t, scope)?;
+                self.form(f, scope)
+            }
+            Match(ref e, ref arms) => {
+                self.form(e, scope)?;
+                for arm in arms.iter() {
+                    self.form(&arm.body, &bind(arm.pattern.binders(), Binding::Local))?;
+                }
+                Ok(())
+            }
+            Record(_, ref fields) => {
+                for &(_, ref f) in fields.iter() {
+                    self.form(f, scope)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::parser;
+
+    fn run(src: &str, interner: &mut Interner) -> Result<Vec<Resolution>, ResolveError> {
+        let defs = parser::parse(src, interner).unwrap();
+        let print = interner.intern("print");
+        resolve(&defs, |n| n == print, interner)
+    }
+
+    #[test]
+    fn resolve_unbound() {
+        let mut interner = Interner::new();
+        match run("def main() = print(fuck)", &mut interner) {
+            Err(ResolveError::Unbound(ref n, _)) => assert_eq!(n, "fuck"),
+            r => panic!("expect unbound variable, found {:?}", r),
+        }
+    }
+
+    #[test]
+    fn resolve_shadowed() {
+        let mut interner = Interner::new();
+        let res = run("def f(x) = { x, let x = 1 in x }", &mut interner).unwrap();
+        let bindings: Vec<_> = res.iter().map(|r| r.binding).collect();
+        assert_eq!(bindings, vec![Binding::Param, Binding::Local]);
+    }
+
+    #[test]
+    fn resolve_forward_global() {
+        let mut interner = Interner::new();
+        let res = run("def main() = print(f(1))\ndef f(x) = x", &mut interner).unwrap();
+        let f = interner.intern("f");
+        let bindings: Vec<_> = res.iter().map(|r| (r.name, r.binding)).collect();
+        assert_eq!(bindings[0].1, Binding::Builtin);
+        assert_eq!(bindings[1], (f, Binding::Global));
+    }
+}
@@ -0,0 +1,154 @@
+/// Expansion of type aliases into their definitions
+
+use std::collections::HashMap;
+
+use types::*;
+use syntax::form::Span;
+
+use super::subst::*;
+use super::error::TypeError;
+
+/// Aliases defined by `type`, with type parameters
+#[derive(Debug, Default)]
+pub struct Aliases {
+    defs: HashMap<Name, (Vec<Name>, Type)>,
+}
+
+impl Aliases {
+    pub fn new() -> Aliases {
+        Aliases { defs: HashMap::new() }
+    }
+
+    pub fn define(&mut self, name: Name, params: Vec<Name>, body: Type) {
+        self.defs.insert(name, (params, body));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.defs.is_empty()
+    }
+
+    /// Replace aliases in type by their definitions, recursively.
+    ///   An alias applied to other number of arguments than its parameters is kept.
+    pub fn expand(&self, ty: &Type, pos: Span) -> Result<Type, TypeError> {
+        self.expand_in(ty, pos, &mut vec![])
+    }
+
+    pub fn expand_scheme(&self, scm: &Scheme, pos: Span) -> Result<Scheme, TypeError> {
+        Ok(match *scm {
+            Scheme::Mono(ref t) => Scheme::Mono(self.expand(t, pos)?),
+            Scheme::Poly(ref bs, ref t) => Scheme::Poly(bs.clone(), self.expand(t, pos)?),
+            Scheme::Slot => Scheme::Slot,
+        })
+    }
+
+    /// `visiting` are aliases being expanded, meeting one of them again is a cycle
+    fn expand_in(&self, ty: &Type, pos: Span, visiting: &mut Vec<Name>) -> Result<Type, TypeError> {
+        use self::Type::*;
+        let (head, args) = ty.split_comp();
+        if let Con(ref n) = *head {
+            if let Some(&(ref params, ref body)) = self.defs.get(n) {
+                if params.len() == args.len() {
+                    if visiting.contains(n) {
+                        return Err(TypeError::CyclicAlias(n.clone(), pos));
+                    }
+                    let mut sub = Subst::new();
+                    for (p, a) in params.iter().zip(args.into_iter()) {
+                        sub.insert(p.clone(), self.expand_in(a, pos, visiting)?);
+                    }
+                    visiting.push(n.clone());
+                    let res = self.expand_in(&body.clone().apply(&sub), pos, visiting);
+                    visiting.pop();
+                    return res;
+                }
+            }
+        }
+        Ok(match *ty {
+            Arr(ref ps, box ref r) => {
+                let ps = ps.iter()
+                    .map(|p| self.expand_in(p, pos, visiting))
+                    .collect::<Result<_, _>>()?;
+                Arr(ps, P(self.expand_in(r, pos, visiting)?))
+            }
+            Prod(ref ts) => {
+                let ts = ts.iter()
+                    .map(|t| self.expand_in(t, pos, visiting))
+                    .collect::<Result<_, _>>()?;
+                Prod(ts)
+            }
+            Comp(box ref c, box ref a) => {
+                let c = self.expand_in(c, pos, visiting)?;
+                Comp(P(c), P(self.expand_in(a, pos, visiting)?))
+            }
+            ref t => t.clone(),
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::*;
+    use syntax::parser;
+    use typeinfer::Infer;
+
+    fn aliases(defs: &[(&str, &[&str], &str)], interner: &mut Interner) -> Aliases {
+        let mut res = Aliases::new();
+        for &(name, params, body) in defs.iter() {
+            let ps = params.iter().map(|p| p.to_string()).collect();
+            res.define(name.to_string(), ps, parser::parse_type(body, interner).body().clone());
+        }
+        res
+    }
+
+    fn ty(src: &str, interner: &mut Interner) -> Type {
+        parser::parse_type(src, interner).body().clone()
+    }
+
+    #[test]
+    fn expand_simple_alias() {
+        let mut interner = Interner::new();
+        let al = aliases(&[("Pair", &[], "Int * Int")], &mut interner);
+        let pos = Span::point(0);
+        assert_eq!(al.expand(&ty("Pair -> Bool", &mut interner), pos), Ok(ty("(Int * Int) -> Bool", &mut interner)));
+        assert_eq!(al.expand(&ty("Int", &mut interner), pos), Ok(ty("Int", &mut interner)));
+    }
+
+    #[test]
+    fn expand_nested_alias() {
+        let mut interner = Interner::new();
+        let al = aliases(&[("Twice", &["a"], "a * a"), ("Op", &[], "Twice Int -> Int")], &mut interner);
+        let pos = Span::point(0);
+        assert_eq!(al.expand(&ty("Op * Twice Bool", &mut interner), pos),
+                   Ok(ty("((Int * Int) -> Int) * (Bool * Bool)", &mut interner)));
+    }
+
+    #[test]
+    fn expand_cyclic_alias() {
+        let mut interner = Interner::new();
+        let al = aliases(&[("A", &[], "B * Int"), ("B", &[], "Bool -> A")], &mut interner);
+        match al.expand(&ty("A", &mut interner), Span::point(3)) {
+            Err(TypeError::CyclicAlias(ref n, pos)) => {
+                assert_eq!(n, "A");
+                assert_eq!(pos, Span::point(3));
+            }
+            r => panic!("expect cyclic alias, found {:?}", r),
+        }
+
+        let src = "type A = B * Int\ntype B = Bool -> A\ndef f() = 1";
+        let mut defs = parser::parse(src, &mut interner).unwrap();
+        let env = Infer::new_env();
+        let errors = Infer::new(&mut interner).infer_defs(&env, &mut defs).unwrap_err();
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn alias_in_annotation() {
+        let mut interner = Interner::new();
+        let src = "type Pair = Int * Int\ndef swap(p: Pair): Pair = (p.1, p.0)";
+        let mut defs = parser::parse(src, &mut interner).unwrap();
+        let env = Infer::new_env();
+        Infer::new(&mut interner).infer_defs(&env, &mut defs).unwrap();
+        assert_eq!(*defs[1].form_type().body(), ty("(Int * Int) -> Int * Int", &mut interner));
+    }
+}
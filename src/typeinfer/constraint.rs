@@ -1,4 +1,3 @@
-use std::collections::HashMap;
 use std::collections::HashSet;
 
 use types::*;
@@ -12,35 +11,10 @@ pub struct Constraint(pub Type, pub Type);
 
 impl Constraint {
     pub fn unify<'a>(self) -> Result<Subst, TypeError> {
-        use self::Type::*;
-        match self {
-            Constraint(Var(n), t) |
-            Constraint(t, Var(n)) => {
-                let mut s = HashMap::new();
-                s.insert(n, t);
-                Ok(s)
-            },
-            Constraint(Arr(l1, l2), Arr(r1, r2)) |
-            Constraint(Prod(l1, l2), Prod(r1, r2)) |
-            Constraint(Comp(l1, l2), Comp(r1, r2)) => {
-                let mut u1 = Constraint(*l1, *r1).unify()?;
-                let u2 = Constraint(*l2, *r2).apply(&u1).unify()?;
-                u1.apply_mut(&u2);
-                u1.extend(u2);
-                Ok(u1)
-            },
-            Constraint(el, er) => {
-                if el == er {
-                    Ok(HashMap::new())
-                } else {
-                    Err(TypeError::MisMatch(el, er))
-                }
-            }
-        }
+        unify(&self.0, &self.1)
     }
 }
 
-
 impl Substituable for Constraint {
     fn apply(self, sub: &Subst) -> Self {
         Constraint(self.0.apply(sub), self.1.apply(sub))
@@ -58,5 +32,3 @@ impl SubstMut for Constraint {
         self.1 = self.1.clone().apply(sub);
     }
 }
-
-
@@ -1,4 +1,7 @@
+use std::fmt;
+
 use types::*;
+use utils::*;
 use syntax::form::*;
 use internal::*;
 
@@ -6,6 +9,103 @@ use internal::*;
 pub enum TypeError {
     NotInScope(String, Span),
     MisMatch(Type, Type),
+    /// Type variable occurs in the type it binds to
+    InfiniteType(Name, Type),
+    /// Inferred type does not satisfy the annotation
+    BadAnnotation(Scheme, Type, Span),
+    /// List item differs from the previous ones
+    ListItem(Type, Type, Span),
+    /// Products with different number of elements
+    Arity(usize, usize),
+    /// Applying more arguments than parameters of the callee
+    ArgCount(usize, usize, Span),
+    /// Argument does not match the parameter type
+    BadArgument(Type, Type, Span),
     HighRank(Type, Span),
     UnknownOperator(BinOp, Span),
+    /// Projecting a non-tuple type or a index out of range
+    BadProjection(Type, usize, Span),
+    /// Fields of record or pattern do not match the variant
+    BadFields(Name, Span),
+    /// Type has no such field
+    NoField(Type, Name, Span),
+    /// Using a value of `Void` type
+    VoidValue(Span),
+    /// Type alias defined by itself
+    CyclicAlias(Name, Span),
+    /// Type constructor applied to other number of arguments than it takes
+    TypeArity(Name, usize, usize, Span),
+    /// Applying a type which is not a constructor
+    NotConstructor(Type, Span),
+    /// Comparing values of a type without equality, like functions
+    NoEquality(Type, Span),
+    /// Field declared twice in a variant
+    DuplicateField(Name, Span),
+    /// Variant declared twice in a `data`
+    DuplicateVariant(Name, Span),
+    /// Printing a value of a type without format, like tuples
+    NotPrintable(Type, Span),
+}
+
+impl TypeError {
+    /// Where the error is found, errors of unification do not know it
+    pub fn pos(&self) -> Option<Span> {
+        use self::TypeError::*;
+        match *self {
+            NotInScope(_, pos) |
+            BadAnnotation(_, _, pos) |
+            ListItem(_, _, pos) |
+            ArgCount(_, _, pos) |
+            BadArgument(_, _, pos) |
+            HighRank(_, pos) |
+            UnknownOperator(_, pos) |
+            BadProjection(_, _, pos) |
+            BadFields(_, pos) |
+            NoField(_, _, pos) |
+            VoidValue(pos) |
+            CyclicAlias(_, pos) |
+            TypeArity(_, _, _, pos) |
+            NotConstructor(_, pos) |
+            NoEquality(_, pos) |
+            DuplicateField(_, pos) |
+            DuplicateVariant(_, pos) |
+            NotPrintable(_, pos) => Some(pos),
+            MisMatch(..) | InfiniteType(..) | Arity(..) => None,
+        }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::TypeError::*;
+        match *self {
+            NotInScope(ref n, _) => write!(f, "`{}` is not in scope", n),
+            MisMatch(ref l, ref r) => write!(f, "mismatched types `{}` and `{}`", l, r),
+            InfiniteType(ref n, ref t) => write!(f, "infinite type: `{}` occurs in `{}`", n, t),
+            BadAnnotation(ref s, ref t, _) => {
+                write!(f, "expected `{}` as annotated, found `{}`", s, t)
+            }
+            ListItem(ref l, ref r, _) => {
+                write!(f, "list items should be `{}`, found `{}`", l, r)
+            }
+            Arity(l, r) => write!(f, "expected {} elements, found {}", l, r),
+            ArgCount(l, r, _) => write!(f, "expected {} args, got {}", l, r),
+            BadArgument(ref l, ref r, _) => {
+                write!(f, "expected argument of `{}`, found `{}`", l, r)
+            }
+            HighRank(ref t, _) => write!(f, "higher rank type `{}` is not supported", t),
+            UnknownOperator(op, _) => write!(f, "unknown operator `{}`", op.as_str()),
+            BadProjection(ref t, i, _) => write!(f, "cannot get element {} of `{}`", i, t),
+            BadFields(ref c, _) => write!(f, "fields do not match variant `{}`", c),
+            NoField(ref t, ref n, _) => write!(f, "`{}` has no field `{}`", t, n),
+            VoidValue(_) => write!(f, "value of `Void` type cannot be used"),
+            CyclicAlias(ref n, _) => write!(f, "type alias `{}` is defined by itself", n),
+            TypeArity(ref n, l, r, _) => write!(f, "type `{}` takes {} arguments, found {}", n, l, r),
+            NotConstructor(ref t, _) => write!(f, "type `{}` cannot be applied", t),
+            NoEquality(ref t, _) => write!(f, "values of `{}` cannot be compared", t),
+            DuplicateField(ref n, _) => write!(f, "field `{}` is already declared", n),
+            DuplicateVariant(ref n, _) => write!(f, "variant `{}` is already declared", n),
+            NotPrintable(ref t, _) => write!(f, "values of `{}` cannot be printed", t),
+        }
+    }
 }
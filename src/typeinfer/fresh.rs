@@ -0,0 +1,71 @@
+use std::collections::HashSet;
+
+use types::*;
+use utils::*;
+
+/// Supply of unique type variable names, `t0`, `t1`, ...
+#[derive(Debug, Clone, Default)]
+pub struct FreshSupply {
+    next: usize,
+    /// Names never given, e.g. type variables written by user
+    reserved: HashSet<Name>,
+}
+
+impl FreshSupply {
+    pub fn new() -> FreshSupply {
+        FreshSupply::default()
+    }
+
+    /// Create a supply never giving the names
+    pub fn avoiding<I>(names: I) -> FreshSupply
+        where I: IntoIterator<Item = Name>
+    {
+        let mut supply = FreshSupply::new();
+        supply.reserve(names);
+        supply
+    }
+
+    /// Stop giving the names
+    pub fn reserve<I>(&mut self, names: I)
+        where I: IntoIterator<Item = Name>
+    {
+        self.reserved.extend(names);
+    }
+
+    pub fn fresh_name(&mut self) -> Name {
+        loop {
+            let name = format!("t{}", self.next);
+            self.next += 1;
+            if !self.reserved.contains(&name) {
+                return name;
+            }
+        }
+    }
+
+    pub fn fresh_var(&mut self) -> Type {
+        Type::Var(self.fresh_name())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fresh_never_repeat() {
+        let mut supply = FreshSupply::new();
+        let names: HashSet<_> = (0..100).map(|_| supply.fresh_name()).collect();
+        assert_eq!(names.len(), 100);
+        assert_eq!(supply.fresh_var(), Type::Var("t100".to_string()));
+    }
+
+    #[test]
+    fn fresh_avoid_reserved() {
+        let user: Vec<Name> = vec!["t0".to_string(), "t2".to_string(), "a".to_string()];
+        let mut supply = FreshSupply::avoiding(user.clone());
+        let names: Vec<_> = (0..3).map(|_| supply.fresh_name()).collect();
+        assert_eq!(names, vec!["t1", "t3", "t4"]);
+        assert!(names.iter().all(|n| !user.contains(n)));
+    }
+}
@@ -18,50 +18,205 @@ use std::ops::Placer;
 use std::mem;
 
 use super::subst::*;
-use super::constraint::{ Constraint };
+use super::constraint::Constraint;
+use super::fresh::FreshSupply;
+use super::alias::Aliases;
+use super::kind::Kinds;
 pub use super::error::{ TypeError };
 
 #[derive(Debug)]
 pub struct Infer<'interner> {
-    unique: usize,
+    supply: FreshSupply,
     interner: &'interner mut Interner,
     constraints: LinkedList<Constraint>,
+    /// Substitution solved so far
+    sub: Subst,
+    /// `data` definitions, with type parameters
+    datas: HashMap<Name, (Vec<Name>, Vec<Variant>)>,
+    /// Type aliases, expanded in annotations before checking
+    aliases: Aliases,
+    /// Arity of type constructors
+    kinds: Kinds,
+    /// Types of printed values, checked after solved
+    printed: Vec<(Type, Span)>,
 }
 
 
-//fn generalize(e: &TypeEnv, ty: Type) -> Scheme {
-//}
+/// Replace bound variables of a scheme with fresh ones
+pub fn instantiate(scm: &Scheme, fresh: &mut FreshSupply) -> Type {
+    use self::Scheme::*;
+    match *scm {
+        Mono(ref ty) => ty.clone(),
+        Poly(ref tvs, ref ty) => {
+            let sub: Subst = tvs.iter().map(|n| (n.clone(), fresh.fresh_var())).collect();
+            ty.clone().apply(&sub)
+        }
+        Slot => unreachable!(),
+    }
+}
+
+/// Replace bound variables of a scheme with rigid types,
+///   which only unify with themselves
+fn skolemize(scm: &Scheme) -> Type {
+    match *scm {
+        Scheme::Poly(ref tvs, ref ty) => {
+            let sub: Subst = tvs.iter().map(|n| (n.clone(), Type::Con(format!("'{}", n)))).collect();
+            ty.clone().apply(&sub)
+        }
+        _ => scm.body().clone(),
+    }
+}
+
+/// Quantify over type variables free in `ty`
+///   but not in the environment
+pub fn generalize(env_free: &HashSet<Name>, ty: &Type) -> Scheme {
+    let mut bounds: Vec<_> = ty.free_vars()
+        .into_iter()
+        .filter(|n| !env_free.contains(n))
+        .collect();
+    if bounds.is_empty() {
+        Scheme::Mono(ty.clone())
+    } else {
+        bounds.sort();
+        Scheme::Poly(bounds, ty.clone())
+    }
+}
 
 fn to_mono(ty: Type) -> Scheme {
     Scheme::Mono(ty)
 }
 
+/// Whether a function type occurs in the type
+fn has_function(ty: &Type) -> bool {
+    let mut found = false;
+    ty.walk(|t| if let Type::Arr(..) = *t {
+        found = true;
+    });
+    found
+}
+
+/// Values of primitive types and strings could be printed,
+///   a type variable may be any of them
+fn is_printable(ty: &Type) -> bool {
+    match *ty {
+        Type::Var(_) => true,
+        Type::Con(ref n) => ["Int", "Long", "Float", "Char", "Bool", "String"].contains(&n.as_str()),
+        _ => false,
+    }
+}
+
+/// Variants and fields of a variant declared more than once,
+///   found at their later declarations
+fn duplicates(variants: &[Variant]) -> Vec<TypeError> {
+    let mut errors = vec![];
+    let mut names = HashSet::new();
+    for v in variants {
+        if !names.insert(&v.name) {
+            errors.push(TypeError::DuplicateVariant(v.name.clone(), v.pos));
+        }
+        let mut fields = HashSet::new();
+        for f in v.fields() {
+            if let Some(ref n) = f.name {
+                if !fields.insert(n) {
+                    errors.push(TypeError::DuplicateField(n.clone(), f.pos));
+                }
+            }
+        }
+    }
+    errors
+}
 
 impl<'i> Infer<'i> {
     pub fn new(interner: &'i mut Interner) -> Infer<'i> {
         Infer {
-            unique: 0,
+            supply: FreshSupply::new(),
             constraints: LinkedList::new(),
+            sub: Subst::new(),
+            datas: HashMap::new(),
+            aliases: Aliases::new(),
+            kinds: Kinds::new(),
+            printed: vec![],
             interner,
         }
     }
 
-    pub fn new_env<'a>() -> TypeEnv<'a> {
-        TypeEnv::new()
+    /// Make a `data` definition visible to record expressions
+    pub fn define_data(&mut self, name: Name, params: Vec<Name>, variants: Vec<Variant>) {
+        self.datas.insert(name, (params, variants));
+    }
+
+    /// Instantiate the type of a variant by its constructor,
+    ///   giving the type of value and types of fields
+    fn variant_of(&mut self, ctor: &str) -> Option<(Type, Vec<(Option<Name>, Type)>)> {
+        let found = self.datas
+            .iter()
+            .filter_map(|(n, &(ref ps, ref vs))| {
+                vs.iter().find(|v| v.name == ctor).map(|v| (n.clone(), ps.clone(), v.clone()))
+            })
+            .next();
+        let (name, params, variant) = match found {
+            Some(f) => f,
+            None => return None,
+        };
+        let tvs: Vec<_> = params.iter().map(|_| self.fresh()).collect();
+        let sub: Subst = params.into_iter().zip(tvs.clone()).collect();
+
+        let mut tys = vec![Type::Con(name)];
+        tys.extend(tvs);
+        let fields = variant.fields()
+            .iter()
+            .map(|f| (f.name.clone(), f.ty.deref().clone().apply(&sub)))
+            .collect();
+        Some((Type::compose_n(tys), fields))
+    }
+
+    /// Get the type of a named field, of the only variant of `data`.
+    ///   Fields of a type with more variants are taken by `match`.
+    fn field_of(&self, ty: &Type, field: &str) -> Option<Type> {
+        let (head, args) = ty.split_comp();
+        let name = match *head {
+            Type::Con(ref n) => n,
+            _ => return None,
+        };
+        let &(ref params, ref variants) = match self.datas.get(name) {
+            Some(d) => d,
+            None => return None,
+        };
+        if variants.len() != 1 {
+            return None;
+        }
+        let sub: Subst = params.iter().cloned().zip(args.into_iter().cloned()).collect();
+        variants[0].fields()
+            .iter()
+            .find(|f| f.name.as_ref().map(|n| n.as_str()) == Some(field))
+            .map(|f| f.ty.deref().clone().apply(&sub))
+    }
+
+    /// Get the type of a positional field, of the only variant of `data`
+    fn position_of(&self, ty: &Type, index: usize) -> Option<Type> {
+        let (head, args) = ty.split_comp();
+        let name = match *head {
+            Type::Con(ref n) => n,
+            _ => return None,
+        };
+        let &(ref params, ref variants) = match self.datas.get(name) {
+            Some(d) => d,
+            None => return None,
+        };
+        if variants.len() != 1 {
+            return None;
+        }
+        let sub: Subst = params.iter().cloned().zip(args.into_iter().cloned()).collect();
+        variants[0].fields().get(index).map(|f| f.ty.deref().clone().apply(&sub))
     }
 
-    /// Get a unique id, then increase the counter
-    fn unique(&mut self) -> usize {
-        self.unique = self.unique + 1;
-        self.unique
+    pub fn new_env<'a>() -> TypeEnv<'a> {
+        TypeEnv::new()
     }
 
-    /// Generate a new temp type variable name
-    /// All generated names start with a dot
+    /// Generate a new temp type variable
     fn fresh(&mut self) -> Type {
-        let mut f = String::from(".");
-        f.push_str(self.unique().to_string().as_str());
-        Type::Var(f)
+        self.supply.fresh_var()
     }
 
     /// Add a constraint
@@ -71,17 +226,7 @@ impl<'i> Infer<'i> {
 
     /// Temporary instantiate a polymorphism type
     fn instantiate(&mut self, scm: &Scheme) -> Type {
-        use self::Scheme::*;
-        match *scm {
-            Mono(ref ty) => ty.deref().clone(),
-            Poly(ref tvs, ref ty) => {
-                let tvs_: Vec<_> = tvs.iter().map(|_| self.fresh()).collect();
-                let sub: Subst = tvs.clone().into_iter().zip(tvs_).collect();
-
-                ty.clone().apply(&sub)
-            }
-            Slot => unreachable!(),
-        }
+        instantiate(scm, &mut self.supply)
     }
 
     /// The main inference algorithm
@@ -109,8 +254,10 @@ impl<'i> Infer<'i> {
                     // 然后因为这个 ty 的生命周期和 env 相同
                     // 然而 env 的生命周期比 'a 短
                     // 所以报错
-                    form.tag.ty = (*ty).clone();
-                    // form.set_scheme();
+                    // Polymorphic bindings get fresh type variables
+                    //   at each use.
+                    let ty = ty.clone();
+                    form.tag.set_type(self.instantiate(&ty));
                 } else {
                     return Err(NotInScope(self.interner.trace(*n).to_owned(), form.tag.pos));
                 }
@@ -128,79 +275,153 @@ impl<'i> Infer<'i> {
                 for p in fun.param.iter_mut() {
                     match p.1 {
                         Slot => p.1 = Scheme::Mono(self.fresh()),
-                        _ => {}
+                        _ => p.1 = self.written_scheme(&p.1, form.tag.pos)?,
                     }
                     extends.push((p.0.to_owned(), p.1.clone()));
                     types.push(p.1.body().clone());
                 }
-                let typaram = if types.is_empty() {
-                    Type::Void
-                } else {
-                    Type::product_n(types)
-                };
                 let mut new_env = e.extend_n(extends);
-                let tbody = self.infer(&mut new_env, fun.body.deref_mut())?;
-                form.tag.ty = Scheme::arrow(typaram, tbody.body().clone());
+                let tbody = self.infer(&mut new_env, fun.body.deref_mut())?.body().clone();
+                // Body should be of the annotated return type
+                match fun.ret {
+                    Slot => {}
+                    ref mut ret => {
+                        *ret = self.written_scheme(ret, form.tag.pos)?;
+                        self.check_annotation(ret, &fun.body)?
+                    }
+                }
+                form.tag.ty = Scheme::arrow(types, tbody);
             }
 
             // A function apply.
-            // Types of arguments should be able to unify with callee.
+            // If the callee is known to be a function, arguments are checked
+            //   against parameters one by one.
+            //   Applying fewer arguments returns a function of the rest parameters.
+            // Otherwise types of arguments should be able to unify with callee.
             // Infer the returned type.
             Apply(box ref mut callee, ref mut args) => {
                 let callee_pos = callee.tag.pos;
                 let ty_callee = self.infer(e, callee)?;
                 let callee_inst = self.instantiate(ty_callee);
-                let mut ty_args: Vec<Type> = vec![];
+                let mut ty_args: Vec<(Type, Span)> = vec![];
 
                 for arg in args.iter_mut() {
-                    ty_args.push(self.infer(e, arg)?
-                                     .body()
-                                     .clone());
+                    let pos = arg.tag.pos;
+                    let ty = self.infer(e, arg)?.body().clone();
+                    self.check_value(&ty, pos)?;
+                    ty_args.push((ty, pos));
+                }
+                if let Var(n) = callee.node {
+                    let name = self.interner.trace(n);
+                    if name == "print" || name == "println" {
+                        self.printed.extend(ty_args.iter().cloned());
+                    }
                 }
 
-                form.tag.set_type(self.fresh());
-
-                let tyfun = Scheme::arrow(Type::product_n(ty_args), form.tag.clone_type());
-
-                self.uni((&callee_inst, callee_pos), (tyfun.body(), form.tag.pos));
+                self.solve_pending()?;
+                let callee_ty = callee_inst.apply(&self.sub);
+                let ret = match callee_ty {
+                    Type::Arr(params, box ret) => {
+                        if ty_args.len() > params.len() {
+                            return Err(ArgCount(params.len(), ty_args.len(), form.tag.pos));
+                        }
+                        let applied = ty_args.len();
+                        for (param, (ty, pos)) in params.iter().zip(ty_args.into_iter()) {
+                            let expected = param.clone().apply(&self.sub);
+                            let found = ty.apply(&self.sub);
+                            match unify(&expected, &found) {
+                                Ok(s) => self.sub = s.compose(&self.sub),
+                                Err(_) => return Err(BadArgument(expected, found, pos)),
+                            }
+                        }
+                        if applied < params.len() {
+                            let rest: Vec<Type> = params.into_iter().skip(applied).collect();
+                            Type::arrow(rest, ret)
+                        } else {
+                            ret
+                        }
+                    }
+                    callee_ty => {
+                        let ret = self.fresh();
+                        let params = ty_args.into_iter().map(|(t, _)| t).collect();
+                        let tyfun = Type::arrow(params, ret.clone());
+                        self.uni((&callee_ty, callee_pos), (&tyfun, form.tag.pos));
+                        ret
+                    }
+                };
+                form.tag.set_type(ret);
             }
 
             // Type of binary ops should exist in environment.
+            // Operands should have values.
             Binary(ref op, ref mut left, ref mut right) => {
+                let (left_pos, right_pos) = (left.tag.pos, right.tag.pos);
                 let ty_left = self.infer(e, left)?.body();
                 let ty_right = self.infer(e, right)?.body();
+                self.check_value(ty_left, left_pos)?;
+                self.check_value(ty_right, right_pos)?;
                 if let Some(ty_op) = e.lookup(&self.interner.intern(&op.as_str())) {
-                    let ty_lr = Type::product(ty_left.clone(), ty_right.clone());
+                    let ty_lr = vec![ty_left.clone(), ty_right.clone()];
 
                     form.tag.ty = to_mono(self.fresh());
 
                     let ty_fun = Scheme::arrow(ty_lr, form.tag.clone_type());
                     self.uni((ty_op.body(), form.tag.pos), (ty_fun.body(), form.tag.pos));
+                    if *op == BinOp::Eq || *op == BinOp::Ne {
+                        self.check_equality(ty_left, form.tag.pos)?;
+                    }
                 } else {
                     return Err(TypeError::UnknownOperator(op.clone(), form.tag.pos.clone()));
                 }
             }
 
             // Let bound
+            // An annotated binding is checked against its annotation,
+            //   which is also the scheme of the name in body.
+            // Otherwise the bound value is inferred and generalized.
             Let(VarDecl(ref name, ref mut ty), box ref mut val, box ref mut body) => {
                 let val_pos = val.tag.pos;
-                if *ty == Scheme::Slot {
-                    *ty = to_mono(self.fresh());
-                }
+                let annot = match *ty {
+                    Scheme::Slot => None,
+                    ref scm => Some(self.written_scheme(scm, form.tag.pos)?),
+                };
+                *ty = match annot {
+                    Some(ref a) => a.clone(),
+                    None => to_mono(self.fresh()),
+                };
                 let tyval =
                     if let Abs(..) = val.node {
                         let old = e.insert(name.to_owned(), ty.to_owned());
-                        let ret = self.infer(e, val)?;
-                        if let Some(t) = old {
-                            e.insert(name.to_owned(), t);
-                        }
-                        ret
+                        let ret = self.infer(e, val);
+                        match old {
+                            Some(t) => e.insert(name.to_owned(), t),
+                            None => e.remove(name),
+                        };
+                        ret?.body().clone()
                     } else {
-                        self.infer(e, val)?
+                        self.infer(e, val)?.body().clone()
                     };
-                self.uni((ty.body(), body.tag.pos), (tyval.body(), val_pos));
 
-                let old = e.insert(name.to_owned(), tyval.to_owned());
+                let scm = match annot {
+                    Some(annot) => {
+                        self.check_annotation(&annot, val)?;
+                        annot
+                    }
+                    None => {
+                        self.uni((ty.body(), body.tag.pos), (&tyval, val_pos));
+
+                        // Generalize the bound value for let-polymorphism,
+                        //   which needs constraints so far solved.
+                        self.solve_pending()?;
+                        let env_free = e.values()
+                            .into_iter()
+                            .flat_map(|s| s.clone().apply(&self.sub).free_vars())
+                            .collect();
+                        generalize(&env_free, &tyval.apply(&self.sub))
+                    }
+                };
+
+                let old = e.insert(name.to_owned(), scm);
                 let tyexp = self.infer(e, body)?;
                 if let Some(t) = old {
                     e.insert(name.to_owned(), t);
@@ -224,7 +445,111 @@ impl<'i> Infer<'i> {
                 form.tag.set_scheme(tytr.clone());
             }
 
-            // Type of a block is the type of last expr
+            // Tuple has a product type of its elements
+            Tuple(ref mut elems) => {
+                let mut types: Vec<Type> = vec![];
+                for f in elems.iter_mut() {
+                    let pos = f.tag.pos;
+                    let ty = self.infer(e, f)?.body().clone();
+                    self.check_value(&ty, pos)?;
+                    types.push(ty);
+                }
+                form.tag.set_type(Type::product_n(types));
+            }
+
+            // Negation keeps the type of operand,
+            //   logical inversion takes a `Bool`
+            Unary(op, box ref mut operand) => {
+                let pos = operand.tag.pos;
+                let ty = self.infer(e, operand)?.body().clone();
+                self.check_value(&ty, pos)?;
+                if op == UnOp::Not {
+                    self.uni((&Type::Con("Bool".to_string()), form.tag.pos), (&ty, pos));
+                }
+                form.tag.set_type(ty);
+            }
+
+            // Type of tuple should be known at projection
+            TupleProj(box ref mut tuple, index) => {
+                let pos = tuple.tag.pos;
+                let ty = self.infer(e, tuple)?.body().clone();
+                self.solve_pending()?;
+                let ty = ty.apply(&self.sub);
+                let elem = match ty {
+                    Type::Prod(ref elems) => elems.get(index).cloned(),
+                    _ => self.position_of(&ty, index),
+                };
+                match elem {
+                    Some(t) => form.tag.set_type(t),
+                    None => return Err(BadProjection(ty, index, pos)),
+                }
+            }
+
+            // Indexed value should be a list, and the index an `Int`
+            Index(box ref mut list, box ref mut index) => {
+                let (list_pos, index_pos) = (list.tag.pos, index.tag.pos);
+                let tylist = self.infer(e, list)?.body().clone();
+                let tyindex = self.infer(e, index)?.body().clone();
+                let tyitem = self.fresh();
+                let expected = Type::compose(Type::Con("List".to_string()), tyitem.clone());
+                self.uni((&tylist, list_pos), (&expected, list_pos));
+                self.uni((&tyindex, index_pos), (&Type::Con("Int".to_string()), index_pos));
+                form.tag.set_type(tyitem);
+            }
+
+            // Record should give all fields of a struct variant,
+            //   fields are reordered to the declaration order.
+            // Fields of a tuple variant are named by their positions.
+            Record(ref ctor, ref mut fields) => {
+                let pos = form.tag.pos;
+                let (ty, decl) = match self.variant_of(ctor) {
+                    Some(v) => v,
+                    None => return Err(NotInScope(ctor.clone(), pos)),
+                };
+                let names: Vec<Name> = decl.iter()
+                    .enumerate()
+                    .map(|(i, &(ref f, _))| f.clone().unwrap_or_else(|| i.to_string()))
+                    .collect();
+                let matched = fields.len() == decl.len() &&
+                    names.iter().all(|f| fields.iter().any(|&(ref n, _)| f == n));
+                if !matched {
+                    return Err(BadFields(ctor.clone(), pos));
+                }
+                fields.sort_by_key(|&(ref n, _)| names.iter().position(|f| f == n));
+
+                for (&mut (_, ref mut f), &(_, ref tyfield)) in fields.iter_mut().zip(decl.iter()) {
+                    let fpos = f.tag.pos;
+                    let tyf = self.infer(e, f)?;
+                    self.uni((tyf.body(), fpos), (tyfield, pos));
+                }
+                form.tag.set_type(ty);
+            }
+
+            // Type of record should be known at field access
+            Field(box ref mut record, ref field) => {
+                let pos = record.tag.pos;
+                let ty = self.infer(e, record)?.body().clone();
+                self.solve_pending()?;
+                let ty = ty.apply(&self.sub);
+                match self.field_of(&ty, field) {
+                    Some(t) => form.tag.set_type(t),
+                    None => return Err(NoField(ty, field.clone(), pos)),
+                }
+            }
+
+            // While loop
+            // Condition should be `Bool`, the loop itself has no value.
+            While(box ref mut cond, box ref mut body) => {
+                let cond_pos = cond.tag.pos;
+                let tycond = self.infer(e, cond)?;
+                self.uni((tycond.body(), cond_pos), (&Type::Con("Bool".to_string()), cond_pos));
+                self.infer(e, body)?;
+                form.tag.set_type(Type::Void);
+            }
+
+            // Type of a block is the type of last expr, `Void` if empty.
+            // Preceding exprs are still checked for their effects,
+            //   but their values are discarded.
             Block(ref mut exps) => {
                 let mut ty = &Scheme::Mono(Type::Void);
                 for f in exps.iter_mut() {
@@ -234,13 +559,20 @@ impl<'i> Infer<'i> {
                 form.tag.set_scheme(ty.clone());
             }
 
-            // List should be mono
+            // List should be mono, an empty list has a fresh item type.
+            // Items are unified at once to find the first divergent one.
             List(ref mut exps) => {
                 let tyitem: Type = self.fresh();
                 for f in exps.iter_mut() {
                     let pos = f.tag.pos;
-                    let ty = self.infer(e, f)?;
-                    self.uni((ty.body(), form.tag.pos), (&tyitem, pos));
+                    let ty = self.infer(e, f)?.body().clone();
+                    self.check_value(&ty, pos)?;
+                    let expected = tyitem.clone().apply(&self.sub);
+                    let found = ty.apply(&self.sub);
+                    match unify(&expected, &found) {
+                        Ok(s) => self.sub = s.compose(&self.sub),
+                        Err(_) => return Err(ListItem(expected, found, pos)),
+                    }
                 }
 
                 form.tag.set_scheme(to_mono(
@@ -248,90 +580,322 @@ impl<'i> Infer<'i> {
                         Type::Con("List".to_string()),
                         tyitem)));
             },
-            _ => unimplemented!(),
+
+            // Patterns should match the type of scrutinee,
+            //   variables of a pattern are bound in its arm.
+            // All arms should be unified.
+            Match(box ref mut scrut, ref mut arms) => {
+                let pos = form.tag.pos;
+                let scrut_pos = scrut.tag.pos;
+                let tyscrut = self.infer(e, scrut)?.body().clone();
+                self.check_value(&tyscrut, scrut_pos)?;
+                let tyres = self.fresh();
+                for arm in arms.iter_mut() {
+                    let mut binds = vec![];
+                    self.infer_pattern(&arm.pattern, &tyscrut, pos, &mut binds)?;
+                    let body_pos = arm.body.tag.pos;
+                    let mut new_env = e.extend_n(binds);
+                    let tybody = self.infer(&mut new_env, arm.body.deref_mut())?.body().clone();
+                    self.uni((&tyres, pos), (&tybody, body_pos));
+                }
+                form.tag.set_type(tyres);
+            }
         }
 
-        // If there is a type annotation, unify it with inferred type
-        if let Some(ref annot) = form.tag.annotate {
-            let anno_inst = self.instantiate(annot);
-            self.uni((&anno_inst, form.tag.pos), (form.tag.ref_type(), form.tag.pos));
+        // If there is a type annotation, check it with inferred type
+        if let Some(annot) = form.tag.annotate.clone() {
+            let annot = self.written_scheme(&annot, form.tag.pos)?;
+            self.check_annotation(&annot, form)?;
+            form.tag.annotate = Some(annot);
         }
 
         Ok(form.tag.ref_scheme())
 
     }
 
-    /// Do type inference over top level definitions
+    /// Check a pattern against the type of values it matches,
+    ///   collecting types of variables it binds
+    fn infer_pattern(&mut self,
+                     pat: &Pattern,
+                     ty: &Type,
+                     pos: Span,
+                     binds: &mut Vec<(Id, Scheme)>)
+                     -> Result<(), TypeError> {
+        match *pat {
+            Pattern::Wildcard => {}
+            Pattern::Var(n) => binds.push((n, to_mono(ty.clone()))),
+            Pattern::Lit(ref l) => self.uni((ty, pos), (&l.lit_type(), pos)),
+            Pattern::Ctor(c, ref ps) => {
+                let ctor = self.interner.trace(c).to_owned();
+                let (tyvariant, fields) = match self.variant_of(&ctor) {
+                    Some(v) => v,
+                    None => return Err(TypeError::NotInScope(ctor, pos)),
+                };
+                if ps.len() != fields.len() {
+                    return Err(TypeError::BadFields(ctor, pos));
+                }
+                self.uni((ty, pos), (&tyvariant, pos));
+                for (p, &(_, ref tyfield)) in ps.iter().zip(fields.iter()) {
+                    self.infer_pattern(p, tyfield, pos, binds)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Values of `Void` type, like result of a `while` or
+    ///   a function returning nothing, could be discarded but not used
+    fn check_value(&mut self, ty: &Type, pos: Span) -> Result<(), TypeError> {
+        self.solve_pending()?;
+        match ty.clone().apply(&self.sub) {
+            Type::Void => Err(TypeError::VoidValue(pos)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Functions have no equality, neither do tuples, lists or `data` containing them
+    fn check_equality(&mut self, ty: &Type, pos: Span) -> Result<(), TypeError> {
+        self.solve_pending()?;
+        let ty = ty.clone().apply(&self.sub);
+        if self.contains_function(&ty, &mut HashSet::new()) {
+            Err(TypeError::NoEquality(ty, pos))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Whether a value of the type may contain a function,
+    ///   also in fields of `data` types not in `seen`
+    fn contains_function(&self, ty: &Type, seen: &mut HashSet<Name>) -> bool {
+        if has_function(ty) {
+            return true;
+        }
+        let mut found = false;
+        ty.walk(|t| if let Type::Con(ref n) = *t {
+            if found || !seen.insert(n.clone()) {
+                return;
+            }
+            if let Some(&(_, ref vs)) = self.datas.get(n) {
+                found = vs.iter()
+                    .flat_map(|v| v.fields())
+                    .any(|f| self.contains_function(&f.ty, seen));
+            }
+        });
+        found
+    }
+
+    /// Expand aliases in a type written in source,
+    ///   then check its type constructors are well applied
+    fn written_scheme(&self, scm: &Scheme, pos: Span) -> Result<Scheme, TypeError> {
+        let res = self.aliases.expand_scheme(scm, pos)?;
+        self.kinds.check_scheme(&res, pos)?;
+        Ok(res)
+    }
+
+    /// Check the inferred type of form is at least as general as
+    ///   the annotation. Bound variables of annotation are rigid,
+    ///   so `forall a. a -> a` rejects `Int -> Int`, while
+    ///   `Int -> Int` accepts `a -> a`.
+    fn check_annotation(&mut self, annot: &Scheme, form: &Form) -> Result<(), TypeError> {
+        self.solve_pending()?;
+        let inferred = form.tag.ref_type().clone().apply(&self.sub);
+        // Same as the annotation up to names of bound variables
+        if let Scheme::Poly(..) = *annot {
+            if generalize(&HashSet::new(), &inferred).normalize() == annot.normalize() {
+                return Ok(());
+            }
+        }
+        let expected = skolemize(annot);
+        match unify(&inferred, &expected) {
+            // Only monomorphic annotation refines the type,
+            //   rigid variables should not escape.
+            Ok(s) => {
+                if let Scheme::Mono(_) = *annot {
+                    self.sub = s.compose(&self.sub);
+                }
+                Ok(())
+            }
+            Err(_) => Err(TypeError::BadAnnotation(annot.clone(), inferred, form.tag.pos)),
+        }
+    }
+
+    /// Infer types of definitions, reporting every group failed
+    ///   with the definition it is found in.
+    ///   A definition failed to check could be of any type,
+    ///   so others using it are still checked without more errors.
     pub fn infer_defs<'a>(&mut self,
                       _env: &'a TypeEnv<'a>,
                       program: &'a mut Vec<Def>)
-                      -> Result<(), TypeError> {
-                
-        // Give each definitions a temporary type if no annotation
-        let mut env = {
-            let name_scms = program
-                .iter()
-                .filter(|ref v| v.is_form())
-                .map(|ref d| (d.name(), match d.form_annot() {
-                    Some(s) => s.clone(),
-                    _ => to_mono(self.fresh())
-                }));
+                      -> Result<(), Vec<(TypeError, Span)>> {
+
+        let mut errors = vec![];
+        for d in program.iter() {
+            let name = self.interner.trace(d.name()).to_owned();
+            match d.node {
+                Item::Alias(ref ps, ref scm) => {
+                    let params = ps.iter().map(|&p| self.interner.trace(p).to_owned()).collect();
+                    self.kinds.define(name.clone(), ps.len());
+                    self.aliases.define(name, params, scm.body().clone());
+                }
+                Item::Alg(ref ps, _) => self.kinds.define(name, ps.len()),
+                _ => {}
+            }
+        }
+        // Aliases are expanded and constructors checked in annotations of definitions,
+        //   which are the types of names before checked
+        for d in program.iter_mut() {
+            let pos = d.pos;
+            let expanded = match d.node {
+                Item::Alias(_, ref scm) => self.written_scheme(scm, pos).map(|_| None),
+                Item::Form(ref f) => match f.tag.annotate {
+                    Some(ref scm) => self.written_scheme(scm, pos).map(Some),
+                    None => Ok(None),
+                },
+                // Fields are of expanded types, for constructors and lowering
+                Item::Alg(_, ref mut vs) => {
+                    errors.extend(duplicates(vs).into_iter().map(|e| (e, pos)));
+                    vs.iter_mut()
+                        .flat_map(|v| v.fields_mut().iter_mut())
+                        .map(|f| {
+                            self.written_scheme(&Scheme::Mono(f.ty.deref().clone()), pos)
+                                .map(|scm| *f.ty = scm.body().clone())
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                        .map(|_| None)
+                }
+            };
+            match expanded {
+                Ok(Some(scm)) => d.form_body_mut().tag.annotate = Some(scm),
+                Ok(None) => {}
+                Err(e) => errors.push((e, pos)),
+            }
+        }
 
-            // Add them into environment
-            _env.extend_n(name_scms)
-        };
+        // Type variables written in definitions
+        //   should not be taken by fresh ones
+        for d in program.iter() {
+            if let Some(scm) = d.form_annot() {
+                self.supply.reserve(scm.body().free_vars());
+            }
+            if let Item::Alg(ref ps, ref vs) = d.node {
+                let name = self.interner.trace(d.name()).to_owned();
+                let params: Vec<_> = ps.iter().map(|&p| self.interner.trace(p).to_owned()).collect();
+                self.supply.reserve(params.clone());
+                self.define_data(name, params, vs.clone());
+            }
+        }
 
-        for d in program.iter_mut() {
-            if d.is_form() {
-                let sub = {
-                    self.infer(&mut env, d.form_body_mut())?;
-                    self.solve()?
+        // Definitions referring to each other are checked together,
+        //   after those they refer to are generalized
+        let forms: HashSet<Id> = program.iter().filter(|d| d.is_form()).map(|d| d.name()).collect();
+        let index: HashMap<Id, usize> = program.iter().enumerate().map(|(i, d)| (d.name(), i)).collect();
+        let graph: HashMap<Id, Vec<Id>> = program.iter()
+            .filter_map(|d| match d.node {
+                Item::Form(ref f) => {
+                    let mut refs = free_vars(&f.node, &HashSet::new());
+                    refs.retain(|r| forms.contains(r));
+                    Some((d.name(), refs))
+                }
+                _ => None,
+            })
+            .collect();
+        let mut groups = strongly_connected(&graph);
+
+        let mut env = _env.sub_env();
+        for group in groups.iter_mut() {
+            group.sort_by_key(|n| index[n]);
+
+            // Give each definitions a temporary type if no annotation
+            for &name in group.iter() {
+                let slot = match program[index[&name]].form_annot() {
+                    Some(s) => s.clone(),
+                    _ => to_mono(self.fresh()),
                 };
-                d.form_body_mut().apply_mut(&sub);
+                env.insert(name, slot);
+            }
 
-                let general = {
-                    let ty = d.form_type().body().to_owned();
-                    let fvs: Vec<_> = ty.ftv().into_iter().collect();
-                    if fvs.is_empty() {
-                        Scheme::Mono(ty)
-                    } else {
-                        Scheme::Poly(fvs, ty)
+            let solved = self.infer_group(&mut env, program, &index, group);
+            for &name in group.iter() {
+                let d = &mut program[index[&name]];
+                let general = match solved {
+                    Ok(ref sub) => {
+                        d.form_body_mut().apply_mut(sub);
+                        // Declared polymorphic type is kept
+                        match d.form_annot().cloned() {
+                            Some(scm @ Scheme::Poly(..)) => scm,
+                            _ => generalize(&HashSet::new(), d.form_type().body()),
+                        }
+                    }
+                    // Taken as `forall a. a` unless annotated
+                    Err(_) => {
+                        match d.form_annot().cloned() {
+                            Some(scm) => scm,
+                            None => generalize(&HashSet::new(), &self.fresh()),
+                        }
                     }
                 };
 
                 d.form_body_mut().tag.set_scheme(general);
 
-                env.insert(d.name().to_owned(), d.form_type().clone());
+                env.insert(name, d.form_type().clone());
+            }
+            if let Err(e) = solved {
+                errors.push((e, program[index[&group[0]]].pos));
+                self.constraints.clear();
+                self.sub.clear();
+                self.printed.clear();
             }
         }
 
-        Ok(())
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
     }
 
+    /// Infer definitions referring to each other, solving their constraints
+    fn infer_group(&mut self,
+                   env: &mut TypeEnv,
+                   program: &mut Vec<Def>,
+                   index: &HashMap<Id, usize>,
+                   group: &[Id])
+                   -> Result<Subst, TypeError> {
+        for &name in group.iter() {
+            let d = &mut program[index[&name]];
+            let pos = d.pos;
+            let ty = self.infer(env, d.form_body_mut())?.body().clone();
+            if d.form_annot().is_none() {
+                let slot = env.lookup(&name).unwrap().body().clone();
+                self.uni((&slot, pos), (&ty, pos));
+            }
+        }
+        let sub = self.solve()?;
+        for (ty, pos) in mem::replace(&mut self.printed, vec![]) {
+            let ty = ty.apply(&sub);
+            if !is_printable(&ty) {
+                return Err(TypeError::NotPrintable(ty, pos));
+            }
+        }
+        Ok(sub)
+    }
 
-    /// Solve constraints. This will move out the `Infer` struct.
-    fn solve<'a>(&mut self) -> Result<Subst, TypeError> {
-        let mut sub = HashMap::<Name, Type>::new();
-        // let mut constraints = self.constraints;
+
+    /// Solve pending constraints, composing the result into
+    ///   the substitution solved so far.
+    fn solve_pending(&mut self) -> Result<(), TypeError> {
         let mut constraints = mem::replace(&mut self.constraints, LinkedList::new());
-        // println!("Type constraints: ");
-        // println!("{:#?}", constraints);
-        let mut step: usize = 1;
         while let Some(cons) = constraints.pop_front() {
-            // println!("Type solve step {:?}: ", step);
-            let new_sub = cons.unify()?;
-            // println!("current unify: ");
-            // println!("{:#?}", new_sub);
-            for ref mut c in constraints.iter_mut() {
-                c.apply_mut(&new_sub);
-            }
-            sub = sub.into_iter().map(|(k, v)| (k, v.apply(&new_sub))).collect();
-            sub.extend(new_sub);
-            // println!("Type substituation: ");
-            // println!("{:#?}", sub);
-            step = step + 1;
+            let new_sub = cons.apply(&self.sub).unify()?;
+            self.sub = new_sub.compose(&self.sub);
         }
-        Ok(sub)
+        Ok(())
+    }
+
+    /// Solve constraints, taking out the whole substitution.
+    fn solve(&mut self) -> Result<Subst, TypeError> {
+        self.solve_pending()?;
+        Ok(mem::replace(&mut self.sub, Subst::new()))
     }
     // pub fn 
 }
@@ -384,9 +948,9 @@ mod tests {
         use self::Type::*;
         let mut interner = Interner::new();
         let ty_op = Scheme::Poly(vec!["a".to_string()],
-                                 Type::Arr(P(Type::Prod(P(Type::Var("a".to_string())),
-                                                          P(Type::Var("a".to_string())))),
-                                             P(Type::Var("a".to_string()))));
+                                 Type::arrow(vec![Type::Var("a".to_string()),
+                                                  Type::Var("a".to_string())],
+                                             Type::Var("a".to_string())));
         let PRIMITIVES: Vec<(&str, &Scheme)> = vec![("+", &ty_op)];
         let mut syn: Form = parse_expr(&mut interner, "(a, b) -> let c = a in { c + b + 1 }");
 
@@ -407,6 +971,7 @@ mod tests {
                     VarDecl(interner.intern("a"), Scheme::con("Int")),
                     VarDecl(interner.intern("b"), Scheme::con("Int"))
                 ],
+                ret: Scheme::Slot,
                 body: box Form::typed(
                     Span::new(9, 36),
                     Scheme::con("Int"),
@@ -449,4 +1014,499 @@ mod tests {
         ))
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn infer_tuple_proj() {
+        let mut interner = Interner::new();
+        let mut syn = parse_expr(&mut interner, "let p = (1, true) in p.1");
+        let mut env = TypeEnv::new();
+        let mut inf = Infer::new(&mut interner);
+
+        assert_eq!(inf.infer(&mut env, &mut syn), Ok(&Scheme::con("Bool")));
+    }
+
+    #[test]
+    fn infer_solved_proj() {
+        let mut interner = Interner::new();
+        let mut syn = parse_expr(&mut interner, "id((1, true)).1");
+        let mut env = TypeEnv::new();
+        env.insert(interner.intern("id"), parser::type_scheme("forall a. a -> a", &mut interner).unwrap());
+        let mut inf = Infer::new(&mut interner);
+
+        // Known to be a tuple only after solved
+        assert_eq!(inf.infer(&mut env, &mut syn), Ok(&Scheme::con("Bool")));
+    }
+
+    #[test]
+    fn infer_tuple_parameter() {
+        let mut interner = Interner::new();
+        let mut pair = parse_expr(&mut interner, "f((1, 2))");
+        let mut two = parse_expr(&mut interner, "f(1, 2)");
+        let mut nested = parse_expr(&mut interner, "(1, (2, 3)).1");
+        let mut env = TypeEnv::new();
+        env.insert(interner.intern("f"), parser::type_scheme("(Int * Int) -> Int", &mut interner).unwrap());
+        let mut inf = Infer::new(&mut interner);
+
+        assert_eq!(inf.infer(&mut env, &mut pair), Ok(&Scheme::con("Int")));
+        match inf.infer(&mut env, &mut two) {
+            Err(TypeError::ArgCount(1, 2, _)) => {}
+            r => panic!("expect wrong number of args, found {:?}", r),
+        }
+        assert_eq!(inf.infer(&mut env, &mut nested).map(|scm| scm.body().to_string()),
+                   Ok(s("Int * Int")));
+    }
+
+    #[test]
+    fn infer_bad_proj() {
+        let mut interner = Interner::new();
+        let mut syn = parse_expr(&mut interner, "(1, 2).2");
+        let mut env = TypeEnv::new();
+        let mut inf = Infer::new(&mut interner);
+
+        match inf.infer(&mut env, &mut syn) {
+            Err(TypeError::BadProjection(_, 2, _)) => {}
+            r => panic!("expect bad projection, found {:?}", r),
+        }
+    }
+
+    #[test]
+    fn infer_record() {
+        let mut interner = Interner::new();
+        let mut syn = parse_expr(&mut interner, "Pt { y: 1.0, x: 1 }");
+        let mut acc = parse_expr(&mut interner, "Pt { x: 1, y: 1.0 }.y");
+        let mut bad = parse_expr(&mut interner, "Pt { x: 1 }");
+        let mut called = parse_expr(&mut interner, "id(Pt { x: 1, y: 1.0 }).x");
+        let mut env = TypeEnv::new();
+        env.insert(interner.intern("id"), parser::type_scheme("forall a. a -> a", &mut interner).unwrap());
+        let mut inf = Infer::new(&mut interner);
+        let field = |n: &str, t: &str| Field {
+            pos: Span::point(0),
+            name: Some(n.to_string()),
+            ty: box Type::Con(t.to_string()),
+        };
+        inf.define_data(s("Point"), vec![], vec![Variant {
+            pos: Span::point(0),
+            name: s("Pt"),
+            body: VariantBody::Struct(vec![field("x", "Int"), field("y", "Float")]),
+        }]);
+
+        assert_eq!(inf.infer(&mut env, &mut syn), Ok(&Scheme::con("Point")));
+        match syn.node {
+            Expr::Record(_, ref fields) => {
+                let names: Vec<_> = fields.iter().map(|&(ref n, _)| n.as_str()).collect();
+                assert_eq!(names, vec!["x", "y"]);
+            }
+            ref n => panic!("expect record, found {:?}", n),
+        }
+        assert_eq!(inf.infer(&mut env, &mut acc), Ok(&Scheme::con("Float")));
+        // Known to be a record only after solved
+        assert_eq!(inf.infer(&mut env, &mut called), Ok(&Scheme::con("Int")));
+        match inf.infer(&mut env, &mut bad) {
+            Err(TypeError::BadFields(..)) => {}
+            r => panic!("expect bad fields, found {:?}", r),
+        }
+
+        // Which variant a value is of is unknown out of `match`
+        let mut ambiguous = parse_expr(&mut interner, "Sq { side: 1 }.side");
+        let mut inf = Infer::new(&mut interner);
+        inf.define_data(s("Shape"), vec![], vec![Variant {
+            pos: Span::point(0),
+            name: s("Sq"),
+            body: VariantBody::Struct(vec![field("side", "Int")]),
+        }, Variant {
+            pos: Span::point(0),
+            name: s("Dot"),
+            body: VariantBody::Tuple(vec![]),
+        }]);
+        match inf.infer(&mut env, &mut ambiguous) {
+            Err(TypeError::NoField(_, ref n, _)) if n == "side" => {}
+            r => panic!("expect no field, found {:?}", r),
+        }
+    }
+
+    #[test]
+    fn infer_char() {
+        let mut interner = Interner::new();
+        let mut ch = parse_expr(&mut interner, "'a'");
+        let mut mixed = parse_expr(&mut interner, "if (true) 'a' else 97");
+        let mut env = TypeEnv::new();
+        let mut inf = Infer::new(&mut interner);
+
+        assert_eq!(inf.infer(&mut env, &mut ch), Ok(&Scheme::con("Char")));
+        inf.infer(&mut env, &mut mixed).unwrap();
+        assert_eq!(inf.solve(),
+                   Err(TypeError::MisMatch(Type::Con(s("Char")), Type::Con(s("Int")))));
+    }
+
+    #[test]
+    fn infer_let_polymorphism() {
+        let mut interner = Interner::new();
+        let mut syn = parse_expr(&mut interner, "let id = (x) -> x in (id(1), id(true))");
+        let mut env = TypeEnv::new();
+        let sub = {
+            let mut inf = Infer::new(&mut interner);
+            inf.infer(&mut env, &mut syn).unwrap();
+            inf.solve().unwrap()
+        };
+        use typeinfer::subst::SubstMut;
+        syn.apply_mut(&sub);
+
+        assert_eq!(syn.tag.ty,
+                   Scheme::Mono(Type::product(Type::Con(s("Int")), Type::Con(s("Bool")))));
+    }
+
+    #[test]
+    fn generalize_skips_env() {
+        let ty = Type::arrow(vec![Type::Var(s("a"))], Type::Var(s("b")));
+        let env_free = vec![s("b")].into_iter().collect();
+        assert_eq!(generalize(&env_free, &ty), Scheme::Poly(vec![s("a")], ty.clone()));
+        let all = vec![s("a"), s("b")].into_iter().collect();
+        assert_eq!(generalize(&all, &ty), Scheme::Mono(ty));
+    }
+
+    #[test]
+    fn check_annotation() {
+        use typeinfer::subst::Substituable;
+        let mut interner = Interner::new();
+        let poly = parser::type_scheme("forall a. a -> a", &mut interner).unwrap();
+        let int_fn = parser::type_scheme("Int -> Int", &mut interner).unwrap();
+        let mut id = parse_expr(&mut interner, "(x) -> x");
+        let mut id_int = id.clone();
+        let mut not_id = parse_expr(&mut interner, "(x) -> if (x) x else x");
+        let mut one = parse_expr(&mut interner, "1");
+        id.tag.annotate = Some(poly.clone());
+        id_int.tag.annotate = Some(int_fn.clone());
+        not_id.tag.annotate = Some(poly.clone());
+        one.tag.annotate = Some(Scheme::con("Bool"));
+
+        let mut env = TypeEnv::new();
+        let mut inf = Infer::new(&mut interner);
+        assert!(inf.infer(&mut env, &mut id).is_ok());
+        // More general inferred type satisfies a specific annotation
+        assert!(inf.infer(&mut env, &mut id_int).is_ok());
+        assert_eq!(inf.solve().map(|sub| id_int.apply(&sub).tag.ty), Ok(int_fn));
+        // But not vice versa
+        match inf.infer(&mut env, &mut not_id) {
+            Err(TypeError::BadAnnotation(ref s, _, _)) => assert_eq!(*s, poly),
+            r => panic!("expect bad annotation, found {:?}", r),
+        }
+        match inf.infer(&mut env, &mut one) {
+            Err(TypeError::BadAnnotation(_, ref t, pos)) => {
+                assert_eq!(*t, Type::Con(s("Int")));
+                assert_eq!(pos, Span::new(0, 1));
+            }
+            r => panic!("expect bad annotation, found {:?}", r),
+        }
+    }
+
+    #[test]
+    fn infer_list() {
+        use typeinfer::subst::Substituable;
+        let mut interner = Interner::new();
+        let mut empty = parse_expr(&mut interner, "[]");
+        let mut ints = parse_expr(&mut interner, "[1, 2]");
+        let mut mixed = parse_expr(&mut interner, "[1, 2, true]");
+        let mut env = TypeEnv::new();
+        let mut inf = Infer::new(&mut interner);
+        let list = |t| Type::compose(Type::Con(s("List")), t);
+
+        match inf.infer(&mut env, &mut empty).map(|scm| scm.body().clone()) {
+            Ok(Type::Comp(_, box Type::Var(_))) => {}
+            r => panic!("expect list of variable, found {:?}", r),
+        }
+        inf.infer(&mut env, &mut ints).unwrap();
+        let sub = inf.solve().unwrap();
+        assert_eq!(ints.apply(&sub).tag.ty, Scheme::Mono(list(Type::Con(s("Int")))));
+        assert_eq!(inf.infer(&mut env, &mut mixed),
+                   Err(TypeError::ListItem(Type::Con(s("Int")), Type::Con(s("Bool")), Span::new(6, 11))));
+    }
+
+    #[test]
+    fn infer_block() {
+        let mut interner = Interner::new();
+        let mut empty = parse_expr(&mut interner, "{}");
+        let mut single = parse_expr(&mut interner, "{ 1 }");
+        let mut multi = parse_expr(&mut interner, "{ 1, 'a', true }");
+        let mut bad = parse_expr(&mut interner, "{ fuck, 1 }");
+        let mut env = TypeEnv::new();
+        let mut inf = Infer::new(&mut interner);
+
+        assert_eq!(inf.infer(&mut env, &mut empty), Ok(&Scheme::Mono(Type::Void)));
+        assert_eq!(inf.infer(&mut env, &mut single), Ok(&Scheme::con("Int")));
+        assert_eq!(inf.infer(&mut env, &mut multi), Ok(&Scheme::con("Bool")));
+        // Discarded statements are checked as well
+        match inf.infer(&mut env, &mut bad) {
+            Err(TypeError::NotInScope(ref n, _)) => assert_eq!(n, "fuck"),
+            r => panic!("expect not in scope, found {:?}", r),
+        }
+    }
+
+    #[test]
+    fn infer_void() {
+        let mut interner = Interner::new();
+        let mut stmts = parse_expr(&mut interner, "{ print(), while (false) 1, 2 }");
+        let mut used = parse_expr(&mut interner, "print() + 1");
+        let mut arg = parse_expr(&mut interner, "id(print())");
+        let mut env = TypeEnv::new();
+        env.insert(interner.intern("print"), parser::type_scheme("Void -> Void", &mut interner).unwrap());
+        env.insert(interner.intern("id"), parser::type_scheme("forall a. a -> a", &mut interner).unwrap());
+        env.insert(interner.intern("+"), parser::type_scheme("forall a. a * a -> a", &mut interner).unwrap());
+        let mut inf = Infer::new(&mut interner);
+
+        // Statements not the last one may be `Void`
+        assert_eq!(inf.infer(&mut env, &mut stmts), Ok(&Scheme::con("Int")));
+        match inf.infer(&mut env, &mut used) {
+            Err(TypeError::VoidValue(pos)) => assert_eq!(pos.start(), 0),
+            r => panic!("expect void value, found {:?}", r),
+        }
+        match inf.infer(&mut env, &mut arg) {
+            Err(TypeError::VoidValue(pos)) => assert_eq!(pos.start(), 3),
+            r => panic!("expect void value, found {:?}", r),
+        }
+    }
+
+    #[test]
+    fn infer_index() {
+        let mut interner = Interner::new();
+        let mut good = parse_expr(&mut interner, "xs[0][1]");
+        let mut bad = parse_expr(&mut interner, "xs[true]");
+        let mut env = TypeEnv::new();
+        let chars = Type::compose(Type::Con(s("List")), Type::Con(s("Char")));
+        env.insert(interner.intern("xs"), Scheme::Mono(Type::compose(Type::Con(s("List")), chars)));
+        let mut inf = Infer::new(&mut interner);
+
+        let ty = inf.infer(&mut env, &mut good).map(|scm| scm.body().clone()).unwrap();
+        let sub = inf.solve().unwrap();
+        assert_eq!(ty.apply(&sub), Type::Con(s("Char")));
+        inf.infer(&mut env, &mut bad).unwrap();
+        assert_eq!(inf.solve(), Err(TypeError::MisMatch(Type::Con(s("Bool")), Type::Con(s("Int")))));
+    }
+
+    #[test]
+    fn infer_let_annotation() {
+        let mut interner = Interner::new();
+        let mut good = parse_expr(&mut interner, "let x: Int = 1 in x");
+        let mut bad = parse_expr(&mut interner, "let x: Int = true in x");
+        let mut rigid = parse_expr(&mut interner, "let f = (x) -> x + 1 in f");
+        if let Expr::Let(VarDecl(_, ref mut scm), ..) = rigid.node {
+            *scm = parser::type_scheme("forall a. a -> a", &mut interner).unwrap();
+        }
+        let mut env = TypeEnv::new();
+        env.insert(interner.intern("+"), parser::type_scheme("forall a. a * a -> a", &mut interner).unwrap());
+        let mut inf = Infer::new(&mut interner);
+
+        assert_eq!(inf.infer(&mut env, &mut good), Ok(&Scheme::con("Int")));
+        assert_eq!(inf.infer(&mut env, &mut bad),
+                   Err(TypeError::BadAnnotation(Scheme::con("Int"), Type::Con(s("Bool")), Span::new(12, 17))));
+        match inf.infer(&mut env, &mut rigid) {
+            Err(TypeError::BadAnnotation(Scheme::Poly(..), _, _)) => {}
+            r => panic!("expect bad annotation, found {:?}", r),
+        }
+    }
+
+    #[test]
+    fn infer_return_annotation() {
+        let mut interner = Interner::new();
+        let mut good = parse_expr(&mut interner, "(x): Int -> x");
+        let mut bad = parse_expr(&mut interner, "(x: Int): Bool -> x + 1");
+        let mut env = TypeEnv::new();
+        env.insert(interner.intern("+"), parser::type_scheme("forall a. a * a -> a", &mut interner).unwrap());
+        let mut inf = Infer::new(&mut interner);
+
+        let ty = inf.infer(&mut env, &mut good).map(|scm| scm.body().clone()).unwrap();
+        let sub = inf.solve().unwrap();
+        assert_eq!(ty.apply(&sub), parser::type_expression("Int -> Int", &mut Interner::new()).unwrap());
+        match inf.infer(&mut env, &mut bad) {
+            Err(TypeError::BadAnnotation(ref annot, ref found, _)) => {
+                assert_eq!(*annot, Scheme::con("Bool"));
+                assert_eq!(*found, Type::Con(s("Int")));
+            }
+            r => panic!("expect bad annotation, found {:?}", r),
+        }
+    }
+
+    #[test]
+    fn infer_let_generalize() {
+        let mut interner = Interner::new();
+        let mut syn = parse_expr(&mut interner, "let id = (x) -> x in { id(1), id }");
+        let mut env = TypeEnv::new();
+        let mut inf = Infer::new(&mut interner);
+
+        // `id` is still polymorphic after used as `Int -> Int`
+        match inf.infer(&mut env, &mut syn).map(|scm| scm.body().clone()) {
+            Ok(Type::Arr(ref ps, box Type::Var(ref b))) => assert_eq!(ps, &vec![Type::Var(b.clone())]),
+            r => panic!("expect polymorphic identity, found {:?}", r),
+        }
+        inf.solve().unwrap();
+    }
+
+    #[test]
+    fn infer_apply_arity() {
+        let mut interner = Interner::new();
+        let mut over = parse_expr(&mut interner, "f(1, true, 2)");
+        let mut under = parse_expr(&mut interner, "f(1)");
+        let mut bad = parse_expr(&mut interner, "f(1, 2)");
+        let mut env = TypeEnv::new();
+        env.insert(interner.intern("f"), parser::type_scheme("Int * Bool -> Int", &mut interner).unwrap());
+        let mut inf = Infer::new(&mut interner);
+
+        match inf.infer(&mut env, &mut over) {
+            Err(TypeError::ArgCount(2, 3, _)) => {}
+            r => panic!("expect wrong number of args, found {:?}", r),
+        }
+        // Partial application returns a function of the rest parameters
+        assert_eq!(inf.infer(&mut env, &mut under).map(|scm| scm.body().clone()),
+                   Ok(Type::arrow(vec![Type::Con(s("Bool"))], Type::Con(s("Int")))));
+        match inf.infer(&mut env, &mut bad) {
+            Err(TypeError::BadArgument(Type::Con(ref l), Type::Con(ref r), _)) => {
+                assert_eq!((l.as_str(), r.as_str()), ("Bool", "Int"));
+            }
+            r => panic!("expect bad argument, found {:?}", r),
+        }
+    }
+
+    #[test]
+    fn infer_mutual_recursion() {
+        let mut interner = Interner::new();
+        let src = "def even(n) = if (n == 0) true else odd(n - 1)\n\
+                   def odd(n) = if (n == 0) false else even(n - 1)\n\
+                   def id(x) = x\n\
+                   def main() = { id(1), id(even(2)) }";
+        let mut defs = parser::parse(src, &mut interner).unwrap();
+        let env = Infer::new_env();
+        let prelude = env.extend_n(vec![
+            (interner.intern("=="), parser::type_scheme("forall a. a * a -> Bool", &mut interner).unwrap()),
+            (interner.intern("-"), parser::type_scheme("forall a. a * a -> a", &mut interner).unwrap()),
+        ]);
+        Infer::new(&mut interner).infer_defs(&prelude, &mut defs).unwrap();
+
+        let int_pred = parser::type_scheme("Int -> Bool", &mut interner).unwrap();
+        assert_eq!(*defs[0].form_type(), int_pred);
+        assert_eq!(*defs[1].form_type(), int_pred);
+        // Generalized before used at different types
+        match *defs[2].form_type() {
+            Scheme::Poly(..) => {}
+            ref s => panic!("expect polymorphic identity, found {:?}", s),
+        }
+    }
+
+    #[test]
+    fn infer_function_equality() {
+        let mut interner = Interner::new();
+        let mut good = parse_expr(&mut interner, "(1, \"a\") == (2, \"b\")");
+        let mut bad = parse_expr(&mut interner, "(x) -> (f, x) == (f, x)");
+        let mut env = TypeEnv::new();
+        env.insert(interner.intern("=="), parser::type_scheme("forall a. a * a -> Bool", &mut interner).unwrap());
+        env.insert(interner.intern("f"), parser::type_scheme("Int -> Int", &mut interner).unwrap());
+        let mut inf = Infer::new(&mut interner);
+
+        let ty = inf.infer(&mut env, &mut good).map(|scm| scm.body().clone()).unwrap();
+        let sub = inf.solve().unwrap();
+        assert_eq!(ty.apply(&sub), Type::Con(s("Bool")));
+        match inf.infer(&mut env, &mut bad) {
+            Err(TypeError::NoEquality(Type::Prod(ref ts), _)) if ts.len() == 2 => {}
+            r => panic!("expect no equality, found {:?}", r),
+        }
+    }
+
+    #[test]
+    fn infer_data_equality() {
+        let check = |src: &str| {
+            let mut interner = Interner::new();
+            let mut env = TypeEnv::new();
+            env.insert(interner.intern("=="), parser::type_scheme("forall a. a * a -> Bool", &mut interner).unwrap());
+            let mut defs = parser::parse(src, &mut interner).unwrap();
+            Infer::new(&mut interner).infer_defs(&env, &mut defs)
+        };
+        let data = "data Shape { Circle(Int), Rect(Int, Bool) }\n\
+                    data Fun { Fun(Shape, Int -> Int) }\n";
+        assert_eq!(check(&format!("{}def f(a: Shape, b: Shape) = a == b", data)), Ok(()));
+        assert_eq!(check(&format!("{}def f(a: List Shape) = a == a", data)), Ok(()));
+        match check(&format!("{}def f(a: Fun) = a == a", data)).unwrap_err().as_slice() {
+            &[(TypeError::NoEquality(Type::Con(ref n), _), _)] => assert_eq!(n, "Fun"),
+            r => panic!("expect no equality, found {:?}", r),
+        }
+    }
+
+    #[test]
+    fn infer_printable() {
+        let check = |src: &str| {
+            let mut interner = Interner::new();
+            let mut env = TypeEnv::new();
+            env.insert(interner.intern("println"), parser::type_scheme("forall a. a -> Void", &mut interner).unwrap());
+            let mut defs = parser::parse(src, &mut interner).unwrap();
+            Infer::new(&mut interner).infer_defs(&env, &mut defs)
+        };
+        assert_eq!(check("def f(c: Char) = println(c)"), Ok(()));
+        // Known only after solved
+        match check("def g(p: Int * Int) = p\ndef f(x) = { println(x), g(x) }").unwrap_err().as_slice() {
+            &[(TypeError::NotPrintable(Type::Prod(_), _), _)] => {}
+            r => panic!("expect not printable, found {:?}", r),
+        }
+        match check("def f(g: Int -> Int) = println(g)").unwrap_err().as_slice() {
+            &[(TypeError::NotPrintable(Type::Arr(..), _), _)] => {}
+            r => panic!("expect not printable, found {:?}", r),
+        }
+    }
+
+    #[test]
+    fn infer_duplicate_declarations() {
+        let mut interner = Interner::new();
+        let src = "data Shape { Circle(Float), Rect { w: Int, h: Int, w: Float }, Circle(Int) }";
+        let mut defs = parser::parse(src, &mut interner).unwrap();
+        let errors: Vec<_> = Infer::new(&mut interner)
+            .infer_defs(&TypeEnv::new(), &mut defs)
+            .unwrap_err()
+            .into_iter()
+            .map(|(e, _)| e)
+            .collect();
+        assert_eq!(errors, vec![
+            TypeError::DuplicateField(s("w"), Span::point(src.rfind("w:").unwrap())),
+            TypeError::DuplicateVariant(s("Circle"), Span::point(src.rfind("Circle").unwrap())),
+        ]);
+    }
+
+    #[test]
+    fn infer_match() {
+        let data = "data Shape { Circle(Int), Rect(Int, Bool) }\n";
+        let check = |body: &str| {
+            let mut interner = Interner::new();
+            let src = format!("{}def area(s) = match (s) {{ {} }}", data, body);
+            let mut defs = parser::parse(&src, &mut interner).unwrap();
+            Infer::new(&mut interner)
+                .infer_defs(&TypeEnv::new(), &mut defs)
+                .map(|_| defs[1].form_type().body().clone())
+        };
+        let area = Type::arrow(vec![Type::Con(s("Shape"))], Type::Con(s("Int")));
+        assert_eq!(check("Circle(r) => r, Rect(w, true) => w, _ => 0"), Ok(area));
+        match check("Square(r) => r").unwrap_err().as_slice() {
+            &[(TypeError::NotInScope(ref c, _), _)] => assert_eq!(c, "Square"),
+            r => panic!("expect unknown constructor, found {:?}", r),
+        }
+        match check("Rect(w) => w").unwrap_err().as_slice() {
+            &[(TypeError::BadFields(ref c, _), _)] => assert_eq!(c, "Rect"),
+            r => panic!("expect wrong fields, found {:?}", r),
+        }
+        assert!(check("Circle(true) => 1, _ => 0").is_err());
+        assert!(check("Circle(r) => r, Rect(_, b) => b").is_err());
+    }
+
+    #[test]
+    fn infer_data_alias_field() {
+        let mut interner = Interner::new();
+        let src = "type Pair = Int * Int\n\
+                   data Seg { Seg(Pair, Pair) }\n\
+                   def len(s) = match (s) { Seg(a, b) => b.0 - a.0 }";
+        let mut env = TypeEnv::new();
+        env.insert(interner.intern("-"), parser::type_scheme("forall a. a * a -> a", &mut interner).unwrap());
+        let mut defs = parser::parse(src, &mut interner).unwrap();
+        assert_eq!(Infer::new(&mut interner).infer_defs(&env, &mut defs), Ok(()));
+        let pair = Type::Prod(vec![Type::Con(s("Int")), Type::Con(s("Int"))]);
+        match defs[1].node {
+            Item::Alg(_, ref vs) => {
+                let tys: Vec<_> = vs[0].fields().iter().map(|f| (*f.ty).clone()).collect();
+                assert_eq!(tys, vec![pair.clone(), pair]);
+            }
+            ref d => panic!("expect data, found {:?}", d),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+/// Arity checking of type constructors applied in written types
+
+use std::collections::HashMap;
+
+use types::*;
+use syntax::form::Span;
+
+use super::error::TypeError;
+
+/// Number of arguments each type constructor takes.
+///   Constructors not known, like those of other modules, are not checked.
+#[derive(Debug)]
+pub struct Kinds {
+    arity: HashMap<Name, usize>,
+}
+
+impl Kinds {
+    /// Builtin types, of which only `List` takes an argument
+    pub fn new() -> Kinds {
+        let mut arity = HashMap::new();
+        for n in ["Int", "Long", "Float", "Char", "Bool", "String"].iter() {
+            arity.insert(n.to_string(), 0);
+        }
+        arity.insert("List".to_string(), 1);
+        Kinds { arity }
+    }
+
+    pub fn define(&mut self, name: Name, arity: usize) {
+        self.arity.insert(name, arity);
+    }
+
+    /// Check every constructor in type is applied to as many arguments as it takes,
+    ///   and nothing else is applied except type variables
+    pub fn check(&self, ty: &Type, pos: Span) -> Result<(), TypeError> {
+        use self::Type::*;
+        let (head, args) = ty.split_comp();
+        match *head {
+            Con(ref n) => {
+                if let Some(&k) = self.arity.get(n) {
+                    if k != args.len() {
+                        return Err(TypeError::TypeArity(n.clone(), k, args.len(), pos));
+                    }
+                }
+            }
+            Var(_) => {}
+            Arr(ref ps, box ref r) => {
+                if !args.is_empty() {
+                    return Err(TypeError::NotConstructor(head.clone(), pos));
+                }
+                for p in ps.iter() {
+                    self.check(p, pos)?;
+                }
+                self.check(r, pos)?;
+            }
+            Prod(ref ts) => {
+                if !args.is_empty() {
+                    return Err(TypeError::NotConstructor(head.clone(), pos));
+                }
+                for t in ts.iter() {
+                    self.check(t, pos)?;
+                }
+            }
+            Void if !args.is_empty() => return Err(TypeError::NotConstructor(Void, pos)),
+            _ => {}
+        }
+        for a in args.into_iter() {
+            self.check(a, pos)?;
+        }
+        Ok(())
+    }
+
+    pub fn check_scheme(&self, scm: &Scheme, pos: Span) -> Result<(), TypeError> {
+        match *scm {
+            Scheme::Mono(ref t) | Scheme::Poly(_, ref t) => self.check(t, pos),
+            Scheme::Slot => Ok(()),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::*;
+    use syntax::parser;
+
+    fn check(src: &str) -> Result<(), TypeError> {
+        let mut interner = Interner::new();
+        let mut kinds = Kinds::new();
+        kinds.define("Pair".to_string(), 2);
+        kinds.check(parser::parse_type(src, &mut interner).body(), Span::new(2, 4))
+    }
+
+    #[test]
+    fn check_type_arity() {
+        assert_eq!(check("List Int"), Ok(()));
+        assert_eq!(check("Pair a Int -> f Bool"), Ok(()));
+        assert_eq!(check("List Int Int"), Err(TypeError::TypeArity("List".to_string(), 1, 2, Span::new(2, 4))));
+        assert_eq!(check("Int -> List"), Err(TypeError::TypeArity("List".to_string(), 1, 0, Span::new(2, 4))));
+        assert_eq!(check("Int Int"), Err(TypeError::TypeArity("Int".to_string(), 0, 1, Span::new(2, 4))));
+
+        // (Int -> Int) Bool
+        let int = Type::Con("Int".to_string());
+        let arr = Type::arrow(vec![int.clone()], int);
+        let ty = Type::compose(arr.clone(), Type::Con("Bool".to_string()));
+        assert_eq!(Kinds::new().check(&ty, Span::point(0)), Err(TypeError::NotConstructor(arr, Span::point(0))));
+    }
+}
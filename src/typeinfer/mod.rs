@@ -1,7 +1,13 @@
 mod subst;
 mod error;
 mod constraint;
+mod fresh;
+mod alias;
+mod kind;
 
 mod infer;
 
 pub use self::infer::*;
+pub use self::fresh::FreshSupply;
+pub use self::alias::Aliases;
+pub use self::kind::Kinds;
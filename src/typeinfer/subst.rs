@@ -8,7 +8,6 @@ use internal::*;
 use utils::*;
 use types::*;
 
-pub type Subst = HashMap<Name, Type>;
 pub trait Substituable {
     fn apply(self, sub: &Subst) -> Self;
     // fn apply_mut(&mut self, sub: &Subst);
@@ -19,48 +18,12 @@ pub trait SubstMut {
     fn apply_mut(&mut self, sub: &Subst);
 }
 
-impl SubstMut for Subst {
-    fn apply_mut(&mut self, sub: &Subst) {
-        for target in self.values_mut() {
-            *target = target.to_owned().apply(sub);
-        }
-    }
-}
-
 impl Substituable for Type {
     fn apply(self, sub: &Subst) -> Self {
-        use self::Type::*;
-        match self {
-            Arr(left, right) => Arr(P((*left).apply(sub)), P((*right).apply(sub))),
-            Prod(left, right) => Prod(P((*left).apply(sub)), P((*right).apply(sub))),
-            Comp(left, right) => Comp(P((*left).apply(sub)), P((*right).apply(sub))),
-            Var(n) => {
-                if let Some(ref t) = sub.get(&n) {
-                    (*t).clone()
-                } else {
-                    Var(n)
-                }
-            }
-            t => t,
-        }
+        sub.apply(&self)
     }
     fn ftv(&self) -> HashSet<Name> {
-        use self::Type::*;
-        match *self {
-            Var(ref n) => {
-                let mut it = HashSet::new();
-                it.insert(n.clone());
-                it
-            }
-            Arr(ref left, ref right) |
-            Comp(ref left, ref right) |
-            Prod(ref left, ref right) => {
-                let mut it = left.ftv();
-                it.extend(right.ftv());
-                it
-            }
-            _ => HashSet::new(),
-        }
+        self.free_vars()
     }
 }
 
@@ -72,29 +35,15 @@ impl Substituable for Scheme {
         match self {
             Mono(ty) => Mono(ty.apply(sub)),
             Poly(bounds, ty) => {
-                let mut s_ = sub.clone();
-                for ref n in &bounds {
-                    s_.remove(n.clone());
-                }
-                Poly(bounds, ty.apply(&s_))
+                let free = sub.without(&bounds);
+                Poly(bounds, ty.apply(&free))
             }
             t => t,
         }
     }
 
     fn ftv(&self) -> HashSet<Name> {
-        use self::Scheme::*;
-        match *self {
-            Mono(ref ty) => (*ty).ftv(),
-            Poly(ref bounds, ref ty) => {
-                let mut res = ty.ftv();
-                for ref n in bounds {
-                    res.remove(n.clone());
-                }
-                return res;
-            }
-            Slot => HashSet::new(),
-        }
+        self.free_vars()
     }
 }
 
@@ -151,7 +100,8 @@ impl SubstMut for Form {
                 fun.param.apply_mut(sub);
                 fun.body.apply_mut(sub);
             }
-            Binary(_, ref mut l, ref mut r) => {
+            Binary(_, ref mut l, ref mut r) |
+            Index(ref mut l, ref mut r) => {
                 l.apply_mut(sub);
                 r.apply_mut(sub);
             }
@@ -168,6 +118,30 @@ impl SubstMut for Form {
                 tr.apply_mut(sub);
                 fl.apply_mut(sub);
             }
+            Tuple(ref mut elems) => {
+                for f in elems.iter_mut() {
+                    f.apply_mut(sub);
+                }
+            }
+            TupleProj(ref mut t, _) |
+            Field(ref mut t, _) => {
+                t.apply_mut(sub);
+            }
+            Record(_, ref mut fields) => {
+                for &mut (_, ref mut f) in fields.iter_mut() {
+                    f.apply_mut(sub);
+                }
+            }
+            While(ref mut cond, ref mut body) => {
+                cond.apply_mut(sub);
+                body.apply_mut(sub);
+            }
+            Match(ref mut e, ref mut arms) => {
+                e.apply_mut(sub);
+                for arm in arms.iter_mut() {
+                    arm.body.apply_mut(sub);
+                }
+            }
             // Lit(Lit),
             // Var(Name),
             _ => {}
@@ -180,3 +154,29 @@ impl SubstMut for VarDecl {
         self.1.apply_mut(sub);
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(n: &str) -> Type {
+        Type::Var(n.to_string())
+    }
+    fn con(n: &str) -> Type {
+        Type::Con(n.to_string())
+    }
+    fn arr(l: Type, r: Type) -> Type {
+        Type::arrow(vec![l], r)
+    }
+    fn subst(ps: Vec<(&str, Type)>) -> Subst {
+        ps.into_iter().map(|(n, t)| (n.to_string(), t)).collect()
+    }
+
+    #[test]
+    fn poly_keeps_bound() {
+        let s = subst(vec![("a", con("Int")), ("b", con("Bool"))]);
+        let scm = Scheme::Poly(vec!["a".to_string()], arr(var("a"), var("b")));
+        assert_eq!(scm.apply(&s), Scheme::Poly(vec!["a".to_string()], arr(var("a"), con("Bool"))));
+    }
+}
@@ -4,20 +4,44 @@ use std::ops::Deref;
 use std::ops::DerefMut;
 
 use std::iter::IntoIterator;
-use std::iter::DoubleEndedIterator;
+use std::iter::FromIterator;
 
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
 
 use utils::*;
+use syntax::form::Span;
+use typeinfer::TypeError;
 
 
 /// Represents a variant of `data` type
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub struct Variant {
+    pub pos: Span,
     pub name: Name,
     pub body: VariantBody,
 }
 
+impl Variant {
+    /// Fields of the variant, empty for a unit variant
+    pub fn fields(&self) -> &[Field] {
+        match self.body {
+            VariantBody::Struct(ref fs) |
+            VariantBody::Tuple(ref fs) => fs,
+            VariantBody::Unit => &[],
+        }
+    }
+
+    pub fn fields_mut(&mut self) -> &mut [Field] {
+        match self.body {
+            VariantBody::Struct(ref mut fs) |
+            VariantBody::Tuple(ref mut fs) => fs,
+            VariantBody::Unit => &mut [],
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub enum VariantBody {
     Struct(Vec<Field>),
@@ -28,7 +52,7 @@ pub enum VariantBody {
 /// A field definition of struct in `data`
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub struct Field {
-//    pub pos: Pos,
+    pub pos: Span,
     pub name: Option<Name>,
     pub ty: P<Type>,
 }
@@ -58,17 +82,70 @@ impl Scheme {
         Scheme::Mono(Type::Con(name.to_string()))
     }
 
-    pub fn body(&self) -> &Type {
+    /// Type of the scheme, `None` if not inferred yet
+    pub fn try_body(&self) -> Option<&Type> {
         match *self {
             Scheme::Mono(ref t) |
-            Scheme::Poly(_, ref t) => t,
-            Scheme::Slot => unreachable!(),
+            Scheme::Poly(_, ref t) => Some(t),
+            Scheme::Slot => None,
+        }
+    }
+
+    /// Type of a scheme known to be inferred, as of every pass after inference
+    pub fn body(&self) -> &Type {
+        match self.try_body() {
+            Some(t) => t,
+            None => panic!("Get type of a scheme not inferred"),
         }
     }
 
-    pub fn arrow(from: Type, to: Type) -> Scheme {
-        Scheme::Mono(Type::Arr(P(from), P(to)))
+    pub fn arrow(params: Vec<Type>, ret: Type) -> Scheme {
+        Scheme::Mono(Type::arrow(params, ret))
+    }
+    /// Free type variables, not bound by `forall`
+    pub fn free_vars(&self) -> HashSet<Name> {
+        match *self {
+            Scheme::Mono(ref ty) => ty.free_vars(),
+            Scheme::Poly(ref bounds, ref ty) => {
+                let mut res = ty.free_vars();
+                for n in bounds.iter() {
+                    res.remove(n);
+                }
+                res
+            }
+            Scheme::Slot => HashSet::new(),
+        }
+    }
+    /// Rename bound variables to `t0`, `t1`, ... in order of first appearance,
+    ///   so schemes differing in names of bound variables are equal.
+    ///   Bound variables not appearing in the type are dropped,
+    ///   names taken by free variables are skipped.
+    pub fn normalize(&self) -> Scheme {
+        match *self {
+            Scheme::Poly(ref bounds, ref ty) => {
+                let free = self.free_vars();
+                let mut renamed = HashMap::new();
+                let mut names = vec![];
+                let mut next = 0;
+                for v in ty.vars_in_order() {
+                    if !bounds.contains(&v) || renamed.contains_key(&v) {
+                        continue;
+                    }
+                    let mut name = format!("t{}", next);
+                    while free.contains(&name) {
+                        next += 1;
+                        name = format!("t{}", next);
+                    }
+                    next += 1;
+                    renamed.insert(v, name.clone());
+                    names.push(name);
+                }
+                Scheme::Poly(names, ty.rename_vars(&renamed))
+            }
+            _ => self.clone(),
+        }
     }
+
     pub fn is_fn(&self) -> bool {
         match self {
             &Scheme::Mono(Type::Arr(..)) |
@@ -85,38 +162,35 @@ pub enum Type {
     Var(Name),
     /// Constant type name
     Con(Name),
-    /// Arrow (->) type
-    Arr(P<Type>, P<Type>),
-    /// Product (*) type
-    Prod(P<Type>, P<Type>),
+    /// Arrow (->) type, from types of parameters,
+    ///   which are kept apart from a parameter of product type
+    Arr(Vec<Type>, P<Type>),
+    /// Product (*) type of elements, a nested product is one element
+    Prod(Vec<Type>),
     /// Composite type
     Comp(P<Type>, P<Type>),
 }
 
 impl Type {
+    pub fn arrow(params: Vec<Type>, ret: Type) -> Type {
+        Type::Arr(params, P(ret))
+    }
     pub fn product(left: Type, right: Type) -> Type {
-        Type::Prod(P(left), P(right))
+        Type::Prod(vec![left, right])
     }
     pub fn compose(callee: Type, arg: Type) -> Type {
         Type::Comp(P(callee), P(arg))
     }
 
+    /// Product of the types, a single type is itself
     pub fn product_n<I>(ts: I) -> Type
-        where I: IntoIterator<Item = Type>,
-              <I as IntoIterator>::IntoIter: DoubleEndedIterator
+        where I: IntoIterator<Item = Type>
     {
-
-        let mut it = ts.into_iter().rev();
-        let last = it.next();
-        match last {
-            Some(last) => {
-                let mut res = last;
-                for ty in it {
-                    res = Type::Prod(P(ty), P(res));
-                }
-                res
-            }
-            _ => panic!("Empty vec to product")
+        let mut ts: Vec<_> = ts.into_iter().collect();
+        match ts.len() {
+            0 => panic!("Empty vec to product"),
+            1 => ts.pop().unwrap(),
+            _ => Type::Prod(ts),
         }
     }
     pub fn compose_n<I>(ts: I) -> Type
@@ -132,36 +206,432 @@ impl Type {
         res
     }
 
+    /// Rebuild the type with its direct children mapped by `f`,
+    ///   a type without children is cloned
+    pub fn map_children<F>(&self, mut f: F) -> Type
+        where F: FnMut(&Type) -> Type
+    {
+        use self::Type::*;
+        match *self {
+            Arr(ref params, ref ret) => {
+                let params = params.iter().map(|p| f(p)).collect();
+                Arr(params, P(f(ret)))
+            }
+            Prod(ref elems) => Prod(elems.iter().map(|e| f(e)).collect()),
+            Comp(ref left, ref right) => Comp(P(f(left)), P(f(right))),
+            Void | Var(_) | Con(_) => self.clone(),
+        }
+    }
+
+    /// Visit the type and every type in it,
+    ///   a type before its children, from left to right
+    pub fn walk<F>(&self, mut f: F)
+        where F: FnMut(&Type)
+    {
+        self.walk_with(&mut f);
+    }
+
+    fn walk_with(&self, f: &mut FnMut(&Type)) {
+        use self::Type::*;
+        f(self);
+        match *self {
+            Arr(ref params, ref ret) => {
+                for p in params.iter() {
+                    p.walk_with(f);
+                }
+                ret.walk_with(f);
+            }
+            Prod(ref elems) => {
+                for e in elems.iter() {
+                    e.walk_with(f);
+                }
+            }
+            Comp(ref left, ref right) => {
+                left.walk_with(f);
+                right.walk_with(f);
+            }
+            Void | Var(_) | Con(_) => {}
+        }
+    }
+
+    /// All type variables in the type
+    pub fn free_vars(&self) -> HashSet<Name> {
+        self.vars_in_order().into_iter().collect()
+    }
+
+    /// Type variables from left to right, repeated as they appear
+    fn vars_in_order(&self) -> Vec<Name> {
+        let mut vars = vec![];
+        self.walk(|t| if let Type::Var(ref n) = *t {
+            vars.push(n.clone());
+        });
+        vars
+    }
+
+    /// Rename type variables, those not in `names` are kept
+    fn rename_vars(&self, names: &HashMap<Name, Name>) -> Type {
+        match *self {
+            Type::Var(ref n) => Type::Var(names.get(n).unwrap_or(n).clone()),
+            _ => self.map_children(|t| t.rename_vars(names)),
+        }
+    }
+
+    /// Split a composite type into its head and arguments
+    /// e.g. `Map k v` gives `(Map, [k, v])`
+    pub fn split_comp(&self) -> (&Type, Vec<&Type>) {
+        let mut args = vec![];
+        let mut head = self;
+        while let Type::Comp(box ref l, box ref r) = *head {
+            args.push(r);
+            head = l;
+        }
+        args.reverse();
+        (head, args)
+    }
+
+    /// Elements of a product, a type other than product is the only one
     pub fn prod_to_vec(&self) -> Vec<&Type> {
+        match *self {
+            Type::Prod(ref elems) => elems.iter().collect(),
+            ref t => vec![t],
+        }
+    }
+}
+
+impl Type {
+    /// Print with parentheses if precedence is lower than context.
+    /// `->` is right associative, `*` binds tighter,
+    ///   composition binds tightest.
+    /// A product nested in a product or parameters is parenthesized,
+    ///   no parameters are written as `Void`.
+    fn fmt_prec(&self, f: &mut fmt::Formatter, prec: u8) -> fmt::Result {
         use self::Type::*;
-        let mut v = vec![];
-        let mut r: &Type = self;
-        loop {
-            if let &Prod(box ref t, box ref rest) = r {
-                v.push(t);
-                r = rest;
+        let own = match *self {
+            Arr(..) => 1,
+            Prod(..) => 2,
+            Comp(..) => 3,
+            _ => 4,
+        };
+        if own < prec {
+            write!(f, "(")?;
+        }
+        match *self {
+            Void => write!(f, "Void")?,
+            Var(ref n) | Con(ref n) => write!(f, "{}", n)?,
+            Arr(ref ps, ref r) => {
+                match ps.len() {
+                    0 => write!(f, "Void")?,
+                    1 => ps[0].fmt_prec(f, 3)?,
+                    _ => Type::fmt_factors(ps, f)?,
+                }
+                write!(f, " -> ")?;
+                r.fmt_prec(f, 1)?;
+            }
+            Prod(ref ts) => Type::fmt_factors(ts, f)?,
+            Comp(ref l, ref r) => {
+                l.fmt_prec(f, 3)?;
+                write!(f, " ")?;
+                r.fmt_prec(f, 4)?;
+            }
+        }
+        if own < prec {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+
+    fn fmt_factors(ts: &[Type], f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, t) in ts.iter().enumerate() {
+            if i > 0 {
+                write!(f, " * ")?;
+            }
+            t.fmt_prec(f, 3)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_prec(f, 0)
+    }
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Scheme::Mono(ref t) => write!(f, "{}", t),
+            Scheme::Poly(ref bs, ref t) => {
+                write!(f, "forall")?;
+                for b in bs.iter() {
+                    write!(f, " {}", b)?;
+                }
+                write!(f, ". {}", t)
+            }
+            Scheme::Slot => write!(f, "_"),
+        }
+    }
+}
+
+/// Substitution of type variables by types, produced by unification
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct Subst(HashMap<Name, Type>);
+
+impl Subst {
+    pub fn new() -> Subst {
+        Subst(HashMap::new())
+    }
+
+    pub fn insert(&mut self, var: Name, ty: Type) {
+        self.0.insert(var, ty);
+    }
+
+    pub fn get(&self, var: &str) -> Option<&Type> {
+        self.0.get(var)
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// Replace type variables in `ty` by their types
+    pub fn apply(&self, ty: &Type) -> Type {
+        match *ty {
+            Type::Var(ref n) => self.0.get(n).cloned().unwrap_or_else(|| ty.clone()),
+            _ => ty.map_children(|t| self.apply(t)),
+        }
+    }
+
+    /// Compose substitutions, `s1.compose(&s2)` is `s1 ∘ s2`
+    ///   which applies `s2` first, then `s1`.
+    pub fn compose(&self, other: &Subst) -> Subst {
+        let mut res: HashMap<_, _> = other.0.iter().map(|(k, v)| (k.clone(), self.apply(v))).collect();
+        for (k, v) in self.0.iter() {
+            res.entry(k.clone()).or_insert_with(|| v.clone());
+        }
+        Subst(res)
+    }
+
+    /// The substitution without variables in `vars`, like bound ones of a scheme
+    pub fn without(&self, vars: &[Name]) -> Subst {
+        Subst(self.0.iter().filter(|&(k, _)| !vars.contains(k)).map(|(k, v)| (k.clone(), v.clone())).collect())
+    }
+}
+
+impl FromIterator<(Name, Type)> for Subst {
+    fn from_iter<I: IntoIterator<Item = (Name, Type)>>(iter: I) -> Subst {
+        Subst(iter.into_iter().collect())
+    }
+}
+
+/// Find the most general substitution making two types equal
+pub fn unify(a: &Type, b: &Type) -> Result<Subst, TypeError> {
+    use self::Type::*;
+    match (a, b) {
+        (&Var(ref n), t) |
+        (t, &Var(ref n)) => bind(n, t),
+        // Parameters are never taken by a variable as a product
+        (&Arr(ref ls, ref l), &Arr(ref rs, ref r)) => {
+            if ls.len() != rs.len() {
+                return Err(TypeError::Arity(ls.len(), rs.len()));
+            }
+            let mut ls = ls.clone();
+            let mut rs = rs.clone();
+            ls.push((**l).clone());
+            rs.push((**r).clone());
+            unify_all(&ls, &rs)
+        }
+        // A nested product is one element, so products unify only in same length
+        (&Prod(ref ls), &Prod(ref rs)) => {
+            if ls.len() != rs.len() {
+                return Err(TypeError::Arity(ls.len(), rs.len()));
+            }
+            unify_all(ls, rs)
+        }
+        (&Comp(ref l1, ref l2), &Comp(ref r1, ref r2)) => {
+            let u1 = unify(l1, r1)?;
+            let u2 = unify(&u1.apply(l2), &u1.apply(r2))?;
+            Ok(u2.compose(&u1))
+        }
+        _ => {
+            if a == b {
+                Ok(Subst::new())
             } else {
-                v.push(r);
-                break;
+                Err(TypeError::MisMatch(a.clone(), b.clone()))
             }
         }
-        v
     }
 }
 
-impl ToString for Type {
-    fn to_string(&self) -> String {
-        use self::Type::*;
-        match self {
-            &Void => String::from("Void"),
-            &Var(ref n) | &Con(ref n) => n.clone(),
-            &Arr(box ref l, box ref r) => l.to_string() + "->" + r.to_string().as_str(),
-            &Prod(box ref l, box ref r) => l.to_string() + "*" + r.to_string().as_str(),
-            &Comp(box ref l, box ref r) => l.to_string() + "+" + r.to_string().as_str(),
+/// Bind a type variable, rejecting infinite types like `a ~ a -> a`
+fn bind(n: &Name, t: &Type) -> Result<Subst, TypeError> {
+    if let Type::Var(ref m) = *t {
+        if m == n {
+            return Ok(Subst::new());
         }
     }
+    if t.free_vars().contains(n) {
+        return Err(TypeError::InfiniteType(n.clone(), t.clone()));
+    }
+    let mut s = Subst::new();
+    s.insert(n.clone(), t.clone());
+    Ok(s)
+}
+
+/// Unify types pairwise from left to right
+fn unify_all(ls: &[Type], rs: &[Type]) -> Result<Subst, TypeError> {
+    let mut sub = Subst::new();
+    for (l, r) in ls.iter().zip(rs.iter()) {
+        let s = unify(&sub.apply(l), &sub.apply(r))?;
+        sub = s.compose(&sub);
+    }
+    Ok(sub)
 }
 
 
 pub type TypeEnv<'a> = SymTable<'a, Id, Scheme>;
 
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syntax::parser;
+
+    fn round_trip(src: &str) -> String {
+        parser::type_scheme(src, &mut Interner::new()).unwrap().to_string()
+    }
+
+    fn names(ns: &[&str]) -> HashSet<Name> {
+        ns.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn var(n: &str) -> Type {
+        Type::Var(n.to_string())
+    }
+    fn con(n: &str) -> Type {
+        Type::Con(n.to_string())
+    }
+    fn arr(l: Type, r: Type) -> Type {
+        Type::arrow(vec![l], r)
+    }
+
+    #[test]
+    fn type_free_vars() {
+        let ty = |src: &str| parser::type_expression(src, &mut Interner::new()).unwrap();
+        assert_eq!(ty("Int -> Bool").free_vars(), names(&[]));
+        assert_eq!(ty("(a -> b) -> List a * c -> b").free_vars(), names(&["a", "b", "c"]));
+    }
+
+    #[test]
+    fn scheme_free_vars() {
+        let scm = Scheme::Poly(vec!["a".to_string()], Type::arrow(vec![var("a")], var("b")));
+        assert_eq!(scm.free_vars(), names(&["b"]));
+        assert_eq!(Scheme::Mono(var("a")).free_vars(), names(&["a"]));
+        assert_eq!(Scheme::Slot.free_vars(), names(&[]));
+    }
+
+    #[test]
+    fn display_type() {
+        assert_eq!(round_trip("(a -> b) -> List a -> List b"), "(a -> b) -> List a -> List b");
+        assert_eq!(round_trip("a * Int -> a"), "a * Int -> a");
+        assert_eq!(round_trip("(a * b) * c"), "(a * b) * c");
+        assert_eq!(round_trip("a * (b * c)"), "a * (b * c)");
+        assert_eq!(round_trip("(Int * Int) -> Int"), "(Int * Int) -> Int");
+        assert_eq!(round_trip("Map k v"), "Map k v");
+    }
+
+    #[test]
+    fn display_scheme() {
+        assert_eq!(round_trip("forall a b. a -> b -> a"), "forall a b. a -> b -> a");
+        assert_eq!(Scheme::arrow(vec![], Type::Con("Int".to_string())).to_string(),
+                   "Void -> Int");
+    }
+
+    #[test]
+    fn normalize_scheme() {
+        let scm = |src: &str| parser::type_scheme(src, &mut Interner::new()).unwrap();
+        assert_eq!(scm("forall a. a -> a").normalize(), scm("forall b. b -> b").normalize());
+        assert_eq!(scm("forall a b. a -> b -> a").normalize(), scm("forall y x. x -> y -> x").normalize());
+        assert_eq!(scm("forall b a. a -> b").normalize().to_string(), "forall t0 t1. t0 -> t1");
+        assert_ne!(scm("forall a b. a -> b").normalize(), scm("forall a. a -> a").normalize());
+        // Free variables are not renamed, nor captured
+        assert_eq!(scm("forall a. a -> t0").normalize().to_string(), "forall t1. t1 -> t0");
+    }
+
+    #[test]
+    fn walk_type() {
+        let ty = parser::type_expression("(a -> Map Int b) -> List (Bool * a) -> Void", &mut Interner::new()).unwrap();
+        let mut cons = vec![];
+        ty.walk(|t| if let Type::Con(ref n) = *t {
+            cons.push(n.clone());
+        });
+        assert_eq!(cons, vec!["Map", "Int", "List", "Bool"]);
+        let renamed = ty.map_children(|t| match *t {
+            Type::Arr(..) => Type::Var("f".to_string()),
+            _ => t.clone(),
+        });
+        assert_eq!(renamed.to_string(), "f -> f");
+    }
+
+    fn subst(ps: Vec<(&str, Type)>) -> Subst {
+        ps.into_iter().map(|(n, t)| (n.to_string(), t)).collect()
+    }
+
+    #[test]
+    fn compose_order() {
+        let s1 = subst(vec![("a", con("Int")), ("c", con("Char"))]);
+        let s2 = subst(vec![("b", arr(var("a"), var("c"))), ("c", con("Bool"))]);
+        let ty = arr(var("b"), var("c"));
+
+        let composed = s1.compose(&s2);
+        assert_eq!(composed.apply(&ty), s1.apply(&s2.apply(&ty)));
+        assert_eq!(composed.apply(&ty), arr(arr(con("Int"), con("Char")), con("Bool")));
+    }
+
+    #[test]
+    fn compose_idempotent() {
+        let s = subst(vec![("a", arr(con("Int"), var("b")))]);
+        let ty = arr(var("a"), var("a"));
+        assert_eq!(s.compose(&s).apply(&ty), s.apply(&ty));
+        assert_eq!(s.apply(&s.apply(&ty)), s.apply(&ty));
+    }
+
+    #[test]
+    fn unify_structural() {
+        let l = arr(var("a"), Type::compose(con("List"), var("b")));
+        let r = arr(con("Int"), Type::compose(con("List"), var("a")));
+        let s = unify(&l, &r).unwrap();
+        assert_eq!(s.apply(&l), arr(con("Int"), Type::compose(con("List"), con("Int"))));
+        assert_eq!(s.apply(&r), arr(con("Int"), Type::compose(con("List"), con("Int"))));
+    }
+
+    #[test]
+    fn unify_occurs_check() {
+        assert_eq!(unify(&var("a"), &arr(var("a"), var("a"))),
+                   Err(TypeError::InfiniteType("a".to_string(), arr(var("a"), var("a")))));
+        assert_eq!(unify(&var("a"), &var("a")), Ok(Subst::new()));
+    }
+
+    #[test]
+    fn unify_arity() {
+        let two = Type::product_n(vec![con("Int"), con("Bool")]);
+        let three = Type::product_n(vec![con("Int"), con("Bool"), con("Char")]);
+        assert_eq!(unify(&two, &three), Err(TypeError::Arity(2, 3)));
+        // A variable ending a product is one element, not the rest of a longer one
+        let open = Type::product_n(vec![con("Int"), var("rest")]);
+        assert_eq!(unify(&open, &three), Err(TypeError::Arity(2, 3)));
+        let nested = Type::product_n(vec![con("Int"), Type::product_n(vec![con("Bool"), con("Char")])]);
+        let s = unify(&open, &nested).unwrap();
+        assert_eq!(s.apply(&open), nested);
+        // A parameter of product type is not two parameters
+        let pair = Type::arrow(vec![two.clone()], con("Int"));
+        let params = Type::arrow(vec![con("Int"), con("Bool")], con("Int"));
+        assert_eq!(unify(&pair, &params), Err(TypeError::Arity(1, 2)));
+        let open = Type::arrow(vec![con("Int"), var("rest")], con("Int"));
+        let params = Type::arrow(vec![con("Int"), con("Bool"), con("Char")], con("Int"));
+        assert_eq!(unify(&open, &params), Err(TypeError::Arity(2, 3)));
+        assert_eq!(unify(&con("Int"), &con("Bool")),
+                   Err(TypeError::MisMatch(con("Int"), con("Bool"))));
+    }
+}
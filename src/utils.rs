@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::collections::HashSet;
 
 pub type P<T> = Box<T>;
 /// Shorter alias of Box
@@ -6,11 +7,15 @@ pub fn P<T>(t: T) -> Box<T> {
     Box::new(t)
 }
 
+/// Interned identifier, cheap to copy and compare.
+///   Its text could be traced back from the `Interner` made it.
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub struct Id(usize);
 
+/// Names of types, which are kept as strings
 pub type Name = String;
 
+/// Map between identifiers and their text, the same text is always the same `Id`
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct Interner {
     forward: HashMap<String, Id>,
@@ -130,7 +135,103 @@ impl<'a: 'b, 'b, K: 'a, T: 'a> SymTable<'a, K, T>
         }
     }
 
+    /// All values in this table and its parents,
+    ///   including shadowed ones
+    pub fn values(&self) -> Vec<&T> {
+        let mut res: Vec<&T> = self.vars.values().collect();
+        if let Some(p) = self.parent {
+            res.extend(p.values());
+        }
+        res
+    }
+
     pub fn unwrap(self) -> HashMap<K, T> {
         self.vars
     }
 }
+
+
+
+/// Strongly connected components of a graph by Tarjan's algorithm.
+///   Components are in topological order, a node only reaches
+///   those in its own component or earlier ones.
+pub fn strongly_connected(graph: &HashMap<Id, Vec<Id>>) -> Vec<Vec<Id>> {
+    let mut tarjan = Tarjan {
+        graph,
+        index: HashMap::new(),
+        lowlink: HashMap::new(),
+        stack: vec![],
+        on_stack: HashSet::new(),
+        groups: vec![],
+    };
+    for &v in graph.keys() {
+        if !tarjan.index.contains_key(&v) {
+            tarjan.connect(v);
+        }
+    }
+    tarjan.groups
+}
+
+struct Tarjan<'g> {
+    graph: &'g HashMap<Id, Vec<Id>>,
+    index: HashMap<Id, usize>,
+    lowlink: HashMap<Id, usize>,
+    stack: Vec<Id>,
+    on_stack: HashSet<Id>,
+    groups: Vec<Vec<Id>>,
+}
+
+impl<'g> Tarjan<'g> {
+    fn connect(&mut self, v: Id) {
+        let idx = self.index.len();
+        self.index.insert(v, idx);
+        self.lowlink.insert(v, idx);
+        self.stack.push(v);
+        self.on_stack.insert(v);
+
+        let graph = self.graph;
+        // Nodes out of the graph have no edges
+        for &w in graph.get(&v).map_or(&[][..], |ws| &ws[..]).iter() {
+            if !self.index.contains_key(&w) {
+                self.connect(w);
+                let low = self.lowlink[&v].min(self.lowlink[&w]);
+                self.lowlink.insert(v, low);
+            } else if self.on_stack.contains(&w) {
+                let low = self.lowlink[&v].min(self.index[&w]);
+                self.lowlink.insert(v, low);
+            }
+        }
+
+        // `v` is the root of a component, which is completed
+        //   after all components it reaches
+        if self.lowlink[&v] == self.index[&v] {
+            let mut group = vec![];
+            loop {
+                let w = self.stack.pop().unwrap();
+                self.on_stack.remove(&w);
+                group.push(w);
+                if w == v {
+                    break;
+                }
+            }
+            self.groups.push(group);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern_same_string() {
+        let mut interner = Interner::new();
+        let a = interner.intern("fuck");
+        let b = interner.intern("shit");
+        assert_eq!(interner.intern("fuck"), a);
+        assert!(a != b);
+        assert_eq!(interner.trace(a), "fuck");
+        assert_eq!(interner.trace_string(b), "shit");
+    }
+}
@@ -0,0 +1,5 @@
+def greet(name) = strcat("Hello, ", name)
+
+def main() =
+    let s = greet("miko") in
+        printLn(s)